@@ -1,14 +1,29 @@
 use falcon;
-use gluon::vm::api::{Userdata, VmType};
+use gluon::vm::api::{OpaqueValue, OwnedFunction, Userdata, VmType};
+use gluon::vm::api::generic::A;
 use gluon::vm::thread::{Traverseable};
 use gluon;
+use std::sync::Arc;
 
+use architecture;
+use call_site;
+use dataflow;
+use format_string;
 use il;
+use jump_table;
+use loader;
+use memory;
+use search;
+use slice;
+use stack_overflow;
+use taint;
+use value_set;
+use xref;
 
 
 fn dead_code_elimination(function: &il::IlFunction) -> il::IlFunction {
     let function = falcon::analysis::dead_code_elimination(&function.x).unwrap();
-    il::IlFunction { x: function }
+    il::IlFunction { x: function.into() }
 }
 
 
@@ -41,18 +56,848 @@ fn constants_eval(constants: &AnalysisConstants, expression: &il::IlExpression)
 }
 
 
+falcon_type_wrapper!(call_site::CallSite, AnalysisCallSite);
+
+fn call_sites(
+    architecture: &architecture::ArchitectureArchitecture,
+    function: &il::IlFunction,
+    count: usize
+) -> Result<Vec<AnalysisCallSite>, String> {
+
+    call_site::call_sites(&**architecture.x, &function.x, count)
+        .map(|call_sites| call_sites.into_iter()
+            .map(|call_site| AnalysisCallSite { x: call_site })
+            .collect())
+        .map_err(|e| format!("{}", e))
+}
+
+fn call_site_address(call_site: &AnalysisCallSite) -> Option<u64> {
+    call_site.x.address
+}
+
+fn call_site_arguments(call_site: &AnalysisCallSite) -> Vec<AnalysisCallArgument> {
+    call_site.x.arguments
+        .iter()
+        .map(|argument| AnalysisCallArgument { x: argument.clone() })
+        .collect()
+}
+
+fn call_site_argument(call_site: &AnalysisCallSite, index: usize)
+    -> Option<AnalysisCallArgument> {
+
+    call_site.x.argument(index)
+        .map(|argument| AnalysisCallArgument { x: argument.clone() })
+}
+
+fn call_site_location(call_site: &AnalysisCallSite) -> il::IlFunctionLocation {
+    il::IlFunctionLocation { x: call_site.x.location.clone() }
+}
+
+fn call_site_target(call_site: &AnalysisCallSite) -> Option<u64> {
+    call_site.x.target
+}
+
+
+falcon_type_wrapper!(call_site::Argument, AnalysisCallArgument);
+
+fn call_argument_constant(argument: &AnalysisCallArgument) -> Option<il::IlConstant> {
+    argument.x.constant
+        .clone()
+        .map(|constant| il::IlConstant { x: constant })
+}
+
+fn call_argument_expression(argument: &AnalysisCallArgument) -> Option<il::IlExpression> {
+    argument.x.expression
+        .clone()
+        .map(|expression| il::IlExpression { x: expression })
+}
+
+fn call_argument_index(argument: &AnalysisCallArgument) -> usize {
+    argument.x.index
+}
+
+fn call_argument_origin(argument: &AnalysisCallArgument) -> il::IlFunctionLocation {
+    il::IlFunctionLocation { x: argument.x.origin.clone() }
+}
+
+fn call_argument_register(argument: &AnalysisCallArgument) -> Option<il::IlScalar> {
+    match argument.x.slot {
+        call_site::Slot::Register(ref scalar) => Some(il::IlScalar { x: scalar.clone() }),
+        call_site::Slot::Stack(_) => None
+    }
+}
+
+fn call_argument_stack_offset(argument: &AnalysisCallArgument) -> Option<usize> {
+    match argument.x.slot {
+        call_site::Slot::Register(_) => None,
+        call_site::Slot::Stack(offset) => Some(offset)
+    }
+}
+
+
+falcon_type_wrapper!(format_string::Finding, AnalysisFormatStringFinding);
+
+fn format_string_check(loader: &loader::LoaderLoader, program: &il::IlProgram)
+    -> Result<Vec<AnalysisFormatStringFinding>, String> {
+
+    format_string::check(&**loader.x, &program.x)
+        .map(|findings| findings.into_iter()
+            .map(|finding| AnalysisFormatStringFinding { x: finding })
+            .collect())
+        .map_err(|e| format!("{}", e))
+}
+
+fn format_string_finding_address(finding: &AnalysisFormatStringFinding) -> Option<u64> {
+    finding.x.address
+}
+
+fn format_string_finding_argument(finding: &AnalysisFormatStringFinding) -> usize {
+    finding.x.argument
+}
+
+fn format_string_finding_caller(finding: &AnalysisFormatStringFinding) -> String {
+    finding.x.caller.clone()
+}
+
+fn format_string_finding_function(finding: &AnalysisFormatStringFinding) -> String {
+    finding.x.function.clone()
+}
+
+fn format_string_finding_location(finding: &AnalysisFormatStringFinding)
+    -> il::IlProgramLocation {
+
+    il::IlProgramLocation { x: finding.x.location.clone() }
+}
+
+fn format_string_finding_reason(finding: &AnalysisFormatStringFinding) -> String {
+    format!("{}", finding.x.reason)
+}
+
+fn format_string_finding_str(finding: &AnalysisFormatStringFinding) -> String {
+    format!("{}", finding.x)
+}
+
+
+
+falcon_type_wrapper!(stack_overflow::Finding, AnalysisStackOverflowFinding);
+
+fn stack_overflow_check(loader: &loader::LoaderLoader, program: &il::IlProgram)
+    -> Result<Vec<AnalysisStackOverflowFinding>, String> {
+
+    stack_overflow::check(&**loader.x, &program.x)
+        .map(|findings| findings.into_iter()
+            .map(|finding| AnalysisStackOverflowFinding { x: finding })
+            .collect())
+        .map_err(|e| format!("{}", e))
+}
+
+fn stack_overflow_finding_address(finding: &AnalysisStackOverflowFinding) -> Option<u64> {
+    finding.x.address
+}
+
+fn stack_overflow_finding_function(finding: &AnalysisStackOverflowFinding) -> String {
+    finding.x.function.clone()
+}
+
+fn stack_overflow_finding_location(finding: &AnalysisStackOverflowFinding)
+    -> il::IlProgramLocation {
+
+    il::IlProgramLocation { x: finding.x.location.clone() }
+}
+
+fn stack_overflow_finding_offset(finding: &AnalysisStackOverflowFinding) -> i64 {
+    finding.x.offset
+}
+
+fn stack_overflow_finding_reason(finding: &AnalysisStackOverflowFinding) -> String {
+    format!("{}", finding.x.reason)
+}
+
+fn stack_overflow_finding_slot_offset(finding: &AnalysisStackOverflowFinding) -> i64 {
+    finding.x.slot_offset
+}
+
+fn stack_overflow_finding_slot_size(finding: &AnalysisStackOverflowFinding) -> u64 {
+    finding.x.slot_size
+}
+
+fn stack_overflow_finding_str(finding: &AnalysisStackOverflowFinding) -> String {
+    format!("{}", finding.x)
+}
+
+
+
+falcon_type_wrapper!(value_set::ValueSets, AnalysisValueSets);
+
+fn value_set_analysis(
+    architecture: &architecture::ArchitectureArchitecture,
+    function: &il::IlFunction
+) -> Result<AnalysisValueSets, String> {
+
+    value_set::value_sets(&**architecture.x, &function.x)
+        .map(|value_sets| AnalysisValueSets { x: value_sets })
+        .map_err(|e| format!("{}", e))
+}
+
+fn value_set_range_of(
+    value_sets: &AnalysisValueSets,
+    location: &il::IlProgramLocation,
+    expression: &il::IlExpression
+) -> Option<AnalysisStridedInterval> {
+
+    value_sets.x.range_of(&location.x, &expression.x)
+        .map(|interval| AnalysisStridedInterval { x: interval })
+}
+
+fn value_set_range_of_scalar(
+    value_sets: &AnalysisValueSets,
+    location: &il::IlProgramLocation,
+    scalar: &il::IlScalar
+) -> Option<AnalysisStridedInterval> {
+
+    value_sets.x.range_of_scalar(&location.x, &scalar.x)
+        .map(|interval| AnalysisStridedInterval { x: interval })
+}
+
+
+falcon_type_wrapper!(value_set::StridedInterval, AnalysisStridedInterval);
+
+fn strided_interval_bits(interval: &AnalysisStridedInterval) -> usize {
+    interval.x.bits
+}
+
+fn strided_interval_contains(interval: &AnalysisStridedInterval, value: u64) -> bool {
+    interval.x.contains(value)
+}
+
+fn strided_interval_hi(interval: &AnalysisStridedInterval) -> u64 {
+    interval.x.hi
+}
+
+fn strided_interval_is_top(interval: &AnalysisStridedInterval) -> bool {
+    interval.x.is_top()
+}
+
+fn strided_interval_lo(interval: &AnalysisStridedInterval) -> u64 {
+    interval.x.lo
+}
+
+fn strided_interval_stride(interval: &AnalysisStridedInterval) -> u64 {
+    interval.x.stride
+}
+
+fn strided_interval_str(interval: &AnalysisStridedInterval) -> String {
+    format!("{}", interval.x)
+}
+
+fn strided_interval_value(interval: &AnalysisStridedInterval) -> Option<u64> {
+    interval.x.value()
+}
+
+
+type GluonState = OpaqueValue<gluon::RootedThread, A>;
+
+/// A dataflow domain whose lattice and transfer functions are written in
+/// gluon. Nothing widens it, so its lattice must have finite height.
+struct GluonDomain {
+    bottom: GluonState,
+    join: OwnedFunction<fn(GluonState, GluonState) -> GluonState>,
+    leq: OwnedFunction<fn(GluonState, GluonState) -> bool>,
+    transfer: OwnedFunction<fn(il::IlOperation, GluonState) -> GluonState>,
+    refine: OwnedFunction<fn(il::IlExpression, GluonState) -> GluonState>
+}
+
+fn gluon_error(error: gluon::vm::Error) -> falcon::error::Error {
+    format!("{}", error).into()
+}
+
+impl dataflow::Domain for GluonDomain {
+    type State = GluonState;
+
+    fn bottom(&self) -> falcon::error::Result<GluonState> {
+        Ok(self.bottom.clone())
+    }
+
+    fn join(&self, lhs: &GluonState, rhs: &GluonState)
+        -> falcon::error::Result<GluonState> {
+        self.join.clone().call(lhs.clone(), rhs.clone()).map_err(gluon_error)
+    }
+
+    fn leq(&self, lhs: &GluonState, rhs: &GluonState) -> falcon::error::Result<bool> {
+        self.leq.clone().call(lhs.clone(), rhs.clone()).map_err(gluon_error)
+    }
+
+    fn transfer(
+        &self,
+        _: &falcon::il::RefProgramLocation,
+        operation: &falcon::il::Operation,
+        state: GluonState
+    ) -> falcon::error::Result<GluonState> {
+        let operation = il::IlOperation { x: operation.clone() };
+        self.transfer.clone().call(operation, state).map_err(gluon_error)
+    }
+
+    fn refine(&self, condition: &falcon::il::Expression, state: GluonState)
+        -> falcon::error::Result<GluonState> {
+        let condition = il::IlExpression { x: condition.clone() };
+        self.refine.clone().call(condition, state).map_err(gluon_error)
+    }
+}
+
+fn dataflow_forward(
+    bottom: GluonState,
+    join: OwnedFunction<fn(GluonState, GluonState) -> GluonState>,
+    leq: OwnedFunction<fn(GluonState, GluonState) -> bool>,
+    transfer: OwnedFunction<fn(il::IlOperation, GluonState) -> GluonState>,
+    refine: OwnedFunction<fn(il::IlExpression, GluonState) -> GluonState>,
+    function: &il::IlFunction
+) -> Result<Vec<(il::IlFunctionLocation, GluonState)>, String> {
+
+    let domain = GluonDomain {
+        bottom: bottom,
+        join: join,
+        leq: leq,
+        transfer: transfer,
+        refine: refine
+    };
+
+    dataflow::forward(&domain, &function.x)
+        .map(|states| states.into_iter()
+            .map(|(location, state)| (il::IlFunctionLocation { x: location }, state))
+            .collect())
+        .map_err(|e| format!("{}", e))
+}
+
+
+falcon_type_wrapper!(jump_table::IndirectBranch, AnalysisIndirectBranch);
+
+fn jump_table_branches(loader: &loader::LoaderLoader, function: &il::IlFunction)
+    -> Result<Vec<AnalysisIndirectBranch>, String> {
+
+    jump_table::indirect_branches(&**loader.x, &function.x)
+        .map(|branches| branches.into_iter()
+            .map(|branch| AnalysisIndirectBranch { x: branch })
+            .collect())
+        .map_err(|e| format!("{}", e))
+}
+
+fn jump_table_resolve(loader: &loader::LoaderLoader, program: &il::IlProgram)
+    -> (il::IlProgram, AnalysisJumpTableResolution) {
+
+    let (program, resolution) = jump_table::resolve_program(&**loader.x, &program.x);
+    (il::IlProgram { x: Arc::new(program) }, AnalysisJumpTableResolution { x: resolution })
+}
+
+fn jump_table_program_recursive(loader: &loader::LoaderLoader)
+    -> Result<(il::IlProgram, AnalysisJumpTableResolution), String> {
+
+    loader::program_recursive_resolution(&**loader.x)
+        .map(|(program, resolution)| (
+            il::IlProgram { x: Arc::new(program) },
+            AnalysisJumpTableResolution { x: resolution }
+        ))
+        .map_err(|e| format!("{}", e))
+}
+
+fn indirect_branch_address(branch: &AnalysisIndirectBranch) -> Option<u64> {
+    branch.x.address
+}
+
+fn indirect_branch_function(branch: &AnalysisIndirectBranch) -> String {
+    branch.x.function.clone()
+}
+
+fn indirect_branch_location(branch: &AnalysisIndirectBranch) -> il::IlFunctionLocation {
+    il::IlFunctionLocation { x: branch.x.location.clone() }
+}
+
+fn indirect_branch_reason(branch: &AnalysisIndirectBranch) -> Option<String> {
+    branch.x.reason.as_ref().map(|reason| format!("{}", reason))
+}
+
+fn indirect_branch_resolved(branch: &AnalysisIndirectBranch) -> bool {
+    branch.x.resolved()
+}
+
+fn indirect_branch_str(branch: &AnalysisIndirectBranch) -> String {
+    format!("{}", branch.x)
+}
+
+fn indirect_branch_table(branch: &AnalysisIndirectBranch) -> Option<u64> {
+    branch.x.table
+}
+
+fn indirect_branch_targets(branch: &AnalysisIndirectBranch) -> Vec<u64> {
+    branch.x.targets.clone()
+}
+
+
+falcon_type_wrapper!(jump_table::Resolution, AnalysisJumpTableResolution);
+
+fn jump_table_resolution_branches(resolution: &AnalysisJumpTableResolution)
+    -> Vec<AnalysisIndirectBranch> {
+
+    resolution.x.branches.iter()
+        .map(|branch| AnalysisIndirectBranch { x: branch.clone() })
+        .collect()
+}
+
+fn jump_table_resolution_failures(resolution: &AnalysisJumpTableResolution)
+    -> Vec<(String, String)> {
+
+    resolution.x.failures.clone()
+}
+
+
+falcon_type_wrapper!(search::Pattern, AnalysisBytePattern);
+
+fn byte_pattern_parse(pattern: String) -> Result<AnalysisBytePattern, String> {
+    search::Pattern::parse(&pattern)
+        .map(|pattern| AnalysisBytePattern { x: pattern })
+        .map_err(|e| format!("{}", e))
+}
+
+fn byte_pattern_masked(bytes: Vec<u8>, mask: Vec<u8>) -> Result<AnalysisBytePattern, String> {
+    search::Pattern::new(bytes, mask)
+        .map(|pattern| AnalysisBytePattern { x: pattern })
+        .map_err(|e| format!("{}", e))
+}
+
+fn byte_pattern_str(pattern: &AnalysisBytePattern) -> String {
+    format!("{}", pattern.x)
+}
+
+fn search_constants(program: &il::IlProgram, values: Vec<(String, u64)>)
+    -> Vec<AnalysisSearchMatch> {
+
+    search::constants(&program.x, &values)
+        .into_iter()
+        .map(|m| AnalysisSearchMatch { x: m })
+        .collect()
+}
+
+fn search_crypto(
+    architecture: &architecture::ArchitectureArchitecture,
+    program: &il::IlProgram,
+    memory: &memory::BackingMemory
+) -> Vec<AnalysisSearchMatch> {
+
+    search::crypto(&**architecture.x, &program.x, &memory.x)
+        .into_iter()
+        .map(|m| AnalysisSearchMatch { x: m })
+        .collect()
+}
+
+fn search_memory(memory: &memory::BackingMemory, pattern: &AnalysisBytePattern) -> Vec<u64> {
+    search::search(&memory.x, &pattern.x)
+}
+
+fn search_program(
+    program: &il::IlProgram,
+    memory: &memory::BackingMemory,
+    pattern: &AnalysisBytePattern
+) -> Vec<AnalysisSearchMatch> {
+
+    search::search_program(&program.x, &memory.x, &pattern.x)
+        .into_iter()
+        .map(|m| AnalysisSearchMatch { x: m })
+        .collect()
+}
+
+
+falcon_type_wrapper!(search::Match, AnalysisSearchMatch);
+
+fn search_match_address(m: &AnalysisSearchMatch) -> Option<u64> {
+    m.x.address
+}
+
+fn search_match_function(m: &AnalysisSearchMatch) -> Option<String> {
+    m.x.function.clone()
+}
+
+fn search_match_location(m: &AnalysisSearchMatch) -> Option<il::IlProgramLocation> {
+    m.x.location.clone().map(|location| il::IlProgramLocation { x: location })
+}
+
+fn search_match_name(m: &AnalysisSearchMatch) -> String {
+    m.x.name.clone()
+}
+
+fn search_match_str(m: &AnalysisSearchMatch) -> String {
+    format!("{}", m.x)
+}
+
+
+falcon_type_wrapper!(slice::Variable, AnalysisSliceCriterion);
+
+fn slice_criterion_scalar(scalar: &il::IlScalar) -> AnalysisSliceCriterion {
+    AnalysisSliceCriterion { x: slice::Variable::scalar(&scalar.x) }
+}
+
+fn slice_criterion_memory(_: ()) -> AnalysisSliceCriterion {
+    AnalysisSliceCriterion { x: slice::Variable::Memory }
+}
+
+fn slice_locations(
+    function: &il::IlFunction,
+    locations: ::std::collections::HashSet<falcon::il::FunctionLocation>
+) -> Vec<il::IlProgramLocation> {
+
+    let mut locations: Vec<falcon::il::ProgramLocation> = locations.into_iter()
+        .map(|location| falcon::il::ProgramLocation::new(function.x.index(), location))
+        .collect();
+    locations.sort();
+    locations.into_iter().map(|location| il::IlProgramLocation { x: location }).collect()
+}
+
+fn slice_backward(
+    architecture: &architecture::ArchitectureArchitecture,
+    function: &il::IlFunction,
+    location: &il::IlProgramLocation,
+    criterion: &AnalysisSliceCriterion
+) -> Result<Vec<il::IlProgramLocation>, String> {
+
+    slice::backward(&**architecture.x, &function.x, location.x.function_location(), criterion.x.clone())
+        .map(|locations| slice_locations(function, locations))
+        .map_err(|e| format!("{}", e))
+}
+
+fn slice_forward(
+    architecture: &architecture::ArchitectureArchitecture,
+    function: &il::IlFunction,
+    location: &il::IlProgramLocation,
+    criterion: &AnalysisSliceCriterion
+) -> Result<Vec<il::IlProgramLocation>, String> {
+
+    slice::forward(&**architecture.x, &function.x, location.x.function_location(), criterion.x.clone())
+        .map(|locations| slice_locations(function, locations))
+        .map_err(|e| format!("{}", e))
+}
+
+fn slice_backward_function(
+    architecture: &architecture::ArchitectureArchitecture,
+    function: &il::IlFunction,
+    location: &il::IlProgramLocation,
+    criterion: &AnalysisSliceCriterion
+) -> Result<il::IlFunction, String> {
+
+    slice::backward(&**architecture.x, &function.x, location.x.function_location(), criterion.x.clone())
+        .and_then(|locations| slice::reduce(&function.x, &locations))
+        .map(|reduced| il::IlFunction { x: reduced.into() })
+        .map_err(|e| format!("{}", e))
+}
+
+fn slice_forward_function(
+    architecture: &architecture::ArchitectureArchitecture,
+    function: &il::IlFunction,
+    location: &il::IlProgramLocation,
+    criterion: &AnalysisSliceCriterion
+) -> Result<il::IlFunction, String> {
+
+    slice::forward(&**architecture.x, &function.x, location.x.function_location(), criterion.x.clone())
+        .and_then(|locations| slice::reduce(&function.x, &locations))
+        .map(|reduced| il::IlFunction { x: reduced.into() })
+        .map_err(|e| format!("{}", e))
+}
+
+
+falcon_type_wrapper!(taint::Config, AnalysisTaintConfig);
+
+fn taint_config_new(_: ()) -> AnalysisTaintConfig {
+    AnalysisTaintConfig { x: taint::Config::new() }
+}
+
+fn taint_config_source_return(config: &AnalysisTaintConfig, function: String)
+    -> AnalysisTaintConfig {
+
+    let mut config = config.clone();
+    config.x.sources.push(taint::Source {
+        function: function,
+        kind: taint::SourceKind::Return
+    });
+    config
+}
+
+fn taint_config_source_argument(
+    config: &AnalysisTaintConfig,
+    function: String,
+    argument: usize
+) -> AnalysisTaintConfig {
+
+    let mut config = config.clone();
+    config.x.sources.push(taint::Source {
+        function: function,
+        kind: taint::SourceKind::Argument(argument)
+    });
+    config
+}
+
+fn taint_config_sink(config: &AnalysisTaintConfig, function: String, argument: usize)
+    -> AnalysisTaintConfig {
+
+    let mut config = config.clone();
+    config.x.sinks.push(taint::Sink { function: function, argument: argument });
+    config
+}
+
+
+falcon_type_wrapper!(taint::Finding, AnalysisTaintFinding);
+falcon_type_wrapper!(taint::Report, AnalysisTaintReport);
+
+fn taint_analysis(
+    loader: &loader::LoaderLoader,
+    program: &il::IlProgram,
+    config: &AnalysisTaintConfig
+) -> Result<AnalysisTaintReport, String> {
+
+    taint::analyze(&**loader.x, &program.x, &config.x)
+        .map(|report| AnalysisTaintReport { x: report })
+        .map_err(|e| format!("{}", e))
+}
+
+fn taint_finding_argument(finding: &AnalysisTaintFinding) -> usize {
+    finding.x.argument
+}
+
+fn taint_finding_function(finding: &AnalysisTaintFinding) -> String {
+    finding.x.function.clone()
+}
+
+fn taint_finding_path(finding: &AnalysisTaintFinding) -> Vec<il::IlProgramLocation> {
+    finding.x.path()
+        .into_iter()
+        .map(|location| il::IlProgramLocation { x: location })
+        .collect()
+}
+
+fn taint_finding_sink(finding: &AnalysisTaintFinding) -> il::IlProgramLocation {
+    il::IlProgramLocation { x: finding.x.sink.clone() }
+}
+
+fn taint_finding_source(finding: &AnalysisTaintFinding) -> il::IlProgramLocation {
+    il::IlProgramLocation { x: finding.x.source.clone() }
+}
+
+fn taint_finding_str(finding: &AnalysisTaintFinding) -> String {
+    let path: Vec<String> = finding.x.path()
+        .iter()
+        .map(|location| format!("{}", location))
+        .collect();
+    format!("{} argument {}: {}", finding.x.function, finding.x.argument, path.join(" -> "))
+}
+
+fn taint_report_converged(report: &AnalysisTaintReport) -> bool {
+    report.x.converged
+}
+
+fn taint_report_failures(report: &AnalysisTaintReport) -> Vec<(String, String)> {
+    report.x.failures.clone()
+}
+
+fn taint_report_findings(report: &AnalysisTaintReport) -> Vec<AnalysisTaintFinding> {
+    report.x.findings.iter()
+        .map(|finding| AnalysisTaintFinding { x: finding.clone() })
+        .collect()
+}
+
+
+falcon_type_wrapper!(xref::Xrefs, AnalysisXrefs);
+
+fn xref_index(
+    architecture: &architecture::ArchitectureArchitecture,
+    program: &il::IlProgram,
+    memory: &memory::BackingMemory
+) -> AnalysisXrefs {
+    AnalysisXrefs { x: xref::xrefs(&**architecture.x, &program.x, &memory.x) }
+}
+
+fn xrefs_all(xrefs: &AnalysisXrefs) -> Vec<AnalysisXref> {
+    xrefs.x.xrefs().iter().map(|xref| AnalysisXref { x: xref.clone() }).collect()
+}
+
+fn xrefs_from(xrefs: &AnalysisXrefs, location: &il::IlProgramLocation) -> Vec<AnalysisXref> {
+    xrefs.x.from(&location.x).into_iter().map(|xref| AnalysisXref { x: xref.clone() }).collect()
+}
+
+fn xrefs_from_data(xrefs: &AnalysisXrefs, address: u64) -> Vec<AnalysisXref> {
+    xrefs.x.from_data(address).into_iter().map(|xref| AnalysisXref { x: xref.clone() }).collect()
+}
+
+fn xrefs_to(xrefs: &AnalysisXrefs, target: u64) -> Vec<AnalysisXref> {
+    xrefs.x.to(target).into_iter().map(|xref| AnalysisXref { x: xref.clone() }).collect()
+}
+
+fn xrefs_to_range(xrefs: &AnalysisXrefs, start: u64, end: u64) -> Vec<AnalysisXref> {
+    xrefs.x.to_range(start, end)
+        .into_iter()
+        .map(|xref| AnalysisXref { x: xref.clone() })
+        .collect()
+}
+
+
+falcon_type_wrapper!(xref::Xref, AnalysisXref);
+
+fn xref_address(xref: &AnalysisXref) -> Option<u64> {
+    xref.x.address
+}
+
+fn xref_data(xref: &AnalysisXref) -> Option<u64> {
+    match xref.x.source {
+        xref::Source::Data(address) => Some(address),
+        xref::Source::Code(_) => None
+    }
+}
+
+fn xref_kind(xref: &AnalysisXref) -> String {
+    format!("{}", xref.x.kind)
+}
+
+fn xref_location(xref: &AnalysisXref) -> Option<il::IlProgramLocation> {
+    match xref.x.source {
+        xref::Source::Code(ref location) => Some(il::IlProgramLocation { x: location.clone() }),
+        xref::Source::Data(_) => None
+    }
+}
+
+fn xref_str(xref: &AnalysisXref) -> String {
+    format!("{}", xref.x)
+}
+
+fn xref_target(xref: &AnalysisXref) -> u64 {
+    xref.x.target
+}
+
+
 pub fn bindings(vm: gluon::RootedThread) -> gluon::RootedThread {
 
     vm.register_type::<AnalysisConstants>("AnalysisConstants", &[]).unwrap();
+    vm.register_type::<AnalysisBytePattern>("AnalysisBytePattern", &[]).unwrap();
+    vm.register_type::<AnalysisCallArgument>("AnalysisCallArgument", &[]).unwrap();
+    vm.register_type::<AnalysisCallSite>("AnalysisCallSite", &[]).unwrap();
+    vm.register_type::<AnalysisFormatStringFinding>("AnalysisFormatStringFinding", &[])
+        .unwrap();
+    vm.register_type::<AnalysisIndirectBranch>("AnalysisIndirectBranch", &[]).unwrap();
+    vm.register_type::<AnalysisJumpTableResolution>("AnalysisJumpTableResolution", &[])
+        .unwrap();
+    vm.register_type::<AnalysisSliceCriterion>("AnalysisSliceCriterion", &[]).unwrap();
+    vm.register_type::<AnalysisStackOverflowFinding>("AnalysisStackOverflowFinding", &[])
+        .unwrap();
+    vm.register_type::<AnalysisTaintConfig>("AnalysisTaintConfig", &[]).unwrap();
+    vm.register_type::<AnalysisTaintFinding>("AnalysisTaintFinding", &[]).unwrap();
+    vm.register_type::<AnalysisTaintReport>("AnalysisTaintReport", &[]).unwrap();
+    vm.register_type::<AnalysisSearchMatch>("AnalysisSearchMatch", &[]).unwrap();
+    vm.register_type::<AnalysisStridedInterval>("AnalysisStridedInterval", &[]).unwrap();
+    vm.register_type::<AnalysisValueSets>("AnalysisValueSets", &[]).unwrap();
+    vm.register_type::<AnalysisXref>("AnalysisXref", &[]).unwrap();
+    vm.register_type::<AnalysisXrefs>("AnalysisXrefs", &[]).unwrap();
 
     fn falcon_loader_prim_loader(vm: &gluon::Thread)
         -> gluon::vm::Result<gluon::vm::ExternModule> {
         
         gluon::vm::ExternModule::new(vm, record! {
+            byte_pattern_masked => primitive!(2, byte_pattern_masked),
+            byte_pattern_parse => primitive!(1, byte_pattern_parse),
+            byte_pattern_str => primitive!(1, byte_pattern_str),
             dead_code_elimination => primitive!(1, dead_code_elimination),
+            call_argument_constant => primitive!(1, call_argument_constant),
+            call_argument_expression => primitive!(1, call_argument_expression),
+            call_argument_index => primitive!(1, call_argument_index),
+            call_argument_origin => primitive!(1, call_argument_origin),
+            call_argument_register => primitive!(1, call_argument_register),
+            call_argument_stack_offset => primitive!(1, call_argument_stack_offset),
+            call_site_address => primitive!(1, call_site_address),
+            call_site_argument => primitive!(2, call_site_argument),
+            call_site_arguments => primitive!(1, call_site_arguments),
+            call_site_location => primitive!(1, call_site_location),
+            call_site_target => primitive!(1, call_site_target),
+            call_sites => primitive!(3, call_sites),
             constants_analysis => primitive!(1, constants_analysis),
             constants_eval => primitive!(2, constants_eval),
-            constants_scalar => primitive!(2, constants_scalar)
+            constants_scalar => primitive!(2, constants_scalar),
+            dataflow_forward => primitive!(6, dataflow_forward),
+            format_string_check => primitive!(2, format_string_check),
+            format_string_finding_address => primitive!(1, format_string_finding_address),
+            format_string_finding_argument => primitive!(1, format_string_finding_argument),
+            format_string_finding_caller => primitive!(1, format_string_finding_caller),
+            format_string_finding_function => primitive!(1, format_string_finding_function),
+            format_string_finding_location => primitive!(1, format_string_finding_location),
+            format_string_finding_reason => primitive!(1, format_string_finding_reason),
+            format_string_finding_str => primitive!(1, format_string_finding_str),
+            indirect_branch_address => primitive!(1, indirect_branch_address),
+            indirect_branch_function => primitive!(1, indirect_branch_function),
+            indirect_branch_location => primitive!(1, indirect_branch_location),
+            indirect_branch_reason => primitive!(1, indirect_branch_reason),
+            indirect_branch_resolved => primitive!(1, indirect_branch_resolved),
+            indirect_branch_str => primitive!(1, indirect_branch_str),
+            indirect_branch_table => primitive!(1, indirect_branch_table),
+            indirect_branch_targets => primitive!(1, indirect_branch_targets),
+            jump_table_branches => primitive!(2, jump_table_branches),
+            jump_table_program_recursive => primitive!(1, jump_table_program_recursive),
+            jump_table_resolve => primitive!(2, jump_table_resolve),
+            jump_table_resolution_branches => primitive!(1, jump_table_resolution_branches),
+            jump_table_resolution_failures => primitive!(1, jump_table_resolution_failures),
+            search_constants => primitive!(2, search_constants),
+            search_crypto => primitive!(3, search_crypto),
+            search_match_address => primitive!(1, search_match_address),
+            search_match_function => primitive!(1, search_match_function),
+            search_match_location => primitive!(1, search_match_location),
+            search_match_name => primitive!(1, search_match_name),
+            search_match_str => primitive!(1, search_match_str),
+            search_memory => primitive!(2, search_memory),
+            search_program => primitive!(3, search_program),
+            slice_backward => primitive!(4, slice_backward),
+            slice_backward_function => primitive!(4, slice_backward_function),
+            slice_criterion_memory => primitive!(1, slice_criterion_memory),
+            slice_criterion_scalar => primitive!(1, slice_criterion_scalar),
+            slice_forward => primitive!(4, slice_forward),
+            slice_forward_function => primitive!(4, slice_forward_function),
+            stack_overflow_check => primitive!(2, stack_overflow_check),
+            stack_overflow_finding_address => primitive!(1, stack_overflow_finding_address),
+            stack_overflow_finding_function => primitive!(1, stack_overflow_finding_function),
+            stack_overflow_finding_location => primitive!(1, stack_overflow_finding_location),
+            stack_overflow_finding_offset => primitive!(1, stack_overflow_finding_offset),
+            stack_overflow_finding_reason => primitive!(1, stack_overflow_finding_reason),
+            stack_overflow_finding_slot_offset =>
+                primitive!(1, stack_overflow_finding_slot_offset),
+            stack_overflow_finding_slot_size => primitive!(1, stack_overflow_finding_slot_size),
+            stack_overflow_finding_str => primitive!(1, stack_overflow_finding_str),
+            strided_interval_bits => primitive!(1, strided_interval_bits),
+            strided_interval_contains => primitive!(2, strided_interval_contains),
+            strided_interval_hi => primitive!(1, strided_interval_hi),
+            strided_interval_is_top => primitive!(1, strided_interval_is_top),
+            strided_interval_lo => primitive!(1, strided_interval_lo),
+            strided_interval_stride => primitive!(1, strided_interval_stride),
+            strided_interval_str => primitive!(1, strided_interval_str),
+            strided_interval_value => primitive!(1, strided_interval_value),
+            taint_analysis => primitive!(3, taint_analysis),
+            taint_config_new => primitive!(1, taint_config_new),
+            taint_config_sink => primitive!(3, taint_config_sink),
+            taint_config_source_argument => primitive!(3, taint_config_source_argument),
+            taint_config_source_return => primitive!(2, taint_config_source_return),
+            taint_finding_argument => primitive!(1, taint_finding_argument),
+            taint_finding_function => primitive!(1, taint_finding_function),
+            taint_finding_path => primitive!(1, taint_finding_path),
+            taint_finding_sink => primitive!(1, taint_finding_sink),
+            taint_finding_source => primitive!(1, taint_finding_source),
+            taint_finding_str => primitive!(1, taint_finding_str),
+            taint_report_converged => primitive!(1, taint_report_converged),
+            taint_report_failures => primitive!(1, taint_report_failures),
+            taint_report_findings => primitive!(1, taint_report_findings),
+            value_set_analysis => primitive!(2, value_set_analysis),
+            value_set_range_of => primitive!(3, value_set_range_of),
+            value_set_range_of_scalar => primitive!(3, value_set_range_of_scalar),
+            xref_address => primitive!(1, xref_address),
+            xref_data => primitive!(1, xref_data),
+            xref_index => primitive!(3, xref_index),
+            xref_kind => primitive!(1, xref_kind),
+            xref_location => primitive!(1, xref_location),
+            xref_str => primitive!(1, xref_str),
+            xref_target => primitive!(1, xref_target),
+            xrefs_all => primitive!(1, xrefs_all),
+            xrefs_from => primitive!(2, xrefs_from),
+            xrefs_from_data => primitive!(2, xrefs_from_data),
+            xrefs_to => primitive!(2, xrefs_to),
+            xrefs_to_range => primitive!(3, xrefs_to_range)
         })
     }
     
@@ -0,0 +1,429 @@
+//! Call-site argument recovery.
+//!
+//! Arguments are located with the architecture's calling convention. Each
+//! argument's value is recovered by walking back through the assignments and
+//! stores of the calling block, which gives an expression over the values
+//! live where the walk stopped. That expression is then resolved to a
+//! constant with Falcon's constants analysis when every path agrees on it.
+//!
+//! `Callees` names the function a call site calls, following calls through
+//! import table entries and stubs such as PLT entries.
+
+use falcon;
+use falcon::architecture::{ArgumentType, Architecture, Endian};
+use falcon::error::Result;
+use falcon::loader::Loader;
+use falcon::memory::backing::Memory;
+use std::collections::{BTreeMap, HashMap};
+
+use il;
+
+
+/// Where the calling convention passes an argument.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Slot {
+    Register(falcon::il::Scalar),
+    /// An offset from the stack pointer at the call.
+    Stack(usize)
+}
+
+
+/// An argument recovered at a call site.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Argument {
+    pub index: usize,
+    pub slot: Slot,
+    /// The argument's value, in terms of the values live at `origin`. `None`
+    /// if a stack argument's store could not be found.
+    pub expression: Option<falcon::il::Expression>,
+    /// The location `expression` is evaluated before.
+    pub origin: falcon::il::FunctionLocation,
+    /// The argument's value, if it is constant on every path to the call.
+    pub constant: Option<falcon::il::Constant>
+}
+
+
+/// A call, and its recovered arguments.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct CallSite {
+    /// The location of the branch making the call.
+    pub location: falcon::il::FunctionLocation,
+    /// The native address of the call, if known.
+    pub address: Option<u64>,
+    /// The call's target, if it is constant.
+    pub target: Option<u64>,
+    pub arguments: Vec<Argument>
+}
+
+impl CallSite {
+    /// The argument at `index`, if it was recovered.
+    pub fn argument(&self, index: usize) -> Option<&Argument> {
+        self.arguments.get(index)
+    }
+}
+
+
+/// The slot of argument `n` under `architecture`'s calling convention.
+pub fn slot(architecture: &Architecture, n: usize) -> Slot {
+    match architecture.calling_convention().argument_type(n) {
+        ArgumentType::Register(scalar) => Slot::Register(scalar),
+        ArgumentType::Stack(offset) => Slot::Stack(offset)
+    }
+}
+
+
+type States = HashMap<falcon::il::FunctionLocation, falcon::analysis::constants::Constants>;
+
+
+/// Walk `expression`, read before the instruction at `position` in `block`,
+/// back through the block's assignments. Stops at the start of the block, or
+/// at an instruction which defines a scalar of the expression some other way.
+fn walk_back(
+    block: &falcon::il::Block,
+    position: usize,
+    expression: falcon::il::Expression
+) -> (falcon::il::Expression, usize) {
+
+    let mut expression = expression;
+    let mut position = position;
+
+    while position > 0 {
+        let operation = block.instructions()[position - 1].operation();
+        let written = il::scalars_written(operation);
+        if !written.iter().any(|scalar| expression.scalars().contains(scalar)) {
+            position -= 1;
+            continue;
+        }
+        match *operation {
+            falcon::il::Operation::Assign { ref dst, ref src } =>
+                expression = il::simplify(&il::substitute(&expression, dst, src)),
+            _ => break
+        }
+        position -= 1;
+    }
+
+    (expression, position)
+}
+
+
+/// Find the value stored to `address`, read before the instruction at
+/// `position` in `block`, and walk it back further.
+fn stored(
+    block: &falcon::il::Block,
+    position: usize,
+    address: falcon::il::Expression
+) -> Option<(falcon::il::Expression, usize)> {
+
+    let mut address = address;
+    let mut position = position;
+
+    while position > 0 {
+        match *block.instructions()[position - 1].operation() {
+            falcon::il::Operation::Store { ref index, ref src } => {
+                if il::base_offset(&il::simplify(index)) == il::base_offset(&address) {
+                    return Some(walk_back(block, position - 1, src.clone()));
+                }
+            },
+            falcon::il::Operation::Assign { ref dst, ref src } => {
+                if address.scalars().contains(&dst) {
+                    address = il::simplify(&il::substitute(&address, dst, src));
+                }
+            },
+            falcon::il::Operation::Load { ref dst, .. } => {
+                if address.scalars().contains(&dst) {
+                    return None;
+                }
+            },
+            falcon::il::Operation::Intrinsic { .. } => return None,
+            _ => {}
+        }
+        position -= 1;
+    }
+
+    None
+}
+
+
+/// The constant value of `expression` before `location`, if every path into
+/// `location` agrees on it.
+fn resolve(
+    function: &falcon::il::Function,
+    states: &States,
+    location: &falcon::il::FunctionLocation,
+    expression: &falcon::il::Expression
+) -> Result<Option<falcon::il::Constant>> {
+
+    let location = falcon::il::RefProgramLocation::new(function, location.apply(function)?);
+
+    let mut constant: Option<falcon::il::Constant> = None;
+    let predecessors = location.backward()?;
+    if predecessors.is_empty() {
+        return Ok(None);
+    }
+
+    for predecessor in predecessors {
+        let predecessor: falcon::il::FunctionLocation =
+            predecessor.function_location().clone().into();
+        let value = match states.get(&predecessor)
+            .and_then(|constants| constants.eval(expression)) {
+            Some(value) => value.clone(),
+            None => return Ok(None)
+        };
+        match constant {
+            Some(ref constant) if *constant != value => return Ok(None),
+            _ => {}
+        }
+        constant = Some(value);
+    }
+
+    Ok(constant)
+}
+
+
+/// Every call in `function`, with its first `count` arguments.
+pub fn call_sites(
+    architecture: &Architecture,
+    function: &falcon::il::Function,
+    count: usize
+) -> Result<Vec<CallSite>> {
+
+    let states: States = falcon::analysis::constants::constants(function)?
+        .into_iter()
+        .map(|(location, constants)| (location.function_location().clone().into(), constants))
+        .collect();
+
+    let stack_pointer = falcon::il::Expression::Scalar(architecture.stack_pointer());
+
+    let mut call_sites = Vec::new();
+
+    for block in function.blocks() {
+        for (position, instruction) in block.instructions().iter().enumerate() {
+            let returns =
+                il::branch_returns(architecture, function, block.index(), instruction.index());
+            if returns != Some(true) {
+                continue;
+            }
+
+            let target = match *instruction.operation() {
+                falcon::il::Operation::Branch { ref target } => target,
+                _ => continue
+            };
+
+            let location = falcon::il::FunctionLocation::Instruction(
+                block.index(), instruction.index());
+            let origin_of = |position: usize| match block.instructions().get(position) {
+                Some(instruction) =>
+                    falcon::il::FunctionLocation::Instruction(block.index(), instruction.index()),
+                None => location.clone()
+            };
+
+            let (target, target_position) = walk_back(block, position, target.clone());
+            let target = resolve(function, &states, &origin_of(target_position), &target)?
+                .and_then(|constant| constant.value_u64());
+
+            let mut arguments = Vec::new();
+            for n in 0..count {
+                let slot = slot(architecture, n);
+                let recovered = match slot {
+                    Slot::Register(ref scalar) => Some(walk_back(
+                        block, position, falcon::il::Expression::Scalar(scalar.clone()))),
+                    Slot::Stack(offset) => {
+                        let address = falcon::il::Expression::add(
+                            stack_pointer.clone(),
+                            falcon::il::expr_const(offset as u64, stack_pointer.bits()))?;
+                        stored(block, position, il::simplify(&address))
+                    }
+                };
+
+                let argument = match recovered {
+                    Some((expression, origin)) => {
+                        let origin = origin_of(origin);
+                        let constant = resolve(function, &states, &origin, &expression)?;
+                        Argument {
+                            index: n,
+                            slot: slot,
+                            expression: Some(expression),
+                            origin: origin,
+                            constant: constant
+                        }
+                    },
+                    None => Argument {
+                        index: n,
+                        slot: slot,
+                        expression: None,
+                        origin: location.clone(),
+                        constant: None
+                    }
+                };
+                arguments.push(argument);
+            }
+
+            call_sites.push(CallSite {
+                location: location.clone(),
+                address: instruction.address(),
+                target: target,
+                arguments: arguments
+            });
+        }
+    }
+
+    Ok(call_sites)
+}
+
+
+/// Names of functions in `program`, plus `extra` names such as imports, by
+/// address.
+pub fn function_names(program: &falcon::il::Program, extra: &[(u64, String)])
+    -> BTreeMap<u64, String> {
+
+    let mut names: BTreeMap<u64, String> = program.functions()
+        .into_iter()
+        .map(|function| (function.address(), function.name()))
+        .collect();
+    for &(address, ref name) in extra {
+        names.insert(address, name.clone());
+    }
+    names
+}
+
+
+/// Look `name` up with `f`, once decorations such as `@plt` and `__imp_` are
+/// removed, and again without a leading underscore.
+pub fn lookup<T, F: Fn(&str) -> Option<T>>(name: &str, f: F) -> Option<T> {
+    let name = name.trim_start_matches("__imp_");
+    let name = name.split('@').next().unwrap_or(name);
+
+    f(name).or_else(|| if name.starts_with('_') { f(&name[1..]) } else { None })
+}
+
+
+/// Read a word of at most 8 bytes from `memory`.
+pub fn read_word(memory: &Memory, address: u64, bytes: usize, endian: &Endian) -> Option<u64> {
+    if bytes > 8 {
+        return None;
+    }
+    let mut value = 0;
+    for i in 0..bytes {
+        let byte = memory.get8(address.checked_add(i as u64)?)? as u64;
+        value |= match *endian {
+            Endian::Little => byte << (i * 8),
+            Endian::Big => byte << ((bytes - 1 - i) * 8)
+        };
+    }
+    Some(value)
+}
+
+
+/// The target of a call made through a pointer loaded from a constant
+/// address, such as an import table entry.
+fn loaded_target(
+    architecture: &Architecture,
+    memory: &Memory,
+    function: &falcon::il::Function,
+    location: &falcon::il::FunctionLocation
+) -> Option<u64> {
+
+    let (block_index, instruction_index) = match *location {
+        falcon::il::FunctionLocation::Instruction(block, instruction) =>
+            (block, instruction),
+        _ => return None
+    };
+    let block = function.block(block_index).ok()?;
+    let position = block.instructions()
+        .iter()
+        .position(|i| i.index() == instruction_index)?;
+
+    let target = match *block.instructions()[position].operation() {
+        falcon::il::Operation::Branch { ref target } => target.clone(),
+        _ => return None
+    };
+
+    for instruction in block.instructions()[..position].iter().rev() {
+        match *instruction.operation() {
+            falcon::il::Operation::Load { ref dst, ref index } => {
+                if falcon::il::Expression::Scalar(dst.clone()) != target {
+                    continue;
+                }
+                let address = match il::simplify(index) {
+                    falcon::il::Expression::Constant(ref c) => c.value_u64()?,
+                    _ => return None
+                };
+                return read_word(
+                    memory, address, architecture.word_size() / 8, &architecture.endian());
+            },
+            falcon::il::Operation::Assign { ref dst, .. } =>
+                if falcon::il::Expression::Scalar(dst.clone()) == target {
+                    return None;
+                },
+            _ => {}
+        }
+    }
+
+    None
+}
+
+
+/// The function an import stub, such as a PLT entry, jumps to.
+fn stub_target(
+    architecture: &Architecture,
+    memory: &Memory,
+    program: &falcon::il::Program,
+    address: u64
+) -> Option<u64> {
+
+    let function = program.function_by_address(address)?;
+    let blocks = function.blocks();
+    if blocks.len() != 1 {
+        return None;
+    }
+    let instruction = blocks[0].instructions().last()?;
+    let location = falcon::il::FunctionLocation::Instruction(
+        blocks[0].index(), instruction.index());
+    loaded_target(architecture, memory, function, &location)
+}
+
+
+
+/// Names the functions call sites call.
+pub struct Callees<'a> {
+    architecture: &'a Architecture,
+    program: &'a falcon::il::Program,
+    memory: Memory,
+    names: BTreeMap<u64, String>
+}
+
+impl<'a> Callees<'a> {
+    pub fn new(loader: &'a Loader, program: &'a falcon::il::Program) -> Result<Callees<'a>> {
+        let extra: Vec<(u64, String)> = loader.function_entries()?
+            .iter()
+            .filter_map(|entry| entry.name().map(|name| (entry.address(), name.to_string())))
+            .collect();
+
+        Ok(Callees {
+            architecture: loader.architecture(),
+            program: program,
+            memory: loader.memory()?,
+            names: function_names(program, &extra)
+        })
+    }
+
+    /// The name of the function at `address`.
+    pub fn name(&self, address: u64) -> Option<&str> {
+        self.names.get(&address).map(|name| name.as_str())
+    }
+
+    /// The address and name of the function `call_site`, in `function`,
+    /// calls. A call to a stub which jumps through memory to a named function
+    /// is named after that function.
+    pub fn callee(&self, function: &falcon::il::Function, call_site: &CallSite)
+        -> Option<(u64, &str)> {
+
+        let target = call_site.target.or_else(|| loaded_target(
+            self.architecture, &self.memory, function, &call_site.location))?;
+
+        let stub = stub_target(self.architecture, &self.memory, self.program, target)
+            .and_then(|stub| self.name(stub).map(|name| (stub, name)));
+
+        stub.or_else(|| self.name(target).map(|name| (target, name)))
+    }
+}
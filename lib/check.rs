@@ -0,0 +1,511 @@
+//! A framework for vulnerability checkers.
+//!
+//! A `Checker` inspects a loaded binary and its lifted program and reports
+//! `Finding`s. Checkers are registered by rule id in a `Registry`, which runs
+//! a selection of them over a binary and collects one `Report`. Checkers may
+//! be written in Rust, or in gluon with `check.checker.new`.
+
+use falcon;
+use falcon::error::Result;
+use gluon::vm::api::{OwnedFunction, Userdata, VmType};
+use gluon::vm::thread::{Traverseable};
+use gluon;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use format_string;
+use il;
+use loader;
+use stack_overflow;
+use unchecked_return;
+use unsafe_call;
+
+
+/// How serious a finding is.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+    Critical
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            Severity::Info => "info",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical"
+        })
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> ::std::result::Result<Severity, String> {
+        match s {
+            "info" => Ok(Severity::Info),
+            "low" => Ok(Severity::Low),
+            "medium" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(format!("unknown severity {}", s))
+        }
+    }
+}
+
+
+/// A problem a checker found.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Finding {
+    /// The id of the rule which produced this finding.
+    pub rule: String,
+    pub severity: Severity,
+    pub message: String,
+    /// Where the problem is, if it has a location in the program.
+    pub location: Option<falcon::il::ProgramLocation>,
+    /// The native address of the problem, if known.
+    pub address: Option<u64>,
+    /// Other locations involved, such as the source of tainted data.
+    pub related: Vec<falcon::il::ProgramLocation>
+}
+
+impl Finding {
+    pub fn new<S: Into<String>, T: Into<String>>(rule: S, severity: Severity, message: T)
+        -> Finding {
+
+        Finding {
+            rule: rule.into(),
+            severity: severity,
+            message: message.into(),
+            location: None,
+            address: None,
+            related: Vec::new()
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}", self.severity, self.rule)?;
+        match (self.address, self.location.as_ref()) {
+            (Some(address), _) => write!(f, " 0x{:x}", address)?,
+            (None, Some(location)) => write!(f, " {}", location)?,
+            (None, None) => {}
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+
+/// Parse a rules file, where each line is a function name followed by
+/// `key=value` settings, and `#` starts a comment. `new` creates a rule for
+/// a function, and `setting` applies each of its settings.
+pub fn parse_rules<T, F>(rules: &str, new: fn(&str) -> T, setting: F) -> Result<Vec<T>>
+    where F: Fn(&mut T, &str, &str) -> ::std::result::Result<(), String> {
+
+    let mut parsed = Vec::new();
+
+    for (number, line) in rules.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut words = line.split_whitespace();
+        let mut rule = match words.next() {
+            Some(function) => new(function),
+            None => continue
+        };
+
+        for word in words {
+            let mut parts = word.splitn(2, '=');
+            let key = parts.next().unwrap_or("");
+            let value = parts.next()
+                .ok_or_else(|| format!("line {}: bad setting {}", number + 1, word))?;
+            setting(&mut rule, key, value)
+                .map_err(|e| format!("line {}: {}", number + 1, e))?;
+        }
+
+        parsed.push(rule);
+    }
+
+    Ok(parsed)
+}
+
+
+/// A vulnerability checker.
+pub trait Checker: fmt::Debug + Send + Sync {
+    /// The rule id this checker is registered under.
+    fn id(&self) -> &str;
+
+    /// A one-line description of what this checker finds.
+    fn description(&self) -> &str;
+
+    fn check(
+        &self,
+        loader: &Arc<falcon::loader::Loader>,
+        program: &Arc<falcon::il::Program>
+    ) -> Result<Vec<Finding>>;
+}
+
+
+/// The findings of a run of one or more checkers.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Report {
+    /// The rule ids of the checkers which ran.
+    pub rules: Vec<String>,
+    pub findings: Vec<Finding>
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for finding in &self.findings {
+            writeln!(f, "{}", finding)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Checkers by rule id.
+#[derive(Clone, Debug, Default)]
+pub struct Registry {
+    checkers: BTreeMap<String, Arc<Checker>>
+}
+
+impl Registry {
+    /// An empty registry.
+    pub fn new() -> Registry {
+        Registry { checkers: BTreeMap::new() }
+    }
+
+    /// A registry with every checker built into osprey.
+    pub fn builtin() -> Registry {
+        let mut registry = Registry::new();
+        registry.register(Arc::new(format_string::FormatStringChecker));
+        registry.register(Arc::new(stack_overflow::StackOverflowChecker));
+        registry.register(Arc::new(unchecked_return::UncheckedReturnChecker::default()));
+        registry.register(Arc::new(unsafe_call::UnsafeCallChecker::default()));
+        registry
+    }
+
+    /// Register `checker`, replacing any checker with the same rule id.
+    pub fn register(&mut self, checker: Arc<Checker>) {
+        self.checkers.insert(checker.id().to_string(), checker);
+    }
+
+    pub fn checker(&self, id: &str) -> Option<&Arc<Checker>> {
+        self.checkers.get(id)
+    }
+
+    /// Every registered checker, ordered by rule id.
+    pub fn checkers(&self) -> Vec<&Arc<Checker>> {
+        self.checkers.values().collect()
+    }
+
+    /// Run the checkers for `rules`, or every checker if `rules` is empty.
+    pub fn run(
+        &self,
+        loader: &Arc<falcon::loader::Loader>,
+        program: &Arc<falcon::il::Program>,
+        rules: &[String]
+    ) -> Result<Report> {
+
+        let checkers: Vec<&Arc<Checker>> = if rules.is_empty() {
+            self.checkers()
+        }
+        else {
+            rules.iter()
+                .map(|rule| self.checker(rule)
+                    .ok_or_else(|| format!("no checker for rule {}", rule).into()))
+                .collect::<Result<Vec<&Arc<Checker>>>>()?
+        };
+
+        let mut report = Report::default();
+        for checker in checkers {
+            report.rules.push(checker.id().to_string());
+            report.findings.append(&mut checker.check(loader, program)?);
+        }
+        report.findings.sort_by(|lhs, rhs|
+            rhs.severity.cmp(&lhs.severity)
+                .then(lhs.address.cmp(&rhs.address))
+                .then(lhs.rule.cmp(&rhs.rule)));
+
+        Ok(report)
+    }
+}
+
+
+falcon_type_wrapper!(Finding, CheckFinding);
+
+fn finding_new(rule: String, severity: String, message: String)
+    -> ::std::result::Result<CheckFinding, String> {
+
+    Ok(CheckFinding { x: Finding::new(rule, severity.parse()?, message) })
+}
+
+fn finding_with_address(finding: &CheckFinding, address: u64) -> CheckFinding {
+    let mut finding = finding.clone();
+    finding.x.address = Some(address);
+    finding
+}
+
+fn finding_with_location(finding: &CheckFinding, location: &il::IlProgramLocation)
+    -> CheckFinding {
+
+    let mut finding = finding.clone();
+    finding.x.location = Some(location.x.clone());
+    finding
+}
+
+fn finding_with_related(finding: &CheckFinding, location: &il::IlProgramLocation)
+    -> CheckFinding {
+
+    let mut finding = finding.clone();
+    finding.x.related.push(location.x.clone());
+    finding
+}
+
+fn finding_address(finding: &CheckFinding) -> Option<u64> {
+    finding.x.address
+}
+
+fn finding_location(finding: &CheckFinding) -> Option<il::IlProgramLocation> {
+    finding.x.location
+        .clone()
+        .map(|location| il::IlProgramLocation { x: location })
+}
+
+fn finding_message(finding: &CheckFinding) -> String {
+    finding.x.message.clone()
+}
+
+fn finding_related(finding: &CheckFinding) -> Vec<il::IlProgramLocation> {
+    finding.x.related
+        .iter()
+        .map(|location| il::IlProgramLocation { x: location.clone() })
+        .collect()
+}
+
+fn finding_rule(finding: &CheckFinding) -> String {
+    finding.x.rule.clone()
+}
+
+fn finding_severity(finding: &CheckFinding) -> String {
+    format!("{}", finding.x.severity)
+}
+
+fn finding_str(finding: &CheckFinding) -> String {
+    format!("{}", finding.x)
+}
+
+
+type GluonCheck = OwnedFunction<fn(loader::LoaderLoader, il::IlProgram) -> Vec<CheckFinding>>;
+
+/// A checker written in gluon.
+struct GluonChecker {
+    id: String,
+    description: String,
+    check: GluonCheck
+}
+
+impl fmt::Debug for GluonChecker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "GluonChecker({})", self.id)
+    }
+}
+
+impl Checker for GluonChecker {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn check(
+        &self,
+        loader: &Arc<falcon::loader::Loader>,
+        program: &Arc<falcon::il::Program>
+    ) -> Result<Vec<Finding>> {
+
+        let findings = self.check.clone()
+            .call(loader::LoaderLoader { x: loader.clone() },
+                  il::IlProgram { x: program.clone() })
+            .map_err(|e| format!("{}", e))?;
+
+        Ok(findings.into_iter().map(|finding| finding.x).collect())
+    }
+}
+
+
+falcon_type_wrapper!(Arc<Checker>, CheckChecker);
+
+fn checker_new(id: String, description: String, check: GluonCheck) -> CheckChecker {
+    CheckChecker {
+        x: Arc::new(GluonChecker { id: id, description: description, check: check })
+    }
+}
+
+fn checker_builtin(_: ()) -> Vec<CheckChecker> {
+    Registry::builtin()
+        .checkers()
+        .into_iter()
+        .map(|checker| CheckChecker { x: checker.clone() })
+        .collect()
+}
+
+fn checker_unsafe_call(rules: String) -> ::std::result::Result<CheckChecker, String> {
+    unsafe_call::UnsafeCallChecker::from_rules(&rules)
+        .map(|checker| CheckChecker { x: Arc::new(checker) })
+        .map_err(|e| format!("{}", e))
+}
+
+fn checker_unchecked_return(rules: String) -> ::std::result::Result<CheckChecker, String> {
+    unchecked_return::UncheckedReturnChecker::from_rules(&rules)
+        .map(|checker| CheckChecker { x: Arc::new(checker) })
+        .map_err(|e| format!("{}", e))
+}
+
+fn checker_description(checker: &CheckChecker) -> String {
+    checker.x.description().to_string()
+}
+
+fn checker_id(checker: &CheckChecker) -> String {
+    checker.x.id().to_string()
+}
+
+fn check_run(
+    checkers: Vec<CheckChecker>,
+    rules: Vec<String>,
+    loader: &loader::LoaderLoader,
+    program: &il::IlProgram
+) -> ::std::result::Result<Vec<CheckFinding>, String> {
+
+    let mut registry = Registry::new();
+    for checker in checkers {
+        registry.register(checker.x);
+    }
+
+    registry.run(&loader.x, &program.x, &rules)
+        .map(|report| report.findings
+            .into_iter()
+            .map(|finding| CheckFinding { x: finding })
+            .collect())
+        .map_err(|e| format!("{}", e))
+}
+
+
+pub fn bindings(vm: gluon::RootedThread) -> gluon::RootedThread {
+
+    vm.register_type::<CheckChecker>("CheckChecker", &[]).unwrap();
+    vm.register_type::<CheckFinding>("CheckFinding", &[]).unwrap();
+
+    fn falcon_check_prim_loader(vm: &gluon::Thread)
+        -> gluon::vm::Result<gluon::vm::ExternModule> {
+
+        gluon::vm::ExternModule::new(vm, record! {
+            check_run => primitive!(4, check_run),
+            checker_builtin => primitive!(1, checker_builtin),
+            checker_description => primitive!(1, checker_description),
+            checker_id => primitive!(1, checker_id),
+            checker_new => primitive!(3, checker_new),
+            checker_unchecked_return => primitive!(1, checker_unchecked_return),
+            checker_unsafe_call => primitive!(1, checker_unsafe_call),
+            finding_address => primitive!(1, finding_address),
+            finding_location => primitive!(1, finding_location),
+            finding_message => primitive!(1, finding_message),
+            finding_new => primitive!(3, finding_new),
+            finding_related => primitive!(1, finding_related),
+            finding_rule => primitive!(1, finding_rule),
+            finding_severity => primitive!(1, finding_severity),
+            finding_str => primitive!(1, finding_str),
+            finding_with_address => primitive!(2, finding_with_address),
+            finding_with_location => primitive!(2, finding_with_location),
+            finding_with_related => primitive!(2, finding_with_related)
+        })
+    }
+
+    gluon::import::add_extern_module(
+        &vm, "falcon_check_prim", falcon_check_prim_loader);
+
+    vm
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Rule {
+        function: String,
+        settings: Vec<(String, String)>
+    }
+
+    fn new(function: &str) -> Rule {
+        Rule { function: function.to_string(), settings: Vec::new() }
+    }
+
+    fn parse(rules: &str) -> Result<Vec<Rule>> {
+        parse_rules(rules, new, |rule, key, value| {
+            if key == "bad" {
+                return Err(format!("bad setting {}", value));
+            }
+            rule.settings.push((key.to_string(), value.to_string()));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn parse_rules_reads_settings() {
+        let rules = parse("gets severity=high destination=0\nsystem nonconstant=0\n").unwrap();
+        assert_eq!(rules, vec![
+            Rule {
+                function: "gets".to_string(),
+                settings: vec![
+                    ("severity".to_string(), "high".to_string()),
+                    ("destination".to_string(), "0".to_string())
+                ]
+            },
+            Rule {
+                function: "system".to_string(),
+                settings: vec![("nonconstant".to_string(), "0".to_string())]
+            }
+        ]);
+    }
+
+    #[test]
+    fn parse_rules_skips_comments_and_blank_lines() {
+        let rules = parse("# rules\n\n  \nstrcpy # unbounded\n\tstrcat a=b=c\n").unwrap();
+        assert_eq!(rules, vec![
+            new("strcpy"),
+            Rule {
+                function: "strcat".to_string(),
+                settings: vec![("a".to_string(), "b=c".to_string())]
+            }
+        ]);
+    }
+
+    #[test]
+    fn parse_rules_reports_line_numbers() {
+        let error = parse("gets\nstrcpy severity\n").unwrap_err();
+        assert_eq!(format!("{}", error), "line 2: bad setting severity");
+
+        let error = parse("gets\n\nstrcpy bad=value\n").unwrap_err();
+        assert_eq!(format!("{}", error), "line 3: bad setting value");
+    }
+
+    #[test]
+    fn severities_round_trip() {
+        for severity in &[Severity::Info, Severity::Low, Severity::Medium, Severity::High,
+                          Severity::Critical] {
+            assert_eq!(format!("{}", severity).parse::<Severity>(), Ok(*severity));
+        }
+        assert!("severe".parse::<Severity>().is_err());
+    }
+}
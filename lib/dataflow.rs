@@ -0,0 +1,182 @@
+//! A generic forward dataflow framework.
+//!
+//! A `Domain` describes an abstract state, how states join and compare, and
+//! how IL operations and edge conditions transform them. `forward` runs a
+//! domain to a fixed point over a function with Falcon's fixed-point engine.
+//!
+//! The engine does not widen, so a domain must have finite height, or widen
+//! in its own `join`, for `forward` to terminate. Domains written in gluon
+//! are no exception.
+
+use falcon;
+use falcon::analysis::fixed_point::{fixed_point_forward, FixedPointAnalysis};
+use falcon::error::{Error, Result};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt;
+
+
+/// An abstract domain for a forward dataflow analysis.
+pub trait Domain {
+    type State: Clone;
+
+    /// The state on entry to the function.
+    fn bottom(&self) -> Result<Self::State>;
+
+    /// The least upper bound of two states.
+    fn join(&self, lhs: &Self::State, rhs: &Self::State) -> Result<Self::State>;
+
+    /// Whether `lhs` is below or equal to `rhs` in the lattice.
+    fn leq(&self, lhs: &Self::State, rhs: &Self::State) -> Result<bool>;
+
+    /// The state after `operation`, at `location`, executes in `state`.
+    fn transfer(
+        &self,
+        location: &falcon::il::RefProgramLocation,
+        operation: &falcon::il::Operation,
+        state: Self::State
+    ) -> Result<Self::State>;
+
+    /// The state along an edge guarded by `condition`.
+    fn refine(&self, _condition: &falcon::il::Expression, state: Self::State)
+        -> Result<Self::State> {
+        Ok(state)
+    }
+}
+
+
+/// Orders states by the domain's `leq`, which is what Falcon's fixed-point
+/// engine uses to detect convergence.
+///
+/// Comparisons cannot fail, so the first error `leq` returns is kept in
+/// `error` for `forward` to return in place of the engine's own. The failed
+/// comparison leaves states unordered, so the analysis stops at its next
+/// step rather than requeueing them forever.
+struct Ordered<'d, D: 'd + Domain> {
+    domain: &'d D,
+    error: &'d RefCell<Option<Error>>,
+    state: D::State
+}
+
+impl<'d, D: 'd + Domain> Ordered<'d, D> {
+    fn leq(&self, lhs: &D::State, rhs: &D::State) -> Option<bool> {
+        match self.domain.leq(lhs, rhs) {
+            Ok(leq) => Some(leq),
+            Err(e) => {
+                let mut error = self.error.borrow_mut();
+                if error.is_none() {
+                    *error = Some(e);
+                }
+                None
+            }
+        }
+    }
+}
+
+impl<'d, D: 'd + Domain> Clone for Ordered<'d, D> {
+    fn clone(&self) -> Self {
+        Ordered { domain: self.domain, error: self.error, state: self.state.clone() }
+    }
+}
+
+impl<'d, D: 'd + Domain> fmt::Debug for Ordered<'d, D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Ordered")
+    }
+}
+
+impl<'d, D: 'd + Domain> PartialEq for Ordered<'d, D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl<'d, D: 'd + Domain> PartialOrd for Ordered<'d, D> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let below = self.leq(&self.state, &other.state)?;
+        let above = self.leq(&other.state, &self.state)?;
+        match (below, above) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Less),
+            (false, true) => Some(Ordering::Greater),
+            (false, false) => None
+        }
+    }
+}
+
+
+struct Analysis<'d, D: 'd + Domain> {
+    domain: &'d D,
+    error: &'d RefCell<Option<Error>>
+}
+
+impl<'d, D: 'd + Domain> Analysis<'d, D> {
+    /// Fails once a comparison has failed.
+    fn check(&self) -> Result<()> {
+        if self.error.borrow().is_some() {
+            return Err("comparing dataflow states failed".into());
+        }
+        Ok(())
+    }
+}
+
+impl<'d, 'f, D: 'd + Domain> FixedPointAnalysis<'f, Ordered<'d, D>> for Analysis<'d, D>
+    where 'd: 'f {
+
+    fn trans(
+        &self,
+        location: falcon::il::RefProgramLocation<'f>,
+        state: Option<Ordered<'d, D>>
+    ) -> Result<Ordered<'d, D>> {
+
+        self.check()?;
+
+        let state = match state {
+            Some(state) => state.state,
+            None => self.domain.bottom()?
+        };
+
+        let state = match *location.function_location() {
+            falcon::il::RefFunctionLocation::Instruction(_, instruction) =>
+                self.domain.transfer(&location, instruction.operation(), state)?,
+            falcon::il::RefFunctionLocation::Edge(edge) => match *edge.condition() {
+                Some(ref condition) => self.domain.refine(condition, state)?,
+                None => state
+            },
+            falcon::il::RefFunctionLocation::EmptyBlock(_) => state
+        };
+
+        Ok(Ordered { domain: self.domain, error: self.error, state: state })
+    }
+
+    fn join(&self, state0: Ordered<'d, D>, state1: &Ordered<'d, D>)
+        -> Result<Ordered<'d, D>> {
+
+        self.check()?;
+
+        Ok(Ordered {
+            domain: self.domain,
+            error: self.error,
+            state: self.domain.join(&state0.state, &state1.state)?
+        })
+    }
+}
+
+
+/// Run `domain` forward over `function` to a fixed point, returning the
+/// state after every location.
+pub fn forward<D: Domain>(domain: &D, function: &falcon::il::Function)
+    -> Result<Vec<(falcon::il::FunctionLocation, D::State)>> {
+
+    let error = RefCell::new(None);
+    let states = fixed_point_forward(Analysis { domain: domain, error: &error }, function);
+    if let Some(error) = error.borrow_mut().take() {
+        return Err(error);
+    }
+    let states = states?;
+
+    Ok(states.into_iter()
+        .map(|(location, state)|
+            (location.function_location().clone().into(), state.state))
+        .collect())
+}
@@ -0,0 +1,445 @@
+//! Heuristic function discovery.
+//!
+//! Falcon's loaders find functions from symbols and the entry point, which
+//! leaves stripped binaries with few. This pass proposes further function
+//! entries from four kinds of evidence:
+//!
+//! * prologue byte patterns for the architecture, in executable memory,
+//! * targets of direct call instructions found by scanning executable memory,
+//! * word-aligned pointers into executable memory found in data, and
+//! * function starts in exception tables, `.eh_frame` for ELF and `.pdata`
+//!   for PE.
+//!
+//! Each kind has a confidence, and evidence from several kinds combines as
+//! independent observations. Addresses which are already function entries
+//! are not proposed.
+
+use falcon;
+use falcon::error::Result;
+use falcon::loader::Loader;
+use falcon::memory::MemoryPermissions;
+use falcon::memory::backing::Memory;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+
+use dwarf;
+use image;
+use search;
+use xref;
+
+
+/// Evidence that an address starts a function.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Source {
+    Prologue,
+    CallTarget,
+    DataPointer,
+    ExceptionTable
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            Source::Prologue => "prologue",
+            Source::CallTarget => "call-target",
+            Source::DataPointer => "data-pointer",
+            Source::ExceptionTable => "exception-table"
+        })
+    }
+}
+
+
+/// A proposed function entry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candidate {
+    pub address: u64,
+    /// How likely the address is to start a function, from 0 to 1.
+    pub confidence: f64,
+    pub sources: BTreeSet<Source>
+}
+
+impl Candidate {
+    /// This candidate as a function entry named after its address.
+    pub fn function_entry(&self) -> falcon::loader::FunctionEntry {
+        falcon::loader::FunctionEntry::new(self.address, Some(format!("sub_{:x}", self.address)))
+    }
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sources: Vec<String> = self.sources.iter().map(|s| format!("{}", s)).collect();
+        write!(f, "0x{:x} {:.2} {}", self.address, self.confidence, sources.join(","))
+    }
+}
+
+
+/// Evidence gathered so far, as the confidence of each observation by
+/// address.
+#[derive(Default)]
+struct Evidence {
+    observations: BTreeMap<u64, Vec<(Source, f64)>>
+}
+
+impl Evidence {
+    fn add(&mut self, address: u64, source: Source, confidence: f64) {
+        self.observations.entry(address).or_insert_with(Vec::new).push((source, confidence));
+    }
+}
+
+
+/// Prologue patterns by architecture name, as `search::Pattern`s, and the
+/// confidence of a match.
+const PROLOGUES: &[(&str, &str, f64)] = &[
+    ("amd64", "55 48 89 e5", 0.6),
+    ("amd64", "f3 0f 1e fa", 0.7),
+    ("amd64", "41 57 41 56", 0.5),
+    ("amd64", "40 53 48 83 ec ??", 0.5),
+    ("amd64", "48 89 5c 24 ??", 0.4),
+    ("x86", "55 89 e5", 0.6),
+    ("x86", "55 8b ec", 0.6),
+    ("x86", "8b ff 55 8b ec", 0.7),
+    ("x86", "f3 0f 1e fb", 0.7),
+    ("mips", "27 bd ff ??", 0.5),
+    ("mipsel", "?? ff bd 27", 0.5),
+    ("ppc", "94 21 ff ??", 0.5),
+    ("ppc", "7c 08 02 a6", 0.4)
+];
+
+
+/// The alignment functions start at on an architecture.
+fn alignment(architecture: &str) -> u64 {
+    match architecture {
+        "amd64" | "x86" => 1,
+        _ => 4
+    }
+}
+
+
+/// Executable sections of memory, as (address, bytes).
+fn code_sections(memory: &Memory) -> Vec<(u64, &[u8])> {
+    memory.sections()
+        .iter()
+        .filter(|&(_, section)| section.permissions().contains(MemoryPermissions::EXECUTE))
+        .map(|(&address, section)| (address, section.data()))
+        .collect()
+}
+
+
+fn prologues(architecture: &str, code: &[(u64, &[u8])], evidence: &mut Evidence) {
+    let patterns: Vec<(search::Pattern, f64)> = PROLOGUES.iter()
+        .filter(|&&(name, _, _)| name == architecture)
+        .map(|&(_, pattern, confidence)|
+            (search::Pattern::parse(pattern).expect("prologue pattern"), confidence))
+        .collect();
+    let alignment = alignment(architecture);
+
+    for &(address, bytes) in code {
+        for &(ref pattern, confidence) in &patterns {
+            for offset in pattern.find(bytes) {
+                let start = address + offset as u64;
+                // Unaligned x86 matches count when they follow padding or a
+                // return.
+                let aligned = if alignment > 1 {
+                    start % alignment == 0
+                }
+                else {
+                    start % 16 == 0 || offset == 0 ||
+                        [0xc3, 0xcc, 0x90, 0x00].contains(&bytes[offset - 1])
+                };
+                if aligned {
+                    evidence.add(start, Source::Prologue, confidence);
+                }
+            }
+        }
+    }
+}
+
+
+/// The target of a direct call instruction at `offset` in `bytes`, mapped at
+/// `address`.
+fn call_target(architecture: &str, address: u64, bytes: &[u8], offset: usize) -> Option<u64> {
+    let word = |big: bool| -> Option<u32> {
+        let b = bytes.get(offset..offset + 4)?;
+        Some(if big {
+            (b[0] as u32) << 24 | (b[1] as u32) << 16 | (b[2] as u32) << 8 | b[3] as u32
+        }
+        else {
+            (b[3] as u32) << 24 | (b[2] as u32) << 16 | (b[1] as u32) << 8 | b[0] as u32
+        })
+    };
+    let pc = address + offset as u64;
+
+    match architecture {
+        "amd64" | "x86" => {
+            if *bytes.get(offset)? != 0xe8 {
+                return None;
+            }
+            let b = bytes.get(offset + 1..offset + 5)?;
+            let displacement =
+                (b[3] as u32) << 24 | (b[2] as u32) << 16 | (b[1] as u32) << 8 | b[0] as u32;
+            let target = (pc + 5).wrapping_add(displacement as i32 as i64 as u64);
+            Some(if architecture == "x86" { target & 0xffff_ffff } else { target })
+        },
+        "mips" | "mipsel" => {
+            let instruction = word(architecture == "mips")?;
+            if instruction >> 26 != 3 {
+                return None;
+            }
+            Some(((pc + 4) & 0xf000_0000) | ((instruction & 0x03ff_ffff) << 2) as u64)
+        },
+        "ppc" => {
+            let instruction = word(true)?;
+            if instruction & 0xfc00_0003 != 0x4800_0001 {
+                return None;
+            }
+            let displacement = ((instruction & 0x03ff_fffc) << 6) as i32 >> 6;
+            Some((pc as u32).wrapping_add(displacement as u32) as u64)
+        },
+        _ => None
+    }
+}
+
+
+fn call_targets(
+    architecture: &str,
+    code: &[(u64, &[u8])],
+    executable: &Fn(u64) -> bool,
+    evidence: &mut Evidence
+) {
+    let step = alignment(architecture) as usize;
+    let mut calls: BTreeMap<u64, usize> = BTreeMap::new();
+
+    for &(address, bytes) in code {
+        for offset in (0..bytes.len()).step_by(step) {
+            if let Some(target) = call_target(architecture, address, bytes, offset) {
+                if target % alignment(architecture) == 0 && executable(target) {
+                    *calls.entry(target).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    // Bytes which happen to decode as a call rarely agree on a target.
+    for (target, count) in calls {
+        let confidence = (0.4 + 0.15 * (count - 1) as f64).min(0.9);
+        evidence.add(target, Source::CallTarget, confidence);
+    }
+}
+
+
+fn data_pointers(loader: &Loader, memory: &Memory, evidence: &mut Evidence) {
+    let architecture = loader.architecture();
+    for (_, pointer) in xref::code_pointers(architecture, memory) {
+        if pointer % alignment(architecture.name()) == 0 {
+            evidence.add(pointer, Source::DataPointer, 0.3);
+        }
+    }
+}
+
+
+/// Read a pointer encoded with the DWARF exception handling encoding
+/// `encoding` at `*offset` in `section`, mapped at `address`.
+fn encoded_pointer(
+    reader: &image::Reader,
+    section: &[u8],
+    address: u64,
+    offset: &mut usize,
+    encoding: u8,
+    wide: bool
+) -> Option<u64> {
+
+    let start = *offset;
+    let value = match encoding & 0x0f {
+        0x00 => { *offset += if wide { 8 } else { 4 }; reader.word(start as u64, wide).ok()? },
+        0x01 => dwarf::leb128(section, offset, false)?,
+        0x02 => { *offset += 2; reader.u16(start as u64).ok()? },
+        0x03 => { *offset += 4; reader.u32(start as u64).ok()? },
+        0x04 => { *offset += 8; reader.u64(start as u64).ok()? },
+        0x09 => dwarf::leb128(section, offset, true)?,
+        0x0a => { *offset += 2; reader.u16(start as u64).ok()? as u16 as i16 as i64 as u64 },
+        0x0b => { *offset += 4; reader.u32(start as u64).ok()? as u32 as i32 as i64 as u64 },
+        0x0c => { *offset += 8; reader.u64(start as u64).ok()? },
+        _ => return None
+    };
+    match encoding & 0x70 {
+        0x00 => Some(value),
+        0x10 => Some(value.wrapping_add(address + start as u64)),
+        _ => None
+    }
+}
+
+
+/// Function starts in the `.eh_frame` section `section`, mapped at
+/// `address`.
+fn eh_frame(section: &[u8], address: u64, little: bool, wide: bool) -> Vec<u64> {
+    let reader = image::Reader::new(section, little);
+    let mut encodings: BTreeMap<usize, u8> = BTreeMap::new();
+    let mut starts = Vec::new();
+    let mut offset = 0;
+
+    while offset + 8 <= section.len() {
+        let record = offset;
+        let (length, header) = match reader.u32(offset as u64) {
+            Ok(0) | Err(_) => break,
+            Ok(0xffff_ffff) => match reader.u64(offset as u64 + 4) {
+                Ok(length) => (length as usize, 12),
+                Err(_) => break
+            },
+            Ok(length) => (length as usize, 4)
+        };
+        // A record must move forward and stay within the section.
+        let end = match record.checked_add(header).and_then(|end| end.checked_add(length)) {
+            Some(end) if end > offset && end <= section.len() => end,
+            _ => break
+        };
+        let id_offset = record + header;
+        let id = match reader.u32(id_offset as u64) {
+            Ok(id) => id as usize,
+            Err(_) => break
+        };
+        let mut cursor = id_offset + 4;
+
+        if id == 0 {
+            // A CIE: find the encoding of its FDEs' addresses.
+            let encoding = (|| {
+                cursor += 1;
+                let augmentation_start = cursor;
+                while *section.get(cursor)? != 0 {
+                    cursor += 1;
+                }
+                let augmentation = section[augmentation_start..cursor].to_vec();
+                cursor += 1;
+                if augmentation.starts_with(b"eh") {
+                    cursor += if wide { 8 } else { 4 };
+                }
+                dwarf::leb128(section, &mut cursor, false)?;
+                dwarf::leb128(section, &mut cursor, true)?;
+                dwarf::leb128(section, &mut cursor, false)?;
+                if augmentation.first() != Some(&b'z') {
+                    return Some(0);
+                }
+                dwarf::leb128(section, &mut cursor, false)?;
+                for &c in &augmentation[1..] {
+                    match c {
+                        b'R' => return section.get(cursor).cloned(),
+                        b'P' => {
+                            let personality = *section.get(cursor)?;
+                            cursor += 1;
+                            encoded_pointer(&reader, section, address, &mut cursor,
+                                personality & 0x7f, wide)?;
+                        },
+                        b'L' => cursor += 1,
+                        _ => {}
+                    }
+                }
+                Some(0)
+            })();
+            if let Some(encoding) = encoding {
+                encodings.insert(record, encoding);
+            }
+        }
+        else if id <= id_offset {
+            // An FDE: its CIE is `id` bytes before the id field.
+            if let Some(&encoding) = encodings.get(&(id_offset - id)) {
+                if let Some(start) =
+                    encoded_pointer(&reader, section, address, &mut cursor, encoding, wide) {
+                    if start != 0 {
+                        starts.push(start);
+                    }
+                }
+            }
+        }
+
+        offset = end;
+    }
+
+    starts
+}
+
+
+/// Function starts in the exception tables of the file `bytes`.
+fn exception_tables(bytes: &[u8]) -> Result<Vec<u64>> {
+    match image::format(bytes) {
+        Some(image::Format::Elf) => {
+            let elf = image::ElfHeader::parse(bytes)?;
+            let little = bytes.get(5) == Some(&1);
+            let mut starts = Vec::new();
+            for (name, _, _, address, offset, size) in elf.sections()? {
+                if name != ".eh_frame" {
+                    continue;
+                }
+                let section = offset.checked_add(size)
+                    .and_then(|end| bytes.get(offset as usize..end as usize))
+                    .ok_or(".eh_frame out of bounds")?;
+                starts.extend(eh_frame(section, address, little, elf.wide));
+            }
+            Ok(starts)
+        },
+        Some(image::Format::Pe) => {
+            // The exception directory holds RUNTIME_FUNCTION entries, each
+            // starting with a function's start address.
+            const EXCEPTION: u64 = 3;
+            let pe = image::PeHeader::parse(bytes)?;
+            let (address, size) = match pe.data_directory(EXCEPTION)? {
+                Some(directory) => directory,
+                None => return Ok(Vec::new())
+            };
+            let segment = image::segments(bytes)?
+                .into_iter()
+                .find(|segment| segment.offset_of(pe.image_base + address).is_some());
+            let offset = match segment.and_then(|s| s.offset_of(pe.image_base + address)) {
+                Some(offset) => offset,
+                None => return Ok(Vec::new())
+            };
+            (0..size / 12)
+                .map(|i| Ok(pe.image_base + pe.reader.u32(pe.reader.entry(offset, i, 12)?)?))
+                .collect()
+        },
+        None => Ok(Vec::new())
+    }
+}
+
+
+/// Propose function entries in `loader`'s memory which are not already
+/// function entries, ordered by address. `bytes`, the file `loader` loaded,
+/// is read for exception tables if given.
+pub fn discover(loader: &Loader, bytes: Option<&[u8]>) -> Result<Vec<Candidate>> {
+    let memory = loader.memory()?;
+    let architecture = loader.architecture().name();
+    let code = code_sections(&memory);
+
+    let executable = |address: u64| memory.permissions(address)
+        .map(|permissions| permissions.contains(MemoryPermissions::EXECUTE))
+        .unwrap_or(false);
+
+    let mut evidence = Evidence::default();
+    prologues(architecture, &code, &mut evidence);
+    call_targets(architecture, &code, &executable, &mut evidence);
+    data_pointers(loader, &memory, &mut evidence);
+    if let Some(bytes) = bytes {
+        for start in exception_tables(bytes)? {
+            if executable(start) {
+                evidence.add(start, Source::ExceptionTable, 0.95);
+            }
+        }
+    }
+
+    let known: HashSet<u64> = loader.function_entries()?
+        .iter()
+        .map(|entry| entry.address())
+        .collect();
+
+    Ok(evidence.observations
+        .into_iter()
+        .filter(|&(address, _)| !known.contains(&address))
+        .map(|(address, observations)| {
+            let doubt = observations.iter()
+                .fold(1.0, |doubt, &(_, confidence)| doubt * (1.0 - confidence));
+            Candidate {
+                address: address,
+                confidence: 1.0 - doubt,
+                sources: observations.iter().map(|&(source, _)| source).collect()
+            }
+        })
+        .collect())
+}
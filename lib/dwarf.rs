@@ -0,0 +1,1748 @@
+//! DWARF debug information.
+//!
+//! Reads the line tables, functions, parameters, local variables and their
+//! types from the DWARF 2 to 5 sections of an ELF file. Compressed sections,
+//! split DWARF and type units are not read, and neither are functions split
+//! into several address ranges, which are known by their first range only.
+//! Functions nested in others and inlined calls are skipped, so the
+//! variables of a function are only those it declares itself.
+//!
+//! Variable locations are kept as DWARF gives them, relative to the
+//! function's frame base. `stack_variables` places them in the frame
+//! recovered by `stack_frame`, as offsets from the entry stack pointer. A
+//! frame base held in a register, such as a frame pointer, is placed by the
+//! offset the register holds through most of the function.
+
+use falcon;
+use falcon::architecture::{Architecture, ReturnAddressType};
+use falcon::error::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use image;
+use stack_frame;
+
+
+/// Read a LEB128 value at `*offset`, advancing it.
+pub fn leb128(bytes: &[u8], offset: &mut usize, signed: bool) -> Option<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(*offset)?;
+        *offset += 1;
+        if shift < 64 {
+            value |= ((byte & 0x7f) as u64) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0 {
+            if signed && shift < 64 && byte & 0x40 != 0 {
+                value |= !0 << shift;
+            }
+            return Some(value);
+        }
+    }
+}
+
+
+/// A position in the source.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u64,
+    /// The column, or 0 if unknown.
+    pub column: u64
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+        if self.column > 0 {
+            write!(f, ":{}", self.column)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// What a function's variable locations are relative to.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum FrameBase {
+    /// The canonical frame address, the stack pointer before the call.
+    Cfa,
+    /// A DWARF register number, plus an offset.
+    Register(u64, i64),
+    Unknown
+}
+
+
+/// Where a variable is.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Location {
+    /// An offset from the frame base.
+    Frame(i64),
+    /// In a DWARF register number.
+    Register(u64),
+    /// At an offset from a DWARF register number's value.
+    RegisterOffset(u64, i64),
+    Address(u64),
+    Unknown
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let signed = |f: &mut fmt::Formatter, offset: i64| {
+            let sign = if offset < 0 { "-" } else { "+" };
+            write!(f, "{}0x{:x}", sign, offset.abs())
+        };
+        match *self {
+            Location::Frame(offset) => { write!(f, "frame")?; signed(f, offset) },
+            Location::Register(register) => write!(f, "reg{}", register),
+            Location::RegisterOffset(register, offset) => {
+                write!(f, "reg{}", register)?;
+                signed(f, offset)
+            },
+            Location::Address(address) => write!(f, "0x{:x}", address),
+            Location::Unknown => write!(f, "?")
+        }
+    }
+}
+
+
+/// A parameter or local variable.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub type_name: Option<String>,
+    pub parameter: bool,
+    /// Locations, each for the native address range `[start, end)` it
+    /// holds over, or for the whole function.
+    pub locations: Vec<(Option<(u64, u64)>, Location)>
+}
+
+impl Variable {
+    /// The location of this variable at `address`.
+    pub fn location_at(&self, address: u64) -> Option<Location> {
+        self.locations.iter()
+            .find(|&&(range, _)| match range {
+                Some((start, end)) => start <= address && address < end,
+                None => true
+            })
+            .map(|&(_, location)| location)
+    }
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref type_name) = self.type_name {
+            write!(f, "{} ", type_name)?;
+        }
+        write!(f, "{}", self.name)?;
+        for &(range, location) in &self.locations {
+            match range {
+                Some((start, end)) => write!(f, " [0x{:x}, 0x{:x}): {}", start, end, location)?,
+                None => write!(f, " {}", location)?
+            }
+        }
+        Ok(())
+    }
+}
+
+
+/// A function with debug information.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub low_pc: u64,
+    /// The first address past the function.
+    pub high_pc: u64,
+    /// Where the function is declared.
+    pub source: Option<SourceLocation>,
+    pub return_type: Option<String>,
+    pub frame_base: FrameBase,
+    pub variables: Vec<Variable>
+}
+
+impl Function {
+    pub fn contains(&self, address: u64) -> bool {
+        self.low_pc <= address && address < self.high_pc
+    }
+
+    pub fn parameters(&self) -> Vec<&Variable> {
+        self.variables.iter().filter(|variable| variable.parameter).collect()
+    }
+
+    pub fn locals(&self) -> Vec<&Variable> {
+        self.variables.iter().filter(|variable| !variable.parameter).collect()
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} [0x{:x}, 0x{:x})", self.name, self.low_pc, self.high_pc)?;
+        if let Some(ref source) = self.source {
+            write!(f, " {}", source)?;
+        }
+        writeln!(f)?;
+        for variable in &self.variables {
+            let kind = if variable.parameter { "parameter" } else { "local" };
+            writeln!(f, "  {} {}", kind, variable)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// The debug information of a binary.
+#[derive(Clone, Debug, Default)]
+pub struct DebugInfo {
+    /// The source of each row of the line tables, or `None` where a
+    /// sequence ends.
+    lines: BTreeMap<u64, Option<SourceLocation>>,
+    /// Functions by their first address.
+    functions: BTreeMap<u64, Function>
+}
+
+impl DebugInfo {
+    /// The source location `address` was compiled from.
+    pub fn source(&self, address: u64) -> Option<&SourceLocation> {
+        self.lines.range(..=address).next_back().and_then(|(_, source)| source.as_ref())
+    }
+
+    /// The function containing `address`.
+    pub fn function(&self, address: u64) -> Option<&Function> {
+        match self.functions.range(..=address).next_back() {
+            Some((_, function)) if function.contains(address) => Some(function),
+            _ => None
+        }
+    }
+
+    /// Functions, ordered by address.
+    pub fn functions(&self) -> Vec<&Function> {
+        self.functions.values().collect()
+    }
+
+    /// The debug information in the file `bytes`, or `None` if it has none.
+    pub fn parse(bytes: &[u8]) -> Result<Option<DebugInfo>> {
+        if image::format(bytes) != Some(image::Format::Elf) {
+            return Ok(None);
+        }
+        let elf = image::ElfHeader::parse(bytes)?;
+        let little = bytes.get(5) == Some(&1);
+
+        const SHF_COMPRESSED: u64 = 0x800;
+        let mut sections: HashMap<String, &[u8]> = HashMap::new();
+        for (name, _, flags, _, offset, size) in elf.sections()? {
+            if !name.starts_with(".debug_") || flags & SHF_COMPRESSED != 0 {
+                continue;
+            }
+            let data = offset.checked_add(size)
+                .and_then(|end| bytes.get(offset as usize..end as usize));
+            if let Some(data) = data {
+                sections.insert(name, data);
+            }
+        }
+        let section = |name: &str| sections.get(name).cloned().unwrap_or(&[]);
+
+        let sections = Sections {
+            info: section(".debug_info"),
+            abbrev: section(".debug_abbrev"),
+            line: section(".debug_line"),
+            str_: section(".debug_str"),
+            line_str: section(".debug_line_str"),
+            str_offsets: section(".debug_str_offsets"),
+            addr: section(".debug_addr"),
+            loc: section(".debug_loc"),
+            loclists: section(".debug_loclists"),
+            little: little
+        };
+        if sections.info.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(parse(&sections)?))
+    }
+}
+
+
+/// The DWARF sections of a file.
+struct Sections<'b> {
+    info: &'b [u8],
+    abbrev: &'b [u8],
+    line: &'b [u8],
+    str_: &'b [u8],
+    line_str: &'b [u8],
+    str_offsets: &'b [u8],
+    addr: &'b [u8],
+    loc: &'b [u8],
+    loclists: &'b [u8],
+    little: bool
+}
+
+
+/// Reads a section from front to back.
+struct Cursor<'b> {
+    bytes: &'b [u8],
+    reader: image::Reader<'b>,
+    offset: usize
+}
+
+impl<'b> Cursor<'b> {
+    fn new(bytes: &'b [u8], little: bool, offset: usize) -> Cursor<'b> {
+        Cursor { bytes: bytes, reader: image::Reader::new(bytes, little), offset: offset }
+    }
+
+    fn fixed(&mut self, size: usize) -> Result<u64> {
+        let value = self.reader.read(self.offset as u64, size)?;
+        self.offset += size;
+        Ok(value)
+    }
+
+    fn u8(&mut self) -> Result<u64> {
+        self.fixed(1)
+    }
+
+    fn u16(&mut self) -> Result<u64> {
+        self.fixed(2)
+    }
+
+    fn uleb(&mut self) -> Result<u64> {
+        Ok(leb128(self.bytes, &mut self.offset, false).ok_or("truncated LEB128")?)
+    }
+
+    fn sleb(&mut self) -> Result<i64> {
+        Ok(leb128(self.bytes, &mut self.offset, true).ok_or("truncated LEB128")? as i64)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let rest = self.bytes.get(self.offset..).ok_or("string out of bounds")?;
+        let end = rest.iter().position(|&b| b == 0).ok_or("unterminated string")?;
+        self.offset += end + 1;
+        Ok(String::from_utf8_lossy(&rest[..end]).into_owned())
+    }
+
+    fn block(&mut self, size: usize) -> Result<Vec<u8>> {
+        let end = self.end(size)?;
+        let block = self.bytes[self.offset..end].to_vec();
+        self.offset = end;
+        Ok(block)
+    }
+
+    /// The offset `length` bytes past the cursor, which must be within the
+    /// section.
+    fn end(&self, length: usize) -> Result<usize> {
+        match self.offset.checked_add(length) {
+            Some(end) if end <= self.bytes.len() => Ok(end),
+            _ => Err(format!("{} bytes at 0x{:x} out of bounds", length, self.offset).into())
+        }
+    }
+
+    /// An initial length, as (length, offset size).
+    fn length(&mut self) -> Result<(usize, usize)> {
+        match self.fixed(4)? {
+            0xffff_ffff => Ok((self.fixed(8)? as usize, 8)),
+            length => Ok((length as usize, 4))
+        }
+    }
+
+    fn at_end(&self, end: usize) -> bool {
+        self.offset >= end || self.offset >= self.bytes.len()
+    }
+}
+
+
+fn string_at(section: &[u8], offset: u64) -> Option<String> {
+    let rest = section.get(offset as usize..)?;
+    let end = rest.iter().position(|&b| b == 0)?;
+    Some(String::from_utf8_lossy(&rest[..end]).into_owned())
+}
+
+
+/// How a unit is encoded.
+#[derive(Clone, Copy, Debug)]
+struct Encoding {
+    version: u64,
+    address_size: usize,
+    offset_size: usize
+}
+
+
+/// An attribute value, before indices into other sections are resolved.
+#[derive(Clone, Debug)]
+enum Value {
+    Address(u64),
+    AddressIndex(u64),
+    Unsigned(u64),
+    Signed(i64),
+    String(String),
+    StringIndex(u64),
+    /// An offset in `.debug_info`.
+    Reference(u64),
+    SectionOffset(u64),
+    LocationListIndex(u64),
+    Block(Vec<u8>),
+    Flag(bool),
+    Unsupported
+}
+
+
+const DW_FORM_ADDR: u64 = 0x01;
+const DW_FORM_BLOCK2: u64 = 0x03;
+const DW_FORM_BLOCK4: u64 = 0x04;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_BLOCK1: u64 = 0x0a;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_FLAG: u64 = 0x0c;
+const DW_FORM_SDATA: u64 = 0x0d;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_UDATA: u64 = 0x0f;
+const DW_FORM_REF_ADDR: u64 = 0x10;
+const DW_FORM_REF1: u64 = 0x11;
+const DW_FORM_REF2: u64 = 0x12;
+const DW_FORM_REF4: u64 = 0x13;
+const DW_FORM_REF8: u64 = 0x14;
+const DW_FORM_REF_UDATA: u64 = 0x15;
+const DW_FORM_INDIRECT: u64 = 0x16;
+const DW_FORM_SEC_OFFSET: u64 = 0x17;
+const DW_FORM_EXPRLOC: u64 = 0x18;
+const DW_FORM_FLAG_PRESENT: u64 = 0x19;
+const DW_FORM_STRX: u64 = 0x1a;
+const DW_FORM_ADDRX: u64 = 0x1b;
+const DW_FORM_REF_SUP4: u64 = 0x1c;
+const DW_FORM_STRP_SUP: u64 = 0x1d;
+const DW_FORM_DATA16: u64 = 0x1e;
+const DW_FORM_LINE_STRP: u64 = 0x1f;
+const DW_FORM_REF_SIG8: u64 = 0x20;
+const DW_FORM_IMPLICIT_CONST: u64 = 0x21;
+const DW_FORM_LOCLISTX: u64 = 0x22;
+const DW_FORM_RNGLISTX: u64 = 0x23;
+const DW_FORM_REF_SUP8: u64 = 0x24;
+const DW_FORM_STRX1: u64 = 0x25;
+const DW_FORM_STRX2: u64 = 0x26;
+const DW_FORM_STRX3: u64 = 0x27;
+const DW_FORM_STRX4: u64 = 0x28;
+const DW_FORM_ADDRX1: u64 = 0x29;
+const DW_FORM_ADDRX2: u64 = 0x2a;
+const DW_FORM_ADDRX3: u64 = 0x2b;
+const DW_FORM_ADDRX4: u64 = 0x2c;
+
+
+/// Read a value of `form`. References are made absolute with `unit`, the
+/// offset of their unit.
+fn value(
+    cursor: &mut Cursor,
+    sections: &Sections,
+    encoding: Encoding,
+    unit: u64,
+    form: u64,
+    implicit: i64
+) -> Result<Value> {
+
+    let reference = |offset: u64| Value::Reference(unit.wrapping_add(offset));
+
+    Ok(match form {
+        DW_FORM_ADDR => Value::Address(cursor.fixed(encoding.address_size)?),
+        DW_FORM_BLOCK1 => { let size = cursor.u8()?; Value::Block(cursor.block(size as usize)?) },
+        DW_FORM_BLOCK2 => { let size = cursor.u16()?; Value::Block(cursor.block(size as usize)?) },
+        DW_FORM_BLOCK4 => { let size = cursor.fixed(4)?; Value::Block(cursor.block(size as usize)?) },
+        DW_FORM_BLOCK | DW_FORM_EXPRLOC => {
+            let size = cursor.uleb()?;
+            Value::Block(cursor.block(size as usize)?)
+        },
+        DW_FORM_DATA1 => Value::Unsigned(cursor.u8()?),
+        DW_FORM_DATA2 => Value::Unsigned(cursor.u16()?),
+        DW_FORM_DATA4 => Value::Unsigned(cursor.fixed(4)?),
+        DW_FORM_DATA8 => Value::Unsigned(cursor.fixed(8)?),
+        DW_FORM_DATA16 => { cursor.block(16)?; Value::Unsupported },
+        DW_FORM_SDATA => Value::Signed(cursor.sleb()?),
+        DW_FORM_UDATA => Value::Unsigned(cursor.uleb()?),
+        DW_FORM_IMPLICIT_CONST => Value::Signed(implicit),
+        DW_FORM_STRING => Value::String(cursor.string()?),
+        DW_FORM_STRP => {
+            let offset = cursor.fixed(encoding.offset_size)?;
+            string_at(sections.str_, offset).map(Value::String).unwrap_or(Value::Unsupported)
+        },
+        DW_FORM_LINE_STRP => {
+            let offset = cursor.fixed(encoding.offset_size)?;
+            string_at(sections.line_str, offset).map(Value::String).unwrap_or(Value::Unsupported)
+        },
+        DW_FORM_STRX => Value::StringIndex(cursor.uleb()?),
+        DW_FORM_STRX1 => Value::StringIndex(cursor.fixed(1)?),
+        DW_FORM_STRX2 => Value::StringIndex(cursor.fixed(2)?),
+        DW_FORM_STRX3 => Value::StringIndex(cursor.fixed(3)?),
+        DW_FORM_STRX4 => Value::StringIndex(cursor.fixed(4)?),
+        DW_FORM_ADDRX => Value::AddressIndex(cursor.uleb()?),
+        DW_FORM_ADDRX1 => Value::AddressIndex(cursor.fixed(1)?),
+        DW_FORM_ADDRX2 => Value::AddressIndex(cursor.fixed(2)?),
+        DW_FORM_ADDRX3 => Value::AddressIndex(cursor.fixed(3)?),
+        DW_FORM_ADDRX4 => Value::AddressIndex(cursor.fixed(4)?),
+        DW_FORM_FLAG => Value::Flag(cursor.u8()? != 0),
+        DW_FORM_FLAG_PRESENT => Value::Flag(true),
+        // DWARF 2 gives global references the size of an address.
+        DW_FORM_REF_ADDR => Value::Reference(cursor.fixed(
+            if encoding.version == 2 { encoding.address_size } else { encoding.offset_size })?),
+        DW_FORM_REF1 => reference(cursor.fixed(1)?),
+        DW_FORM_REF2 => reference(cursor.fixed(2)?),
+        DW_FORM_REF4 => reference(cursor.fixed(4)?),
+        DW_FORM_REF8 => reference(cursor.fixed(8)?),
+        DW_FORM_REF_UDATA => reference(cursor.uleb()?),
+        DW_FORM_SEC_OFFSET => Value::SectionOffset(cursor.fixed(encoding.offset_size)?),
+        DW_FORM_LOCLISTX => Value::LocationListIndex(cursor.uleb()?),
+        DW_FORM_RNGLISTX => { cursor.uleb()?; Value::Unsupported },
+        DW_FORM_REF_SIG8 | DW_FORM_REF_SUP8 => { cursor.fixed(8)?; Value::Unsupported },
+        DW_FORM_REF_SUP4 => { cursor.fixed(4)?; Value::Unsupported },
+        DW_FORM_STRP_SUP => { cursor.fixed(encoding.offset_size)?; Value::Unsupported },
+        DW_FORM_INDIRECT => {
+            let form = cursor.uleb()?;
+            value(cursor, sections, encoding, unit, form, implicit)?
+        },
+        _ => return Err(format!("unknown DWARF form 0x{:x}", form).into())
+    })
+}
+
+
+/// An abbreviation: a DIE's tag, whether it has children, and its
+/// attributes as (name, form, implicit constant).
+struct Abbreviation {
+    tag: u64,
+    children: bool,
+    attributes: Vec<(u64, u64, i64)>
+}
+
+fn abbreviations(sections: &Sections, offset: u64) -> Result<HashMap<u64, Abbreviation>> {
+    let mut cursor = Cursor::new(sections.abbrev, sections.little, offset as usize);
+    let mut abbreviations = HashMap::new();
+    loop {
+        let code = cursor.uleb()?;
+        if code == 0 {
+            break;
+        }
+        let tag = cursor.uleb()?;
+        let children = cursor.u8()? != 0;
+        let mut attributes = Vec::new();
+        loop {
+            let name = cursor.uleb()?;
+            let form = cursor.uleb()?;
+            if name == 0 && form == 0 {
+                break;
+            }
+            let implicit = if form == DW_FORM_IMPLICIT_CONST { cursor.sleb()? } else { 0 };
+            attributes.push((name, form, implicit));
+        }
+        abbreviations.insert(code, Abbreviation {
+            tag: tag,
+            children: children,
+            attributes: attributes
+        });
+    }
+    Ok(abbreviations)
+}
+
+
+/// A debugging information entry.
+struct Die {
+    offset: u64,
+    depth: usize,
+    tag: u64,
+    attributes: Vec<(u64, Value)>
+}
+
+impl Die {
+    fn get(&self, name: u64) -> Option<&Value> {
+        self.attributes.iter()
+            .find(|&&(attribute, _)| attribute == name)
+            .map(|&(_, ref value)| value)
+    }
+}
+
+
+const DW_TAG_ARRAY_TYPE: u64 = 0x01;
+const DW_TAG_CLASS_TYPE: u64 = 0x02;
+const DW_TAG_ENUMERATION_TYPE: u64 = 0x04;
+const DW_TAG_FORMAL_PARAMETER: u64 = 0x05;
+const DW_TAG_POINTER_TYPE: u64 = 0x0f;
+const DW_TAG_REFERENCE_TYPE: u64 = 0x10;
+const DW_TAG_STRUCTURE_TYPE: u64 = 0x13;
+const DW_TAG_SUBROUTINE_TYPE: u64 = 0x15;
+const DW_TAG_TYPEDEF: u64 = 0x16;
+const DW_TAG_UNION_TYPE: u64 = 0x17;
+const DW_TAG_INLINED_SUBROUTINE: u64 = 0x1d;
+const DW_TAG_SUBRANGE_TYPE: u64 = 0x21;
+const DW_TAG_BASE_TYPE: u64 = 0x24;
+const DW_TAG_CONST_TYPE: u64 = 0x26;
+const DW_TAG_SUBPROGRAM: u64 = 0x2e;
+const DW_TAG_VARIABLE: u64 = 0x34;
+const DW_TAG_VOLATILE_TYPE: u64 = 0x35;
+const DW_TAG_RESTRICT_TYPE: u64 = 0x37;
+
+const DW_AT_LOCATION: u64 = 0x02;
+const DW_AT_NAME: u64 = 0x03;
+const DW_AT_STMT_LIST: u64 = 0x10;
+const DW_AT_LOW_PC: u64 = 0x11;
+const DW_AT_HIGH_PC: u64 = 0x12;
+const DW_AT_COMP_DIR: u64 = 0x1b;
+const DW_AT_UPPER_BOUND: u64 = 0x2f;
+const DW_AT_ABSTRACT_ORIGIN: u64 = 0x31;
+const DW_AT_COUNT: u64 = 0x37;
+const DW_AT_DECL_FILE: u64 = 0x3a;
+const DW_AT_DECL_LINE: u64 = 0x3b;
+const DW_AT_DECLARATION: u64 = 0x3c;
+const DW_AT_FRAME_BASE: u64 = 0x40;
+const DW_AT_SPECIFICATION: u64 = 0x47;
+const DW_AT_TYPE: u64 = 0x49;
+const DW_AT_LINKAGE_NAME: u64 = 0x6e;
+const DW_AT_STR_OFFSETS_BASE: u64 = 0x72;
+const DW_AT_ADDR_BASE: u64 = 0x73;
+const DW_AT_LOCLISTS_BASE: u64 = 0x8c;
+
+
+/// A unit's entries, and what is needed to resolve their values.
+struct Unit<'s, 'b: 's> {
+    sections: &'s Sections<'b>,
+    encoding: Encoding,
+    dies: Vec<Die>,
+    str_offsets_base: u64,
+    addr_base: u64,
+    loclists_base: u64,
+    base_address: u64
+}
+
+impl<'s, 'b> Unit<'s, 'b> {
+    fn unsigned(&self, value: Option<&Value>) -> Option<u64> {
+        match value {
+            Some(&Value::Unsigned(value)) | Some(&Value::SectionOffset(value)) => Some(value),
+            Some(&Value::Signed(value)) => Some(value as u64),
+            _ => None
+        }
+    }
+
+    fn string(&self, value: Option<&Value>) -> Option<String> {
+        match value {
+            Some(&Value::String(ref string)) => Some(string.clone()),
+            Some(&Value::StringIndex(index)) => {
+                let size = self.encoding.offset_size as u64;
+                let offset = index.checked_mul(size)
+                    .and_then(|offset| self.str_offsets_base.checked_add(offset))?;
+                let offset = image::Reader::new(self.sections.str_offsets, self.sections.little)
+                    .read(offset, size as usize)
+                    .ok()?;
+                string_at(self.sections.str_, offset)
+            },
+            _ => None
+        }
+    }
+
+    fn address(&self, value: Option<&Value>) -> Option<u64> {
+        match value {
+            Some(&Value::Address(address)) => Some(address),
+            Some(&Value::AddressIndex(index)) => {
+                let size = self.encoding.address_size;
+                let offset = index.checked_mul(size as u64)
+                    .and_then(|offset| self.addr_base.checked_add(offset))?;
+                image::Reader::new(self.sections.addr, self.sections.little)
+                    .read(offset, size)
+                    .ok()
+            },
+            _ => None
+        }
+    }
+
+    fn reference(&self, value: Option<&Value>) -> Option<u64> {
+        match value {
+            Some(&Value::Reference(offset)) => Some(offset),
+            _ => None
+        }
+    }
+}
+
+
+/// Parse the unit at `offset` in `.debug_info`, returning it and the offset
+/// of the next unit.
+fn unit<'s, 'b>(sections: &'s Sections<'b>, offset: usize) -> Result<(Option<Unit<'s, 'b>>, usize)> {
+    let mut cursor = Cursor::new(sections.info, sections.little, offset);
+    let (length, offset_size) = cursor.length()?;
+    let end = cursor.end(length)?;
+    let version = cursor.u16()?;
+
+    let (abbreviation_offset, address_size, unit_type) = if version >= 5 {
+        let unit_type = cursor.u8()?;
+        let address_size = cursor.u8()?;
+        (cursor.fixed(offset_size)?, address_size, unit_type)
+    }
+    else {
+        let abbreviation_offset = cursor.fixed(offset_size)?;
+        (abbreviation_offset, cursor.u8()?, 1)
+    };
+
+    // Only compile units are read.
+    const DW_UT_COMPILE: u64 = 1;
+    if version < 2 || version > 5 || unit_type != DW_UT_COMPILE {
+        return Ok((None, end));
+    }
+
+    let encoding = Encoding {
+        version: version,
+        address_size: address_size as usize,
+        offset_size: offset_size
+    };
+    let abbreviations = abbreviations(sections, abbreviation_offset)?;
+
+    let mut dies = Vec::new();
+    let mut depth = 0;
+    while !cursor.at_end(end) {
+        let die_offset = cursor.offset as u64;
+        let code = cursor.uleb()?;
+        if code == 0 {
+            depth = if depth > 0 { depth - 1 } else { 0 };
+            continue;
+        }
+        let abbreviation = abbreviations.get(&code)
+            .ok_or(format!("unknown abbreviation {} at 0x{:x}", code, die_offset))?;
+        let mut attributes = Vec::new();
+        for &(name, form, implicit) in &abbreviation.attributes {
+            let value = value(&mut cursor, sections, encoding, offset as u64, form, implicit)?;
+            attributes.push((name, value));
+        }
+        dies.push(Die {
+            offset: die_offset,
+            depth: depth,
+            tag: abbreviation.tag,
+            attributes: attributes
+        });
+        if abbreviation.children {
+            depth += 1;
+        }
+    }
+
+    let mut unit = Unit {
+        sections: sections,
+        encoding: encoding,
+        dies: Vec::new(),
+        // The bases default to just past the sections' DWARF 5 headers.
+        str_offsets_base: if offset_size == 8 { 16 } else { 8 },
+        addr_base: if offset_size == 8 { 16 } else { 8 },
+        loclists_base: if offset_size == 8 { 20 } else { 12 },
+        base_address: 0
+    };
+    if let Some(root) = dies.first() {
+        if let Some(base) = unit.unsigned(root.get(DW_AT_STR_OFFSETS_BASE)) {
+            unit.str_offsets_base = base;
+        }
+        if let Some(base) = unit.unsigned(root.get(DW_AT_ADDR_BASE)) {
+            unit.addr_base = base;
+        }
+        if let Some(base) = unit.unsigned(root.get(DW_AT_LOCLISTS_BASE)) {
+            unit.loclists_base = base;
+        }
+        unit.base_address = unit.address(root.get(DW_AT_LOW_PC)).unwrap_or(0);
+    }
+    unit.dies = dies;
+
+    Ok((Some(unit), end))
+}
+
+
+const DW_LNCT_PATH: u64 = 1;
+const DW_LNCT_DIRECTORY_INDEX: u64 = 2;
+
+/// Join a file name to its directory.
+fn join(directory: Option<&String>, name: String) -> String {
+    match directory {
+        Some(directory) if !name.starts_with('/') && !directory.is_empty() =>
+            format!("{}/{}", directory.trim_end_matches('/'), name),
+        _ => name
+    }
+}
+
+
+/// Read the DWARF 5 directory or file name entries at the cursor, as (path,
+/// directory index).
+fn entries(cursor: &mut Cursor, unit: &Unit) -> Result<Vec<(String, u64)>> {
+    let format_count = cursor.u8()?;
+    let mut formats = Vec::new();
+    for _ in 0..format_count {
+        formats.push((cursor.uleb()?, cursor.uleb()?));
+    }
+    let count = cursor.uleb()?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let mut path = String::new();
+        let mut directory = 0;
+        for &(content, form) in &formats {
+            let value = value(cursor, unit.sections, unit.encoding, 0, form, 0)?;
+            match content {
+                DW_LNCT_PATH => path = unit.string(Some(&value)).unwrap_or_default(),
+                DW_LNCT_DIRECTORY_INDEX => directory = unit.unsigned(Some(&value)).unwrap_or(0),
+                _ => {}
+            }
+        }
+        entries.push((path, directory));
+    }
+    Ok(entries)
+}
+
+
+/// Run the line program at `offset`, adding its rows to `lines`. Returns the
+/// program's file names, indexed as `DW_AT_decl_file` indexes them.
+fn line_program(
+    unit: &Unit,
+    offset: u64,
+    comp_dir: Option<&String>,
+    comp_name: Option<&String>,
+    lines: &mut BTreeMap<u64, Option<SourceLocation>>
+) -> Result<Vec<String>> {
+
+    let sections = unit.sections;
+    let mut cursor = Cursor::new(sections.line, sections.little, offset as usize);
+    let (length, offset_size) = cursor.length()?;
+    let end = cursor.end(length)?;
+    let version = cursor.u16()?;
+    let mut encoding = unit.encoding;
+    encoding.offset_size = offset_size;
+    if version >= 5 {
+        encoding.address_size = cursor.u8()? as usize;
+        cursor.u8()?;
+    }
+    let header_length = cursor.fixed(offset_size)? as usize;
+    let program = cursor.end(header_length)?;
+    let minimum_instruction_length = cursor.u8()?;
+    if version >= 4 {
+        cursor.u8()?;
+    }
+    let default_is_stmt = cursor.u8()? != 0;
+    let line_base = cursor.u8()? as u8 as i8 as i64;
+    let line_range = cursor.u8()?;
+    let opcode_base = cursor.u8()?;
+    if line_range == 0 {
+        return Err("line program with a line range of 0".into());
+    }
+    let mut opcode_lengths = Vec::new();
+    for _ in 1..opcode_base {
+        opcode_lengths.push(cursor.u8()?);
+    }
+
+    let mut files = Vec::new();
+    if version >= 5 {
+        let header = Unit {
+            sections: sections,
+            encoding: encoding,
+            dies: Vec::new(),
+            str_offsets_base: unit.str_offsets_base,
+            addr_base: unit.addr_base,
+            loclists_base: unit.loclists_base,
+            base_address: unit.base_address
+        };
+        let directories: Vec<String> = entries(&mut cursor, &header)?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        for (path, directory) in entries(&mut cursor, &header)? {
+            files.push(join(directories.get(directory as usize), path));
+        }
+    }
+    else {
+        let mut directories = Vec::new();
+        loop {
+            let directory = cursor.string()?;
+            if directory.is_empty() {
+                break;
+            }
+            directories.push(directory);
+        }
+        // File 0 is the unit's own file.
+        files.push(join(comp_dir, comp_name.cloned().unwrap_or_default()));
+        loop {
+            let name = cursor.string()?;
+            if name.is_empty() {
+                break;
+            }
+            let directory = cursor.uleb()?;
+            cursor.uleb()?;
+            cursor.uleb()?;
+            let directory = if directory == 0 { comp_dir } else {
+                directories.get(directory as usize - 1)
+            };
+            files.push(join(directory, name));
+        }
+    }
+
+    cursor.offset = program;
+    let mut address = 0u64;
+    let mut file = 1u64;
+    let mut line = 1i64;
+    let mut column = 0u64;
+    let mut is_stmt = default_is_stmt;
+    // Sequences at address 0 are code the linker discarded.
+    let mut discarded = false;
+
+    let row = |address: u64, file: u64, line: i64, column: u64,
+               lines: &mut BTreeMap<u64, Option<SourceLocation>>| {
+        if let Some(name) = files.get(file as usize) {
+            lines.insert(address, Some(SourceLocation {
+                file: name.clone(),
+                line: line as u64,
+                column: column
+            }));
+        }
+    };
+
+    while !cursor.at_end(end) {
+        let opcode = cursor.u8()?;
+        if opcode >= opcode_base {
+            let adjusted = opcode - opcode_base;
+            address = address.wrapping_add((adjusted / line_range) * minimum_instruction_length);
+            line = line.wrapping_add(line_base + (adjusted % line_range) as i64);
+            if !discarded && is_stmt {
+                row(address, file, line, column, lines);
+            }
+            continue;
+        }
+        match opcode {
+            0 => {
+                let length = cursor.uleb()? as usize;
+                let next = cursor.end(length)?;
+                match cursor.u8()? {
+                    // DW_LNE_end_sequence
+                    1 => {
+                        if !discarded {
+                            lines.entry(address).or_insert(None);
+                        }
+                        address = 0;
+                        file = 1;
+                        line = 1;
+                        column = 0;
+                        is_stmt = default_is_stmt;
+                        discarded = false;
+                    },
+                    // DW_LNE_set_address
+                    2 => {
+                        address = cursor.fixed(length.saturating_sub(1))?;
+                        discarded = address == 0;
+                    },
+                    _ => {}
+                }
+                cursor.offset = next;
+            },
+            // DW_LNS_copy
+            1 => if !discarded && is_stmt {
+                row(address, file, line, column, lines);
+            },
+            2 => address = address.wrapping_add(cursor.uleb()? * minimum_instruction_length),
+            3 => line = line.wrapping_add(cursor.sleb()?),
+            4 => file = cursor.uleb()?,
+            5 => column = cursor.uleb()?,
+            6 => is_stmt = !is_stmt,
+            8 => {
+                let adjusted = 255 - opcode_base;
+                address = address.wrapping_add((adjusted / line_range) * minimum_instruction_length);
+            },
+            9 => address = address.wrapping_add(cursor.u16()?),
+            12 => { cursor.uleb()?; },
+            _ => {
+                for _ in 0..opcode_lengths.get(opcode as usize - 1).cloned().unwrap_or(0) {
+                    cursor.uleb()?;
+                }
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+
+/// Decode a location expression made of a single operation.
+fn location(expression: &[u8], unit: &Unit) -> Location {
+    let mut cursor = Cursor::new(expression, unit.sections.little, 1);
+    let location = match expression.first() {
+        // DW_OP_addr
+        Some(&0x03) => cursor.fixed(unit.encoding.address_size).map(Location::Address),
+        // DW_OP_reg0 to DW_OP_reg31
+        Some(&op) if op >= 0x50 && op <= 0x6f => Ok(Location::Register((op - 0x50) as u64)),
+        // DW_OP_breg0 to DW_OP_breg31
+        Some(&op) if op >= 0x70 && op <= 0x8f =>
+            cursor.sleb().map(|offset| Location::RegisterOffset((op - 0x70) as u64, offset)),
+        // DW_OP_regx
+        Some(&0x90) => cursor.uleb().map(Location::Register),
+        // DW_OP_fbreg
+        Some(&0x91) => cursor.sleb().map(Location::Frame),
+        // DW_OP_bregx
+        Some(&0x92) => cursor.uleb()
+            .and_then(|register| cursor.sleb().map(|offset| (register, offset)))
+            .map(|(register, offset)| Location::RegisterOffset(register, offset)),
+        _ => Ok(Location::Unknown)
+    };
+    location.unwrap_or(Location::Unknown)
+}
+
+
+/// Read the location list at `offset` in `.debug_loc` or, for DWARF 5,
+/// `.debug_loclists`.
+fn location_list(unit: &Unit, offset: u64) -> Result<Vec<(Option<(u64, u64)>, Location)>> {
+    let sections = unit.sections;
+    let size = unit.encoding.address_size;
+    let mut locations = Vec::new();
+    let mut base = unit.base_address;
+
+    if unit.encoding.version < 5 {
+        let mut cursor = Cursor::new(sections.loc, sections.little, offset as usize);
+        let max = if size == 8 { !0 } else { (1u64 << (size * 8)) - 1 };
+        loop {
+            let start = cursor.fixed(size)?;
+            let end = cursor.fixed(size)?;
+            if start == 0 && end == 0 {
+                break;
+            }
+            if start == max {
+                base = end;
+                continue;
+            }
+            let length = cursor.u16()? as usize;
+            let expression = cursor.block(length)?;
+            let range = (base.wrapping_add(start), base.wrapping_add(end));
+            locations.push((Some(range), location(&expression, unit)));
+        }
+        return Ok(locations);
+    }
+
+    let mut cursor = Cursor::new(sections.loclists, sections.little, offset as usize);
+    let address = |index: u64| unit.address(Some(&Value::AddressIndex(index)))
+        .ok_or("location list address index out of bounds");
+    loop {
+        let range = match cursor.u8()? {
+            // DW_LLE_end_of_list
+            0 => break,
+            // DW_LLE_base_addressx
+            1 => { base = address(cursor.uleb()?)?; continue },
+            // DW_LLE_startx_endx
+            2 => {
+                let start = address(cursor.uleb()?)?;
+                Some((start, address(cursor.uleb()?)?))
+            },
+            // DW_LLE_startx_length
+            3 => {
+                let start = address(cursor.uleb()?)?;
+                Some((start, start.wrapping_add(cursor.uleb()?)))
+            },
+            // DW_LLE_offset_pair
+            4 => {
+                let start = base.wrapping_add(cursor.uleb()?);
+                Some((start, base.wrapping_add(cursor.uleb()?)))
+            },
+            // DW_LLE_default_location
+            5 => None,
+            // DW_LLE_base_address
+            6 => { base = cursor.fixed(size)?; continue },
+            // DW_LLE_start_end
+            7 => {
+                let start = cursor.fixed(size)?;
+                Some((start, cursor.fixed(size)?))
+            },
+            // DW_LLE_start_length
+            8 => {
+                let start = cursor.fixed(size)?;
+                Some((start, start.wrapping_add(cursor.uleb()?)))
+            },
+            kind => return Err(format!("unknown location list entry {}", kind).into())
+        };
+        let length = cursor.uleb()? as usize;
+        let expression = cursor.block(length)?;
+        locations.push((range, location(&expression, unit)));
+    }
+    Ok(locations)
+}
+
+
+/// The locations of a variable, from its `DW_AT_location`.
+fn locations(unit: &Unit, value: Option<&Value>) -> Vec<(Option<(u64, u64)>, Location)> {
+    let list = match value {
+        Some(&Value::Block(ref expression)) => return vec![(None, location(expression, unit))],
+        Some(&Value::SectionOffset(offset)) => Some(offset),
+        // Before DWARF 4, location list offsets are plain constants.
+        Some(&Value::Unsigned(offset)) if unit.encoding.version < 4 => Some(offset),
+        Some(&Value::LocationListIndex(index)) => {
+            let size = unit.encoding.offset_size;
+            index.checked_mul(size as u64)
+                .and_then(|offset| unit.loclists_base.checked_add(offset))
+                .and_then(|offset| image::Reader::new(unit.sections.loclists, unit.sections.little)
+                    .read(offset, size)
+                    .ok())
+                .and_then(|offset| unit.loclists_base.checked_add(offset))
+        },
+        _ => None
+    };
+    list.and_then(|offset| location_list(unit, offset).ok()).unwrap_or_default()
+}
+
+
+fn frame_base(unit: &Unit, value: Option<&Value>) -> FrameBase {
+    match value {
+        // DW_OP_call_frame_cfa
+        Some(&Value::Block(ref expression)) if expression.first() == Some(&0x9c) =>
+            FrameBase::Cfa,
+        Some(&Value::Block(ref expression)) => match location(expression, unit) {
+            Location::Register(register) => FrameBase::Register(register, 0),
+            Location::RegisterOffset(register, offset) => FrameBase::Register(register, offset),
+            _ => FrameBase::Unknown
+        },
+        _ => FrameBase::Unknown
+    }
+}
+
+
+/// A type, for naming.
+struct Type {
+    tag: u64,
+    name: Option<String>,
+    type_: Option<u64>,
+    count: Option<u64>
+}
+
+/// A variable, before its name and type are resolved through references.
+struct RawVariable {
+    name: Option<String>,
+    origin: Option<u64>,
+    type_: Option<u64>,
+    parameter: bool,
+    locations: Vec<(Option<(u64, u64)>, Location)>
+}
+
+/// A function, before its name and types are resolved through references.
+struct RawFunction {
+    name: Option<String>,
+    origin: Option<u64>,
+    low_pc: u64,
+    high_pc: u64,
+    source: Option<SourceLocation>,
+    type_: Option<u64>,
+    frame_base: FrameBase,
+    variables: Vec<RawVariable>
+}
+
+
+/// The name of the type at `offset`.
+fn type_name(types: &HashMap<u64, Type>, offset: Option<u64>, depth: usize) -> String {
+    let (tag, name, inner, count) = match offset.and_then(|offset| types.get(&offset)) {
+        Some(t) => (t.tag, t.name.clone(), t.type_, t.count),
+        None => return "void".to_string()
+    };
+    if depth > 16 {
+        return "...".to_string();
+    }
+    let inner_name = || type_name(types, inner, depth + 1);
+    let named = |kind: &str| format!("{} {}", kind, name.clone().unwrap_or("<anonymous>".to_string()));
+
+    match tag {
+        DW_TAG_POINTER_TYPE => format!("{} *", inner_name()),
+        DW_TAG_REFERENCE_TYPE => format!("{} &", inner_name()),
+        DW_TAG_CONST_TYPE => format!("const {}", inner_name()),
+        DW_TAG_VOLATILE_TYPE => format!("volatile {}", inner_name()),
+        DW_TAG_RESTRICT_TYPE => format!("{} restrict", inner_name()),
+        DW_TAG_ARRAY_TYPE => match count {
+            Some(count) => format!("{}[{}]", inner_name(), count),
+            None => format!("{}[]", inner_name())
+        },
+        DW_TAG_STRUCTURE_TYPE => named("struct"),
+        DW_TAG_UNION_TYPE => named("union"),
+        DW_TAG_ENUMERATION_TYPE => named("enum"),
+        DW_TAG_CLASS_TYPE => named("class"),
+        DW_TAG_SUBROUTINE_TYPE => format!("{} (*)()", inner_name()),
+        _ => name.unwrap_or("?".to_string())
+    }
+}
+
+
+fn is_type(tag: u64) -> bool {
+    match tag {
+        DW_TAG_ARRAY_TYPE | DW_TAG_CLASS_TYPE | DW_TAG_ENUMERATION_TYPE |
+        DW_TAG_POINTER_TYPE | DW_TAG_REFERENCE_TYPE | DW_TAG_STRUCTURE_TYPE |
+        DW_TAG_SUBROUTINE_TYPE | DW_TAG_TYPEDEF | DW_TAG_UNION_TYPE |
+        DW_TAG_BASE_TYPE | DW_TAG_CONST_TYPE | DW_TAG_VOLATILE_TYPE |
+        DW_TAG_RESTRICT_TYPE => true,
+        _ => false
+    }
+}
+
+
+fn parse(sections: &Sections) -> Result<DebugInfo> {
+    let mut lines = BTreeMap::new();
+    let mut names: HashMap<u64, String> = HashMap::new();
+    let mut origins: HashMap<u64, u64> = HashMap::new();
+    let mut types: HashMap<u64, Type> = HashMap::new();
+    let mut functions: Vec<RawFunction> = Vec::new();
+
+    let mut offset = 0;
+    while offset < sections.info.len() {
+        let (unit, next) = unit(sections, offset)?;
+        if next <= offset || next > sections.info.len() {
+            return Err(format!("unit at 0x{:x} has a bad length", offset).into());
+        }
+        offset = next;
+        let unit = match unit {
+            Some(unit) => unit,
+            None => continue
+        };
+
+        let files = match unit.dies.first() {
+            Some(root) => match unit.unsigned(root.get(DW_AT_STMT_LIST)) {
+                Some(stmt_list) => line_program(
+                    &unit,
+                    stmt_list,
+                    unit.string(root.get(DW_AT_COMP_DIR)).as_ref(),
+                    unit.string(root.get(DW_AT_NAME)).as_ref(),
+                    &mut lines
+                ).unwrap_or_default(),
+                None => Vec::new()
+            },
+            None => Vec::new()
+        };
+
+        // The function whose entries are being read, by its depth, and the
+        // depth of a subtree being skipped.
+        let mut current: Option<usize> = None;
+        let mut skip: Option<usize> = None;
+        let mut array: Option<(u64, usize)> = None;
+
+        for die in &unit.dies {
+            let name = unit.string(die.get(DW_AT_NAME))
+                .or_else(|| unit.string(die.get(DW_AT_LINKAGE_NAME)));
+            if let Some(ref name) = name {
+                names.insert(die.offset, name.clone());
+            }
+            let origin = unit.reference(die.get(DW_AT_ABSTRACT_ORIGIN))
+                .or_else(|| unit.reference(die.get(DW_AT_SPECIFICATION)));
+            if let Some(origin) = origin {
+                origins.insert(die.offset, origin);
+            }
+
+            if is_type(die.tag) {
+                types.insert(die.offset, Type {
+                    tag: die.tag,
+                    name: name.clone(),
+                    type_: unit.reference(die.get(DW_AT_TYPE)),
+                    count: None
+                });
+                if die.tag == DW_TAG_ARRAY_TYPE {
+                    array = Some((die.offset, die.depth));
+                }
+            }
+            if die.tag == DW_TAG_SUBRANGE_TYPE {
+                if let Some((array, depth)) = array {
+                    let count = unit.unsigned(die.get(DW_AT_COUNT))
+                        .or_else(|| unit.unsigned(die.get(DW_AT_UPPER_BOUND)).and_then(|b| b.checked_add(1)));
+                    if depth + 1 == die.depth {
+                        if let Some(array) = types.get_mut(&array) {
+                            array.count = array.count.or(count);
+                        }
+                    }
+                }
+            }
+
+            if let Some(depth) = skip {
+                if die.depth > depth {
+                    continue;
+                }
+                skip = None;
+            }
+            if let Some(depth) = current {
+                if die.depth <= depth {
+                    current = None;
+                }
+            }
+
+            match die.tag {
+                DW_TAG_SUBPROGRAM => {
+                    if current.is_some() {
+                        skip = Some(die.depth);
+                        continue;
+                    }
+                    if let Some(&Value::Flag(true)) = die.get(DW_AT_DECLARATION) {
+                        continue;
+                    }
+                    let low_pc = match unit.address(die.get(DW_AT_LOW_PC)) {
+                        Some(low_pc) if low_pc != 0 => low_pc,
+                        _ => continue
+                    };
+                    let high_pc = match die.get(DW_AT_HIGH_PC) {
+                        Some(&Value::Unsigned(size)) => low_pc.wrapping_add(size),
+                        Some(&Value::Signed(size)) => low_pc.wrapping_add(size as u64),
+                        value => unit.address(value).unwrap_or(low_pc.wrapping_add(1))
+                    };
+                    let source = unit.unsigned(die.get(DW_AT_DECL_FILE))
+                        .and_then(|file| files.get(file as usize))
+                        .map(|file| SourceLocation {
+                            file: file.clone(),
+                            line: unit.unsigned(die.get(DW_AT_DECL_LINE)).unwrap_or(0),
+                            column: 0
+                        });
+                    functions.push(RawFunction {
+                        name: name,
+                        origin: origin,
+                        low_pc: low_pc,
+                        high_pc: high_pc,
+                        source: source,
+                        type_: unit.reference(die.get(DW_AT_TYPE)),
+                        frame_base: frame_base(&unit, die.get(DW_AT_FRAME_BASE)),
+                        variables: Vec::new()
+                    });
+                    current = Some(die.depth);
+                },
+                DW_TAG_INLINED_SUBROUTINE => if current.is_some() {
+                    skip = Some(die.depth);
+                },
+                DW_TAG_FORMAL_PARAMETER | DW_TAG_VARIABLE => if current.is_some() {
+                    let variable = RawVariable {
+                        name: name,
+                        origin: origin,
+                        type_: unit.reference(die.get(DW_AT_TYPE)),
+                        parameter: die.tag == DW_TAG_FORMAL_PARAMETER,
+                        locations: locations(&unit, die.get(DW_AT_LOCATION))
+                    };
+                    if let Some(function) = functions.last_mut() {
+                        function.variables.push(variable);
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    // Names and types may be given by an abstract origin or specification.
+    let name_of = |name: Option<String>, origin: Option<u64>| -> Option<String> {
+        let mut name = name;
+        let mut origin = origin;
+        for _ in 0..8 {
+            if name.is_some() {
+                break;
+            }
+            let offset = match origin {
+                Some(offset) => offset,
+                None => break
+            };
+            name = names.get(&offset).cloned();
+            origin = origins.get(&offset).cloned();
+        }
+        name
+    };
+
+    let functions = functions.into_iter()
+        .map(|function| {
+            let variables = function.variables.into_iter()
+                .map(|variable| Variable {
+                    name: name_of(variable.name, variable.origin)
+                        .unwrap_or("<anonymous>".to_string()),
+                    type_name: variable.type_.map(|t| type_name(&types, Some(t), 0)),
+                    parameter: variable.parameter,
+                    locations: variable.locations
+                })
+                .collect();
+            let function = Function {
+                name: name_of(function.name, function.origin)
+                    .unwrap_or(format!("sub_{:x}", function.low_pc)),
+                low_pc: function.low_pc,
+                high_pc: function.high_pc,
+                source: function.source,
+                return_type: function.type_.map(|t| type_name(&types, Some(t), 0)),
+                frame_base: function.frame_base,
+                variables: variables
+            };
+            (function.low_pc, function)
+        })
+        .collect();
+
+    Ok(DebugInfo { lines: lines, functions: functions })
+}
+
+
+/// The name of the scalar Falcon uses for DWARF register `register`.
+pub fn register_name(architecture: &Architecture, register: u64) -> Option<&'static str> {
+    const AMD64: &[&str] = &[
+        "rax", "rdx", "rcx", "rbx", "rsi", "rdi", "rbp", "rsp",
+        "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15"
+    ];
+    const X86: &[&str] = &["eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi"];
+    const MIPS: &[&str] = &[
+        "$zero", "$at", "$v0", "$v1", "$a0", "$a1", "$a2", "$a3",
+        "$t0", "$t1", "$t2", "$t3", "$t4", "$t5", "$t6", "$t7",
+        "$s0", "$s1", "$s2", "$s3", "$s4", "$s5", "$s6", "$s7",
+        "$t8", "$t9", "$k0", "$k1", "$gp", "$sp", "$fp", "$ra"
+    ];
+    const PPC: &[&str] = &[
+        "r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7",
+        "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15",
+        "r16", "r17", "r18", "r19", "r20", "r21", "r22", "r23",
+        "r24", "r25", "r26", "r27", "r28", "r29", "r30", "r31"
+    ];
+
+    let registers = match architecture.name() {
+        "amd64" => AMD64,
+        "x86" => X86,
+        "mips" | "mipsel" => MIPS,
+        "ppc" => PPC,
+        _ => return None
+    };
+    registers.get(register as usize).cloned()
+}
+
+
+/// The offset from the entry stack pointer held by the scalar `name` at most
+/// locations of a function, given its offsets.
+fn register_offset(
+    offsets: &HashMap<falcon::il::FunctionLocation, stack_frame::Offsets>,
+    name: &str
+) -> Option<i64> {
+
+    let mut counts: BTreeMap<i64, usize> = BTreeMap::new();
+    for state in offsets.values() {
+        if let Some(&offset) = state.get(name) {
+            *counts.entry(offset).or_insert(0) += 1;
+        }
+    }
+    counts.into_iter().max_by_key(|&(_, count)| count).map(|(offset, _)| offset)
+}
+
+
+/// The variables of `debug_function` held in `function`'s stack frame, by
+/// their offset from the entry stack pointer.
+pub fn stack_variables<'f>(
+    architecture: &Architecture,
+    function: &falcon::il::Function,
+    debug_function: &'f Function
+) -> Result<Vec<(i64, &'f Variable)>> {
+
+    let offsets = stack_frame::offsets(architecture, function)?;
+    let register = |register: u64| register_name(architecture, register)
+        .and_then(|name| register_offset(&offsets, name));
+
+    let frame_base = match debug_function.frame_base {
+        FrameBase::Cfa => match *architecture.calling_convention().return_address_type() {
+            ReturnAddressType::Stack(_) => Some((architecture.word_size() / 8) as i64),
+            ReturnAddressType::Register(_) => Some(0)
+        },
+        FrameBase::Register(r, offset) => register(r).map(|base| base.wrapping_add(offset)),
+        FrameBase::Unknown => None
+    };
+
+    let mut variables = Vec::new();
+    for variable in &debug_function.variables {
+        let offset = variable.locations.iter()
+            .filter_map(|&(_, location)| match location {
+                Location::Frame(offset) => frame_base.map(|base| base.wrapping_add(offset)),
+                Location::RegisterOffset(r, offset) =>
+                    register(r).map(|base| base.wrapping_add(offset)),
+                _ => None
+            })
+            .next();
+        if let Some(offset) = offset {
+            variables.push((offset, variable));
+        }
+    }
+    variables.sort_by_key(|&(offset, _)| offset);
+    Ok(variables)
+}
+
+
+/// The variables of `debug_function` held in registers at `address`, by the
+/// name of the register's scalar.
+pub fn register_variables<'f>(
+    architecture: &Architecture,
+    debug_function: &'f Function,
+    address: u64
+) -> Vec<(&'static str, &'f Variable)> {
+
+    debug_function.variables.iter()
+        .filter_map(|variable| match variable.location_at(address) {
+            Some(Location::Register(register)) =>
+                register_name(architecture, register).map(|name| (name, variable)),
+            _ => None
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debug_sections<'b>(info: &'b [u8], abbrev: &'b [u8], line: &'b [u8], str_: &'b [u8],
+                    loc: &'b [u8], loclists: &'b [u8]) -> Sections<'b> {
+        Sections {
+            info: info,
+            abbrev: abbrev,
+            line: line,
+            str_: str_,
+            line_str: &[],
+            str_offsets: &[],
+            addr: &[],
+            loc: loc,
+            loclists: loclists,
+            little: true
+        }
+    }
+
+    fn encoding(version: u64) -> Encoding {
+        Encoding { version: version, address_size: 8, offset_size: 4 }
+    }
+
+    fn test_unit<'s, 'b>(sections: &'s Sections<'b>, version: u64) -> Unit<'s, 'b> {
+        Unit {
+            sections: sections,
+            encoding: encoding(version),
+            dies: Vec::new(),
+            str_offsets_base: 0,
+            addr_base: 0,
+            loclists_base: 0,
+            base_address: 0x400
+        }
+    }
+
+    fn le(value: u64, size: usize) -> Vec<u8> {
+        (0..size).map(|i| (value >> (8 * i)) as u8).collect()
+    }
+
+    fn read(bytes: &[u8], form: u64, implicit: i64) -> Result<Value> {
+        let sections = debug_sections(&[], &[], &[], b"x\0hello\0", &[], &[]);
+        let mut cursor = Cursor::new(bytes, true, 0);
+        value(&mut cursor, &sections, encoding(4), 0x100, form, implicit)
+    }
+
+    fn debug(value: Result<Value>) -> String {
+        format!("{:?}", value.unwrap())
+    }
+
+    #[test]
+    fn leb128_values() {
+        let mut offset = 0;
+        assert_eq!(leb128(&[0xe5, 0x8e, 0x26], &mut offset, false), Some(624485));
+        assert_eq!(offset, 3);
+
+        let mut offset = 0;
+        assert_eq!(leb128(&[0x7f], &mut offset, true), Some(!0));
+        let mut offset = 0;
+        assert_eq!(leb128(&[0xc0, 0xbb, 0x78], &mut offset, true).map(|v| v as i64),
+                   Some(-123456));
+        let mut offset = 0;
+        assert_eq!(leb128(&[0x3f], &mut offset, true), Some(0x3f));
+    }
+
+    #[test]
+    fn leb128_malformed() {
+        let mut offset = 0;
+        assert_eq!(leb128(&[0x80, 0x80], &mut offset, false), None);
+        let mut offset = 5;
+        assert_eq!(leb128(&[0x01], &mut offset, false), None);
+
+        // Bits past the 64th are dropped rather than overflowing.
+        let mut bytes = vec![0xff; 12];
+        bytes.push(0x01);
+        let mut offset = 0;
+        assert_eq!(leb128(&bytes, &mut offset, false), Some(!0));
+        assert_eq!(offset, 13);
+    }
+
+    #[test]
+    fn value_forms() {
+        assert_eq!(debug(read(&[0x34, 0x12], DW_FORM_DATA2, 0)), "Unsigned(4660)");
+        assert_eq!(debug(read(&[0x7e], DW_FORM_SDATA, 0)), "Signed(-2)");
+        assert_eq!(debug(read(b"ab\0", DW_FORM_STRING, 0)), "String(\"ab\")");
+        assert_eq!(debug(read(&[2, 0, 0, 0], DW_FORM_STRP, 0)), "String(\"hello\")");
+        assert_eq!(debug(read(&[9, 0, 0, 0], DW_FORM_STRP, 0)), "Unsupported");
+        assert_eq!(debug(read(&[0x10, 0, 0, 0], DW_FORM_REF4, 0)), "Reference(272)");
+        assert_eq!(debug(read(&[0x10, 0, 0, 0], DW_FORM_REF_ADDR, 0)), "Reference(16)");
+        assert_eq!(debug(read(&[2, 0x91, 0x7f], DW_FORM_BLOCK1, 0)), "Block([145, 127])");
+        assert_eq!(debug(read(&[3], DW_FORM_STRX1, 0)), "StringIndex(3)");
+        assert_eq!(debug(read(&[0x0b, 7], DW_FORM_INDIRECT, 0)), "Unsigned(7)");
+        assert_eq!(debug(read(&[], DW_FORM_IMPLICIT_CONST, -3)), "Signed(-3)");
+        assert_eq!(debug(read(&[], DW_FORM_FLAG_PRESENT, 0)), "Flag(true)");
+    }
+
+    #[test]
+    fn value_malformed() {
+        assert!(read(&[1, 2], DW_FORM_DATA4, 0).is_err());
+        assert!(read(&[5, 1], DW_FORM_BLOCK1, 0).is_err());
+        assert!(read(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+                     DW_FORM_BLOCK, 0).is_err());
+        assert!(read(b"ab", DW_FORM_STRING, 0).is_err());
+        assert!(read(&[0x80], DW_FORM_UDATA, 0).is_err());
+        assert!(read(&[], 0x99, 0).is_err());
+    }
+
+    #[test]
+    fn unit_indices_out_of_range() {
+        let sections = debug_sections(&[], &[], &[], &[], &[], &[]);
+        let unit = test_unit(&sections, 5);
+        assert_eq!(unit.string(Some(&Value::StringIndex(u64::max_value()))), None);
+        assert_eq!(unit.address(Some(&Value::AddressIndex(u64::max_value()))), None);
+        assert_eq!(unit.address(Some(&Value::Address(0x10))), Some(0x10));
+    }
+
+    #[test]
+    fn abbreviation_table() {
+        let abbrev = [
+            1, 0x11, 1, 0x03, 0x08, 0x3b, 0x21, 0x7e, 0, 0,
+            2, 0x2e, 0, 0x11, 0x01, 0, 0,
+            0
+        ];
+        let sections = debug_sections(&[], &abbrev, &[], &[], &[], &[]);
+        let abbreviations = abbreviations(&sections, 0).unwrap();
+        assert_eq!(abbreviations.len(), 2);
+        let unit = &abbreviations[&1];
+        assert_eq!(unit.tag, 0x11);
+        assert!(unit.children);
+        assert_eq!(unit.attributes, vec![(0x03, 0x08, 0), (0x3b, 0x21, -2)]);
+        let subprogram = &abbreviations[&2];
+        assert_eq!(subprogram.tag, DW_TAG_SUBPROGRAM);
+        assert!(!subprogram.children);
+        assert_eq!(subprogram.attributes, vec![(DW_AT_LOW_PC, DW_FORM_ADDR, 0)]);
+    }
+
+    #[test]
+    fn abbreviation_table_truncated() {
+        let sections = debug_sections(&[], &[1, 0x11, 1, 0x03, 0x08], &[], &[], &[], &[]);
+        assert!(abbreviations(&sections, 0).is_err());
+        let sections = debug_sections(&[], &[], &[], &[], &[], &[]);
+        assert!(abbreviations(&sections, 4).is_err());
+    }
+
+    /// A DWARF 4 line program with `program` as its opcodes.
+    fn line_section(line_range: u8, program: &[u8]) -> Vec<u8> {
+        let mut header = vec![1, 1, 1, 0xfb, line_range, 13];
+        header.extend_from_slice(&[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+        header.extend_from_slice(b"src\0\0");
+        header.extend_from_slice(b"a.c\0\x01\x00\x00\0");
+
+        let mut unit = vec![4, 0];
+        unit.extend(le(header.len() as u64, 4));
+        unit.extend(header);
+        unit.extend_from_slice(program);
+
+        let mut section = le(unit.len() as u64, 4);
+        section.extend(unit);
+        section
+    }
+
+    fn run(section: &[u8]) -> Result<(Vec<String>, BTreeMap<u64, Option<SourceLocation>>)> {
+        let sections = debug_sections(&[], &[], section, &[], &[], &[]);
+        let unit = test_unit(&sections, 4);
+        let mut lines = BTreeMap::new();
+        let files = line_program(&unit, 0, Some(&"/work".to_string()),
+            Some(&"main.c".to_string()), &mut lines)?;
+        Ok((files, lines))
+    }
+
+    #[test]
+    fn line_program_rows() {
+        let program = [
+            0, 9, 2, 0x00, 0x10, 0, 0, 0, 0, 0, 0,
+            5, 3,
+            1,
+            76,
+            2, 4,
+            0, 1, 1
+        ];
+        let (files, lines) = run(&line_section(14, &program)).unwrap();
+        assert_eq!(files, vec!["/work/main.c".to_string(), "src/a.c".to_string()]);
+
+        let location = |line| Some(SourceLocation {
+            file: "src/a.c".to_string(),
+            line: line,
+            column: 3
+        });
+        let expected: BTreeMap<u64, Option<SourceLocation>> = vec![
+            (0x1000, location(1)),
+            (0x1004, location(3)),
+            (0x1008, None)
+        ].into_iter().collect();
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    fn line_program_malformed() {
+        assert!(run(&line_section(0, &[])).is_err());
+
+        let mut section = line_section(14, &[0, 1, 1]);
+        section[0] = 0xf0;
+        assert!(run(&section).is_err());
+
+        let mut section = line_section(14, &[0, 1, 1]);
+        section[6] = 0xf0;
+        assert!(run(&section).is_err());
+
+        let section = line_section(14, &[0, 0xff, 0xff, 0xff, 0xff, 0x0f, 1]);
+        assert!(run(&section).is_err());
+
+        assert!(run(&line_section(14, &[])[..20]).is_err());
+    }
+
+    #[test]
+    fn location_list_dwarf4() {
+        let mut loc = le(0x10, 8);
+        loc.extend(le(0x20, 8));
+        loc.extend_from_slice(&[1, 0, 0x50]);
+        loc.extend(le(u64::max_value(), 8));
+        loc.extend(le(0x1000, 8));
+        loc.extend(le(0, 8));
+        loc.extend(le(8, 8));
+        loc.extend_from_slice(&[2, 0, 0x91, 0x70]);
+        loc.extend_from_slice(&[0; 16]);
+
+        let sections = debug_sections(&[], &[], &[], &[], &loc, &[]);
+        let unit = test_unit(&sections, 4);
+        assert_eq!(location_list(&unit, 0).unwrap(), vec![
+            (Some((0x410, 0x420)), Location::Register(0)),
+            (Some((0x1000, 0x1008)), Location::Frame(-16))
+        ]);
+
+        // Without its terminator the list is truncated.
+        let sections = debug_sections(&[], &[], &[], &[], &loc[..loc.len() - 16], &[]);
+        assert!(location_list(&test_unit(&sections, 4), 0).is_err());
+    }
+
+    #[test]
+    fn location_list_dwarf5() {
+        let mut loclists = vec![4, 0x10, 0x20, 1, 0x50, 8];
+        loclists.extend(le(0x2000, 8));
+        loclists.extend_from_slice(&[0x10, 2, 0x91, 0x70, 5, 1, 0x51, 0]);
+
+        let sections = debug_sections(&[], &[], &[], &[], &[], &loclists);
+        let unit = test_unit(&sections, 5);
+        assert_eq!(location_list(&unit, 0).unwrap(), vec![
+            (Some((0x410, 0x420)), Location::Register(0)),
+            (Some((0x2000, 0x2010)), Location::Frame(-16)),
+            (None, Location::Register(1))
+        ]);
+
+        assert!(location_list(&unit, 100).is_err());
+    }
+
+    #[test]
+    fn location_list_malformed() {
+        let sections = debug_sections(&[], &[], &[], &[], &[], &[0x30]);
+        assert!(location_list(&test_unit(&sections, 5), 0).is_err());
+
+        let sections = debug_sections(&[], &[], &[], &[], &[], &[4, 0x10, 0x20, 9, 0x50, 0]);
+        assert!(location_list(&test_unit(&sections, 5), 0).is_err());
+
+        // An address index past `.debug_addr` is an error, not a panic.
+        let sections = debug_sections(&[], &[], &[], &[], &[], &[2, 0x7f, 0x7f, 1, 0x50, 0]);
+        assert!(location_list(&test_unit(&sections, 5), 0).is_err());
+    }
+
+    #[test]
+    fn units_must_fit_the_section() {
+        let info = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+        assert!(parse(&debug_sections(&info, &[], &[], &[], &[], &[])).is_err());
+
+        let info = [0x40, 0, 0, 0, 4, 0];
+        assert!(parse(&debug_sections(&info, &[], &[], &[], &[], &[])).is_err());
+    }
+
+    #[test]
+    fn join_paths() {
+        assert_eq!(join(Some(&"src/".to_string()), "a.c".to_string()), "src/a.c");
+        assert_eq!(join(Some(&"src".to_string()), "/abs/a.c".to_string()), "/abs/a.c");
+        assert_eq!(join(None, "a.c".to_string()), "a.c");
+    }
+}
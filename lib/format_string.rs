@@ -0,0 +1,219 @@
+//! A format string vulnerability checker.
+//!
+//! Every call to a printf-family function is checked for a format argument
+//! which is the address of a constant string in non-writable memory. Calls
+//! are matched by name, so both functions in the program and imports reached
+//! through a stub or a pointer in memory are checked.
+
+use falcon;
+use falcon::error::Result;
+use falcon::loader::Loader;
+use falcon::memory::MemoryPermissions;
+use falcon::memory::backing::Memory;
+use std::fmt;
+use std::sync::Arc;
+
+use call_site;
+use check;
+
+
+/// The rule id of the format string checker.
+pub const RULE: &str = "format-string";
+
+
+/// printf-family functions, and the index of their format argument.
+pub const FUNCTIONS: &[(&str, usize)] = &[
+    ("printf", 0),
+    ("vprintf", 0),
+    ("fprintf", 1),
+    ("vfprintf", 1),
+    ("dprintf", 1),
+    ("vdprintf", 1),
+    ("sprintf", 1),
+    ("vsprintf", 1),
+    ("snprintf", 2),
+    ("vsnprintf", 2),
+    ("syslog", 1),
+    ("vsyslog", 1),
+    ("__printf_chk", 1),
+    ("__vprintf_chk", 1),
+    ("__fprintf_chk", 2),
+    ("__vfprintf_chk", 2),
+    ("__sprintf_chk", 3),
+    ("__vsprintf_chk", 3),
+    ("__snprintf_chk", 4),
+    ("__vsnprintf_chk", 4)
+];
+
+
+/// The format argument index of `name`.
+pub fn format_argument(name: &str) -> Option<usize> {
+    call_site::lookup(name, |name| FUNCTIONS.iter()
+        .find(|&&(function, _)| function == name)
+        .map(|&(_, index)| index))
+}
+
+
+/// Why a format argument is unsafe.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Reason {
+    /// The format argument could not be recovered at the call.
+    Unknown,
+    /// The format argument is not constant.
+    NotConstant,
+    /// The format argument is a constant address outside loaded memory.
+    Unmapped(u64),
+    /// The format string is in writable memory.
+    Writable(u64)
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Reason::Unknown => write!(f, "format argument could not be recovered"),
+            Reason::NotConstant => write!(f, "format argument is not constant"),
+            Reason::Unmapped(address) =>
+                write!(f, "format string at 0x{:x} is not in loaded memory", address),
+            Reason::Writable(address) =>
+                write!(f, "format string at 0x{:x} is in writable memory", address)
+        }
+    }
+}
+
+
+/// A call to a printf-family function with an unsafe format argument.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Finding {
+    /// The call.
+    pub location: falcon::il::ProgramLocation,
+    /// The native address of the call, if known.
+    pub address: Option<u64>,
+    /// The name of the function containing the call.
+    pub caller: String,
+    /// The printf-family function called.
+    pub function: String,
+    /// The index of the format argument.
+    pub argument: usize,
+    pub reason: Reason
+}
+
+impl Finding {
+    /// This finding as a checker finding.
+    pub fn finding(&self) -> check::Finding {
+        let severity = match self.reason {
+            Reason::NotConstant | Reason::Writable(_) => check::Severity::High,
+            Reason::Unknown | Reason::Unmapped(_) => check::Severity::Medium
+        };
+        let message = format!("{} calls {}: {}", self.caller, self.function, self.reason);
+
+        let mut finding = check::Finding::new(RULE, severity, message);
+        finding.location = Some(self.location.clone());
+        finding.address = self.address;
+        finding
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.address {
+            Some(address) => write!(f, "0x{:x}", address)?,
+            None => write!(f, "{}", self.location)?
+        }
+        write!(f, " {} calls {}: {}", self.caller, self.function, self.reason)
+    }
+}
+
+
+/// Check a format argument.
+fn reason(memory: &Memory, argument: Option<&call_site::Argument>) -> Option<Reason> {
+    let argument = match argument {
+        Some(argument) => argument,
+        None => return Some(Reason::Unknown)
+    };
+
+    if argument.expression.is_none() {
+        return Some(Reason::Unknown);
+    }
+
+    let address = match argument.constant.as_ref().and_then(|c| c.value_u64()) {
+        Some(address) => address,
+        None => return Some(Reason::NotConstant)
+    };
+
+    match memory.permissions(address) {
+        None => Some(Reason::Unmapped(address)),
+        Some(permissions) =>
+            if permissions.contains(MemoryPermissions::WRITE) {
+                Some(Reason::Writable(address))
+            }
+            else {
+                None
+            }
+    }
+}
+
+
+/// Check every call to a printf-family function in `program`.
+pub fn check(loader: &Loader, program: &falcon::il::Program) -> Result<Vec<Finding>> {
+    let callees = call_site::Callees::new(loader, program)?;
+    let memory = loader.memory()?;
+
+    let arguments = FUNCTIONS.iter().map(|&(_, index)| index + 1).max().unwrap_or(0);
+
+    let mut findings = Vec::new();
+
+    for function in program.functions() {
+        for call_site in call_site::call_sites(loader.architecture(), function, arguments)? {
+            let name = match callees.callee(function, &call_site) {
+                Some((_, name)) => name,
+                None => continue
+            };
+            let argument = match format_argument(name) {
+                Some(argument) => argument,
+                None => continue
+            };
+
+            if let Some(reason) = reason(&memory, call_site.argument(argument)) {
+                let location = call_site.location.apply(function)?;
+                let location = falcon::il::RefProgramLocation::new(function, location);
+                findings.push(Finding {
+                    location: location.into(),
+                    address: call_site.address,
+                    caller: function.name(),
+                    function: name.to_string(),
+                    argument: argument,
+                    reason: reason
+                });
+            }
+        }
+    }
+
+    Ok(findings)
+}
+
+
+/// The format string checker, for the checker registry.
+#[derive(Clone, Debug)]
+pub struct FormatStringChecker;
+
+impl check::Checker for FormatStringChecker {
+    fn id(&self) -> &str {
+        RULE
+    }
+
+    fn description(&self) -> &str {
+        "printf-family calls whose format argument is not a constant, read-only string"
+    }
+
+    fn check(
+        &self,
+        loader: &Arc<Loader>,
+        program: &Arc<falcon::il::Program>
+    ) -> Result<Vec<check::Finding>> {
+
+        Ok(check(&**loader, program)?
+            .iter()
+            .map(|finding| finding.finding())
+            .collect())
+    }
+}
@@ -0,0 +1,173 @@
+//! Shared handles to Falcon IL.
+//!
+//! Gluon values are cloned freely, so wrapping Falcon's IL types directly
+//! means every accessor deep-copies blocks, graphs and functions. These
+//! handles instead hold an `Arc` to the owning program or function and an
+//! index into it. They dereference to the underlying Falcon type, so the
+//! wrapper types in `il` can use them wherever the Falcon type is expected.
+
+use falcon;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+
+
+/// A function, either standalone or owned by a program.
+#[derive(Clone, Debug)]
+pub enum FunctionHandle {
+    Owned(Arc<falcon::il::Function>),
+    Program(Arc<falcon::il::Program>, usize)
+}
+
+impl FunctionHandle {
+    /// A handle to every function in `program`.
+    pub fn program_functions(program: &Arc<falcon::il::Program>) -> Vec<FunctionHandle> {
+        program.functions()
+            .into_iter()
+            .map(|function| FunctionHandle::from_program(program, function))
+            .collect()
+    }
+
+    /// A handle to `function`, which must belong to `program`.
+    pub fn from_program(
+        program: &Arc<falcon::il::Program>,
+        function: &falcon::il::Function
+    ) -> FunctionHandle {
+        match function.index() {
+            Some(index) => FunctionHandle::Program(program.clone(), index),
+            None => FunctionHandle::Owned(Arc::new(function.clone()))
+        }
+    }
+}
+
+impl Deref for FunctionHandle {
+    type Target = falcon::il::Function;
+
+    fn deref(&self) -> &falcon::il::Function {
+        match *self {
+            FunctionHandle::Owned(ref function) => &**function,
+            FunctionHandle::Program(ref program, index) =>
+                program.function(index).expect("function handle out of bounds")
+        }
+    }
+}
+
+impl From<falcon::il::Function> for FunctionHandle {
+    fn from(function: falcon::il::Function) -> FunctionHandle {
+        FunctionHandle::Owned(Arc::new(function))
+    }
+}
+
+
+/// A control flow graph, either standalone or owned by a function.
+#[derive(Clone, Debug)]
+pub enum ControlFlowGraphHandle {
+    Owned(Arc<falcon::il::ControlFlowGraph>),
+    Function(FunctionHandle)
+}
+
+impl ControlFlowGraphHandle {
+    /// A handle to every block in this graph.
+    pub fn block_handles(&self) -> Vec<BlockHandle> {
+        self.deref()
+            .blocks()
+            .into_iter()
+            .map(|block| BlockHandle::Graph(self.clone(), block.index()))
+            .collect()
+    }
+
+    /// A handle to the block at `index`, if it exists.
+    pub fn block_handle(&self, index: usize) -> Option<BlockHandle> {
+        self.deref().block(index).ok()?;
+        Some(BlockHandle::Graph(self.clone(), index))
+    }
+}
+
+impl Deref for ControlFlowGraphHandle {
+    type Target = falcon::il::ControlFlowGraph;
+
+    fn deref(&self) -> &falcon::il::ControlFlowGraph {
+        match *self {
+            ControlFlowGraphHandle::Owned(ref control_flow_graph) =>
+                &**control_flow_graph,
+            ControlFlowGraphHandle::Function(ref function) =>
+                function.control_flow_graph()
+        }
+    }
+}
+
+impl From<falcon::il::ControlFlowGraph> for ControlFlowGraphHandle {
+    fn from(control_flow_graph: falcon::il::ControlFlowGraph) -> ControlFlowGraphHandle {
+        ControlFlowGraphHandle::Owned(Arc::new(control_flow_graph))
+    }
+}
+
+impl From<FunctionHandle> for ControlFlowGraphHandle {
+    fn from(function: FunctionHandle) -> ControlFlowGraphHandle {
+        ControlFlowGraphHandle::Function(function)
+    }
+}
+
+
+/// A block, either standalone or owned by a control flow graph.
+#[derive(Clone, Debug)]
+pub enum BlockHandle {
+    Owned(Arc<falcon::il::Block>),
+    Graph(ControlFlowGraphHandle, usize)
+}
+
+impl BlockHandle {
+    /// The function this block belongs to, if any.
+    pub fn function(&self) -> Option<&FunctionHandle> {
+        match *self {
+            BlockHandle::Graph(ControlFlowGraphHandle::Function(ref function), _) =>
+                Some(function),
+            _ => None
+        }
+    }
+}
+
+impl Deref for BlockHandle {
+    type Target = falcon::il::Block;
+
+    fn deref(&self) -> &falcon::il::Block {
+        match *self {
+            BlockHandle::Owned(ref block) => &**block,
+            BlockHandle::Graph(ref control_flow_graph, index) =>
+                control_flow_graph.block(index).expect("block handle out of bounds")
+        }
+    }
+}
+
+impl From<falcon::il::Block> for BlockHandle {
+    fn from(block: falcon::il::Block) -> BlockHandle {
+        BlockHandle::Owned(Arc::new(block))
+    }
+}
+
+impl PartialEq for BlockHandle {
+    fn eq(&self, other: &BlockHandle) -> bool {
+        self.deref() == other.deref()
+    }
+}
+
+impl Eq for BlockHandle {}
+
+impl PartialOrd for BlockHandle {
+    fn partial_cmp(&self, other: &BlockHandle) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BlockHandle {
+    fn cmp(&self, other: &BlockHandle) -> Ordering {
+        self.deref().cmp(other.deref())
+    }
+}
+
+impl Hash for BlockHandle {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.deref().hash(state)
+    }
+}
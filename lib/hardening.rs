@@ -0,0 +1,396 @@
+//! Security hardening of a binary, in the manner of `checksec`.
+//!
+//! Properties are read from the headers of the file. Stack canaries and
+//! FORTIFY_SOURCE are detected on ELF from the names of symbols in the
+//! dynamic and static symbol tables and of the loader's function entries, so
+//! a stripped, statically linked binary reports neither.
+//!
+//! An ELF is PIE when it is a shared object which either asks for a program
+//! interpreter or sets `DF_1_PIE`, so a shared library is not reported as
+//! PIE.
+
+use falcon::error::Result;
+use falcon::loader::Loader;
+use std::collections::BTreeSet;
+use std::fmt;
+
+use image;
+
+
+/// How much of the relocations are read-only after loading.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Relro {
+    None,
+    /// Relocations are read-only, but the PLT's GOT is resolved lazily.
+    Partial,
+    Full
+}
+
+impl fmt::Display for Relro {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            Relro::None => "none",
+            Relro::Partial => "partial",
+            Relro::Full => "full"
+        })
+    }
+}
+
+
+/// The hardening of a binary. ELF properties are `None` for PE, and the
+/// other way around.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Hardening {
+    pub format: image::Format,
+    pub pie: Option<bool>,
+    pub nx: Option<bool>,
+    pub relro: Option<Relro>,
+    pub canary: Option<bool>,
+    pub fortify: Option<bool>,
+    /// The fortified functions the binary calls.
+    pub fortified: Vec<String>,
+    pub dep: Option<bool>,
+    pub aslr: Option<bool>,
+    pub high_entropy_va: Option<bool>,
+    pub cfg: Option<bool>,
+    /// `None` for 64-bit images, where exception handlers are always
+    /// table-based.
+    pub safe_seh: Option<bool>,
+    pub gs: Option<bool>
+}
+
+impl Hardening {
+    fn new(format: image::Format) -> Hardening {
+        Hardening {
+            format: format,
+            pie: None,
+            nx: None,
+            relro: None,
+            canary: None,
+            fortify: None,
+            fortified: Vec::new(),
+            dep: None,
+            aslr: None,
+            high_entropy_va: None,
+            cfg: None,
+            safe_seh: None,
+            gs: None
+        }
+    }
+
+    /// The properties which apply to this binary's format, as (name, value).
+    pub fn properties(&self) -> Vec<(&'static str, String)> {
+        let flag = |value: Option<bool>| match value {
+            Some(true) => "yes".to_string(),
+            Some(false) => "no".to_string(),
+            None => "n/a".to_string()
+        };
+        match self.format {
+            image::Format::Elf => vec![
+                ("pie", flag(self.pie)),
+                ("nx", flag(self.nx)),
+                ("relro", self.relro.map(|relro| format!("{}", relro))
+                    .unwrap_or("n/a".to_string())),
+                ("canary", flag(self.canary)),
+                ("fortify", flag(self.fortify))
+            ],
+            image::Format::Pe => vec![
+                ("dep", flag(self.dep)),
+                ("aslr", flag(self.aslr)),
+                ("high-entropy-va", flag(self.high_entropy_va)),
+                ("cfg", flag(self.cfg)),
+                ("safe-seh", flag(self.safe_seh)),
+                ("gs", flag(self.gs))
+            ]
+        }
+    }
+}
+
+impl fmt::Display for Hardening {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in self.properties() {
+            writeln!(f, "{:<16}{}", name, value)?;
+        }
+        if !self.fortified.is_empty() {
+            writeln!(f, "{:<16}{}", "fortified", self.fortified.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+
+const ET_DYN: u64 = 3;
+
+const PT_INTERP: u64 = 3;
+const PT_GNU_STACK: u64 = 0x6474_e551;
+const PT_GNU_RELRO: u64 = 0x6474_e552;
+const PF_X: u64 = 1;
+
+const DT_FLAGS: u64 = 30;
+const DT_FLAGS_1: u64 = 0x6fff_fffb;
+const DT_BIND_NOW: u64 = 24;
+const DF_BIND_NOW: u64 = 0x8;
+const DF_1_NOW: u64 = 0x1;
+const DF_1_PIE: u64 = 0x0800_0000;
+
+
+/// The hardening of the ELF `bytes`, where `entries` names the loader's
+/// function entries.
+fn elf(bytes: &[u8], entries: Vec<String>) -> Result<Hardening> {
+    let elf = image::ElfHeader::parse(bytes)?;
+    let program_headers = elf.program_headers()?;
+    let dynamic = elf.dynamic()?;
+
+    let mut hardening = Hardening::new(image::Format::Elf);
+
+    let interpreter = program_headers.iter().any(|header| header.0 == PT_INTERP);
+    let pie_flag = dynamic.iter().any(|&(tag, value)| tag == DT_FLAGS_1 && value & DF_1_PIE != 0);
+    hardening.pie = Some(elf.kind == ET_DYN && (interpreter || pie_flag));
+
+    // Without a GNU_STACK header the stack is executable.
+    hardening.nx = Some(program_headers.iter()
+        .find(|header| header.0 == PT_GNU_STACK)
+        .map(|header| header.1 & PF_X == 0)
+        .unwrap_or(false));
+
+    let bind_now = dynamic.iter().any(|&(tag, value)| match tag {
+        DT_BIND_NOW => true,
+        DT_FLAGS => value & DF_BIND_NOW != 0,
+        DT_FLAGS_1 => value & DF_1_NOW != 0,
+        _ => false
+    });
+    hardening.relro = Some(
+        if !program_headers.iter().any(|header| header.0 == PT_GNU_RELRO) {
+            Relro::None
+        }
+        else if bind_now {
+            Relro::Full
+        }
+        else {
+            Relro::Partial
+        });
+
+    // Versioned names, such as `__memcpy_chk@GLIBC_2.3.4`, are compared
+    // without their version.
+    let mut symbols: BTreeSet<String> = BTreeSet::new();
+    symbols.extend(elf.symbol_names(".dynsym", ".dynstr")?);
+    symbols.extend(elf.symbol_names(".symtab", ".strtab")?);
+    symbols.extend(entries);
+    let symbols: BTreeSet<String> = symbols.into_iter()
+        .map(|symbol| symbol.split('@').next().unwrap_or("").to_string())
+        .collect();
+
+    hardening.canary = Some(
+        symbols.contains("__stack_chk_fail") || symbols.contains("__stack_chk_guard"));
+
+    hardening.fortified = symbols.iter()
+        .filter(|symbol| symbol.starts_with("__") && symbol.ends_with("_chk"))
+        .filter(|symbol| !symbol.starts_with("__stack_chk"))
+        .cloned()
+        .collect();
+    hardening.fortify = Some(!hardening.fortified.is_empty());
+
+    Ok(hardening)
+}
+
+
+const IMAGE_DLLCHARACTERISTICS_HIGH_ENTROPY_VA: u64 = 0x20;
+const IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE: u64 = 0x40;
+const IMAGE_DLLCHARACTERISTICS_NX_COMPAT: u64 = 0x100;
+const IMAGE_DLLCHARACTERISTICS_NO_SEH: u64 = 0x400;
+const IMAGE_DLLCHARACTERISTICS_GUARD_CF: u64 = 0x4000;
+
+const IMAGE_DIRECTORY_ENTRY_LOAD_CONFIG: u64 = 10;
+
+
+fn pe(bytes: &[u8]) -> Result<Hardening> {
+    let pe = image::PeHeader::parse(bytes)?;
+    let characteristics = pe.dll_characteristics()?;
+    let has = |flag: u64| Some(characteristics & flag != 0);
+
+    let mut hardening = Hardening::new(image::Format::Pe);
+    hardening.dep = has(IMAGE_DLLCHARACTERISTICS_NX_COMPAT);
+    hardening.aslr = has(IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE);
+    hardening.high_entropy_va = if pe.wide {
+        has(IMAGE_DLLCHARACTERISTICS_HIGH_ENTROPY_VA)
+    }
+    else {
+        None
+    };
+    hardening.cfg = has(IMAGE_DLLCHARACTERISTICS_GUARD_CF);
+
+    // The load configuration holds the /GS cookie and the SafeSEH handler
+    // table, at offsets which depend on the image's width. Fields past the
+    // configuration's size are absent.
+    let (cookie, handlers) = if pe.wide { (0x58, 0x60) } else { (0x3c, 0x40) };
+    let load_config = match pe.data_directory(IMAGE_DIRECTORY_ENTRY_LOAD_CONFIG)? {
+        Some((address, _)) => pe.offset_of(address)?,
+        None => None
+    };
+    let field = |field: u64| -> Result<u64> {
+        let offset = match load_config {
+            Some(offset) => offset,
+            None => return Ok(0)
+        };
+        if field + if pe.wide { 8 } else { 4 } > pe.reader.u32(offset)? {
+            return Ok(0);
+        }
+        pe.reader.word(offset + field, pe.wide)
+    };
+
+    hardening.gs = Some(field(cookie)? != 0);
+    hardening.safe_seh = if pe.wide {
+        None
+    }
+    else if characteristics & IMAGE_DLLCHARACTERISTICS_NO_SEH != 0 {
+        Some(true)
+    }
+    else {
+        Some(field(handlers)? != 0)
+    };
+
+    Ok(hardening)
+}
+
+
+/// The hardening of `bytes`, the file `loader` loaded.
+pub fn hardening(loader: &Loader, bytes: &[u8]) -> Result<Hardening> {
+    match image::format(bytes) {
+        Some(image::Format::Elf) => {
+            let entries = loader.function_entries()?
+                .iter()
+                .filter_map(|entry| entry.name().map(|name| name.to_string()))
+                .collect();
+            elf(bytes, entries)
+        },
+        Some(image::Format::Pe) => pe(bytes),
+        None => Err("unrecognized binary format".into())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ET_EXEC: u64 = 2;
+    const PT_DYNAMIC: u64 = 2;
+
+    fn put(bytes: &mut Vec<u8>, offset: usize, value: u64, size: usize) {
+        if bytes.len() < offset + size {
+            bytes.resize(offset + size, 0);
+        }
+        for i in 0..size {
+            bytes[offset + i] = (value >> (8 * i)) as u8;
+        }
+    }
+
+    /// A little-endian 64-bit ELF of type `kind`, with program headers of
+    /// (type, flags) and a dynamic section holding `dynamic`.
+    fn elf_bytes(kind: u64, headers: &[(u64, u64)], dynamic: &[(u64, u64)]) -> Vec<u8> {
+        let mut bytes = b"\x7fELF\x02\x01".to_vec();
+        put(&mut bytes, 0x10, kind, 2);
+        put(&mut bytes, 0x20, 0x40, 8);
+        put(&mut bytes, 0x36, 0x38, 2);
+        put(&mut bytes, 0x38, headers.len() as u64, 2);
+
+        let dynamic_offset = 0x40 + headers.len() * 0x38;
+        for (i, &(kind, flags)) in headers.iter().enumerate() {
+            let h = 0x40 + i * 0x38;
+            put(&mut bytes, h, kind, 4);
+            put(&mut bytes, h + 4, flags, 4);
+            if kind == PT_DYNAMIC {
+                put(&mut bytes, h + 8, dynamic_offset as u64, 8);
+                put(&mut bytes, h + 0x20, (dynamic.len() as u64 + 1) * 16, 8);
+            }
+        }
+        for (i, &(tag, value)) in dynamic.iter().enumerate() {
+            put(&mut bytes, dynamic_offset + i * 16, tag, 8);
+            put(&mut bytes, dynamic_offset + i * 16 + 8, value, 8);
+        }
+        put(&mut bytes, dynamic_offset + dynamic.len() * 16, 0, 16);
+        bytes
+    }
+
+    #[test]
+    fn elf_pie_executable() {
+        let bytes = elf_bytes(ET_DYN,
+            &[(PT_INTERP, 4), (PT_GNU_STACK, 6), (PT_GNU_RELRO, 4), (PT_DYNAMIC, 6)],
+            &[(DT_FLAGS_1, DF_1_NOW | DF_1_PIE)]);
+        let entries = vec!["__stack_chk_fail".to_string(),
+                           "__memcpy_chk@GLIBC_2.3.4".to_string()];
+        let hardening = elf(&bytes, entries).unwrap();
+        assert_eq!(hardening.pie, Some(true));
+        assert_eq!(hardening.nx, Some(true));
+        assert_eq!(hardening.relro, Some(Relro::Full));
+        assert_eq!(hardening.canary, Some(true));
+        assert_eq!(hardening.fortify, Some(true));
+        assert_eq!(hardening.fortified, vec!["__memcpy_chk".to_string()]);
+        assert_eq!(hardening.dep, None);
+    }
+
+    #[test]
+    fn elf_static_pie_sets_flag() {
+        let bytes = elf_bytes(ET_DYN, &[(PT_DYNAMIC, 6)], &[(DT_FLAGS_1, DF_1_PIE)]);
+        assert_eq!(elf(&bytes, Vec::new()).unwrap().pie, Some(true));
+    }
+
+    #[test]
+    fn elf_shared_object_is_not_pie() {
+        let bytes = elf_bytes(ET_DYN, &[(PT_DYNAMIC, 6)], &[(DT_FLAGS, DF_BIND_NOW)]);
+        let hardening = elf(&bytes, Vec::new()).unwrap();
+        assert_eq!(hardening.pie, Some(false));
+        assert_eq!(hardening.nx, Some(false));
+        assert_eq!(hardening.relro, Some(Relro::None));
+        assert_eq!(hardening.canary, Some(false));
+        assert_eq!(hardening.fortify, Some(false));
+    }
+
+    #[test]
+    fn elf_executable() {
+        let bytes = elf_bytes(ET_EXEC,
+            &[(PT_INTERP, 4), (PT_GNU_STACK, 7), (PT_GNU_RELRO, 4), (PT_DYNAMIC, 6)],
+            &[]);
+        let hardening = elf(&bytes, Vec::new()).unwrap();
+        assert_eq!(hardening.pie, Some(false));
+        assert_eq!(hardening.nx, Some(false));
+        assert_eq!(hardening.relro, Some(Relro::Partial));
+    }
+
+    #[test]
+    fn elf_truncated() {
+        let mut bytes = elf_bytes(ET_DYN, &[(PT_DYNAMIC, 6)], &[]);
+        bytes.truncate(0x50);
+        assert!(elf(&bytes, Vec::new()).is_err());
+    }
+
+    /// A 32-bit PE with `characteristics` and no load configuration.
+    fn pe_bytes(characteristics: u64) -> Vec<u8> {
+        let mut bytes = b"MZ".to_vec();
+        put(&mut bytes, 0x3c, 0x40, 4);
+        put(&mut bytes, 0x40, 0x4550, 4);
+        put(&mut bytes, 0x54, 0xe0, 2);
+        put(&mut bytes, 0x58, 0x10b, 2);
+        put(&mut bytes, 0x58 + 0x46, characteristics, 2);
+        put(&mut bytes, 0x58 + 0x5c, 0, 4);
+        bytes
+    }
+
+    #[test]
+    fn pe_characteristics() {
+        let bytes = pe_bytes(
+            IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE | IMAGE_DLLCHARACTERISTICS_NX_COMPAT);
+        let hardening = pe(&bytes).unwrap();
+        assert_eq!(hardening.dep, Some(true));
+        assert_eq!(hardening.aslr, Some(true));
+        assert_eq!(hardening.high_entropy_va, None);
+        assert_eq!(hardening.cfg, Some(false));
+        assert_eq!(hardening.gs, Some(false));
+        assert_eq!(hardening.safe_seh, Some(false));
+        assert_eq!(hardening.pie, None);
+
+        let hardening = pe(&pe_bytes(IMAGE_DLLCHARACTERISTICS_NO_SEH)).unwrap();
+        assert_eq!(hardening.dep, Some(false));
+        assert_eq!(hardening.safe_seh, Some(true));
+    }
+}
@@ -1,7 +1,17 @@
 use falcon;
+use falcon::architecture::{Architecture, ReturnAddressType};
 use gluon::vm::api::{Userdata, VmType};
 use gluon::vm::thread::{Traverseable};
 use gluon;
+use std::sync::Arc;
+
+use architecture;
+use dwarf;
+use handle::{BlockHandle, ControlFlowGraphHandle, FunctionHandle};
+use memory;
+use stack_frame;
+use strings;
+use xref;
 
 
 
@@ -27,9 +37,7 @@ fn constant_str(constant: &IlConstant) -> String {
     format!("{}", constant.x)
 }
 
-fn constant_eq(lhs: &IlConstant, rhs: &IlConstant) -> bool {
-    lhs.x == rhs.x
-}
+falcon_type_ord!(IlConstant, constant_eq, constant_compare, constant_hash);
 
 
 falcon_type_wrapper!(falcon::il::Scalar, IlScalar);
@@ -54,9 +62,7 @@ fn scalar_str(scalar: &IlScalar) -> String {
     format!("{}", scalar.x)
 }
 
-fn scalar_eq(lhs: &IlScalar, rhs: &IlScalar) -> bool {
-    lhs.x == rhs.x
-}
+falcon_type_ord!(IlScalar, scalar_eq, scalar_compare, scalar_hash);
 
 
 falcon_type_wrapper!(falcon::il::Expression, IlExpression);
@@ -125,11 +131,11 @@ fn expression_zext(bits: usize, expr: &IlExpression) -> IlExpression {
 }
 
 fn expression_sext(bits: usize, expr: &IlExpression) -> IlExpression {
-    IlExpression { x: falcon::il::Expression::zext(bits, expr.x.clone()).unwrap() }
+    IlExpression { x: falcon::il::Expression::sext(bits, expr.x.clone()).unwrap() }
 }
 
 fn expression_trun(bits: usize, expr: &IlExpression) -> IlExpression {
-    IlExpression { x: falcon::il::Expression::zext(bits, expr.x.clone()).unwrap() }
+    IlExpression { x: falcon::il::Expression::trun(bits, expr.x.clone()).unwrap() }
 }
 
 fn expression_ite(cond: &IlExpression, then: &IlExpression, else_: &IlExpression)
@@ -272,6 +278,311 @@ fn expression_str(expr: &IlExpression) -> String {
     format!("{}", expr.x)
 }
 
+falcon_type_ord!(IlExpression, expression_eq, expression_compare, expression_hash);
+
+fn expression_bits(expr: &IlExpression) -> usize {
+    expr.x.bits()
+}
+
+fn expression_scalars(expr: &IlExpression) -> Vec<IlScalar> {
+    let mut scalars: Vec<IlScalar> = Vec::new();
+    for scalar in expr.x.scalars() {
+        if !scalars.iter().any(|s| s.x == *scalar) {
+            scalars.push(IlScalar { x: scalar.clone() });
+        }
+    }
+    scalars
+}
+
+fn expression_substitute(
+    expr: &IlExpression,
+    scalar: &IlScalar,
+    replacement: &IlExpression
+) -> IlExpression {
+    IlExpression { x: substitute(&expr.x, &scalar.x, &replacement.x) }
+}
+
+fn expression_simplify(expr: &IlExpression) -> IlExpression {
+    IlExpression { x: simplify(&expr.x) }
+}
+
+
+/// Replace every read of `scalar` in `expression` with `replacement`.
+pub fn substitute(
+    expression: &falcon::il::Expression,
+    scalar: &falcon::il::Scalar,
+    replacement: &falcon::il::Expression
+) -> falcon::il::Expression {
+    replace_scalars(expression, &|s| Some(
+        if s == scalar {
+            replacement.clone()
+        }
+        else {
+            falcon::il::Expression::Scalar(s.clone())
+        }
+    )).unwrap()
+}
+
+
+/// Replace every scalar in `expression` with `f(scalar)` simultaneously, so
+/// replacements are never themselves rewritten. Fails if `f` fails for any
+/// scalar.
+pub fn replace_scalars<F>(expression: &falcon::il::Expression, f: &F)
+    -> Option<falcon::il::Expression>
+    where F: Fn(&falcon::il::Scalar) -> Option<falcon::il::Expression> {
+
+    use falcon::il::Expression;
+
+    let sub = |e: &Expression| replace_scalars(e, f).map(Box::new);
+
+    Some(match *expression {
+        Expression::Scalar(ref s) => f(s)?,
+        Expression::Constant(_) => expression.clone(),
+        Expression::Add(ref l, ref r) => Expression::Add(sub(l)?, sub(r)?),
+        Expression::Sub(ref l, ref r) => Expression::Sub(sub(l)?, sub(r)?),
+        Expression::Mul(ref l, ref r) => Expression::Mul(sub(l)?, sub(r)?),
+        Expression::Divu(ref l, ref r) => Expression::Divu(sub(l)?, sub(r)?),
+        Expression::Modu(ref l, ref r) => Expression::Modu(sub(l)?, sub(r)?),
+        Expression::Divs(ref l, ref r) => Expression::Divs(sub(l)?, sub(r)?),
+        Expression::Mods(ref l, ref r) => Expression::Mods(sub(l)?, sub(r)?),
+        Expression::And(ref l, ref r) => Expression::And(sub(l)?, sub(r)?),
+        Expression::Or(ref l, ref r) => Expression::Or(sub(l)?, sub(r)?),
+        Expression::Xor(ref l, ref r) => Expression::Xor(sub(l)?, sub(r)?),
+        Expression::Shl(ref l, ref r) => Expression::Shl(sub(l)?, sub(r)?),
+        Expression::Shr(ref l, ref r) => Expression::Shr(sub(l)?, sub(r)?),
+        Expression::Cmpeq(ref l, ref r) => Expression::Cmpeq(sub(l)?, sub(r)?),
+        Expression::Cmpneq(ref l, ref r) => Expression::Cmpneq(sub(l)?, sub(r)?),
+        Expression::Cmplts(ref l, ref r) => Expression::Cmplts(sub(l)?, sub(r)?),
+        Expression::Cmpltu(ref l, ref r) => Expression::Cmpltu(sub(l)?, sub(r)?),
+        Expression::Zext(bits, ref e) => Expression::Zext(bits, sub(e)?),
+        Expression::Sext(bits, ref e) => Expression::Sext(bits, sub(e)?),
+        Expression::Trun(bits, ref e) => Expression::Trun(bits, sub(e)?),
+        Expression::Ite(ref c, ref t, ref e) =>
+            Expression::Ite(sub(c)?, sub(t)?, sub(e)?)
+    })
+}
+
+
+/// The value of a constant, sign-extended from its width.
+pub fn constant_signed(constant: &falcon::il::Constant) -> Option<i64> {
+    let value = constant.value_u64()?;
+    let bits = constant.bits();
+    if bits >= 64 {
+        return Some(value as i64);
+    }
+    let shift = 64 - bits;
+    Some(((value << shift) as i64) >> shift)
+}
+
+
+/// Split `expression` into a non-constant base and a constant offset,
+/// folding nested additions and subtractions of constants, so `(rsp - 0x20)
+/// + 0x8` becomes `(Some(rsp), -0x18)`. A constant has no base.
+pub fn base_offset(expression: &falcon::il::Expression)
+    -> (Option<falcon::il::Expression>, i64) {
+
+    use falcon::il::Expression;
+
+    match *expression {
+        Expression::Constant(ref c) => match constant_signed(c) {
+            Some(value) => (None, value),
+            None => (Some(expression.clone()), 0)
+        },
+        Expression::Add(ref l, ref r) => {
+            let (lbase, loffset) = base_offset(l);
+            let (rbase, roffset) = base_offset(r);
+            match (lbase, rbase) {
+                (base, None) | (None, base) =>
+                    (base, loffset.wrapping_add(roffset)),
+                _ => (Some(expression.clone()), 0)
+            }
+        },
+        Expression::Sub(ref l, ref r) => match base_offset(r) {
+            (None, roffset) => {
+                let (base, loffset) = base_offset(l);
+                (base, loffset.wrapping_sub(roffset))
+            },
+            _ => (Some(expression.clone()), 0)
+        },
+        _ => (Some(expression.clone()), 0)
+    }
+}
+
+
+fn constant_is(expression: &falcon::il::Expression, value: u64) -> bool {
+    match *expression {
+        falcon::il::Expression::Constant(ref c) => c.value_u64() == Some(value),
+        _ => false
+    }
+}
+
+fn constant_is_ones(expression: &falcon::il::Expression) -> bool {
+    let bits = expression.bits();
+    if bits > 64 {
+        return false;
+    }
+    let mask = if bits == 64 { 0xffff_ffff_ffff_ffff } else { (1 << bits) - 1 };
+    constant_is(expression, mask)
+}
+
+fn is_constant(expression: &falcon::il::Expression) -> bool {
+    match *expression {
+        falcon::il::Expression::Constant(_) => true,
+        _ => false
+    }
+}
+
+fn constant_expression(value: u64, bits: usize) -> falcon::il::Expression {
+    falcon::il::Expression::Constant(falcon::il::Constant::new(value, bits))
+}
+
+
+/// Algebraically simplify an expression.
+///
+/// Simplification is bottom-up, and performs constant folding, identity
+/// elimination, collapsing of nested `zext`/`trun` operations, and
+/// normalization of commutative operations and comparisons so constants
+/// appear on the right-hand side.
+pub fn simplify(expression: &falcon::il::Expression) -> falcon::il::Expression {
+    use falcon::il::Expression;
+
+    let expression = match *expression {
+        Expression::Scalar(_) |
+        Expression::Constant(_) => return expression.clone(),
+        Expression::Add(ref l, ref r) =>
+            Expression::Add(Box::new(simplify(l)), Box::new(simplify(r))),
+        Expression::Sub(ref l, ref r) =>
+            Expression::Sub(Box::new(simplify(l)), Box::new(simplify(r))),
+        Expression::Mul(ref l, ref r) =>
+            Expression::Mul(Box::new(simplify(l)), Box::new(simplify(r))),
+        Expression::Divu(ref l, ref r) =>
+            Expression::Divu(Box::new(simplify(l)), Box::new(simplify(r))),
+        Expression::Modu(ref l, ref r) =>
+            Expression::Modu(Box::new(simplify(l)), Box::new(simplify(r))),
+        Expression::Divs(ref l, ref r) =>
+            Expression::Divs(Box::new(simplify(l)), Box::new(simplify(r))),
+        Expression::Mods(ref l, ref r) =>
+            Expression::Mods(Box::new(simplify(l)), Box::new(simplify(r))),
+        Expression::And(ref l, ref r) =>
+            Expression::And(Box::new(simplify(l)), Box::new(simplify(r))),
+        Expression::Or(ref l, ref r) =>
+            Expression::Or(Box::new(simplify(l)), Box::new(simplify(r))),
+        Expression::Xor(ref l, ref r) =>
+            Expression::Xor(Box::new(simplify(l)), Box::new(simplify(r))),
+        Expression::Shl(ref l, ref r) =>
+            Expression::Shl(Box::new(simplify(l)), Box::new(simplify(r))),
+        Expression::Shr(ref l, ref r) =>
+            Expression::Shr(Box::new(simplify(l)), Box::new(simplify(r))),
+        Expression::Cmpeq(ref l, ref r) =>
+            Expression::Cmpeq(Box::new(simplify(l)), Box::new(simplify(r))),
+        Expression::Cmpneq(ref l, ref r) =>
+            Expression::Cmpneq(Box::new(simplify(l)), Box::new(simplify(r))),
+        Expression::Cmplts(ref l, ref r) =>
+            Expression::Cmplts(Box::new(simplify(l)), Box::new(simplify(r))),
+        Expression::Cmpltu(ref l, ref r) =>
+            Expression::Cmpltu(Box::new(simplify(l)), Box::new(simplify(r))),
+        Expression::Zext(bits, ref e) => Expression::Zext(bits, Box::new(simplify(e))),
+        Expression::Sext(bits, ref e) => Expression::Sext(bits, Box::new(simplify(e))),
+        Expression::Trun(bits, ref e) => Expression::Trun(bits, Box::new(simplify(e))),
+        Expression::Ite(ref c, ref t, ref e) =>
+            Expression::Ite(
+                Box::new(simplify(c)),
+                Box::new(simplify(t)),
+                Box::new(simplify(e)))
+    };
+
+    if expression.scalars().is_empty() {
+        if let Ok(constant) = falcon::executor::eval(&expression) {
+            return Expression::Constant(constant);
+        }
+    }
+
+    let bits = expression.bits();
+
+    match expression {
+        // Move constants to the right of commutative operations
+        Expression::Add(l, r) =>
+            if is_constant(&l) && !is_constant(&r) { simplify(&Expression::Add(r, l)) }
+            else if constant_is(&r, 0) { *l }
+            else { Expression::Add(l, r) },
+        Expression::Mul(l, r) =>
+            if is_constant(&l) && !is_constant(&r) { simplify(&Expression::Mul(r, l)) }
+            else if constant_is(&r, 1) { *l }
+            else if constant_is(&r, 0) { constant_expression(0, bits) }
+            else { Expression::Mul(l, r) },
+        Expression::And(l, r) =>
+            if is_constant(&l) && !is_constant(&r) { simplify(&Expression::And(r, l)) }
+            else if constant_is(&r, 0) { constant_expression(0, bits) }
+            else if constant_is_ones(&r) || l == r { *l }
+            else { Expression::And(l, r) },
+        Expression::Or(l, r) =>
+            if is_constant(&l) && !is_constant(&r) { simplify(&Expression::Or(r, l)) }
+            else if constant_is(&r, 0) || l == r { *l }
+            else { Expression::Or(l, r) },
+        Expression::Xor(l, r) =>
+            if is_constant(&l) && !is_constant(&r) { simplify(&Expression::Xor(r, l)) }
+            else if constant_is(&r, 0) { *l }
+            else if l == r { constant_expression(0, bits) }
+            else { Expression::Xor(l, r) },
+        Expression::Sub(l, r) =>
+            if constant_is(&r, 0) { *l }
+            else if l == r { constant_expression(0, bits) }
+            else { Expression::Sub(l, r) },
+        Expression::Divu(l, r) =>
+            if constant_is(&r, 1) { *l } else { Expression::Divu(l, r) },
+        Expression::Divs(l, r) =>
+            if constant_is(&r, 1) { *l } else { Expression::Divs(l, r) },
+        Expression::Shl(l, r) =>
+            if constant_is(&r, 0) { *l } else { Expression::Shl(l, r) },
+        Expression::Shr(l, r) =>
+            if constant_is(&r, 0) { *l } else { Expression::Shr(l, r) },
+        Expression::Cmpeq(l, r) =>
+            if l == r { constant_expression(1, 1) }
+            else if is_constant(&l) && !is_constant(&r) { Expression::Cmpeq(r, l) }
+            else { Expression::Cmpeq(l, r) },
+        Expression::Cmpneq(l, r) =>
+            if l == r { constant_expression(0, 1) }
+            else if is_constant(&l) && !is_constant(&r) { Expression::Cmpneq(r, l) }
+            else { Expression::Cmpneq(l, r) },
+        Expression::Cmpltu(l, r) =>
+            if l == r { constant_expression(0, 1) }
+            else { Expression::Cmpltu(l, r) },
+        Expression::Cmplts(l, r) =>
+            if l == r { constant_expression(0, 1) }
+            else { Expression::Cmplts(l, r) },
+        Expression::Zext(bits, e) =>
+            if e.bits() == bits { *e }
+            else {
+                match *e {
+                    Expression::Zext(_, inner) => Expression::Zext(bits, inner),
+                    e => Expression::Zext(bits, Box::new(e))
+                }
+            },
+        Expression::Sext(bits, e) =>
+            if e.bits() == bits { *e } else { Expression::Sext(bits, e) },
+        Expression::Trun(bits, e) =>
+            if e.bits() == bits { *e }
+            else {
+                match *e {
+                    Expression::Trun(_, inner) => Expression::Trun(bits, inner),
+                    Expression::Zext(_, ref inner) |
+                    Expression::Sext(_, ref inner) if inner.bits() == bits =>
+                        (**inner).clone(),
+                    Expression::Zext(_, inner) =>
+                        if inner.bits() > bits { Expression::Trun(bits, inner) }
+                        else { Expression::Zext(bits, inner) },
+                    e => Expression::Trun(bits, Box::new(e))
+                }
+            },
+        Expression::Ite(c, t, e) =>
+            if constant_is(&c, 0) { *e }
+            else if is_constant(&c) { *t }
+            else if t == e { *t }
+            else { Expression::Ite(c, t, e) },
+        expression => expression
+    }
+}
+
 
 falcon_type_wrapper!(falcon::il::Intrinsic, IlIntrinsic);
 
@@ -283,6 +594,12 @@ fn intrinsic_instruction_str(intrinsic: &IlIntrinsic) -> String {
     intrinsic.x.instruction_str().to_string()
 }
 
+fn intrinsic_str(intrinsic: &IlIntrinsic) -> String {
+    format!("{}", intrinsic.x)
+}
+
+falcon_type_ord!(IlIntrinsic, intrinsic_eq, intrinsic_compare, intrinsic_hash);
+
 
 falcon_type_wrapper!(falcon::il::Operation, IlOperation);
 
@@ -379,6 +696,195 @@ fn operation_str(operation: &IlOperation) -> String {
     format!("{}", operation.x)
 }
 
+falcon_type_ord!(IlOperation, operation_eq, operation_compare, operation_hash);
+
+/// Scalars read by an operation. Intrinsics are opaque and read nothing we
+/// can name.
+pub fn scalars_read(operation: &falcon::il::Operation) -> Vec<&falcon::il::Scalar> {
+    let scalars = match *operation {
+        falcon::il::Operation::Assign { ref src, .. } => src.scalars(),
+        falcon::il::Operation::Store { ref index, ref src } => {
+            let mut scalars = index.scalars();
+            scalars.append(&mut src.scalars());
+            scalars
+        },
+        falcon::il::Operation::Load { ref index, .. } => index.scalars(),
+        falcon::il::Operation::Branch { ref target } => target.scalars(),
+        falcon::il::Operation::Intrinsic { .. } |
+        falcon::il::Operation::Nop => Vec::new()
+    };
+    let mut unique: Vec<&falcon::il::Scalar> = Vec::new();
+    for scalar in scalars {
+        if !unique.contains(&scalar) {
+            unique.push(scalar);
+        }
+    }
+    unique
+}
+
+/// Scalars written by an operation.
+pub fn scalars_written(operation: &falcon::il::Operation) -> Vec<&falcon::il::Scalar> {
+    match *operation {
+        falcon::il::Operation::Assign { ref dst, .. } |
+        falcon::il::Operation::Load { ref dst, .. } => vec![dst],
+        falcon::il::Operation::Store { .. } |
+        falcon::il::Operation::Branch { .. } |
+        falcon::il::Operation::Intrinsic { .. } |
+        falcon::il::Operation::Nop => Vec::new()
+    }
+}
+
+/// Whether an operation may read memory. Intrinsics are assumed to.
+pub fn reads_memory(operation: &falcon::il::Operation) -> bool {
+    match *operation {
+        falcon::il::Operation::Load { .. } |
+        falcon::il::Operation::Intrinsic { .. } => true,
+        _ => false
+    }
+}
+
+/// Whether an operation may write memory. Intrinsics are assumed to.
+pub fn writes_memory(operation: &falcon::il::Operation) -> bool {
+    match *operation {
+        falcon::il::Operation::Store { .. } |
+        falcon::il::Operation::Intrinsic { .. } => true,
+        _ => false
+    }
+}
+
+/// Whether `target` is the return address register.
+fn is_return_register(architecture: &Architecture, target: &falcon::il::Expression) -> bool {
+    match (architecture.calling_convention().return_address_type(), target) {
+        (&ReturnAddressType::Register(ref register), &falcon::il::Expression::Scalar(ref scalar)) =>
+            register == scalar,
+        _ => false
+    }
+}
+
+/// Whether a branch to `target` returns to the caller: `target` is the
+/// return address register, or was loaded from the stack pointer earlier in
+/// the block, before `position`.
+fn is_return_target(
+    architecture: &Architecture,
+    block: &falcon::il::Block,
+    position: usize,
+    target: &falcon::il::Expression
+) -> bool {
+    if is_return_register(architecture, target) {
+        return true;
+    }
+
+    let target = match *target {
+        falcon::il::Expression::Scalar(ref scalar) => scalar,
+        _ => return false
+    };
+
+    let stack_pointer = architecture.stack_pointer();
+    for instruction in block.instructions()[..position].iter().rev() {
+        match *instruction.operation() {
+            falcon::il::Operation::Load { ref dst, ref index } if dst == target =>
+                return index.scalars() == vec![&stack_pointer],
+            ref operation => if scalars_written(operation).contains(&target) {
+                return false;
+            }
+        }
+    }
+    false
+}
+
+/// Whether the branch at `instruction_index` in `block_index` returns control
+/// to the function.
+///
+/// Falcon lifts direct and conditional jumps into edges, so the `Branch`
+/// operations that remain are calls, returns and indirect jumps. A branch
+/// control comes back from, either because instructions follow it or because
+/// its block has successors, is a call. A branch to the return address, in
+/// its register or just loaded from the stack, leaves the function. Any other
+/// branch, such as an indirect jump, is neither, and gives `None`.
+pub fn branch_returns(
+    architecture: &Architecture,
+    function: &falcon::il::Function,
+    block_index: usize,
+    instruction_index: usize
+) -> Option<bool> {
+    let block = function.block(block_index).ok()?;
+    let position = block.instructions()
+        .iter()
+        .position(|i| i.index() == instruction_index)?;
+
+    let target = match *block.instructions()[position].operation() {
+        falcon::il::Operation::Branch { ref target } => target,
+        _ => return None
+    };
+
+    if position + 1 < block.instructions().len() {
+        return Some(true);
+    }
+
+    let successors = function.control_flow_graph()
+        .edges_out(block_index)
+        .map(|edges| edges.len())
+        .unwrap_or(0);
+
+    if successors > 0 {
+        Some(true)
+    }
+    else if is_return_target(architecture, block, position, target) {
+        Some(false)
+    }
+    else {
+        None
+    }
+}
+
+fn operation_scalars_read(operation: &IlOperation) -> Vec<IlScalar> {
+    scalars_read(&operation.x)
+        .into_iter()
+        .map(|s| IlScalar { x: s.clone() })
+        .collect()
+}
+
+fn operation_scalars_written(operation: &IlOperation) -> Vec<IlScalar> {
+    scalars_written(&operation.x)
+        .into_iter()
+        .map(|s| IlScalar { x: s.clone() })
+        .collect()
+}
+
+fn operation_reads_memory(operation: &IlOperation) -> bool {
+    reads_memory(&operation.x)
+}
+
+fn operation_writes_memory(operation: &IlOperation) -> bool {
+    writes_memory(&operation.x)
+}
+
+/// Whether an operation is a branch which calls. Without its block this
+/// cannot tell a call from an indirect jump, or from a return through the
+/// stack, so prefer `instruction_is_call`.
+fn operation_is_call(
+    operation: &IlOperation,
+    architecture: &architecture::ArchitectureArchitecture
+) -> bool {
+    match operation.x {
+        falcon::il::Operation::Branch { .. } => !operation_is_return(operation, architecture),
+        _ => false
+    }
+}
+
+/// Whether an operation is a branch to the return address register. Returns
+/// through the stack need the instruction's block, with `instruction_is_return`.
+fn operation_is_return(
+    operation: &IlOperation,
+    architecture: &architecture::ArchitectureArchitecture
+) -> bool {
+    match operation.x {
+        falcon::il::Operation::Branch { ref target } =>
+            is_return_register(&**architecture.x, target),
+        _ => false
+    }
+}
+
 
 falcon_type_wrapper!(falcon::il::Instruction, IlInstruction);
 
@@ -402,8 +908,52 @@ fn instruction_str(instruction: &IlInstruction) -> String {
     format!("{}", instruction.x)
 }
 
+falcon_type_ord!(IlInstruction, instruction_eq, instruction_compare, instruction_hash);
+
+fn instruction_scalars_read(instruction: &IlInstruction) -> Vec<IlScalar> {
+    scalars_read(instruction.x.operation())
+        .into_iter()
+        .map(|s| IlScalar { x: s.clone() })
+        .collect()
+}
+
+fn instruction_scalars_written(instruction: &IlInstruction) -> Vec<IlScalar> {
+    scalars_written(instruction.x.operation())
+        .into_iter()
+        .map(|s| IlScalar { x: s.clone() })
+        .collect()
+}
+
+fn instruction_reads_memory(instruction: &IlInstruction) -> bool {
+    reads_memory(instruction.x.operation())
+}
+
+fn instruction_writes_memory(instruction: &IlInstruction) -> bool {
+    writes_memory(instruction.x.operation())
+}
+
+fn instruction_is_call(
+    instruction: &IlInstruction,
+    block: &IlBlock,
+    function: &IlFunction,
+    architecture: &architecture::ArchitectureArchitecture
+) -> bool {
+    branch_returns(&**architecture.x, &function.x, block.x.index(), instruction.x.index())
+        == Some(true)
+}
+
+fn instruction_is_return(
+    instruction: &IlInstruction,
+    block: &IlBlock,
+    function: &IlFunction,
+    architecture: &architecture::ArchitectureArchitecture
+) -> bool {
+    branch_returns(&**architecture.x, &function.x, block.x.index(), instruction.x.index())
+        == Some(false)
+}
+
 
-falcon_type_wrapper!(falcon::il::Block, IlBlock);
+falcon_type_wrapper!(BlockHandle, IlBlock);
 
 fn block_index(block: &IlBlock) -> usize {
     block.x.index()
@@ -418,33 +968,35 @@ fn block_instructions(block: &IlBlock) -> Vec<IlInstruction> {
 }
 
 fn block_assign(block: &IlBlock, dst: &IlScalar, src: &IlExpression) -> IlBlock {
-    let mut block = block.clone();
-    block.x.assign(dst.x.clone(), src.x.clone());
-    block
+    let mut block = (*block.x).clone();
+    block.assign(dst.x.clone(), src.x.clone());
+    IlBlock { x: block.into() }
 }
 
 fn block_store(block: &IlBlock, index: &IlExpression, src: &IlExpression) -> IlBlock {
-    let mut block = block.clone();
-    block.x.store(index.x.clone(), src.x.clone());
-    block
+    let mut block = (*block.x).clone();
+    block.store(index.x.clone(), src.x.clone());
+    IlBlock { x: block.into() }
 }
 
 fn block_load(block: &IlBlock, dst: &IlScalar, index: &IlExpression) -> IlBlock {
-    let mut block = block.clone();
-    block.x.load(dst.x.clone(), index.x.clone());
-    block
+    let mut block = (*block.x).clone();
+    block.load(dst.x.clone(), index.x.clone());
+    IlBlock { x: block.into() }
 }
 
 fn block_branch(block: &IlBlock, target: &IlExpression) -> IlBlock {
-    let mut block = block.clone();
-    block.x.branch(target.x.clone());
-    block
+    let mut block = (*block.x).clone();
+    block.branch(target.x.clone());
+    IlBlock { x: block.into() }
 }
 
 fn block_str(block: &IlBlock) -> String {
-    format!("{}", block.x)
+    format!("{}", *block.x)
 }
 
+falcon_type_ord!(IlBlock, block_eq, block_compare, block_hash);
+
 
 falcon_type_wrapper!(falcon::il::Edge, IlEdge);
 
@@ -473,16 +1025,18 @@ fn edge_str(edge: &IlEdge) -> String {
     format!("{}", edge.x)
 }
 
+falcon_type_ord!(IlEdge, edge_eq, edge_compare, edge_hash);
+
+
+falcon_type_wrapper!(ControlFlowGraphHandle, IlControlFlowGraph);
 
-falcon_type_wrapper!(falcon::il::ControlFlowGraph, IlControlFlowGraph);
-    
 fn control_flow_graph_blocks(control_flow_graph: &IlControlFlowGraph)
     -> Vec<IlBlock> {
 
     control_flow_graph.x
-                      .blocks()
-                      .iter()
-                      .map(|b| IlBlock { x: (*b).clone() })
+                      .block_handles()
+                      .into_iter()
+                      .map(|b| IlBlock { x: b })
                       .collect::<Vec<IlBlock>>()
 }
 
@@ -501,15 +1055,33 @@ fn control_flow_graph_dot_graph(control_flow_graph: &IlControlFlowGraph) -> Stri
 }
 
 fn control_flow_graph_str(control_flow_graph: &IlControlFlowGraph) -> String {
-    format!("{}", control_flow_graph.x)
+    format!("{}", *control_flow_graph.x)
+}
+
+type ControlFlowGraphKey<'a> =
+    (Option<usize>, Vec<&'a falcon::il::Block>, Vec<&'a falcon::il::Edge>);
+
+/// Graphs compare by their entry, blocks and edges.
+fn control_flow_graph_key(control_flow_graph: &falcon::il::ControlFlowGraph)
+    -> ControlFlowGraphKey {
+
+    (control_flow_graph.entry(), control_flow_graph.blocks(), control_flow_graph.edges())
 }
 
+falcon_type_ord!(
+    IlControlFlowGraph,
+    control_flow_graph_eq,
+    control_flow_graph_compare,
+    control_flow_graph_hash,
+    control_flow_graph_key
+);
+
 
-falcon_type_wrapper!(falcon::il::Function, IlFunction);
+falcon_type_wrapper!(FunctionHandle, IlFunction);
 
 fn function_control_flow_graph(function: &IlFunction) -> IlControlFlowGraph {
     IlControlFlowGraph {
-        x: function.x.control_flow_graph().clone()
+        x: ControlFlowGraphHandle::Function(function.x.clone())
     }
 }
 
@@ -526,56 +1098,444 @@ fn function_address(function: &IlFunction) -> u64 {
 }
 
 fn function_blocks(function: &IlFunction) -> Vec<IlBlock> {
-    function.x.blocks().iter().map(|b| IlBlock { x: (*b).clone() }).collect()
+    ControlFlowGraphHandle::Function(function.x.clone())
+        .block_handles()
+        .into_iter()
+        .map(|b| IlBlock { x: b })
+        .collect()
 }
 
 fn function_block(function: &IlFunction, index: usize) -> Option<IlBlock> {
-    match function.x.block(index).ok() {
-        Some(block) => Some(IlBlock { x: block.clone() }),
-        None => None
-    }
+    ControlFlowGraphHandle::Function(function.x.clone())
+        .block_handle(index)
+        .map(|b| IlBlock { x: b })
+}
+
+fn function_str(function: &IlFunction) -> String {
+    format!("{}@0x{:x}", function.x.name(), function.x.address())
+}
+
+type FunctionKey<'a> = (Option<usize>, u64, String, ControlFlowGraphKey<'a>);
+
+/// Functions compare by their index in a program, address and name, then
+/// their graph, so a function and a transformed copy of it differ.
+fn function_key(function: &falcon::il::Function) -> FunctionKey {
+    (
+        function.index(),
+        function.address(),
+        function.name(),
+        control_flow_graph_key(function.control_flow_graph())
+    )
 }
 
+falcon_type_ord!(IlFunction, function_eq, function_compare, function_hash, function_key);
+
 
-falcon_type_wrapper!(falcon::il::Program, IlProgram);
+falcon_type_wrapper!(Arc<falcon::il::Program>, IlProgram);
 
 fn program_new(_: ()) -> IlProgram {
-    IlProgram { x: falcon::il::Program::new() }
+    IlProgram { x: Arc::new(falcon::il::Program::new()) }
 }
 
 fn program_function_by_name(program: &IlProgram, name: &str) -> Option<IlFunction> {
     for function in program.x.functions() {
         if function.name() == name {
-            return Some(IlFunction { x: function.clone() });
+            return Some(IlFunction {
+                x: FunctionHandle::from_program(&program.x, function)
+            });
         }
     }
     None
 }
 
 fn program_functions(program: &IlProgram) -> Vec<IlFunction> {
-    program.x
-           .functions()
-           .into_iter()
-           .map(|f| IlFunction { x: f.clone() }).collect()
+    FunctionHandle::program_functions(&program.x)
+        .into_iter()
+        .map(|f| IlFunction { x: f }).collect()
 }
 
 fn program_function_by_address(program: &IlProgram, address: u64)
     -> Option<IlFunction> {
 
     match program.x.function_by_address(address) {
-        Some(function) => Some(IlFunction { x: function.clone() }),
+        Some(function) => Some(IlFunction {
+            x: FunctionHandle::from_program(&program.x, function)
+        }),
         None => None
     }
 }
 
 fn program_add_function(program: &IlProgram, function: &IlFunction)
     -> IlProgram {
-    
-    println!("In program_add_function");
 
-    let mut program = program.clone();
-    program.x.add_function(function.x.clone());
-    program
+    let mut program = (*program.x).clone();
+    program.add_function((*function.x).clone());
+    IlProgram { x: Arc::new(program) }
+}
+
+fn program_str(program: &IlProgram) -> String {
+    format!("{}", program.x)
+}
+
+/// Programs compare by their functions.
+fn program_key(program: &falcon::il::Program) -> Vec<FunctionKey> {
+    program.functions().into_iter().map(function_key).collect()
+}
+
+falcon_type_ord!(IlProgram, program_eq, program_compare, program_hash, program_key);
+
+/// The strings in `memory` which code in `program` references, with the
+/// locations referencing each.
+fn program_string_refs(
+    program: &IlProgram,
+    architecture: &architecture::ArchitectureArchitecture,
+    memory: &memory::BackingMemory,
+    min_length: usize
+) -> Vec<(memory::MemoryString, Vec<IlProgramLocation>)> {
+
+    strings::string_refs(&**architecture.x, &program.x, &memory.x, min_length)
+        .into_iter()
+        .map(|string_ref| {
+            let locations = string_ref.references
+                .into_iter()
+                .filter_map(|reference| match reference.source {
+                    xref::Source::Code(location) => Some(IlProgramLocation { x: location }),
+                    xref::Source::Data(_) => None
+                })
+                .collect();
+            (memory::MemoryString { x: string_ref.string }, locations)
+        })
+        .collect()
+}
+
+
+/// A position in a walk over every instruction of a set of functions.
+///
+/// Cursors are immutable. Advancing one returns the next instruction and a
+/// new cursor, so gluon can drive the walk lazily without materializing the
+/// instructions of a whole program.
+#[derive(Clone, Debug)]
+pub struct InstructionCursor {
+    functions: Arc<Vec<FunctionHandle>>,
+    function: usize,
+    blocks: Arc<Vec<usize>>,
+    block: usize,
+    instruction: usize
+}
+
+impl InstructionCursor {
+    pub fn new(functions: Vec<FunctionHandle>) -> InstructionCursor {
+        let blocks = functions.first()
+            .map(|function| block_indices(function))
+            .unwrap_or(Vec::new());
+        InstructionCursor {
+            functions: Arc::new(functions),
+            function: 0,
+            blocks: Arc::new(blocks),
+            block: 0,
+            instruction: 0
+        }
+    }
+
+    /// The instruction under this cursor and the cursor following it.
+    pub fn next(&self)
+        -> Option<(FunctionHandle, BlockHandle, falcon::il::Instruction,
+                   falcon::il::ProgramLocation, InstructionCursor)> {
+
+        let mut cursor = self.clone();
+        loop {
+            let function = cursor.functions.get(cursor.function)?.clone();
+
+            let block_index = match cursor.blocks.get(cursor.block) {
+                Some(block_index) => *block_index,
+                None => {
+                    cursor.function += 1;
+                    cursor.blocks = Arc::new(
+                        cursor.functions.get(cursor.function)
+                            .map(|function| block_indices(function))
+                            .unwrap_or(Vec::new()));
+                    cursor.block = 0;
+                    cursor.instruction = 0;
+                    continue;
+                }
+            };
+
+            let instruction = function.block(block_index).ok()
+                .and_then(|block| block.instructions().get(cursor.instruction))
+                .cloned();
+
+            let instruction = match instruction {
+                Some(instruction) => instruction,
+                None => {
+                    cursor.block += 1;
+                    cursor.instruction = 0;
+                    continue;
+                }
+            };
+
+            let location = falcon::il::ProgramLocation::new(
+                function.index(),
+                falcon::il::FunctionLocation::Instruction(
+                    block_index, instruction.index()));
+            let block = BlockHandle::Graph(
+                ControlFlowGraphHandle::Function(function.clone()), block_index);
+
+            cursor.instruction += 1;
+
+            return Some((function, block, instruction, location, cursor));
+        }
+    }
+}
+
+fn block_indices(function: &FunctionHandle) -> Vec<usize> {
+    function.blocks().into_iter().map(|block| block.index()).collect()
+}
+
+falcon_type_wrapper!(InstructionCursor, IlInstructionCursor);
+
+fn instruction_cursor_next(cursor: &IlInstructionCursor)
+    -> Option<(IlFunction, IlBlock, IlInstruction, IlProgramLocation, IlInstructionCursor)> {
+
+    cursor.x.next().map(|(function, block, instruction, location, cursor)| (
+        IlFunction { x: function },
+        IlBlock { x: block },
+        IlInstruction { x: instruction },
+        IlProgramLocation { x: location },
+        IlInstructionCursor { x: cursor }
+    ))
+}
+
+fn program_instruction_cursor(program: &IlProgram) -> IlInstructionCursor {
+    IlInstructionCursor {
+        x: InstructionCursor::new(FunctionHandle::program_functions(&program.x))
+    }
+}
+
+fn function_instruction_cursor(function: &IlFunction) -> IlInstructionCursor {
+    IlInstructionCursor { x: InstructionCursor::new(vec![function.x.clone()]) }
+}
+
+
+fn function_stack_frame(
+    architecture: &architecture::ArchitectureArchitecture,
+    function: &IlFunction
+) -> Result<IlStackFrame, String> {
+
+    stack_frame::stack_frame(&**architecture.x, &function.x)
+        .map(|stack_frame| IlStackFrame { x: stack_frame })
+        .map_err(|e| format!("{}", e))
+}
+
+
+falcon_type_wrapper!(stack_frame::StackFrame, IlStackFrame);
+
+fn stack_frame_size(stack_frame: &IlStackFrame) -> u64 {
+    stack_frame.x.size
+}
+
+fn stack_frame_slots(stack_frame: &IlStackFrame) -> Vec<IlStackSlot> {
+    stack_frame.x.slots
+        .iter()
+        .map(|slot| IlStackSlot { x: slot.clone() })
+        .collect()
+}
+
+fn stack_frame_slot(stack_frame: &IlStackFrame, offset: i64) -> Option<IlStackSlot> {
+    stack_frame.x.slot(offset).map(|slot| IlStackSlot { x: slot.clone() })
+}
+
+fn stack_frame_locals(stack_frame: &IlStackFrame) -> Vec<IlStackSlot> {
+    stack_frame.x.locals()
+        .into_iter()
+        .map(|slot| IlStackSlot { x: slot.clone() })
+        .collect()
+}
+
+fn stack_frame_arguments(stack_frame: &IlStackFrame) -> Vec<IlStackSlot> {
+    stack_frame.x.arguments()
+        .into_iter()
+        .map(|slot| IlStackSlot { x: slot.clone() })
+        .collect()
+}
+
+fn stack_frame_str(stack_frame: &IlStackFrame) -> String {
+    format!("{}", stack_frame.x)
+}
+
+
+falcon_type_wrapper!(stack_frame::Slot, IlStackSlot);
+
+fn stack_slot_offset(slot: &IlStackSlot) -> i64 {
+    slot.x.offset
+}
+
+fn stack_slot_size(slot: &IlStackSlot) -> u64 {
+    slot.x.size
+}
+
+fn stack_slot_kind(slot: &IlStackSlot) -> String {
+    format!("{}", slot.x.kind)
+}
+
+fn stack_slot_buffer(slot: &IlStackSlot) -> bool {
+    slot.x.buffer
+}
+
+fn stack_slot_accesses(slot: &IlStackSlot) -> Vec<IlStackAccess> {
+    slot.x.accesses
+        .iter()
+        .map(|access| IlStackAccess { x: access.clone() })
+        .collect()
+}
+
+fn stack_slot_str(slot: &IlStackSlot) -> String {
+    format!("{}", slot.x)
+}
+
+
+falcon_type_wrapper!(stack_frame::Access, IlStackAccess);
+
+fn stack_access_location(access: &IlStackAccess) -> IlFunctionLocation {
+    IlFunctionLocation { x: access.x.location.clone() }
+}
+
+fn stack_access_offset(access: &IlStackAccess) -> i64 {
+    access.x.offset
+}
+
+fn stack_access_size(access: &IlStackAccess) -> u64 {
+    access.x.size
+}
+
+fn stack_access_kind(access: &IlStackAccess) -> String {
+    format!("{}", access.x.kind)
+}
+
+
+/// A source location as (file, line, column).
+fn source_tuple(source: &dwarf::SourceLocation) -> (String, u64, u64) {
+    (source.file.clone(), source.line, source.column)
+}
+
+
+falcon_type_wrapper!(Arc<dwarf::DebugInfo>, IlDebugInfo);
+
+/// The DWARF debug information of the binary `filename`, if it has any.
+fn debug_info_from_file(filename: String) -> Result<Option<IlDebugInfo>, String> {
+    let bytes = ::std::fs::read(&filename).map_err(|e| format!("{}", e))?;
+    dwarf::DebugInfo::parse(&bytes)
+        .map(|debug_info| debug_info.map(|debug_info| IlDebugInfo { x: Arc::new(debug_info) }))
+        .map_err(|e| format!("{}", e))
+}
+
+fn debug_info_source(debug_info: &IlDebugInfo, address: u64) -> Option<(String, u64, u64)> {
+    debug_info.x.source(address).map(source_tuple)
+}
+
+fn debug_info_function(debug_info: &IlDebugInfo, address: u64) -> Option<IlDebugFunction> {
+    debug_info.x.function(address).map(|function| IlDebugFunction { x: function.clone() })
+}
+
+fn debug_info_functions(debug_info: &IlDebugInfo) -> Vec<IlDebugFunction> {
+    debug_info.x.functions()
+        .into_iter()
+        .map(|function| IlDebugFunction { x: function.clone() })
+        .collect()
+}
+
+/// The debug information of the function at `function`'s address.
+fn function_debug(function: &IlFunction, debug_info: &IlDebugInfo) -> Option<IlDebugFunction> {
+    debug_info_function(debug_info, function.x.address())
+}
+
+
+falcon_type_wrapper!(dwarf::Function, IlDebugFunction);
+
+fn debug_variables(variables: Vec<&dwarf::Variable>) -> Vec<IlDebugVariable> {
+    variables.into_iter().map(|variable| IlDebugVariable { x: variable.clone() }).collect()
+}
+
+fn debug_function_name(function: &IlDebugFunction) -> String {
+    function.x.name.clone()
+}
+
+fn debug_function_low_pc(function: &IlDebugFunction) -> u64 {
+    function.x.low_pc
+}
+
+fn debug_function_high_pc(function: &IlDebugFunction) -> u64 {
+    function.x.high_pc
+}
+
+fn debug_function_source(function: &IlDebugFunction) -> Option<(String, u64, u64)> {
+    function.x.source.as_ref().map(source_tuple)
+}
+
+fn debug_function_return_type(function: &IlDebugFunction) -> Option<String> {
+    function.x.return_type.clone()
+}
+
+fn debug_function_parameters(function: &IlDebugFunction) -> Vec<IlDebugVariable> {
+    debug_variables(function.x.parameters())
+}
+
+fn debug_function_locals(function: &IlDebugFunction) -> Vec<IlDebugVariable> {
+    debug_variables(function.x.locals())
+}
+
+fn debug_function_variables(function: &IlDebugFunction) -> Vec<IlDebugVariable> {
+    debug_variables(function.x.variables.iter().collect())
+}
+
+fn debug_function_str(function: &IlDebugFunction) -> String {
+    format!("{}", function.x)
+}
+
+/// The variables of `debug_function` in `function`'s stack frame, by their
+/// offset from the entry stack pointer.
+fn debug_function_stack_variables(
+    architecture: &architecture::ArchitectureArchitecture,
+    function: &IlFunction,
+    debug_function: &IlDebugFunction
+) -> Result<Vec<(i64, IlDebugVariable)>, String> {
+
+    dwarf::stack_variables(&**architecture.x, &function.x, &debug_function.x)
+        .map(|variables| variables.into_iter()
+            .map(|(offset, variable)| (offset, IlDebugVariable { x: variable.clone() }))
+            .collect())
+        .map_err(|e| format!("{}", e))
+}
+
+/// The variables of `debug_function` in registers at `address`, by register.
+fn debug_function_register_variables(
+    architecture: &architecture::ArchitectureArchitecture,
+    debug_function: &IlDebugFunction,
+    address: u64
+) -> Vec<(String, IlDebugVariable)> {
+
+    dwarf::register_variables(&**architecture.x, &debug_function.x, address)
+        .into_iter()
+        .map(|(register, variable)| (register.to_string(), IlDebugVariable { x: variable.clone() }))
+        .collect()
+}
+
+
+falcon_type_wrapper!(dwarf::Variable, IlDebugVariable);
+
+fn debug_variable_name(variable: &IlDebugVariable) -> String {
+    variable.x.name.clone()
+}
+
+fn debug_variable_type_name(variable: &IlDebugVariable) -> Option<String> {
+    variable.x.type_name.clone()
+}
+
+fn debug_variable_parameter(variable: &IlDebugVariable) -> bool {
+    variable.x.parameter
+}
+
+fn debug_variable_str(variable: &IlDebugVariable) -> String {
+    format!("{}", variable.x)
 }
 
 
@@ -585,6 +1545,17 @@ fn program_location_format(program_location: &IlProgramLocation) -> String {
     format!("{}", program_location.x)
 }
 
+fn program_location_str(program_location: &IlProgramLocation) -> String {
+    format!("{}", program_location.x)
+}
+
+falcon_type_ord!(
+    IlProgramLocation,
+    program_location_eq,
+    program_location_compare,
+    program_location_hash
+);
+
 fn program_location_from_address(program: &IlProgram, address: u64)
     -> Option<IlProgramLocation> {
 
@@ -617,8 +1588,30 @@ fn program_location_instruction(
 }
 
 
+fn program_location_source(
+    program_location: &IlProgramLocation,
+    program: &IlProgram,
+    debug_info: &IlDebugInfo
+) -> Option<(String, u64, u64)> {
+
+    let address = program_location.x.apply(&program.x).ok()?.instruction()?.address()?;
+    debug_info.x.source(address).map(source_tuple)
+}
+
+
 falcon_type_wrapper!(falcon::il::FunctionLocation, IlFunctionLocation);
 
+fn function_location_str(function_location: &IlFunctionLocation) -> String {
+    format!("{}", function_location.x)
+}
+
+falcon_type_ord!(
+    IlFunctionLocation,
+    function_location_eq,
+    function_location_compare,
+    function_location_hash
+);
+
 fn function_location_type(function_location: &IlFunctionLocation) -> String {
     match function_location.x {
         falcon::il::FunctionLocation::Instruction(_, _) => "instruction",
@@ -654,7 +1647,7 @@ fn function_location_block_get(function_location: &IlFunctionLocation, function:
 
     if let Some(ref_function_location) = function_location.x.apply(&function.x).ok() {
         if let Some(block) = ref_function_location.block() {
-            return Some(IlBlock { x: block.clone() });
+            return function_block(function, block.index());
         }
     }
     None
@@ -695,6 +1688,13 @@ pub fn bindings (vm: gluon::RootedThread) -> gluon::RootedThread {
     vm.register_type::<IlProgram>("IlProgram", &[]).unwrap();
     vm.register_type::<IlProgramLocation>("IlProgramLocation", &[]).unwrap();
     vm.register_type::<IlFunctionLocation>("IlFunctionLocation", &[]).unwrap();
+    vm.register_type::<IlInstructionCursor>("IlInstructionCursor", &[]).unwrap();
+    vm.register_type::<IlStackFrame>("IlStackFrame", &[]).unwrap();
+    vm.register_type::<IlStackSlot>("IlStackSlot", &[]).unwrap();
+    vm.register_type::<IlStackAccess>("IlStackAccess", &[]).unwrap();
+    vm.register_type::<IlDebugInfo>("IlDebugInfo", &[]).unwrap();
+    vm.register_type::<IlDebugFunction>("IlDebugFunction", &[]).unwrap();
+    vm.register_type::<IlDebugVariable>("IlDebugVariable", &[]).unwrap();
 
     fn falcon_il_prim_loader(vm: &gluon::Thread)
         -> gluon::vm::Result<gluon::vm::ExternModule> {
@@ -707,21 +1707,51 @@ pub fn bindings (vm: gluon::RootedThread) -> gluon::RootedThread {
             block_load => primitive!(3, block_load),
             block_branch => primitive!(2, block_branch),
             block_str => primitive!(1, block_str),
+            block_eq => primitive!(2, block_eq),
+            block_compare => primitive!(2, block_compare),
+            block_hash => primitive!(1, block_hash),
             constant_bits => primitive!(1, constant_bits),
             constant_eq => primitive!(2, constant_eq),
             constant_format => primitive!(1, constant_format),
             constant_new => primitive!(2, constant_new),
             constant_str => primitive!(1, constant_str),
             constant_value_u64 => primitive!(1, constant_value_u64),
+            constant_compare => primitive!(2, constant_compare),
+            constant_hash => primitive!(1, constant_hash),
+            control_flow_graph_compare => primitive!(2, control_flow_graph_compare),
+            control_flow_graph_eq => primitive!(2, control_flow_graph_eq),
+            control_flow_graph_hash => primitive!(1, control_flow_graph_hash),
             control_flow_graph_blocks => primitive!(1, control_flow_graph_blocks),
             control_flow_graph_dot_graph => primitive!(1, control_flow_graph_dot_graph),
             control_flow_graph_edges => primitive!(1, control_flow_graph_edges),
             control_flow_graph_str => primitive!(1, control_flow_graph_str),
+            debug_function_high_pc => primitive!(1, debug_function_high_pc),
+            debug_function_locals => primitive!(1, debug_function_locals),
+            debug_function_low_pc => primitive!(1, debug_function_low_pc),
+            debug_function_name => primitive!(1, debug_function_name),
+            debug_function_parameters => primitive!(1, debug_function_parameters),
+            debug_function_register_variables => primitive!(3, debug_function_register_variables),
+            debug_function_return_type => primitive!(1, debug_function_return_type),
+            debug_function_source => primitive!(1, debug_function_source),
+            debug_function_stack_variables => primitive!(3, debug_function_stack_variables),
+            debug_function_str => primitive!(1, debug_function_str),
+            debug_function_variables => primitive!(1, debug_function_variables),
+            debug_info_from_file => primitive!(1, debug_info_from_file),
+            debug_info_function => primitive!(2, debug_info_function),
+            debug_info_functions => primitive!(1, debug_info_functions),
+            debug_info_source => primitive!(2, debug_info_source),
+            debug_variable_name => primitive!(1, debug_variable_name),
+            debug_variable_parameter => primitive!(1, debug_variable_parameter),
+            debug_variable_str => primitive!(1, debug_variable_str),
+            debug_variable_type_name => primitive!(1, debug_variable_type_name),
             edge_has_condition => primitive!(1, edge_has_condition),
             edge_condition => primitive!(1, edge_condition),
             edge_head => primitive!(1, edge_head),
             edge_tail => primitive!(1, edge_tail),
             edge_str => primitive!(1, edge_str),
+            edge_eq => primitive!(2, edge_eq),
+            edge_compare => primitive!(2, edge_compare),
+            edge_hash => primitive!(1, edge_hash),
             expression_format => primitive!(1, expression_format),
             expression_scalar => primitive!(1, expression_scalar),
             expression_constant => primitive!(1, expression_constant),
@@ -755,11 +1785,25 @@ pub fn bindings (vm: gluon::RootedThread) -> gluon::RootedThread {
             expression_get_else => primitive!(1, expression_get_else),
             expression_get_bits => primitive!(1, expression_get_bits),
             expression_str => primitive!(1, expression_str),
+            expression_bits => primitive!(1, expression_bits),
+            expression_scalars => primitive!(1, expression_scalars),
+            expression_simplify => primitive!(1, expression_simplify),
+            expression_substitute => primitive!(3, expression_substitute),
+            expression_eq => primitive!(2, expression_eq),
+            expression_compare => primitive!(2, expression_compare),
+            expression_hash => primitive!(1, expression_hash),
+            function_compare => primitive!(2, function_compare),
+            function_eq => primitive!(2, function_eq),
+            function_hash => primitive!(1, function_hash),
+            function_str => primitive!(1, function_str),
             function_address => primitive!(1, function_address),
             function_block => primitive!(2, function_block),
+            function_debug => primitive!(2, function_debug),
             function_blocks => primitive!(1, function_blocks),
             function_control_flow_graph => primitive!(1, function_control_flow_graph),
             function_index => primitive!(1, function_index),
+            function_instruction_cursor => primitive!(1, function_instruction_cursor),
+            function_stack_frame => primitive!(2, function_stack_frame),
             function_name => primitive!(1, function_name),
             function_location_type => primitive!(1, function_location_type),
             function_location_instruction => primitive!(2, function_location_instruction),
@@ -768,11 +1812,29 @@ pub fn bindings (vm: gluon::RootedThread) -> gluon::RootedThread {
             function_location_instruction_get => primitive!(2, function_location_instruction_get),
             function_location_edge_get => primitive!(2, function_location_edge_get),
             function_location_block_get => primitive!(2, function_location_block_get),
+            function_location_eq => primitive!(2, function_location_eq),
+            function_location_compare => primitive!(2, function_location_compare),
+            function_location_hash => primitive!(1, function_location_hash),
+            function_location_str => primitive!(1, function_location_str),
             instruction_address => primitive!(1, instruction_address),
+            instruction_cursor_next => primitive!(1, instruction_cursor_next),
             instruction_format => primitive!(1, instruction_format),
             instruction_index => primitive!(1, instruction_index),
             instruction_operation => primitive!(1, instruction_operation),
             instruction_str => primitive!(1, instruction_str),
+            instruction_scalars_read => primitive!(1, instruction_scalars_read),
+            instruction_scalars_written => primitive!(1, instruction_scalars_written),
+            instruction_reads_memory => primitive!(1, instruction_reads_memory),
+            instruction_writes_memory => primitive!(1, instruction_writes_memory),
+            instruction_is_call => primitive!(4, instruction_is_call),
+            instruction_is_return => primitive!(4, instruction_is_return),
+            instruction_eq => primitive!(2, instruction_eq),
+            instruction_compare => primitive!(2, instruction_compare),
+            instruction_hash => primitive!(1, instruction_hash),
+            intrinsic_compare => primitive!(2, intrinsic_compare),
+            intrinsic_eq => primitive!(2, intrinsic_eq),
+            intrinsic_hash => primitive!(1, intrinsic_hash),
+            intrinsic_str => primitive!(1, intrinsic_str),
             intrinsic_mnemonic => primitive!(1, intrinsic_mnemonic),
             intrinsic_instruction_str => primitive!(1, intrinsic_instruction_str),
             operation_format => primitive!(1, operation_format),
@@ -790,22 +1852,60 @@ pub fn bindings (vm: gluon::RootedThread) -> gluon::RootedThread {
             operation_branch_target => primitive!(1, operation_branch_target),
             operation_intrinsic_intrinsic => primitive!(1, operation_intrinsic_intrinsic),
             operation_str => primitive!(1, operation_str),
+            operation_scalars_read => primitive!(1, operation_scalars_read),
+            operation_scalars_written => primitive!(1, operation_scalars_written),
+            operation_reads_memory => primitive!(1, operation_reads_memory),
+            operation_writes_memory => primitive!(1, operation_writes_memory),
+            operation_is_call => primitive!(2, operation_is_call),
+            operation_is_return => primitive!(2, operation_is_return),
+            operation_eq => primitive!(2, operation_eq),
+            operation_compare => primitive!(2, operation_compare),
+            operation_hash => primitive!(1, operation_hash),
+            program_compare => primitive!(2, program_compare),
+            program_eq => primitive!(2, program_eq),
+            program_hash => primitive!(1, program_hash),
+            program_str => primitive!(1, program_str),
             program_add_function => primitive!(2, program_add_function),
             program_function_by_address => primitive!(2, program_function_by_address),
             program_function_by_name => primitive!(2, program_function_by_name),
             program_functions => primitive!(1, program_functions),
+            program_instruction_cursor => primitive!(1, program_instruction_cursor),
             program_new => primitive!(1, program_new),
+            program_string_refs => primitive!(4, program_string_refs),
             program_location_format => primitive!(1, program_location_format),
             program_location_from_address => primitive!(2, program_location_from_address),
             program_location_function_location => primitive!(1, program_location_function_location),
             program_location_instruction => primitive!(2, program_location_instruction),
             program_location_new => primitive!(2, program_location_new),
+            program_location_source => primitive!(3, program_location_source),
+            program_location_eq => primitive!(2, program_location_eq),
+            program_location_compare => primitive!(2, program_location_compare),
+            program_location_hash => primitive!(1, program_location_hash),
+            program_location_str => primitive!(1, program_location_str),
             scalar_bits => primitive!(1, scalar_bits),
             scalar_eq => primitive!(2, scalar_eq),
             scalar_format => primitive!(1, scalar_format),
             scalar_name => primitive!(1, scalar_name),
             scalar_new => primitive!(2, scalar_new),
-            scalar_str => primitive!(1, scalar_str)
+            scalar_str => primitive!(1, scalar_str),
+            scalar_compare => primitive!(2, scalar_compare),
+            scalar_hash => primitive!(1, scalar_hash),
+            stack_access_kind => primitive!(1, stack_access_kind),
+            stack_access_location => primitive!(1, stack_access_location),
+            stack_access_offset => primitive!(1, stack_access_offset),
+            stack_access_size => primitive!(1, stack_access_size),
+            stack_frame_arguments => primitive!(1, stack_frame_arguments),
+            stack_frame_locals => primitive!(1, stack_frame_locals),
+            stack_frame_size => primitive!(1, stack_frame_size),
+            stack_frame_slot => primitive!(2, stack_frame_slot),
+            stack_frame_slots => primitive!(1, stack_frame_slots),
+            stack_frame_str => primitive!(1, stack_frame_str),
+            stack_slot_accesses => primitive!(1, stack_slot_accesses),
+            stack_slot_buffer => primitive!(1, stack_slot_buffer),
+            stack_slot_kind => primitive!(1, stack_slot_kind),
+            stack_slot_offset => primitive!(1, stack_slot_offset),
+            stack_slot_size => primitive!(1, stack_slot_size),
+            stack_slot_str => primitive!(1, stack_slot_str)
         })
     }
     
@@ -0,0 +1,497 @@
+//! Reading ELF and PE headers directly from a binary's bytes.
+//!
+//! Falcon's loaders expose the memory a binary maps, but not where that
+//! memory came from in the file, nor the headers describing how the binary
+//! was built. This module reads just enough of the headers to map native
+//! addresses back to file offsets and to find those properties.
+
+use falcon::error::Result;
+
+
+/// A range of the file mapped into memory.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Segment {
+    /// The address the segment is mapped at.
+    pub address: u64,
+    /// The segment's offset in the file.
+    pub offset: u64,
+    /// The number of bytes of the file mapped.
+    pub size: u64
+}
+
+impl Segment {
+    /// The file offset of `address`, if this segment maps it from the file.
+    pub fn offset_of(&self, address: u64) -> Option<u64> {
+        if address >= self.address && address - self.address < self.size {
+            Some(self.offset + (address - self.address))
+        }
+        else {
+            None
+        }
+    }
+}
+
+
+/// Reads integers of a fixed endianness from a byte slice.
+#[derive(Clone, Copy, Debug)]
+pub struct Reader<'b> {
+    bytes: &'b [u8],
+    little: bool
+}
+
+impl<'b> Reader<'b> {
+    pub fn new(bytes: &'b [u8], little: bool) -> Reader<'b> {
+        Reader { bytes: bytes, little: little }
+    }
+
+    /// An unsigned integer of `size` bytes.
+    pub fn read(&self, offset: u64, size: usize) -> Result<u64> {
+        let start = offset as usize;
+        let bytes = start.checked_add(size)
+            .and_then(|end| self.bytes.get(start..end))
+            .ok_or_else(|| format!("read of {} bytes at 0x{:x} out of bounds", size, offset))?;
+        let mut value = 0;
+        for i in 0..size {
+            let byte = if self.little { bytes[size - 1 - i] } else { bytes[i] };
+            value = (value << 8) | byte as u64;
+        }
+        Ok(value)
+    }
+
+    pub fn u8(&self, offset: u64) -> Result<u64> {
+        self.read(offset, 1)
+    }
+
+    pub fn u16(&self, offset: u64) -> Result<u64> {
+        self.read(offset, 2)
+    }
+
+    pub fn u32(&self, offset: u64) -> Result<u64> {
+        self.read(offset, 4)
+    }
+
+    pub fn u64(&self, offset: u64) -> Result<u64> {
+        self.read(offset, 8)
+    }
+
+    /// The NUL-terminated string at `offset`, empty if it is out of bounds.
+    pub fn string(&self, offset: u64) -> String {
+        let bytes = self.bytes.get(offset as usize..).unwrap_or(&[]);
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).into_owned()
+    }
+
+    /// A 32 or 64-bit word.
+    pub fn word(&self, offset: u64, wide: bool) -> Result<u64> {
+        if wide { self.u64(offset) } else { self.u32(offset) }
+    }
+
+    /// The offset of entry `index` of a table of `size` byte entries at
+    /// `base`, which must start within the bytes.
+    pub fn entry(&self, base: u64, index: u64, size: u64) -> Result<u64> {
+        index.checked_mul(size)
+            .and_then(|offset| base.checked_add(offset))
+            .and_then(|offset| if offset < self.bytes.len() as u64 { Some(offset) } else { None })
+            .ok_or_else(|| format!("entry {} of table at 0x{:x} out of bounds", index, base).into())
+    }
+}
+
+
+/// The container format of a binary.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Format {
+    Elf,
+    Pe
+}
+
+/// The container format of `bytes`, if it is one this module reads.
+pub fn format(bytes: &[u8]) -> Option<Format> {
+    if bytes.starts_with(b"\x7fELF") {
+        Some(Format::Elf)
+    }
+    else if bytes.starts_with(b"MZ") {
+        Some(Format::Pe)
+    }
+    else {
+        None
+    }
+}
+
+
+/// An ELF file's header fields this module uses.
+#[derive(Clone, Copy, Debug)]
+pub struct ElfHeader<'b> {
+    pub reader: Reader<'b>,
+    /// Whether this is a 64-bit ELF.
+    pub wide: bool,
+    pub kind: u64,
+    pub program_headers: u64,
+    pub program_header_size: u64,
+    pub program_header_count: u64,
+    pub section_headers: u64,
+    pub section_header_size: u64,
+    pub section_header_count: u64,
+    pub section_names: u64
+}
+
+impl<'b> ElfHeader<'b> {
+    pub fn parse(bytes: &'b [u8]) -> Result<ElfHeader<'b>> {
+        if format(bytes) != Some(Format::Elf) || bytes.len() < 6 {
+            return Err("not an ELF".into());
+        }
+        let wide = bytes[4] == 2;
+        let reader = Reader::new(bytes, bytes[5] == 1);
+        let (phoff, shoff, rest) = if wide { (0x20, 0x28, 0x34) } else { (0x1c, 0x20, 0x28) };
+
+        Ok(ElfHeader {
+            reader: reader,
+            wide: wide,
+            kind: reader.u16(0x10)?,
+            program_headers: reader.word(phoff, wide)?,
+            section_headers: reader.word(shoff, wide)?,
+            program_header_size: reader.u16(rest + 2)?,
+            program_header_count: reader.u16(rest + 4)?,
+            section_header_size: reader.u16(rest + 6)?,
+            section_header_count: reader.u16(rest + 8)?,
+            section_names: reader.u16(rest + 10)?
+        })
+    }
+
+    /// Each program header as (type, flags, offset, address, file size,
+    /// memory size).
+    pub fn program_headers(&self) -> Result<Vec<(u64, u64, u64, u64, u64, u64)>> {
+        let r = &self.reader;
+        (0..self.program_header_count)
+            .map(|i| {
+                let h = r.entry(self.program_headers, i, self.program_header_size)?;
+                if self.wide {
+                    Ok((r.u32(h)?, r.u32(h + 4)?, r.u64(h + 8)?, r.u64(h + 0x10)?,
+                        r.u64(h + 0x20)?, r.u64(h + 0x28)?))
+                }
+                else {
+                    Ok((r.u32(h)?, r.u32(h + 0x18)?, r.u32(h + 4)?, r.u32(h + 8)?,
+                        r.u32(h + 0x10)?, r.u32(h + 0x14)?))
+                }
+            })
+            .collect()
+    }
+
+    /// Each section header as (name, type, flags, address, offset, size).
+    pub fn sections(&self) -> Result<Vec<(String, u64, u64, u64, u64, u64)>> {
+        let r = &self.reader;
+        let headers: Vec<(u64, u64, u64, u64, u64, u64)> = (0..self.section_header_count)
+            .map(|i| {
+                let h = r.entry(self.section_headers, i, self.section_header_size)?;
+                if self.wide {
+                    Ok((r.u32(h)?, r.u32(h + 4)?, r.u64(h + 8)?, r.u64(h + 0x10)?,
+                        r.u64(h + 0x18)?, r.u64(h + 0x20)?))
+                }
+                else {
+                    Ok((r.u32(h)?, r.u32(h + 4)?, r.u32(h + 8)?, r.u32(h + 0xc)?,
+                        r.u32(h + 0x10)?, r.u32(h + 0x14)?))
+                }
+            })
+            .collect::<Result<_>>()?;
+
+        let names = headers.get(self.section_names as usize).map(|header| header.4);
+        Ok(headers.iter()
+            .map(|&(name, kind, flags, address, offset, size)| {
+                let name = names.and_then(|names| names.checked_add(name))
+                    .map(|name| r.string(name))
+                    .unwrap_or_default();
+                (name, kind, flags, address, offset, size)
+            })
+            .collect())
+    }
+
+    /// The entries of the dynamic section as (tag, value), or none if the
+    /// file is not dynamically linked.
+    pub fn dynamic(&self) -> Result<Vec<(u64, u64)>> {
+        const PT_DYNAMIC: u64 = 2;
+        const DT_NULL: u64 = 0;
+
+        let r = &self.reader;
+        let size = if self.wide { 16 } else { 8 };
+        let mut entries = Vec::new();
+        for (kind, _, offset, _, file_size, _) in self.program_headers()? {
+            if kind != PT_DYNAMIC {
+                continue;
+            }
+            for i in 0..file_size / size {
+                let entry = r.entry(offset, i, size)?;
+                let tag = r.word(entry, self.wide)?;
+                if tag == DT_NULL {
+                    break;
+                }
+                entries.push((tag, r.word(entry + size / 2, self.wide)?));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// The names of the symbols in the section named `table`, read from the
+    /// string table section named `strings`.
+    pub fn symbol_names(&self, table: &str, strings: &str) -> Result<Vec<String>> {
+        let sections = self.sections()?;
+        let find = |name: &str| sections.iter()
+            .find(|section| section.0 == name)
+            .map(|&(_, _, _, _, offset, size)| (offset, size));
+        let ((offset, size), (names, _)) = match (find(table), find(strings)) {
+            (Some(table), Some(strings)) => (table, strings),
+            _ => return Ok(Vec::new())
+        };
+
+        let entry_size = if self.wide { 24 } else { 16 };
+        let mut symbols = Vec::new();
+        for i in 0..size / entry_size {
+            let entry = self.reader.entry(offset, i, entry_size)?;
+            let name = names.checked_add(self.reader.u32(entry)?)
+                .map(|name| self.reader.string(name))
+                .unwrap_or_default();
+            if !name.is_empty() {
+                symbols.push(name);
+            }
+        }
+        Ok(symbols)
+    }
+}
+
+
+/// A PE file's header fields this module uses.
+#[derive(Clone, Copy, Debug)]
+pub struct PeHeader<'b> {
+    pub reader: Reader<'b>,
+    /// Whether this is a PE32+ image.
+    pub wide: bool,
+    pub image_base: u64,
+    /// The offset of the optional header.
+    pub optional_header: u64,
+    pub section_headers: u64,
+    pub section_count: u64
+}
+
+impl<'b> PeHeader<'b> {
+    pub fn parse(bytes: &'b [u8]) -> Result<PeHeader<'b>> {
+        if format(bytes) != Some(Format::Pe) {
+            return Err("not a PE".into());
+        }
+        let reader = Reader::new(bytes, true);
+        let pe = reader.u32(0x3c)?;
+        if reader.u32(pe)? != 0x4550 {
+            return Err("missing PE signature".into());
+        }
+        let section_count = reader.u16(pe + 6)?;
+        let optional_size = reader.u16(pe + 0x14)?;
+        let optional_header = pe + 0x18;
+        let wide = reader.u16(optional_header)? == 0x20b;
+        let image_base = if wide {
+            reader.u64(optional_header + 0x18)?
+        }
+        else {
+            reader.u32(optional_header + 0x1c)?
+        };
+
+        Ok(PeHeader {
+            reader: reader,
+            wide: wide,
+            image_base: image_base,
+            optional_header: optional_header,
+            section_headers: optional_header + optional_size,
+            section_count: section_count
+        })
+    }
+
+    /// Each section header as (virtual address, raw data offset, raw data
+    /// size, characteristics).
+    pub fn sections(&self) -> Result<Vec<(u64, u64, u64, u64)>> {
+        let r = &self.reader;
+        (0..self.section_count)
+            .map(|i| {
+                let h = r.entry(self.section_headers, i, 0x28)?;
+                Ok((r.u32(h + 0xc)?, r.u32(h + 0x14)?, r.u32(h + 0x10)?, r.u32(h + 0x24)?))
+            })
+            .collect()
+    }
+
+    /// The DLL characteristics flags of the optional header.
+    pub fn dll_characteristics(&self) -> Result<u64> {
+        self.reader.u16(self.optional_header + 0x46)
+    }
+
+    /// The file offset of the relative virtual address `address`, if a
+    /// section maps it from the file.
+    pub fn offset_of(&self, address: u64) -> Result<Option<u64>> {
+        Ok(self.sections()?
+            .into_iter()
+            .filter(|&(section, _, size, _)| address >= section && address - section < size)
+            .map(|(section, offset, _, _)| offset + (address - section))
+            .next())
+    }
+
+    /// The (virtual address, size) of data directory `index`, if the image
+    /// has one.
+    pub fn data_directory(&self, index: u64) -> Result<Option<(u64, u64)>> {
+        let r = &self.reader;
+        let (count, directories) = if self.wide { (0x6c, 0x70) } else { (0x5c, 0x60) };
+        if index >= r.u32(self.optional_header + count)? {
+            return Ok(None);
+        }
+        let directory = self.optional_header + directories + index * 8;
+        match (r.u32(directory)?, r.u32(directory + 4)?) {
+            (0, _) | (_, 0) => Ok(None),
+            (address, size) => Ok(Some((address, size)))
+        }
+    }
+}
+
+
+const PT_LOAD: u64 = 1;
+
+/// The segments of the file `bytes` maps into memory, at the addresses the
+/// file asks for.
+pub fn segments(bytes: &[u8]) -> Result<Vec<Segment>> {
+    match format(bytes) {
+        Some(Format::Elf) => Ok(ElfHeader::parse(bytes)?
+            .program_headers()?
+            .into_iter()
+            .filter(|&(kind, _, _, _, _, _)| kind == PT_LOAD)
+            .map(|(_, _, offset, address, size, _)|
+                Segment { address: address, offset: offset, size: size })
+            .collect()),
+        Some(Format::Pe) => {
+            let pe = PeHeader::parse(bytes)?;
+            Ok(pe.sections()?
+                .into_iter()
+                .map(|(address, offset, size, _)| Segment {
+                    address: pe.image_base + address,
+                    offset: offset,
+                    size: size
+                })
+                .collect())
+        },
+        None => Err("unrecognized binary format".into())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put(bytes: &mut Vec<u8>, offset: usize, value: u64, size: usize) {
+        if bytes.len() < offset + size {
+            bytes.resize(offset + size, 0);
+        }
+        for i in 0..size {
+            bytes[offset + i] = (value >> (8 * i)) as u8;
+        }
+    }
+
+    /// A little-endian 64-bit ELF with one PT_LOAD program header.
+    fn elf() -> Vec<u8> {
+        let mut bytes = b"\x7fELF\x02\x01".to_vec();
+        put(&mut bytes, 0x10, 3, 2);
+        put(&mut bytes, 0x20, 0x40, 8);
+        put(&mut bytes, 0x28, 0, 8);
+        put(&mut bytes, 0x36, 0x38, 2);
+        put(&mut bytes, 0x38, 1, 2);
+        put(&mut bytes, 0x40, PT_LOAD, 4);
+        put(&mut bytes, 0x44, 5, 4);
+        put(&mut bytes, 0x48, 0, 8);
+        put(&mut bytes, 0x50, 0x400000, 8);
+        put(&mut bytes, 0x60, 0x78, 8);
+        put(&mut bytes, 0x68, 0x1000, 8);
+        bytes
+    }
+
+    /// A PE32+ image with one section.
+    fn pe() -> Vec<u8> {
+        let mut bytes = b"MZ".to_vec();
+        put(&mut bytes, 0x3c, 0x40, 4);
+        put(&mut bytes, 0x40, 0x4550, 4);
+        put(&mut bytes, 0x46, 1, 2);
+        put(&mut bytes, 0x54, 0xf0, 2);
+        put(&mut bytes, 0x58, 0x20b, 2);
+        put(&mut bytes, 0x70, 0x140000000, 8);
+        put(&mut bytes, 0x148 + 0xc, 0x1000, 4);
+        put(&mut bytes, 0x148 + 0x10, 0x200, 4);
+        put(&mut bytes, 0x148 + 0x14, 0x400, 4);
+        put(&mut bytes, 0x148 + 0x24, 0x60000020, 4);
+        bytes
+    }
+
+    #[test]
+    fn reader_endianness() {
+        let bytes = [1, 2, 3, 4];
+        assert_eq!(Reader::new(&bytes, true).u32(0).unwrap(), 0x04030201);
+        assert_eq!(Reader::new(&bytes, false).u32(0).unwrap(), 0x01020304);
+        assert!(Reader::new(&bytes, true).u32(1).is_err());
+        assert!(Reader::new(&bytes, true).read(u64::max_value(), 4).is_err());
+    }
+
+    #[test]
+    fn reader_entries_are_bounded() {
+        let bytes = [0; 16];
+        let reader = Reader::new(&bytes, true);
+        assert_eq!(reader.entry(4, 2, 4).unwrap(), 12);
+        assert!(reader.entry(4, 3, 4).is_err());
+        assert!(reader.entry(u64::max_value(), 1, 1).is_err());
+        assert!(reader.entry(0, u64::max_value(), 2).is_err());
+    }
+
+    #[test]
+    fn elf_header_parse() {
+        let bytes = elf();
+        let header = ElfHeader::parse(&bytes).unwrap();
+        assert!(header.wide);
+        assert_eq!(header.kind, 3);
+        assert_eq!(header.program_headers, 0x40);
+        assert_eq!(header.program_header_count, 1);
+        assert_eq!(header.program_headers().unwrap(),
+                   vec![(PT_LOAD, 5, 0, 0x400000, 0x78, 0x1000)]);
+        assert_eq!(segments(&bytes).unwrap(),
+                   vec![Segment { address: 0x400000, offset: 0, size: 0x78 }]);
+    }
+
+    #[test]
+    fn elf_header_rejects_malformed() {
+        assert!(ElfHeader::parse(b"\x7fEL").is_err());
+        assert!(ElfHeader::parse(b"\x7fELF\x02\x01").is_err());
+        assert!(ElfHeader::parse(b"MZ\x00\x00\x00\x00").is_err());
+
+        let mut bytes = elf();
+        put(&mut bytes, 0x20, u64::max_value(), 8);
+        assert!(ElfHeader::parse(&bytes).unwrap().program_headers().is_err());
+
+        let mut bytes = elf();
+        put(&mut bytes, 0x38, 0xffff, 2);
+        assert!(ElfHeader::parse(&bytes).unwrap().program_headers().is_err());
+    }
+
+    #[test]
+    fn pe_header_parse() {
+        let bytes = pe();
+        let header = PeHeader::parse(&bytes).unwrap();
+        assert!(header.wide);
+        assert_eq!(header.image_base, 0x140000000);
+        assert_eq!(header.section_headers, 0x148);
+        assert_eq!(header.sections().unwrap(), vec![(0x1000, 0x400, 0x200, 0x60000020)]);
+        assert_eq!(header.offset_of(0x1010).unwrap(), Some(0x410));
+        assert_eq!(header.offset_of(0x1200).unwrap(), None);
+    }
+
+    #[test]
+    fn pe_header_rejects_malformed() {
+        let mut bytes = pe();
+        put(&mut bytes, 0x40, 0, 4);
+        assert!(PeHeader::parse(&bytes).is_err());
+
+        let mut bytes = pe();
+        put(&mut bytes, 0x3c, 0xffffffff, 4);
+        assert!(PeHeader::parse(&bytes).is_err());
+
+        let mut bytes = pe();
+        put(&mut bytes, 0x46, 0xffff, 2);
+        assert!(PeHeader::parse(&bytes).unwrap().sections().is_err());
+    }
+}
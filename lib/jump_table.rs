@@ -0,0 +1,458 @@
+//! Jump table and indirect branch resolution.
+//!
+//! Falcon cannot follow a `Branch` whose target is not constant, so a switch
+//! compiled to a jump table leaves its function's control flow graph ending
+//! at the jump. An indirect branch is resolved when, within its block, the
+//! target is computed from a single load whose address depends on a value
+//! the value-set analysis bounds on entry to the block. Every address in that
+//! range is read from memory as a table entry, and the target computed from
+//! each entry, so both absolute tables and tables of offsets from a base are
+//! understood.
+//!
+//! Resolving a function replaces each resolved `Branch` with a `nop` and adds
+//! an edge to every target, guarded by the branch target equalling it.
+//! Targets which are not yet blocks of the function are lifted and inserted,
+//! so code they share with the rest of the function may appear twice.
+//! Branches through a single constant address, such as import stubs, and
+//! returns, whether through the stack or the return address register, are
+//! not jump tables and are left alone. Branches still unresolved after
+//! `MAX_ROUNDS` rounds are reported as they were found in the last round.
+
+use falcon;
+use falcon::architecture::Architecture;
+use falcon::error::Result;
+use falcon::loader::Loader;
+use falcon::memory::MemoryPermissions;
+use falcon::memory::backing::Memory;
+use falcon::translator::Translator;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use call_site;
+use il;
+use stack_frame;
+use value_set;
+
+
+/// The largest table read for one branch.
+pub const MAX_ENTRIES: u64 = 1024;
+
+/// Rounds of resolution, so tables reached through resolved tables are
+/// resolved too.
+const MAX_ROUNDS: usize = 8;
+
+
+/// Why an indirect branch was not resolved.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Reason {
+    /// The target is not computed from a load in the branch's block.
+    NoTable,
+    /// The table index is not bounded, or the table is too large.
+    Unbounded,
+    /// A table entry could not be read.
+    Unreadable(u64),
+    /// A target computed from an entry is not constant.
+    NotConstant,
+    /// A target is not in executable memory.
+    NotCode(u64)
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Reason::NoTable => write!(f, "target is not loaded from a table"),
+            Reason::Unbounded => write!(f, "table index is not bounded"),
+            Reason::Unreadable(address) =>
+                write!(f, "table entry at 0x{:x} is not in loaded memory", address),
+            Reason::NotConstant => write!(f, "target is not constant"),
+            Reason::NotCode(address) =>
+                write!(f, "target 0x{:x} is not in executable memory", address)
+        }
+    }
+}
+
+
+/// An indirect branch, and its targets if it was resolved.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct IndirectBranch {
+    /// The branch, at the time it was found.
+    pub location: falcon::il::FunctionLocation,
+    /// The native address of the branch, if known.
+    pub address: Option<u64>,
+    /// The name of the function containing the branch.
+    pub function: String,
+    /// The lowest table address read.
+    pub table: Option<u64>,
+    /// The targets, in address order.
+    pub targets: Vec<u64>,
+    /// Why the branch was not resolved, or `None` if it was.
+    pub reason: Option<Reason>
+}
+
+impl IndirectBranch {
+    pub fn resolved(&self) -> bool {
+        self.reason.is_none()
+    }
+}
+
+
+/// The indirect branches of a program, found while resolving its jump tables.
+#[derive(Clone, Debug, Default)]
+pub struct Resolution {
+    /// Every indirect branch found, resolved or not.
+    pub branches: Vec<IndirectBranch>,
+    /// Functions which failed to resolve, by name, with the error. They are
+    /// kept as they were.
+    pub failures: Vec<(String, String)>
+}
+
+impl fmt::Display for IndirectBranch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.address {
+            Some(address) => write!(f, "0x{:x}", address)?,
+            None => write!(f, "{}", self.location)?
+        }
+        write!(f, " {}: ", self.function)?;
+        match self.reason {
+            Some(ref reason) => write!(f, "unresolved, {}", reason),
+            None => {
+                let targets: Vec<String> = self.targets.iter()
+                    .map(|target| format!("0x{:x}", target))
+                    .collect();
+                if let Some(table) = self.table {
+                    write!(f, "table at 0x{:x}, ", table)?;
+                }
+                write!(f, "targets {}", targets.join(", "))
+            }
+        }
+    }
+}
+
+
+/// A branch target computed from one load.
+struct Table {
+    /// The target, with the loaded value replaced by `entry`.
+    target: falcon::il::Expression,
+    entry: falcon::il::Scalar,
+    /// The address loaded from, in terms of scalars on entry to the block.
+    address: falcon::il::Expression
+}
+
+/// How the branch at `position` in `block` computes its target, or `None` if
+/// the target is not computed from exactly one load.
+fn table(block: &falcon::il::Block, position: usize, target: &falcon::il::Expression)
+    -> Option<Table> {
+
+    let mut target = target.clone();
+    let mut loads: Vec<(falcon::il::Scalar, falcon::il::Expression)> = Vec::new();
+
+    for instruction in block.instructions()[..position].iter().rev() {
+        match *instruction.operation() {
+            falcon::il::Operation::Assign { ref dst, ref src } => {
+                target = il::substitute(&target, dst, src);
+                for load in &mut loads {
+                    load.1 = il::substitute(&load.1, dst, src);
+                }
+            },
+            falcon::il::Operation::Load { ref dst, ref index } => {
+                let read = target.scalars().contains(&dst) ||
+                    loads.iter().any(|load| load.1.scalars().contains(&dst));
+                if !read {
+                    continue;
+                }
+                let entry = falcon::il::Scalar::new(
+                    format!("__table_entry_{}", loads.len()), dst.bits());
+                let replacement = falcon::il::Expression::Scalar(entry.clone());
+                target = il::substitute(&target, dst, &replacement);
+                for load in &mut loads {
+                    load.1 = il::substitute(&load.1, dst, &replacement);
+                }
+                loads.push((entry, index.clone()));
+            },
+            falcon::il::Operation::Branch { .. } => break,
+            falcon::il::Operation::Store { .. } |
+            falcon::il::Operation::Intrinsic { .. } |
+            falcon::il::Operation::Nop => {}
+        }
+    }
+
+    if loads.len() != 1 {
+        return None;
+    }
+    let (entry, address) = loads.pop()?;
+    Some(Table { target: target, entry: entry, address: address })
+}
+
+
+/// Resolve the indirect branch at `instruction` in `block`, or `None` if it is
+/// a return or a branch through a single constant address.
+fn resolve(
+    architecture: &Architecture,
+    memory: &Memory,
+    function: &falcon::il::Function,
+    value_sets: &value_set::ValueSets,
+    block: &falcon::il::Block,
+    instruction: &falcon::il::Instruction,
+    target: &falcon::il::Expression
+) -> Result<Option<IndirectBranch>> {
+
+    let location = falcon::il::FunctionLocation::Instruction(block.index(), instruction.index());
+    let mut branch = IndirectBranch {
+        location: location,
+        address: instruction.address(),
+        function: function.name(),
+        table: None,
+        targets: Vec::new(),
+        reason: None
+    };
+
+    let position = block.instructions()
+        .iter()
+        .position(|i| i.index() == instruction.index())
+        .ok_or("branch missing from its block")?;
+    let first = block.instructions()[0].index();
+    let entry = value_sets.at(&falcon::il::FunctionLocation::Instruction(block.index(), first))
+        .cloned()
+        .unwrap_or_default();
+
+    let table = match table(block, position, target) {
+        Some(table) => table,
+        None => {
+            branch.reason = Some(Reason::NoTable);
+            return Ok(Some(branch));
+        }
+    };
+
+    // Returns load from the stack, and stubs from one constant address.
+    if stack_frame::offset_of(architecture, &entry.offsets, &table.address).is_some() ||
+        table.address.scalars().is_empty() {
+        return Ok(None);
+    }
+
+    let addresses = entry.eval(&table.address);
+    if addresses.is_top() || addresses.size() > MAX_ENTRIES {
+        branch.reason = Some(Reason::Unbounded);
+        return Ok(Some(branch));
+    }
+    branch.table = Some(addresses.lo);
+
+    let bits = table.entry.bits();
+    let mut targets = BTreeSet::new();
+    let mut address = addresses.lo;
+    loop {
+        let value = match call_site::read_word(memory, address, bits / 8, &architecture.endian()) {
+            Some(value) => value,
+            None => {
+                branch.reason = Some(Reason::Unreadable(address));
+                return Ok(Some(branch));
+            }
+        };
+
+        let value = falcon::il::expr_const(value, bits);
+        let target = il::substitute(&table.target, &table.entry, &value);
+        let target = if target.scalars().is_empty() {
+            falcon::executor::eval(&target)?.value_u64()
+        }
+        else {
+            entry.eval(&target).value()
+        };
+        let target = match target {
+            Some(target) => target,
+            None => {
+                branch.reason = Some(Reason::NotConstant);
+                return Ok(Some(branch));
+            }
+        };
+
+        let executable = memory.permissions(target)
+            .map(|permissions| permissions.contains(MemoryPermissions::EXECUTE))
+            .unwrap_or(false);
+        if !executable {
+            branch.reason = Some(Reason::NotCode(target));
+            return Ok(Some(branch));
+        }
+        targets.insert(target);
+
+        if address >= addresses.hi || addresses.stride == 0 {
+            break;
+        }
+        address += addresses.stride;
+    }
+
+    branch.targets = targets.into_iter().collect();
+    Ok(Some(branch))
+}
+
+
+fn branches(architecture: &Architecture, memory: &Memory, function: &falcon::il::Function)
+    -> Result<Vec<IndirectBranch>> {
+
+    let mut value_sets = None;
+    let mut branches = Vec::new();
+
+    for block in function.blocks() {
+        for instruction in block.instructions() {
+            let target = match *instruction.operation() {
+                falcon::il::Operation::Branch { ref target } => target,
+                _ => continue
+            };
+            if let falcon::il::Expression::Constant(_) = *target {
+                continue;
+            }
+            // Calls, and returns through the stack or the return address
+            // register, are not jump tables.
+            let returns =
+                il::branch_returns(architecture, function, block.index(), instruction.index());
+            if returns.is_some() {
+                continue;
+            }
+
+            if value_sets.is_none() {
+                value_sets = Some(value_set::value_sets(architecture, function)?);
+            }
+            let value_sets = value_sets.as_ref().unwrap();
+
+            let branch = resolve(
+                architecture, memory, function, value_sets, block, instruction, target)?;
+            if let Some(branch) = branch {
+                branches.push(branch);
+            }
+        }
+    }
+
+    Ok(branches)
+}
+
+
+/// The indirect branches in `function`, resolved where possible.
+pub fn indirect_branches(loader: &Loader, function: &falcon::il::Function)
+    -> Result<Vec<IndirectBranch>> {
+
+    branches(loader.architecture(), &loader.memory()?, function)
+}
+
+
+/// The first native address of `block`.
+fn block_address(block: &falcon::il::Block) -> Option<u64> {
+    block.instructions().iter().filter_map(|instruction| instruction.address()).next()
+}
+
+
+/// Add edges from the resolved `branch` to its targets.
+fn add_targets(
+    architecture: &Architecture,
+    memory: &Memory,
+    function: &mut falcon::il::Function,
+    branch: &IndirectBranch
+) -> Result<()> {
+
+    let (block_index, instruction_index) = match branch.location {
+        falcon::il::FunctionLocation::Instruction(block, instruction) => (block, instruction),
+        _ => return Ok(())
+    };
+
+    let target = match *function.block(block_index)?
+        .instruction(instruction_index)
+        .ok_or("branch missing from its block")?
+        .operation() {
+        falcon::il::Operation::Branch { ref target } => target.clone(),
+        _ => return Ok(())
+    };
+
+    let mut blocks: HashMap<u64, usize> = function.blocks()
+        .into_iter()
+        .filter_map(|block| block_address(block).map(|address| (address, block.index())))
+        .collect();
+    let translator = architecture.translator();
+
+    for &address in &branch.targets {
+        let tail = match blocks.get(&address).cloned() {
+            Some(tail) => tail,
+            None => {
+                let lifted = translator.translate_function(memory, address)?;
+                let lifted = lifted.control_flow_graph();
+                let entry = lifted.entry().ok_or("lifted target has no entry block")?;
+                let indices = function.control_flow_graph_mut().insert(lifted)?;
+                for block in lifted.blocks() {
+                    if let (Some(address), Some(&index)) =
+                        (block_address(block), indices.get(&block.index())) {
+                        blocks.entry(address).or_insert(index);
+                    }
+                }
+                *indices.get(&entry).ok_or("lifted entry block was not inserted")?
+            }
+        };
+
+        let condition = falcon::il::Expression::cmpeq(
+            target.clone(), falcon::il::expr_const(address, target.bits()))?;
+        function.control_flow_graph_mut().conditional_edge(block_index, tail, condition)?;
+    }
+
+    *function.control_flow_graph_mut()
+        .block_mut(block_index)?
+        .instruction_mut(instruction_index)
+        .ok_or("branch missing from its block")?
+        .operation_mut() = falcon::il::Operation::Nop;
+
+    Ok(())
+}
+
+
+/// A copy of `function` with its jump tables resolved, and every indirect
+/// branch found, resolved or not.
+pub fn resolve_function(loader: &Loader, function: &falcon::il::Function)
+    -> Result<(falcon::il::Function, Vec<IndirectBranch>)> {
+
+    let architecture = loader.architecture();
+    let memory = loader.memory()?;
+
+    let mut function = function.clone();
+    let mut resolved = Vec::new();
+    let mut unresolved = Vec::new();
+
+    // Unresolved branches are found again every round, so only the last
+    // round's are kept.
+    for _ in 0..MAX_ROUNDS {
+        let (done, remaining): (Vec<IndirectBranch>, Vec<IndirectBranch>) =
+            branches(architecture, &memory, &function)?
+                .into_iter()
+                .partition(|branch| branch.resolved());
+        unresolved = remaining;
+        if done.is_empty() {
+            break;
+        }
+        for branch in done {
+            add_targets(architecture, &memory, &mut function, &branch)?;
+            resolved.push(branch);
+        }
+    }
+    resolved.extend(unresolved);
+
+    Ok((function, resolved))
+}
+
+
+/// A copy of `program` with the jump tables of every function resolved, and
+/// every indirect branch found. A function which fails to resolve is kept
+/// as it was, and recorded in the resolution's failures.
+pub fn resolve_program(loader: &Loader, program: &falcon::il::Program)
+    -> (falcon::il::Program, Resolution) {
+
+    let mut resolved = falcon::il::Program::new();
+    let mut resolution = Resolution::default();
+
+    for function in program.functions() {
+        match resolve_function(loader, function) {
+            Ok((function, branches)) => {
+                resolved.add_function(function);
+                resolution.branches.extend(branches);
+            },
+            Err(e) => {
+                resolution.failures.push((function.name(), format!("{}", e)));
+                resolved.add_function(function.clone());
+            }
+        }
+    }
+
+    (resolved, resolution)
+}
@@ -17,11 +17,84 @@ macro_rules! falcon_type_wrapper {
 }
 
 
+/// Generates `eq`, `compare` and `hash` primitives for a wrapper type whose
+/// wrapped Falcon type implements `Eq`, `Ord` and `Hash`. `compare` returns
+/// -1, 0 or 1 so gluon can map it onto `Ordering`.
+///
+/// Types which do not implement them are compared by a key instead, given as
+/// a function from the dereferenced wrapped value.
+#[macro_use]
+macro_rules! falcon_type_ord {
+    ($n: ident, $eq: ident, $compare: ident, $hash: ident) => {
+        fn $eq(lhs: &$n, rhs: &$n) -> bool {
+            lhs.x == rhs.x
+        }
+
+        fn $compare(lhs: &$n, rhs: &$n) -> i32 {
+            match lhs.x.cmp(&rhs.x) {
+                ::std::cmp::Ordering::Less => -1,
+                ::std::cmp::Ordering::Equal => 0,
+                ::std::cmp::Ordering::Greater => 1
+            }
+        }
+
+        fn $hash(v: &$n) -> u64 {
+            use std::hash::{Hash, Hasher};
+            let mut hasher = ::std::collections::hash_map::DefaultHasher::new();
+            v.x.hash(&mut hasher);
+            hasher.finish()
+        }
+    };
+    ($n: ident, $eq: ident, $compare: ident, $hash: ident, $key: ident) => {
+        fn $eq(lhs: &$n, rhs: &$n) -> bool {
+            $key(&*lhs.x) == $key(&*rhs.x)
+        }
+
+        fn $compare(lhs: &$n, rhs: &$n) -> i32 {
+            match $key(&*lhs.x).cmp(&$key(&*rhs.x)) {
+                ::std::cmp::Ordering::Less => -1,
+                ::std::cmp::Ordering::Equal => 0,
+                ::std::cmp::Ordering::Greater => 1
+            }
+        }
+
+        fn $hash(v: &$n) -> u64 {
+            use std::hash::{Hash, Hasher};
+            let mut hasher = ::std::collections::hash_map::DefaultHasher::new();
+            $key(&*v.x).hash(&mut hasher);
+            hasher.finish()
+        }
+    };
+}
+
+
 pub mod analysis;
 pub mod architecture;
+pub mod call_site;
+pub mod check;
+pub mod dataflow;
+pub mod discovery;
+pub mod dwarf;
+pub mod format_string;
+pub mod handle;
+pub mod hardening;
 pub mod il;
+pub mod image;
+pub mod jump_table;
 pub mod loader;
 pub mod memory;
+pub mod output;
+pub mod query;
+pub mod search;
+pub mod slice;
+pub mod stack_frame;
+pub mod stack_overflow;
+pub mod strings;
+pub mod taint;
+pub mod unchecked_return;
+pub mod unsafe_call;
+pub mod value_set;
+pub mod xref;
 
 
 fn hex(v: u64) -> String {
@@ -81,10 +154,28 @@ pub fn attach_bindings(vm: gluon::RootedThread) -> gluon::RootedThread {
     let vm = il::bindings(vm);
     let vm = memory::bindings(vm);
     let vm = loader::bindings(vm);
+    let vm = query::bindings(vm);
+    let vm = check::bindings(vm);
+    let vm = output::bindings(vm);
     vm
 }
 
 
+/// Run a gluon script which evaluates to an array of checkers, built with
+/// `check.checker.new`.
+pub fn load_checkers(code: &str) -> Result<Vec<std::sync::Arc<check::Checker>>, String> {
+    let vm = gluon::new_vm();
+
+    let vm = attach_bindings(vm);
+
+    let mut compiler = gluon::Compiler::new();
+    let (checkers, _) = compiler.run_expr::<Vec<check::CheckChecker>>(&vm, "checkers", code)
+        .map_err(|e| format!("{}", e))?;
+
+    Ok(checkers.into_iter().map(|checker| checker.x).collect())
+}
+
+
 pub fn run_code(code: &str) -> gluon::RootedThread {
     let vm = gluon::new_vm();
 
@@ -7,7 +7,11 @@ use std::path::Path;
 use std::sync::Arc;
 
 use architecture;
+use discovery;
+use hardening;
 use il;
+use image;
+use jump_table;
 use memory;
 
 falcon_type_wrapper!(Arc<falcon::loader::Elf>, LoaderElf);
@@ -39,7 +43,7 @@ fn elf_from_file(filename: String) -> LoaderElf {
 }
 
 fn elf_function(elf: &LoaderElf, address: u64) -> il::IlFunction {
-    il::IlFunction { x: elf.x.function(address).unwrap() }
+    il::IlFunction { x: elf.x.function(address).unwrap().into() }
 }
 
 fn elf_memory(elf: &LoaderElf) -> memory::BackingMemory {
@@ -47,11 +51,11 @@ fn elf_memory(elf: &LoaderElf) -> memory::BackingMemory {
 }
 
 fn elf_program(elf: &LoaderElf) -> il::IlProgram {
-    il::IlProgram { x: elf.x.program().unwrap() }
+    il::IlProgram { x: Arc::new(elf.x.program().unwrap()) }
 }
 
 fn elf_program_recursive(elf: &LoaderElf) -> il::IlProgram {
-    il::IlProgram { x: elf.x.program_recursive().unwrap() }
+    il::IlProgram { x: Arc::new(program_recursive(&*elf.x).unwrap()) }
 }
 
 
@@ -68,7 +72,7 @@ fn elf_linker_architecture(elf_linker: &LoaderElfLinker)
 fn elf_linker_function(elf_linker: &LoaderElfLinker, address: u64)
     -> Option<il::IlFunction> {
 
-    elf_linker.x.function(address).ok().map(|f| il::IlFunction {x: f})
+    elf_linker.x.function(address).ok().map(|f| il::IlFunction { x: f.into() })
 }
 
 fn elf_linker_function_entries(elf_linker: &LoaderElfLinker)
@@ -102,7 +106,7 @@ fn elf_linker_new(
 }
 
 fn elf_linker_program(elf_linker: &LoaderElfLinker) -> il::IlProgram {
-    il::IlProgram { x: elf_linker.x.program().unwrap() }
+    il::IlProgram { x: Arc::new(elf_linker.x.program().unwrap()) }
 }
 
 fn elf_linker_program_entry(elf_linker: &LoaderElfLinker) -> u64 {
@@ -110,7 +114,7 @@ fn elf_linker_program_entry(elf_linker: &LoaderElfLinker) -> u64 {
 }
 
 fn elf_linker_program_recursive(elf_linker: &LoaderElfLinker) -> il::IlProgram {
-    il::IlProgram { x: elf_linker.x.program_recursive().unwrap() }
+    il::IlProgram { x: Arc::new(program_recursive(&*elf_linker.x).unwrap()) }
 }
 
 
@@ -130,7 +134,7 @@ fn pe_from_file(filename: String) -> LoaderPe {
 }
 
 fn pe_function(pe: &LoaderPe, address: u64) -> il::IlFunction {
-    il::IlFunction { x: pe.x.function(address).unwrap() }
+    il::IlFunction { x: pe.x.function(address).unwrap().into() }
 }
 
 fn pe_function_entries(pe: &LoaderPe) -> Vec<LoaderFunctionEntry> {
@@ -147,31 +151,43 @@ fn pe_memory(pe: &LoaderPe) -> memory::BackingMemory {
 }
 
 fn pe_program(pe: &LoaderPe) -> il::IlProgram {
-    il::IlProgram { x: pe.x.program().unwrap() }
+    il::IlProgram { x: Arc::new(pe.x.program().unwrap()) }
 }
 
 fn pe_program_recursive(pe: &LoaderPe) -> il::IlProgram {
-    il::IlProgram { x: pe.x.program_recursive().unwrap() }
+    il::IlProgram { x: Arc::new(program_recursive(&*pe.x).unwrap()) }
 }
 
 falcon_type_wrapper!(Arc<falcon::loader::Loader>, LoaderLoader);
 
-fn loader_from_file(filename: String) -> Option<LoaderLoader> {
-    let path = Path::new(&filename);
+/// Load `path` as an ELF, falling back to a PE.
+pub fn load(path: &Path) -> Option<Arc<falcon::loader::Loader>> {
+    if let Ok(elf) = falcon::loader::Elf::from_file(path) {
+        return Some(Arc::new(elf));
+    }
 
-    let loader = falcon::loader::Elf::from_file(&path)
+    falcon::loader::Pe::from_file(path)
         .ok()
-        .map(|elf| Arc::new(elf));
+        .map(|pe| Arc::new(pe) as Arc<falcon::loader::Loader>)
+}
 
-    if let Some(loader) = loader {
-        return Some(LoaderLoader {x: loader});
-    }
+/// Lift every function reachable from `loader`'s function entries, with jump
+/// tables resolved. Use `program_recursive_resolution` to learn which
+/// branches were not resolved, and which functions failed to resolve.
+pub fn program_recursive(loader: &Loader) -> falcon::error::Result<falcon::il::Program> {
+    program_recursive_resolution(loader).map(|(program, _)| program)
+}
+
+/// `program_recursive`, with the indirect branches found resolving it.
+pub fn program_recursive_resolution(loader: &Loader)
+    -> falcon::error::Result<(falcon::il::Program, jump_table::Resolution)> {
 
-    let loader = falcon::loader::Pe::from_file(&path)
-            .ok()
-            .map(|pe| Arc::new(pe))?;
+    let program = loader.program_recursive()?;
+    Ok(jump_table::resolve_program(loader, &program))
+}
 
-    Some(LoaderLoader { x: loader })
+fn loader_from_file(filename: String) -> Option<LoaderLoader> {
+    load(Path::new(&filename)).map(|loader| LoaderLoader { x: loader })
 }
 
 fn loader_architecture(loader: &LoaderLoader) -> architecture::ArchitectureArchitecture {
@@ -180,8 +196,29 @@ fn loader_architecture(loader: &LoaderLoader) -> architecture::ArchitectureArchi
     }
 }
 
+fn loader_discover_functions(loader: &LoaderLoader, filename: String)
+    -> Result<Vec<LoaderFunctionCandidate>, String> {
+
+    // Exception tables are read from the file when it is still there.
+    let bytes = ::std::fs::read(&filename).ok();
+    discovery::discover(&*loader.x, bytes.as_ref().map(|bytes| bytes.as_slice()))
+        .map(|candidates| candidates.into_iter()
+            .map(|candidate| LoaderFunctionCandidate { x: candidate })
+            .collect())
+        .map_err(|e| format!("{}", e))
+}
+
+fn loader_hardening(loader: &LoaderLoader, filename: String)
+    -> Result<LoaderHardening, String> {
+
+    let bytes = ::std::fs::read(&filename).map_err(|e| format!("{}: {}", filename, e))?;
+    hardening::hardening(&*loader.x, &bytes)
+        .map(|hardening| LoaderHardening { x: hardening })
+        .map_err(|e| format!("{}", e))
+}
+
 fn loader_function(loader: &LoaderLoader, address: u64) -> il::IlFunction {
-    il::IlFunction { x: loader.x.function(address).unwrap() }
+    il::IlFunction { x: loader.x.function(address).unwrap().into() }
 }
 
 fn loader_function_entries(loader: &LoaderLoader) -> Vec<LoaderFunctionEntry> {
@@ -198,11 +235,11 @@ fn loader_memory(loader: &LoaderLoader) -> memory::BackingMemory {
 }
 
 fn loader_program(loader: &LoaderLoader) -> il::IlProgram {
-    il::IlProgram { x: loader.x.program().unwrap() }
+    il::IlProgram { x: Arc::new(loader.x.program().unwrap()) }
 }
 
 fn loader_program_recursive(loader: &LoaderLoader) -> il::IlProgram {
-    il::IlProgram { x: loader.x.program_recursive().unwrap() }
+    il::IlProgram { x: Arc::new(program_recursive(&*loader.x).unwrap()) }
 }
 
 falcon_type_wrapper!(falcon::loader::FunctionEntry, LoaderFunctionEntry);
@@ -219,12 +256,131 @@ fn function_entry_str(function_entry: &LoaderFunctionEntry) -> String {
     format!("{}", function_entry.x)
 }
 
+// FunctionEntry does not implement the comparison traits, so entries are
+// compared by address, then name.
+fn function_entry_key(function_entry: &LoaderFunctionEntry) -> (u64, Option<&str>) {
+    (function_entry.x.address(), function_entry.x.name())
+}
+
+fn function_entry_eq(lhs: &LoaderFunctionEntry, rhs: &LoaderFunctionEntry) -> bool {
+    function_entry_key(lhs) == function_entry_key(rhs)
+}
+
+fn function_entry_compare(lhs: &LoaderFunctionEntry, rhs: &LoaderFunctionEntry) -> i32 {
+    match function_entry_key(lhs).cmp(&function_entry_key(rhs)) {
+        ::std::cmp::Ordering::Less => -1,
+        ::std::cmp::Ordering::Equal => 0,
+        ::std::cmp::Ordering::Greater => 1
+    }
+}
+
+fn function_entry_hash(function_entry: &LoaderFunctionEntry) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = ::std::collections::hash_map::DefaultHasher::new();
+    function_entry_key(function_entry).hash(&mut hasher);
+    hasher.finish()
+}
+
+
+falcon_type_wrapper!(discovery::Candidate, LoaderFunctionCandidate);
+
+fn function_candidate_address(candidate: &LoaderFunctionCandidate) -> u64 {
+    candidate.x.address
+}
+
+fn function_candidate_confidence(candidate: &LoaderFunctionCandidate) -> f64 {
+    candidate.x.confidence
+}
+
+fn function_candidate_entry(candidate: &LoaderFunctionCandidate) -> LoaderFunctionEntry {
+    LoaderFunctionEntry { x: candidate.x.function_entry() }
+}
+
+fn function_candidate_sources(candidate: &LoaderFunctionCandidate) -> Vec<String> {
+    candidate.x.sources.iter().map(|source| format!("{}", source)).collect()
+}
+
+fn function_candidate_str(candidate: &LoaderFunctionCandidate) -> String {
+    format!("{}", candidate.x)
+}
+
+
+falcon_type_wrapper!(hardening::Hardening, LoaderHardening);
+
+fn hardening_aslr(hardening: &LoaderHardening) -> Option<bool> {
+    hardening.x.aslr
+}
+
+fn hardening_canary(hardening: &LoaderHardening) -> Option<bool> {
+    hardening.x.canary
+}
+
+fn hardening_cfg(hardening: &LoaderHardening) -> Option<bool> {
+    hardening.x.cfg
+}
+
+fn hardening_dep(hardening: &LoaderHardening) -> Option<bool> {
+    hardening.x.dep
+}
+
+fn hardening_format(hardening: &LoaderHardening) -> String {
+    match hardening.x.format {
+        image::Format::Elf => "elf".to_string(),
+        image::Format::Pe => "pe".to_string()
+    }
+}
+
+fn hardening_fortified(hardening: &LoaderHardening) -> Vec<String> {
+    hardening.x.fortified.clone()
+}
+
+fn hardening_fortify(hardening: &LoaderHardening) -> Option<bool> {
+    hardening.x.fortify
+}
+
+fn hardening_gs(hardening: &LoaderHardening) -> Option<bool> {
+    hardening.x.gs
+}
+
+fn hardening_high_entropy_va(hardening: &LoaderHardening) -> Option<bool> {
+    hardening.x.high_entropy_va
+}
+
+fn hardening_nx(hardening: &LoaderHardening) -> Option<bool> {
+    hardening.x.nx
+}
+
+fn hardening_pie(hardening: &LoaderHardening) -> Option<bool> {
+    hardening.x.pie
+}
+
+fn hardening_properties(hardening: &LoaderHardening) -> Vec<(String, String)> {
+    hardening.x.properties()
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect()
+}
+
+fn hardening_relro(hardening: &LoaderHardening) -> Option<String> {
+    hardening.x.relro.map(|relro| format!("{}", relro))
+}
+
+fn hardening_safe_seh(hardening: &LoaderHardening) -> Option<bool> {
+    hardening.x.safe_seh
+}
+
+fn hardening_str(hardening: &LoaderHardening) -> String {
+    format!("{}", hardening.x)
+}
+
 
 pub fn bindings(vm: gluon::RootedThread) -> gluon::RootedThread {
 
     vm.register_type::<LoaderElf>("LoaderElf", &[]).unwrap();
     vm.register_type::<LoaderElfLinker>("LoaderElfLinker", &[]).unwrap();
+    vm.register_type::<LoaderFunctionCandidate>("LoaderFunctionCandidate", &[]).unwrap();
     vm.register_type::<LoaderFunctionEntry>("LoaderFunctionEntry", &[]).unwrap();
+    vm.register_type::<LoaderHardening>("LoaderHardening", &[]).unwrap();
     vm.register_type::<LoaderLoader>("LoaderLoader", &[]).unwrap();
     vm.register_type::<LoaderPe>("LoaderPe", &[]).unwrap();
 
@@ -248,12 +404,37 @@ pub fn bindings(vm: gluon::RootedThread) -> gluon::RootedThread {
             elf_linker_program => primitive!(1, elf_linker_program),
             elf_linker_program_entry => primitive!(1, elf_linker_program_entry),
             elf_linker_program_recursive => primitive!(1, elf_linker_program_recursive),
+            function_candidate_address => primitive!(1, function_candidate_address),
+            function_candidate_confidence => primitive!(1, function_candidate_confidence),
+            function_candidate_entry => primitive!(1, function_candidate_entry),
+            function_candidate_sources => primitive!(1, function_candidate_sources),
+            function_candidate_str => primitive!(1, function_candidate_str),
             function_entry_name => primitive!(1, function_entry_name),
             function_entry_address => primitive!(1, function_entry_address),
             function_entry_str => primitive!(1, function_entry_str),
+            function_entry_eq => primitive!(2, function_entry_eq),
+            function_entry_compare => primitive!(2, function_entry_compare),
+            function_entry_hash => primitive!(1, function_entry_hash),
+            hardening_aslr => primitive!(1, hardening_aslr),
+            hardening_canary => primitive!(1, hardening_canary),
+            hardening_cfg => primitive!(1, hardening_cfg),
+            hardening_dep => primitive!(1, hardening_dep),
+            hardening_format => primitive!(1, hardening_format),
+            hardening_fortified => primitive!(1, hardening_fortified),
+            hardening_fortify => primitive!(1, hardening_fortify),
+            hardening_gs => primitive!(1, hardening_gs),
+            hardening_high_entropy_va => primitive!(1, hardening_high_entropy_va),
+            hardening_nx => primitive!(1, hardening_nx),
+            hardening_pie => primitive!(1, hardening_pie),
+            hardening_properties => primitive!(1, hardening_properties),
+            hardening_relro => primitive!(1, hardening_relro),
+            hardening_safe_seh => primitive!(1, hardening_safe_seh),
+            hardening_str => primitive!(1, hardening_str),
             loader_architecture => primitive!(1, loader_architecture),
+            loader_discover_functions => primitive!(2, loader_discover_functions),
             loader_from_file => primitive!(1, loader_from_file),
             loader_function => primitive!(2, loader_function),
+            loader_hardening => primitive!(2, loader_hardening),
             loader_function_entries => primitive!(1, loader_function_entries),
             loader_memory => primitive!(1, loader_memory),
             loader_program => primitive!(1, loader_program),
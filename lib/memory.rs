@@ -3,12 +3,66 @@ use gluon::vm::api::{Userdata, VmType};
 use gluon::vm::thread::{Traverseable};
 use gluon;
 
+use architecture;
+use strings;
+
 falcon_type_wrapper!(memory::backing::Memory, BackingMemory);
 
+fn memory_strings(
+    memory: &BackingMemory,
+    architecture: &architecture::ArchitectureArchitecture,
+    min_length: usize
+) -> Vec<MemoryString> {
+    strings::strings(&**architecture.x, &memory.x, min_length)
+        .into_iter()
+        .map(|string| MemoryString { x: string })
+        .collect()
+}
+
+
+falcon_type_wrapper!(strings::MemoryString, MemoryString);
+
+fn string_address(string: &MemoryString) -> u64 {
+    string.x.address
+}
+
+fn string_encoding(string: &MemoryString) -> String {
+    format!("{}", string.x.encoding)
+}
+
+fn string_size(string: &MemoryString) -> usize {
+    string.x.size()
+}
+
+fn string_str(string: &MemoryString) -> String {
+    format!("{}", string.x)
+}
+
+fn string_value(string: &MemoryString) -> String {
+    string.x.value.clone()
+}
+
 
 pub fn bindings (vm: gluon::RootedThread) -> gluon::RootedThread {
 
     vm.register_type::<BackingMemory>("BackingMemory", &[]).unwrap();
+    vm.register_type::<MemoryString>("MemoryString", &[]).unwrap();
+
+    fn falcon_memory_prim_loader(vm: &gluon::Thread)
+        -> gluon::vm::Result<gluon::vm::ExternModule> {
+
+        gluon::vm::ExternModule::new(vm, record! {
+            memory_strings => primitive!(3, memory_strings),
+            string_address => primitive!(1, string_address),
+            string_encoding => primitive!(1, string_encoding),
+            string_size => primitive!(1, string_size),
+            string_str => primitive!(1, string_str),
+            string_value => primitive!(1, string_value)
+        })
+    }
+
+    gluon::import::add_extern_module(
+        &vm, "falcon_memory_prim", falcon_memory_prim_loader);
     
     vm
-}
\ No newline at end of file
+}
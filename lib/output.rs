@@ -0,0 +1,615 @@
+//! Serializing findings and script results.
+//!
+//! Reports render as plain text, as JSON lines with one finding per line, or
+//! as a SARIF 2.1.0 log. Findings are annotated with the file offset and
+//! symbol of their native address where those can be recovered, and with the
+//! source line when the binary carries DWARF line tables.
+
+use falcon;
+use gluon::vm::api::{Userdata, VmType};
+use gluon::vm::thread::{Traverseable};
+use gluon;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use check::{self, Checker};
+use dwarf;
+use il;
+use image;
+
+
+/// A JSON value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    /// An unsigned integer, for addresses and offsets.
+    Uint(u64),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Fields are written in order.
+    Object(Vec<(String, Json)>)
+}
+
+impl Json {
+    pub fn string<S: Into<String>>(s: S) -> Json {
+        Json::String(s.into())
+    }
+
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(fields.into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect())
+    }
+
+    /// Addresses and offsets are always written as integers.
+    pub fn address(address: u64) -> Json {
+        Json::Uint(address)
+    }
+
+    pub fn option<T, F: Fn(T) -> Json>(value: Option<T>, f: F) -> Json {
+        value.map(f).unwrap_or(Json::Null)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Int(i) => write!(f, "{}", i),
+            Json::Uint(u) => write!(f, "{}", u),
+            Json::Float(x) if x.is_finite() => write!(f, "{}", x),
+            Json::Float(_) => write!(f, "null"),
+            Json::String(ref s) => write_string(f, s),
+            Json::Array(ref values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(ref fields) => {
+                write!(f, "{{")?;
+                for (i, &(ref name, ref value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, name)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+
+/// A report output format.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Sarif
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "sarif" => Ok(Format::Sarif),
+            _ => Err(format!("unknown output format {}", s))
+        }
+    }
+}
+
+
+/// Maps native addresses to file offsets, symbols and source lines.
+#[derive(Clone, Debug, Default)]
+pub struct Symbolizer {
+    segments: Vec<image::Segment>,
+    symbols: BTreeMap<u64, String>,
+    functions: BTreeMap<usize, String>,
+    debug_info: Option<Arc<dwarf::DebugInfo>>
+}
+
+impl Symbolizer {
+    /// A symbolizer for `program`, lifted from the file `bytes`, where
+    /// `symbols` names addresses the program does not.
+    pub fn new(
+        bytes: Option<&[u8]>,
+        program: &falcon::il::Program,
+        symbols: &[(u64, String)]
+    ) -> Symbolizer {
+
+        let mut names: BTreeMap<u64, String> = symbols.iter().cloned().collect();
+        let mut functions = BTreeMap::new();
+        for function in program.functions() {
+            names.insert(function.address(), function.name());
+            if let Some(index) = function.index() {
+                functions.insert(index, function.name());
+            }
+        }
+
+        Symbolizer {
+            segments: bytes.and_then(|bytes| image::segments(bytes).ok())
+                .unwrap_or(Vec::new()),
+            symbols: names,
+            functions: functions,
+            debug_info: bytes.and_then(|bytes| dwarf::DebugInfo::parse(bytes).ok())
+                .and_then(|debug_info| debug_info)
+                .map(Arc::new)
+        }
+    }
+
+    /// The file offset `address` is loaded from.
+    pub fn offset(&self, address: u64) -> Option<u64> {
+        self.segments.iter().filter_map(|segment| segment.offset_of(address)).next()
+    }
+
+    /// The nearest symbol at or before `address`, as `name` or `name+0x10`.
+    pub fn symbol(&self, address: u64) -> Option<String> {
+        let (base, name) = self.symbols.range(..=address).next_back()?;
+        if *base == address {
+            Some(name.clone())
+        }
+        else {
+            Some(format!("{}+0x{:x}", name, address - base))
+        }
+    }
+
+    /// The source line `address` was compiled from.
+    pub fn source(&self, address: u64) -> Option<&dwarf::SourceLocation> {
+        self.debug_info.as_ref().and_then(|debug_info| debug_info.source(address))
+    }
+
+    /// The name of the function containing `location`.
+    pub fn function(&self, location: &falcon::il::ProgramLocation) -> Option<String> {
+        location.function_index().and_then(|index| self.functions.get(&index).cloned())
+    }
+}
+
+
+fn location_json(
+    symbolizer: &Symbolizer,
+    location: Option<&falcon::il::ProgramLocation>,
+    address: Option<u64>
+) -> Json {
+
+    Json::object(vec![
+        ("address", Json::option(address, Json::address)),
+        ("offset", Json::option(address.and_then(|a| symbolizer.offset(a)), Json::address)),
+        ("symbol", Json::option(address.and_then(|a| symbolizer.symbol(a)), Json::string)),
+        ("function", Json::option(location.and_then(|l| symbolizer.function(l)), Json::string)),
+        ("location", Json::option(location, |l| Json::string(format!("{}", l)))),
+        ("source", Json::option(address.and_then(|a| symbolizer.source(a)), |source|
+            Json::object(vec![
+                ("file", Json::string(source.file.clone())),
+                ("line", Json::Int(source.line as i64)),
+                ("column", Json::Int(source.column as i64))
+            ])))
+    ])
+}
+
+/// The native address of `location`, if it is an instruction with one.
+fn location_address(
+    program: &falcon::il::Program,
+    location: &falcon::il::ProgramLocation
+) -> Option<u64> {
+    location.apply(program).ok()?.instruction()?.address()
+}
+
+
+/// A finding as a JSON object.
+pub fn finding_json(
+    symbolizer: &Symbolizer,
+    program: &falcon::il::Program,
+    finding: &check::Finding
+) -> Json {
+
+    let mut fields = vec![
+        ("rule".to_string(), Json::string(finding.rule.clone())),
+        ("severity".to_string(), Json::string(format!("{}", finding.severity))),
+        ("message".to_string(), Json::string(finding.message.clone()))
+    ];
+    if let Json::Object(location) =
+        location_json(symbolizer, finding.location.as_ref(), finding.address) {
+        fields.extend(location.into_iter());
+    }
+    let related = finding.related.iter()
+        .map(|location| location_json(
+            symbolizer, Some(location), location_address(program, location)))
+        .collect();
+    fields.push(("related".to_string(), Json::Array(related)));
+
+    Json::Object(fields)
+}
+
+
+/// A report as JSON lines, one finding per line.
+pub fn json_lines(
+    symbolizer: &Symbolizer,
+    program: &falcon::il::Program,
+    report: &check::Report
+) -> String {
+
+    report.findings.iter()
+        .map(|finding| format!("{}\n", finding_json(symbolizer, program, finding)))
+        .collect()
+}
+
+
+fn sarif_level(severity: check::Severity) -> &'static str {
+    match severity {
+        check::Severity::Critical | check::Severity::High => "error",
+        check::Severity::Medium => "warning",
+        check::Severity::Low | check::Severity::Info => "note"
+    }
+}
+
+fn sarif_location(
+    symbolizer: &Symbolizer,
+    uri: &str,
+    location: Option<&falcon::il::ProgramLocation>,
+    address: Option<u64>,
+    message: Option<String>
+) -> Json {
+
+    // A location with a source line points at the source file rather than
+    // the binary.
+    let mut physical = match address.and_then(|address| symbolizer.source(address)) {
+        Some(source) => {
+            let mut region = vec![("startLine", Json::Int(source.line as i64))];
+            if source.column > 0 {
+                region.push(("startColumn", Json::Int(source.column as i64)));
+            }
+            vec![
+                ("artifactLocation", Json::object(vec![
+                    ("uri", Json::string(source.file.clone()))
+                ])),
+                ("region", Json::object(region))
+            ]
+        },
+        None => {
+            let mut physical = vec![
+                ("artifactLocation", Json::object(vec![
+                    ("uri", Json::string(uri)),
+                    ("index", Json::Int(0))
+                ]))
+            ];
+            if let Some(offset) = address.and_then(|address| symbolizer.offset(address)) {
+                physical.push(("region", Json::object(vec![
+                    ("byteOffset", Json::address(offset))
+                ])));
+            }
+            physical
+        }
+    };
+    if let Some(address) = address {
+        let mut fields = vec![("absoluteAddress", Json::address(address))];
+        if let Some(symbol) = symbolizer.symbol(address) {
+            fields.push(("name", Json::string(symbol)));
+        }
+        physical.push(("address", Json::object(fields)));
+    }
+
+    let mut fields = vec![("physicalLocation", Json::object(physical))];
+    if let Some(function) = location.and_then(|location| symbolizer.function(location)) {
+        fields.push(("logicalLocations", Json::Array(vec![Json::object(vec![
+            ("name", Json::string(function)),
+            ("kind", Json::string("function"))
+        ])])));
+    }
+    if let Some(message) = message {
+        fields.push(("message", Json::object(vec![("text", Json::string(message))])));
+    }
+    Json::object(fields)
+}
+
+
+/// A report as a SARIF 2.1.0 log. `rules` gives the id and description of
+/// every rule which ran, and `uri` names the analyzed binary.
+pub fn sarif(
+    symbolizer: &Symbolizer,
+    program: &falcon::il::Program,
+    report: &check::Report,
+    rules: &[(String, String)],
+    uri: &str
+) -> Json {
+
+    let rule_index = |id: &str| rules.iter().position(|&(ref rule, _)| rule == id);
+
+    let results = report.findings.iter()
+        .map(|finding| {
+            let mut fields = vec![
+                ("ruleId", Json::string(finding.rule.clone())),
+                ("level", Json::string(sarif_level(finding.severity))),
+                ("message", Json::object(vec![
+                    ("text", Json::string(finding.message.clone()))
+                ])),
+                ("locations", Json::Array(vec![sarif_location(
+                    symbolizer, uri, finding.location.as_ref(), finding.address, None)])),
+                ("properties", Json::object(vec![
+                    ("severity", Json::string(format!("{}", finding.severity))),
+                    ("location", Json::option(finding.location.as_ref(),
+                        |l| Json::string(format!("{}", l))))
+                ]))
+            ];
+            if let Some(index) = rule_index(&finding.rule) {
+                fields.push(("ruleIndex", Json::Int(index as i64)));
+            }
+            if !finding.related.is_empty() {
+                fields.push(("relatedLocations", Json::Array(finding.related.iter()
+                    .enumerate()
+                    .map(|(i, location)| {
+                        let location = sarif_location(
+                            symbolizer,
+                            uri,
+                            Some(location),
+                            location_address(program, location),
+                            Some(format!("{}", location)));
+                        match location {
+                            Json::Object(mut fields) => {
+                                fields.insert(0, ("id".to_string(), Json::Int(i as i64)));
+                                Json::Object(fields)
+                            },
+                            location => location
+                        }
+                    })
+                    .collect())));
+            }
+            Json::object(fields)
+        })
+        .collect();
+
+    let rules = rules.iter()
+        .map(|&(ref id, ref description)| Json::object(vec![
+            ("id", Json::string(id.clone())),
+            ("shortDescription", Json::object(vec![
+                ("text", Json::string(description.clone()))
+            ]))
+        ]))
+        .collect();
+
+    Json::object(vec![
+        ("$schema", Json::string("https://json.schemastore.org/sarif-2.1.0.json")),
+        ("version", Json::string("2.1.0")),
+        ("runs", Json::Array(vec![Json::object(vec![
+            ("tool", Json::object(vec![
+                ("driver", Json::object(vec![
+                    ("name", Json::string("osprey")),
+                    ("version", Json::string(env!("CARGO_PKG_VERSION"))),
+                    ("informationUri", Json::string("https://github.com/falconre/osprey")),
+                    ("rules", Json::Array(rules))
+                ]))
+            ])),
+            ("artifacts", Json::Array(vec![Json::object(vec![
+                ("location", Json::object(vec![("uri", Json::string(uri))]))
+            ])])),
+            ("results", Json::Array(results))
+        ])]))
+    ])
+}
+
+
+/// Render `report` in `format`.
+pub fn render(
+    format: Format,
+    symbolizer: &Symbolizer,
+    program: &falcon::il::Program,
+    report: &check::Report,
+    rules: &[(String, String)],
+    uri: &str
+) -> String {
+
+    match format {
+        Format::Text => format!("{}", report),
+        Format::Json => json_lines(symbolizer, program, report),
+        Format::Sarif => format!("{}\n", sarif(symbolizer, program, report, rules, uri))
+    }
+}
+
+
+falcon_type_wrapper!(Json, OutputJson);
+
+fn json_null(_: ()) -> OutputJson {
+    OutputJson { x: Json::Null }
+}
+
+fn json_bool(b: bool) -> OutputJson {
+    OutputJson { x: Json::Bool(b) }
+}
+
+fn json_int(i: i64) -> OutputJson {
+    OutputJson { x: Json::Int(i) }
+}
+
+fn json_float(x: f64) -> OutputJson {
+    OutputJson { x: Json::Float(x) }
+}
+
+fn json_string(s: String) -> OutputJson {
+    OutputJson { x: Json::String(s) }
+}
+
+fn json_array(values: Vec<OutputJson>) -> OutputJson {
+    OutputJson { x: Json::Array(values.into_iter().map(|value| value.x).collect()) }
+}
+
+fn json_object(fields: Vec<(String, OutputJson)>) -> OutputJson {
+    OutputJson {
+        x: Json::Object(fields.into_iter().map(|(name, value)| (name, value.x)).collect())
+    }
+}
+
+fn json_address(address: u64) -> OutputJson {
+    OutputJson { x: Json::address(address) }
+}
+
+fn json_str(json: &OutputJson) -> String {
+    format!("{}", json.x)
+}
+
+fn json_finding(finding: &check::CheckFinding, program: &il::IlProgram) -> OutputJson {
+    let symbolizer = Symbolizer::new(None, &program.x, &[]);
+    OutputJson { x: finding_json(&symbolizer, &program.x, &finding.x) }
+}
+
+fn json_sarif(
+    checkers: Vec<check::CheckChecker>,
+    findings: Vec<check::CheckFinding>,
+    program: &il::IlProgram,
+    filename: String
+) -> OutputJson {
+
+    let bytes = ::std::fs::read(&filename).ok();
+    let symbolizer = Symbolizer::new(bytes.as_ref().map(|b| b.as_slice()), &program.x, &[]);
+
+    let findings: Vec<check::Finding> = findings.into_iter().map(|f| f.x).collect();
+    let rules: Vec<(String, String)> = checkers.iter()
+        .map(|checker| (checker.x.id().to_string(), checker.x.description().to_string()))
+        .collect();
+
+    let report = check::Report {
+        rules: rules.iter().map(|&(ref id, _)| id.clone()).collect(),
+        findings: findings
+    };
+
+    OutputJson { x: sarif(&symbolizer, &program.x, &report, &rules, &filename) }
+}
+
+
+pub fn bindings(vm: gluon::RootedThread) -> gluon::RootedThread {
+
+    vm.register_type::<OutputJson>("OutputJson", &[]).unwrap();
+
+    fn falcon_output_prim_loader(vm: &gluon::Thread)
+        -> gluon::vm::Result<gluon::vm::ExternModule> {
+
+        gluon::vm::ExternModule::new(vm, record! {
+            json_address => primitive!(1, json_address),
+            json_array => primitive!(1, json_array),
+            json_bool => primitive!(1, json_bool),
+            json_finding => primitive!(2, json_finding),
+            json_float => primitive!(1, json_float),
+            json_int => primitive!(1, json_int),
+            json_null => primitive!(1, json_null),
+            json_object => primitive!(1, json_object),
+            json_sarif => primitive!(4, json_sarif),
+            json_str => primitive!(1, json_str),
+            json_string => primitive!(1, json_string)
+        })
+    }
+
+    gluon::import::add_extern_module(
+        &vm, "falcon_output_prim", falcon_output_prim_loader);
+
+    vm
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field<'j>(json: &'j Json, name: &str) -> &'j Json {
+        match *json {
+            Json::Object(ref fields) => fields.iter()
+                .find(|&&(ref field, _)| field == name)
+                .map(|&(_, ref value)| value)
+                .unwrap_or_else(|| panic!("no field {} in {}", name, json)),
+            _ => panic!("{} is not an object", json)
+        }
+    }
+
+    fn element(json: &Json, index: usize) -> &Json {
+        match *json {
+            Json::Array(ref values) => &values[index],
+            _ => panic!("{} is not an array", json)
+        }
+    }
+
+    #[test]
+    fn strings_are_escaped() {
+        let json = Json::string("a\"b\\c\nd\re\tf\u{1}g\u{e9}");
+        assert_eq!(format!("{}", json), "\"a\\\"b\\\\c\\nd\\re\\tf\\u0001g\u{e9}\"");
+    }
+
+    #[test]
+    fn objects_keep_field_order() {
+        let json = Json::object(vec![
+            ("b", Json::Int(-1)),
+            ("a", Json::Array(vec![Json::Null, Json::Bool(true), Json::Float(1.5)])),
+            ("c", Json::Float(::std::f64::NAN))
+        ]);
+        assert_eq!(format!("{}", json), "{\"b\":-1,\"a\":[null,true,1.5],\"c\":null}");
+    }
+
+    #[test]
+    fn addresses_are_integers() {
+        assert_eq!(format!("{}", Json::address(0x1000)), "4096");
+        assert_eq!(format!("{}", Json::address(u64::max_value())), "18446744073709551615");
+    }
+
+    #[test]
+    fn sarif_shape() {
+        let program = falcon::il::Program::new();
+        let symbolizer = Symbolizer::new(None, &program, &[(0x1000, "main".to_string())]);
+
+        let mut finding = check::Finding::new("rule-b", check::Severity::High, "bad");
+        finding.address = Some(0x1000);
+        let report = check::Report {
+            rules: vec!["rule-a".to_string(), "rule-b".to_string()],
+            findings: vec![finding]
+        };
+        let rules = vec![
+            ("rule-a".to_string(), "Finds a".to_string()),
+            ("rule-b".to_string(), "Finds b".to_string())
+        ];
+
+        let log = sarif(&symbolizer, &program, &report, &rules, "a.out");
+        assert_eq!(field(&log, "version"), &Json::string("2.1.0"));
+
+        let run = element(field(&log, "runs"), 0);
+        let driver = field(field(run, "tool"), "driver");
+        let rule = element(field(driver, "rules"), 1);
+        assert_eq!(field(rule, "id"), &Json::string("rule-b"));
+        assert_eq!(field(field(rule, "shortDescription"), "text"), &Json::string("Finds b"));
+
+        let result = element(field(run, "results"), 0);
+        assert_eq!(field(result, "ruleId"), &Json::string("rule-b"));
+        assert_eq!(field(result, "ruleIndex"), &Json::Int(1));
+        assert_eq!(field(result, "level"), &Json::string("error"));
+
+        let location = element(field(result, "locations"), 0);
+        let address = field(field(location, "physicalLocation"), "address");
+        assert_eq!(field(address, "absoluteAddress"), &Json::Uint(0x1000));
+        assert_eq!(field(address, "name"), &Json::string("main"));
+    }
+}
@@ -0,0 +1,677 @@
+//! A small pattern language for finding IL operations.
+//!
+//! Patterns mirror the way Falcon displays IL:
+//!
+//! ```text
+//! store [$sp + $c:constant], $x
+//! load $dst, [rsp:64 + _]
+//! assign $dst, zext.64($src)
+//! branch $target:scalar
+//! ```
+//!
+//! Operations are `assign`, `store`, `load`, `branch`, `intrinsic`, `nop` or
+//! `_` for any operation. Expressions are built from `_` (matches anything),
+//! captures (`$name`, optionally `:scalar` or `:constant`), scalar names
+//! (`rsp`, `rsp:64`), constants (`0x10`, `16:32`), the binary operators
+//! `+ - * /u %u /s %s & | ^ << >> == != <u <s`, `zext.N(..)`, `sext.N(..)`,
+//! `trun.N(..)` (where `N` may be `_`) and `ite(.., .., ..)`. A capture used
+//! twice must bind equal expressions. Commutative operators match in either
+//! order.
+
+use falcon;
+use gluon::vm::api::{Userdata, VmType};
+use gluon::vm::thread::{Traverseable};
+use gluon;
+use std::collections::BTreeMap;
+
+use handle::FunctionHandle;
+use il;
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BinaryOp {
+    Add, Sub, Mul, Divu, Modu, Divs, Mods, And, Or, Xor, Shl, Shr,
+    Cmpeq, Cmpneq, Cmplts, Cmpltu
+}
+
+impl BinaryOp {
+    fn commutative(&self) -> bool {
+        match *self {
+            BinaryOp::Add | BinaryOp::Mul | BinaryOp::And | BinaryOp::Or |
+            BinaryOp::Xor | BinaryOp::Cmpeq | BinaryOp::Cmpneq => true,
+            _ => false
+        }
+    }
+
+    fn precedence(&self) -> usize {
+        match *self {
+            BinaryOp::Or => 1,
+            BinaryOp::Xor => 2,
+            BinaryOp::And => 3,
+            BinaryOp::Cmpeq | BinaryOp::Cmpneq |
+            BinaryOp::Cmplts | BinaryOp::Cmpltu => 4,
+            BinaryOp::Shl | BinaryOp::Shr => 5,
+            BinaryOp::Add | BinaryOp::Sub => 6,
+            BinaryOp::Mul | BinaryOp::Divu | BinaryOp::Modu |
+            BinaryOp::Divs | BinaryOp::Mods => 7
+        }
+    }
+
+    fn from_token(token: &str) -> Option<BinaryOp> {
+        Some(match token {
+            "+" => BinaryOp::Add,
+            "-" => BinaryOp::Sub,
+            "*" => BinaryOp::Mul,
+            "/u" => BinaryOp::Divu,
+            "%u" => BinaryOp::Modu,
+            "/s" => BinaryOp::Divs,
+            "%s" => BinaryOp::Mods,
+            "&" => BinaryOp::And,
+            "|" => BinaryOp::Or,
+            "^" => BinaryOp::Xor,
+            "<<" => BinaryOp::Shl,
+            ">>" => BinaryOp::Shr,
+            "==" => BinaryOp::Cmpeq,
+            "!=" => BinaryOp::Cmpneq,
+            "<s" => BinaryOp::Cmplts,
+            "<u" => BinaryOp::Cmpltu,
+            _ => return None
+        })
+    }
+}
+
+fn binary_parts(expression: &falcon::il::Expression)
+    -> Option<(BinaryOp, &falcon::il::Expression, &falcon::il::Expression)> {
+
+    use falcon::il::Expression;
+
+    let (op, l, r): (BinaryOp, &Box<Expression>, &Box<Expression>) = match *expression {
+        Expression::Add(ref l, ref r) => (BinaryOp::Add, l, r),
+        Expression::Sub(ref l, ref r) => (BinaryOp::Sub, l, r),
+        Expression::Mul(ref l, ref r) => (BinaryOp::Mul, l, r),
+        Expression::Divu(ref l, ref r) => (BinaryOp::Divu, l, r),
+        Expression::Modu(ref l, ref r) => (BinaryOp::Modu, l, r),
+        Expression::Divs(ref l, ref r) => (BinaryOp::Divs, l, r),
+        Expression::Mods(ref l, ref r) => (BinaryOp::Mods, l, r),
+        Expression::And(ref l, ref r) => (BinaryOp::And, l, r),
+        Expression::Or(ref l, ref r) => (BinaryOp::Or, l, r),
+        Expression::Xor(ref l, ref r) => (BinaryOp::Xor, l, r),
+        Expression::Shl(ref l, ref r) => (BinaryOp::Shl, l, r),
+        Expression::Shr(ref l, ref r) => (BinaryOp::Shr, l, r),
+        Expression::Cmpeq(ref l, ref r) => (BinaryOp::Cmpeq, l, r),
+        Expression::Cmpneq(ref l, ref r) => (BinaryOp::Cmpneq, l, r),
+        Expression::Cmplts(ref l, ref r) => (BinaryOp::Cmplts, l, r),
+        Expression::Cmpltu(ref l, ref r) => (BinaryOp::Cmpltu, l, r),
+        _ => return None
+    };
+
+    Some((op, &**l, &**r))
+}
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CastOp { Zext, Sext, Trun }
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CaptureKind { Any, Scalar, Constant }
+
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ExpressionPattern {
+    Wildcard,
+    Capture(String, CaptureKind),
+    Scalar(String, Option<usize>),
+    Constant(u64, Option<usize>),
+    Binary(BinaryOp, Box<ExpressionPattern>, Box<ExpressionPattern>),
+    Cast(CastOp, Option<usize>, Box<ExpressionPattern>),
+    Ite(Box<ExpressionPattern>, Box<ExpressionPattern>, Box<ExpressionPattern>)
+}
+
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Pattern {
+    Any,
+    Assign(ExpressionPattern, ExpressionPattern),
+    Store(ExpressionPattern, ExpressionPattern),
+    Load(ExpressionPattern, ExpressionPattern),
+    Branch(ExpressionPattern),
+    Intrinsic,
+    Nop
+}
+
+
+/// Expressions bound by a successful match, by capture name.
+pub type Captures = BTreeMap<String, falcon::il::Expression>;
+
+
+impl ExpressionPattern {
+    pub fn matches(&self, expression: &falcon::il::Expression, captures: &mut Captures)
+        -> bool {
+
+        use falcon::il::Expression;
+
+        match *self {
+            ExpressionPattern::Wildcard => true,
+            ExpressionPattern::Capture(ref name, kind) => {
+                let kind_ok = match (kind, expression) {
+                    (CaptureKind::Any, _) => true,
+                    (CaptureKind::Scalar, &Expression::Scalar(_)) => true,
+                    (CaptureKind::Constant, &Expression::Constant(_)) => true,
+                    _ => false
+                };
+                if !kind_ok {
+                    return false;
+                }
+                if let Some(bound) = captures.get(name) {
+                    return bound == expression;
+                }
+                captures.insert(name.clone(), expression.clone());
+                true
+            },
+            ExpressionPattern::Scalar(ref name, bits) => match *expression {
+                Expression::Scalar(ref scalar) =>
+                    scalar.name() == name && bits.map_or(true, |b| b == scalar.bits()),
+                _ => false
+            },
+            ExpressionPattern::Constant(value, bits) => match *expression {
+                Expression::Constant(ref constant) =>
+                    constant.value_u64() == Some(value) &&
+                    bits.map_or(true, |b| b == constant.bits()),
+                _ => false
+            },
+            ExpressionPattern::Binary(op, ref lhs, ref rhs) => {
+                let (eop, l, r) = match binary_parts(expression) {
+                    Some(parts) => parts,
+                    None => return false
+                };
+                if eop != op {
+                    return false;
+                }
+                let mut attempt = captures.clone();
+                if lhs.matches(l, &mut attempt) && rhs.matches(r, &mut attempt) {
+                    *captures = attempt;
+                    return true;
+                }
+                if op.commutative() {
+                    let mut attempt = captures.clone();
+                    if lhs.matches(r, &mut attempt) && rhs.matches(l, &mut attempt) {
+                        *captures = attempt;
+                        return true;
+                    }
+                }
+                false
+            },
+            ExpressionPattern::Cast(op, bits, ref pattern) => {
+                let (ebits, inner) = match (op, expression) {
+                    (CastOp::Zext, &Expression::Zext(b, ref e)) |
+                    (CastOp::Sext, &Expression::Sext(b, ref e)) |
+                    (CastOp::Trun, &Expression::Trun(b, ref e)) => (b, e),
+                    _ => return false
+                };
+                bits.map_or(true, |b| b == ebits) && pattern.matches(inner, captures)
+            },
+            ExpressionPattern::Ite(ref c, ref t, ref e) => match *expression {
+                Expression::Ite(ref ec, ref et, ref ee) => {
+                    let mut attempt = captures.clone();
+                    if c.matches(ec, &mut attempt) &&
+                       t.matches(et, &mut attempt) &&
+                       e.matches(ee, &mut attempt) {
+                        *captures = attempt;
+                        true
+                    }
+                    else {
+                        false
+                    }
+                },
+                _ => false
+            }
+        }
+    }
+}
+
+
+impl Pattern {
+    pub fn parse(text: &str) -> Result<Pattern, String> {
+        let mut parser = Parser { tokens: tokenize(text)?, position: 0 };
+        let pattern = parser.pattern()?;
+        match parser.peek() {
+            None => Ok(pattern),
+            Some(token) => Err(format!("unexpected `{}` after pattern", token))
+        }
+    }
+
+    /// Match an operation, returning the bound captures on success.
+    pub fn matches(&self, operation: &falcon::il::Operation) -> Option<Captures> {
+        use falcon::il::Operation;
+
+        let mut captures = Captures::new();
+        let matched = match (self, operation) {
+            (&Pattern::Any, _) => true,
+            (&Pattern::Assign(ref dst, ref src), &Operation::Assign { dst: ref d, src: ref s }) =>
+                dst.matches(&falcon::il::Expression::Scalar(d.clone()), &mut captures) &&
+                src.matches(s, &mut captures),
+            (&Pattern::Store(ref index, ref src), &Operation::Store { index: ref i, src: ref s }) =>
+                index.matches(i, &mut captures) && src.matches(s, &mut captures),
+            (&Pattern::Load(ref dst, ref index), &Operation::Load { dst: ref d, index: ref i }) =>
+                dst.matches(&falcon::il::Expression::Scalar(d.clone()), &mut captures) &&
+                index.matches(i, &mut captures),
+            (&Pattern::Branch(ref target), &Operation::Branch { target: ref t }) =>
+                target.matches(t, &mut captures),
+            (&Pattern::Intrinsic, &Operation::Intrinsic { .. }) => true,
+            (&Pattern::Nop, &Operation::Nop) => true,
+            _ => false
+        };
+        if matched { Some(captures) } else { None }
+    }
+
+    /// Every instruction in `functions` matching this pattern.
+    pub fn search(&self, functions: Vec<FunctionHandle>)
+        -> Vec<(falcon::il::ProgramLocation, Captures)> {
+
+        let mut results = Vec::new();
+        let mut cursor = il::InstructionCursor::new(functions);
+        while let Some((_, _, instruction, location, next)) = cursor.next() {
+            if let Some(captures) = self.matches(instruction.operation()) {
+                results.push((location, captures));
+            }
+            cursor = next;
+        }
+        results
+    }
+}
+
+
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        }
+        else if c.is_alphanumeric() || c == '_' || c == '$' {
+            let start = i;
+            i += 1;
+            while i < chars.len() &&
+                  (chars[i].is_alphanumeric() || chars[i] == '_' ||
+                   chars[i] == '.' || chars[i] == ':') {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        }
+        else {
+            let pair: String = chars[i..].iter().take(2).collect();
+            match pair.as_str() {
+                "<<" | ">>" | "==" | "!=" | "<s" | "<u" | "/u" | "%u" | "/s" | "%s" => {
+                    tokens.push(pair);
+                    i += 2;
+                },
+                _ => match c {
+                    '[' | ']' | '(' | ')' | ',' | '+' | '-' | '*' | '&' | '|' | '^' => {
+                        tokens.push(c.to_string());
+                        i += 1;
+                    },
+                    _ => return Err(format!("unexpected character `{}` in pattern", c))
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+
+struct Parser {
+    tokens: Vec<String>,
+    position: usize
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|t| t.as_str())
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let token = self.tokens.get(self.position)
+            .cloned()
+            .ok_or("unexpected end of pattern".to_string())?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let token = self.next()?;
+        if token == expected {
+            Ok(())
+        }
+        else {
+            Err(format!("expected `{}`, found `{}`", expected, token))
+        }
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, String> {
+        let keyword = self.next()?;
+        Ok(match keyword.as_str() {
+            "_" => Pattern::Any,
+            "nop" => Pattern::Nop,
+            "intrinsic" => Pattern::Intrinsic,
+            "branch" => Pattern::Branch(self.expression(0)?),
+            "assign" => {
+                let dst = self.expression(0)?;
+                self.expect(",")?;
+                Pattern::Assign(dst, self.expression(0)?)
+            },
+            "store" => {
+                self.expect("[")?;
+                let index = self.expression(0)?;
+                self.expect("]")?;
+                self.expect(",")?;
+                Pattern::Store(index, self.expression(0)?)
+            },
+            "load" => {
+                let dst = self.expression(0)?;
+                self.expect(",")?;
+                self.expect("[")?;
+                let index = self.expression(0)?;
+                self.expect("]")?;
+                Pattern::Load(dst, index)
+            },
+            _ => return Err(format!("unknown operation `{}`", keyword))
+        })
+    }
+
+    fn expression(&mut self, min_precedence: usize) -> Result<ExpressionPattern, String> {
+        let mut lhs = self.atom()?;
+        loop {
+            let op = match self.peek().and_then(BinaryOp::from_token) {
+                Some(op) => op,
+                None => break
+            };
+            if op.precedence() <= min_precedence {
+                break;
+            }
+            self.next()?;
+            let rhs = self.expression(op.precedence())?;
+            lhs = ExpressionPattern::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn atom(&mut self) -> Result<ExpressionPattern, String> {
+        let token = self.next()?;
+
+        if token == "(" {
+            let expression = self.expression(0)?;
+            self.expect(")")?;
+            return Ok(expression);
+        }
+        if token == "_" {
+            return Ok(ExpressionPattern::Wildcard);
+        }
+        if token.starts_with('$') {
+            let mut parts = token[1..].splitn(2, ':');
+            let name = parts.next().unwrap_or("").to_string();
+            if name.is_empty() {
+                return Err(format!("capture `{}` has no name", token));
+            }
+            let kind = match parts.next() {
+                None => CaptureKind::Any,
+                Some("scalar") => CaptureKind::Scalar,
+                Some("constant") => CaptureKind::Constant,
+                Some(kind) => return Err(format!("unknown capture kind `{}`", kind))
+            };
+            return Ok(ExpressionPattern::Capture(name, kind));
+        }
+        if token == "ite" {
+            self.expect("(")?;
+            let c = self.expression(0)?;
+            self.expect(",")?;
+            let t = self.expression(0)?;
+            self.expect(",")?;
+            let e = self.expression(0)?;
+            self.expect(")")?;
+            return Ok(ExpressionPattern::Ite(Box::new(c), Box::new(t), Box::new(e)));
+        }
+        for &(prefix, op) in &[("zext.", CastOp::Zext),
+                               ("sext.", CastOp::Sext),
+                               ("trun.", CastOp::Trun)] {
+            if token.starts_with(prefix) {
+                let bits = parse_bits(&token[prefix.len()..])?;
+                self.expect("(")?;
+                let inner = self.expression(0)?;
+                self.expect(")")?;
+                return Ok(ExpressionPattern::Cast(op, bits, Box::new(inner)));
+            }
+        }
+
+        let mut parts = token.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let bits = match parts.next() {
+            Some(bits) => parse_bits(bits)?,
+            None => None
+        };
+
+        if name.chars().next().map_or(false, |c| c.is_digit(10)) {
+            let value = if name.starts_with("0x") {
+                u64::from_str_radix(&name[2..], 16)
+            }
+            else {
+                name.parse::<u64>()
+            };
+            let value = value.map_err(|_| format!("invalid constant `{}`", name))?;
+            Ok(ExpressionPattern::Constant(value, bits))
+        }
+        else {
+            Ok(ExpressionPattern::Scalar(name.to_string(), bits))
+        }
+    }
+}
+
+fn parse_bits(bits: &str) -> Result<Option<usize>, String> {
+    if bits == "_" {
+        return Ok(None);
+    }
+    bits.parse::<usize>()
+        .map(Some)
+        .map_err(|_| format!("invalid bit width `{}`", bits))
+}
+
+
+falcon_type_wrapper!(Pattern, QueryPattern);
+
+fn pattern_compile(text: String) -> Result<QueryPattern, String> {
+    Pattern::parse(&text).map(|pattern| QueryPattern { x: pattern })
+}
+
+fn matches_to_gluon(matches: Vec<(falcon::il::ProgramLocation, Captures)>)
+    -> Vec<(il::IlProgramLocation, Vec<(String, il::IlExpression)>)> {
+
+    matches.into_iter()
+        .map(|(location, captures)| (
+            il::IlProgramLocation { x: location },
+            captures.into_iter()
+                .map(|(name, expression)| (name, il::IlExpression { x: expression }))
+                .collect()
+        ))
+        .collect()
+}
+
+fn pattern_function(pattern: &QueryPattern, function: &il::IlFunction)
+    -> Vec<(il::IlProgramLocation, Vec<(String, il::IlExpression)>)> {
+
+    matches_to_gluon(pattern.x.search(vec![function.x.clone()]))
+}
+
+fn pattern_program(pattern: &QueryPattern, program: &il::IlProgram)
+    -> Vec<(il::IlProgramLocation, Vec<(String, il::IlExpression)>)> {
+
+    matches_to_gluon(pattern.x.search(FunctionHandle::program_functions(&program.x)))
+}
+
+fn pattern_operation(pattern: &QueryPattern, operation: &il::IlOperation)
+    -> Option<Vec<(String, il::IlExpression)>> {
+
+    pattern.x.matches(&operation.x).map(|captures|
+        captures.into_iter()
+            .map(|(name, expression)| (name, il::IlExpression { x: expression }))
+            .collect())
+}
+
+
+pub fn bindings(vm: gluon::RootedThread) -> gluon::RootedThread {
+
+    vm.register_type::<QueryPattern>("QueryPattern", &[]).unwrap();
+
+    fn falcon_query_prim_loader(vm: &gluon::Thread)
+        -> gluon::vm::Result<gluon::vm::ExternModule> {
+
+        gluon::vm::ExternModule::new(vm, record! {
+            pattern_compile => primitive!(1, pattern_compile),
+            pattern_function => primitive!(2, pattern_function),
+            pattern_operation => primitive!(2, pattern_operation),
+            pattern_program => primitive!(2, pattern_program)
+        })
+    }
+
+    gluon::import::add_extern_module(
+        &vm, "falcon_query_prim", falcon_query_prim_loader);
+
+    vm
+}
+
+
+#[cfg(test)]
+mod tests {
+    use falcon::il::{Constant, Expression, Operation, Scalar};
+    use super::*;
+
+    fn scalar(name: &str, bits: usize) -> Expression {
+        Expression::Scalar(Scalar::new(name, bits))
+    }
+
+    fn constant(value: u64, bits: usize) -> Expression {
+        Expression::Constant(Constant::new(value, bits))
+    }
+
+    fn add(lhs: Expression, rhs: Expression) -> Expression {
+        Expression::Add(Box::new(lhs), Box::new(rhs))
+    }
+
+    #[test]
+    fn tokenize_splits_operators() {
+        assert_eq!(
+            tokenize("store [$sp + 0x10:64], x<<y").unwrap(),
+            vec!["store", "[", "$sp", "+", "0x10:64", "]", ",", "x", "<<", "y"]);
+        assert_eq!(tokenize("a <u b /s c").unwrap(), vec!["a", "<u", "b", "/s", "c"]);
+        assert!(tokenize("load $x, [#]").is_err());
+    }
+
+    #[test]
+    fn parse_rejects_unnamed_captures() {
+        assert!(Pattern::parse("load $, [rsp:64]").is_err());
+        assert!(Pattern::parse("assign x:64, $:constant").is_err());
+        assert!(Pattern::parse("assign x:64, $c:constant").is_ok());
+    }
+
+    #[test]
+    fn parse_operations() {
+        assert_eq!(Pattern::parse("_").unwrap(), Pattern::Any);
+        assert_eq!(Pattern::parse("nop").unwrap(), Pattern::Nop);
+        assert_eq!(
+            Pattern::parse("load $dst, [rsp:64]").unwrap(),
+            Pattern::Load(
+                ExpressionPattern::Capture("dst".to_string(), CaptureKind::Any),
+                ExpressionPattern::Scalar("rsp".to_string(), Some(64))));
+        assert_eq!(
+            Pattern::parse("branch $t:constant").unwrap(),
+            Pattern::Branch(ExpressionPattern::Capture("t".to_string(), CaptureKind::Constant)));
+    }
+
+    #[test]
+    fn parse_precedence_and_associativity() {
+        let a = || Box::new(ExpressionPattern::Scalar("a".to_string(), None));
+        let b = || Box::new(ExpressionPattern::Scalar("b".to_string(), None));
+        let c = || Box::new(ExpressionPattern::Scalar("c".to_string(), None));
+
+        // Multiplication binds tighter than addition.
+        assert_eq!(
+            Pattern::parse("branch a + b * c").unwrap(),
+            Pattern::Branch(ExpressionPattern::Binary(BinaryOp::Add, a(),
+                Box::new(ExpressionPattern::Binary(BinaryOp::Mul, b(), c())))));
+        // Operators of equal precedence associate to the left.
+        assert_eq!(
+            Pattern::parse("branch a - b - c").unwrap(),
+            Pattern::Branch(ExpressionPattern::Binary(BinaryOp::Sub,
+                Box::new(ExpressionPattern::Binary(BinaryOp::Sub, a(), b())), c())));
+        assert_eq!(
+            Pattern::parse("branch (a | b) & c").unwrap(),
+            Pattern::Branch(ExpressionPattern::Binary(BinaryOp::And,
+                Box::new(ExpressionPattern::Binary(BinaryOp::Or, a(), b())), c())));
+    }
+
+    #[test]
+    fn parse_casts_and_constants() {
+        assert_eq!(
+            Pattern::parse("assign $d, zext._(16:8)").unwrap(),
+            Pattern::Assign(
+                ExpressionPattern::Capture("d".to_string(), CaptureKind::Any),
+                ExpressionPattern::Cast(CastOp::Zext, None,
+                    Box::new(ExpressionPattern::Constant(16, Some(8))))));
+        assert_eq!(
+            Pattern::parse("branch 0x10").unwrap(),
+            Pattern::Branch(ExpressionPattern::Constant(0x10, None)));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Pattern::parse("").is_err());
+        assert!(Pattern::parse("jump x").is_err());
+        assert!(Pattern::parse("store [x, y").is_err());
+        assert!(Pattern::parse("branch x y").is_err());
+        assert!(Pattern::parse("branch $x:register").is_err());
+        assert!(Pattern::parse("branch 0xzz").is_err());
+        assert!(Pattern::parse("branch zext.q(x)").is_err());
+        assert!(Pattern::parse("branch ite(a, b)").is_err());
+    }
+
+    #[test]
+    fn match_captures() {
+        let pattern = Pattern::parse("store [$sp + $c:constant], $x").unwrap();
+        let store = Operation::Store {
+            index: add(scalar("rsp", 64), constant(8, 64)),
+            src: scalar("rax", 64)
+        };
+        let captures = pattern.matches(&store).unwrap();
+        assert_eq!(captures["sp"], scalar("rsp", 64));
+        assert_eq!(captures["c"], constant(8, 64));
+        assert_eq!(captures["x"], scalar("rax", 64));
+
+        // The constant capture only binds constants.
+        let store = Operation::Store {
+            index: add(scalar("rsp", 64), scalar("rbx", 64)),
+            src: scalar("rax", 64)
+        };
+        assert!(pattern.matches(&store).is_none());
+    }
+
+    #[test]
+    fn match_commutative() {
+        let pattern = Pattern::parse("branch rsp + 8").unwrap();
+        let branch = |target| Operation::Branch { target: target };
+        assert!(pattern.matches(&branch(add(constant(8, 64), scalar("rsp", 64)))).is_some());
+
+        let pattern = Pattern::parse("branch rsp - 8").unwrap();
+        let sub = Expression::Sub(Box::new(constant(8, 64)), Box::new(scalar("rsp", 64)));
+        assert!(pattern.matches(&branch(sub)).is_none());
+    }
+
+    #[test]
+    fn match_repeated_capture() {
+        let pattern = Pattern::parse("branch $x + $x").unwrap();
+        let branch = |target| Operation::Branch { target: target };
+        assert!(pattern.matches(&branch(add(scalar("a", 32), scalar("a", 32)))).is_some());
+        assert!(pattern.matches(&branch(add(scalar("a", 32), scalar("b", 32)))).is_none());
+    }
+}
@@ -0,0 +1,353 @@
+//! Byte pattern and constant search.
+//!
+//! Patterns are written as hex bytes separated by whitespace, where `??`
+//! matches any byte and `?` matches any nibble, as in `55 48 89 e5` or
+//! `8b 4? ?? 0f`. Patterns with arbitrary bit masks are built from bytes and
+//! a mask.
+//!
+//! The crypto scan looks for well-known tables in memory, such as the AES
+//! S-box, and for well-known constants, such as hash initialization vectors,
+//! in the operands of instructions. Constants built from several
+//! instructions, as on MIPS and PowerPC, are not found by the latter.
+
+use falcon;
+use falcon::architecture::{Architecture, Endian};
+use falcon::error::Result;
+use falcon::memory::backing::Memory;
+use std::fmt;
+
+use xref;
+
+
+/// A byte pattern where each byte matches under a mask.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Pattern {
+    bytes: Vec<u8>,
+    mask: Vec<u8>
+}
+
+impl Pattern {
+    /// A pattern matching bytes `b` where `b & mask == bytes & mask`.
+    pub fn new(bytes: Vec<u8>, mask: Vec<u8>) -> Result<Pattern> {
+        if bytes.is_empty() {
+            return Err("empty pattern".into());
+        }
+        if bytes.len() != mask.len() {
+            return Err("pattern and mask lengths differ".into());
+        }
+        let bytes = bytes.iter().zip(&mask).map(|(b, m)| b & m).collect();
+        Ok(Pattern { bytes: bytes, mask: mask })
+    }
+
+    /// A pattern matching `bytes` exactly.
+    pub fn exact(bytes: &[u8]) -> Result<Pattern> {
+        Pattern::new(bytes.to_vec(), vec![0xff; bytes.len()])
+    }
+
+    /// Parse a pattern of hex bytes with `?` wildcard nibbles.
+    pub fn parse(pattern: &str) -> Result<Pattern> {
+        let mut bytes = Vec::new();
+        let mut mask = Vec::new();
+        for token in pattern.split_whitespace() {
+            let nibbles: Vec<char> = token.chars().collect();
+            if nibbles.len() != 2 {
+                return Err(format!("invalid pattern byte {}", token).into());
+            }
+            let mut byte = 0;
+            let mut byte_mask = 0;
+            for &nibble in &nibbles {
+                byte <<= 4;
+                byte_mask <<= 4;
+                if nibble != '?' {
+                    byte |= nibble.to_digit(16)
+                        .ok_or(format!("invalid pattern byte {}", token))? as u8;
+                    byte_mask |= 0xf;
+                }
+            }
+            bytes.push(byte);
+            mask.push(byte_mask);
+        }
+        Pattern::new(bytes, mask)
+    }
+
+    /// The length of the pattern in bytes.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Whether the pattern has no bytes. Always false, as `Pattern::new`
+    /// rejects empty patterns.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Whether `bytes` start with a match of this pattern.
+    pub fn matches(&self, bytes: &[u8]) -> bool {
+        bytes.len() >= self.bytes.len() &&
+            self.bytes.iter()
+                .zip(&self.mask)
+                .zip(bytes)
+                .all(|((p, m), b)| b & m == *p)
+    }
+
+    /// The offsets of matches of this pattern in `data`.
+    pub fn find(&self, data: &[u8]) -> Vec<usize> {
+        if data.len() < self.len() {
+            return Vec::new();
+        }
+        (0..data.len() - self.len() + 1)
+            .filter(|&offset| self.matches(&data[offset..]))
+            .collect()
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().zip(&self.mask)
+            .map(|(&b, &m)| match m {
+                0xff => format!("{:02x}", b),
+                0xf0 => format!("{:x}?", b >> 4),
+                0x0f => format!("?{:x}", b & 0xf),
+                0x00 => "??".to_string(),
+                _ => format!("{:02x}&{:02x}", b, m)
+            })
+            .collect();
+        write!(f, "{}", bytes.join(" "))
+    }
+}
+
+
+/// Something found by a search.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Match {
+    /// The pattern, or the name of the known value, which matched.
+    pub name: String,
+    /// The native address of the match, if known.
+    pub address: Option<u64>,
+    /// The instruction a constant was found in.
+    pub location: Option<falcon::il::ProgramLocation>,
+    /// The function containing the match, if any.
+    pub function: Option<String>
+}
+
+impl fmt::Display for Match {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.address {
+            Some(address) => write!(f, "0x{:x}", address)?,
+            None => write!(f, "?")?
+        }
+        if let Some(ref function) = self.function {
+            write!(f, " in {}", function)?;
+        }
+        write!(f, ": {}", self.name)
+    }
+}
+
+
+/// The addresses of matches of `pattern` in `memory`. Matches do not span
+/// sections.
+pub fn search(memory: &Memory, pattern: &Pattern) -> Vec<u64> {
+    memory.sections()
+        .iter()
+        .flat_map(|(&address, section)| pattern.find(section.data())
+            .into_iter()
+            .map(move |offset| address + offset as u64))
+        .collect()
+}
+
+
+/// The native address ranges of functions, from their first to their last
+/// instruction.
+struct Extents {
+    extents: Vec<(u64, u64, String)>
+}
+
+impl Extents {
+    fn new(program: &falcon::il::Program) -> Extents {
+        let extents = program.functions()
+            .into_iter()
+            .filter_map(|function| {
+                let addresses = function.blocks()
+                    .into_iter()
+                    .flat_map(|block| block.instructions())
+                    .filter_map(|instruction| instruction.address());
+                let (lo, hi) = addresses.fold(None, |extent, address| match extent {
+                    Some((lo, hi)) => Some((::std::cmp::min(lo, address),
+                                            ::std::cmp::max(hi, address))),
+                    None => Some((address, address))
+                })?;
+                Some((lo, hi, function.name()))
+            })
+            .collect();
+        Extents { extents: extents }
+    }
+
+    /// The name of the smallest function whose extent contains `address`.
+    fn function(&self, address: u64) -> Option<String> {
+        self.extents.iter()
+            .filter(|&&(lo, hi, _)| lo <= address && address <= hi)
+            .min_by_key(|&&(lo, hi, _)| hi - lo)
+            .map(|&(_, _, ref name)| name.clone())
+    }
+}
+
+
+/// Matches of `pattern` in `memory`, with the functions of `program`
+/// containing them.
+pub fn search_program(program: &falcon::il::Program, memory: &Memory, pattern: &Pattern)
+    -> Vec<Match> {
+
+    let extents = Extents::new(program);
+    search(memory, pattern)
+        .into_iter()
+        .map(|address| Match {
+            name: format!("{}", pattern),
+            address: Some(address),
+            location: None,
+            function: extents.function(address)
+        })
+        .collect()
+}
+
+
+/// The instructions in `program` with an operand containing one of the named
+/// `values`.
+pub fn constants(program: &falcon::il::Program, values: &[(String, u64)]) -> Vec<Match> {
+    let mut matches = Vec::new();
+
+    for function in program.functions() {
+        for block in function.blocks() {
+            for instruction in block.instructions() {
+                let expressions = match *instruction.operation() {
+                    falcon::il::Operation::Assign { ref src, .. } => vec![src],
+                    falcon::il::Operation::Store { ref index, ref src } => vec![index, src],
+                    falcon::il::Operation::Load { ref index, .. } => vec![index],
+                    falcon::il::Operation::Branch { ref target } => vec![target],
+                    _ => continue
+                };
+                let mut found = Vec::new();
+                for expression in expressions {
+                    xref::constants(expression, &mut found);
+                }
+
+                for &(ref name, value) in values {
+                    if !found.contains(&value) {
+                        continue;
+                    }
+                    let location = falcon::il::FunctionLocation::Instruction(
+                        block.index(), instruction.index());
+                    matches.push(Match {
+                        name: name.clone(),
+                        address: instruction.address(),
+                        location: Some(falcon::il::ProgramLocation::new(
+                            function.index(), location)),
+                        function: Some(function.name())
+                    });
+                }
+            }
+        }
+    }
+
+    matches
+}
+
+
+/// Constants of cryptographic algorithms, by name.
+const CRYPTO_CONSTANTS: &[(&str, u64)] = &[
+    ("md5/sha1 h0", 0x67452301),
+    ("md5/sha1 h1", 0xefcdab89),
+    ("md5/sha1 h2", 0x98badcfe),
+    ("md5/sha1 h3", 0x10325476),
+    ("sha1 h4", 0xc3d2e1f0),
+    ("md5 t1", 0xd76aa478),
+    ("md5 t2", 0xe8c7b756),
+    ("sha1 k2", 0x6ed9eba1),
+    ("sha1 k3", 0x8f1bbcdc),
+    ("sha1 k4", 0xca62c1d6),
+    ("sha256 h0", 0x6a09e667),
+    ("sha256 h1", 0xbb67ae85),
+    ("sha256 h2", 0x3c6ef372),
+    ("sha256 h3", 0xa54ff53a),
+    ("sha256 k0", 0x428a2f98),
+    ("sha256 k1", 0x71374491),
+    ("sha512 h0", 0x6a09e667f3bcc908),
+    ("sha512 h1", 0xbb67ae8584caa73b),
+    ("crc32 polynomial", 0xedb88320),
+    ("crc32 polynomial", 0x04c11db7),
+    ("tea delta", 0x9e3779b9),
+    ("tea delta", 0x61c88647),
+    ("chacha/salsa sigma", 0x61707865),
+    ("chacha/salsa sigma", 0x3320646e),
+    ("chacha/salsa sigma", 0x79622d32),
+    ("chacha/salsa sigma", 0x6b206574),
+    ("blowfish p0", 0x243f6a88),
+    ("blowfish p1", 0x85a308d3),
+    ("aes te0", 0xc66363a5),
+    ("fnv-1 offset basis", 0x811c9dc5),
+    ("fnv-1 prime", 0x01000193),
+    ("fnv-1 offset basis", 0xcbf29ce484222325),
+    ("fnv-1 prime", 0x100000001b3)
+];
+
+
+/// Byte tables of cryptographic algorithms, by their first bytes.
+const CRYPTO_TABLES: &[(&str, &str)] = &[
+    ("aes s-box", "63 7c 77 7b f2 6b 6f c5 30 01 67 2b fe d7 ab 76"),
+    ("aes inverse s-box", "52 09 6a d5 30 36 a5 38 bf 40 a3 9e 81 f3 d7 fb"),
+    ("des s1", "0e 04 0d 01 02 0f 0b 08 03 0a 06 0c 05 09 00 07"),
+    ("base64 alphabet", "41 42 43 44 45 46 47 48 49 4a 4b 4c 4d 4e 4f 50")
+];
+
+
+/// Word tables of cryptographic algorithms, by their first words, searched
+/// for in the architecture's byte order.
+const CRYPTO_WORD_TABLES: &[(&str, &[u32])] = &[
+    ("sha256 k", &[0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5]),
+    ("md5 t", &[0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee]),
+    ("aes te0", &[0xc66363a5, 0xf87c7c84, 0xee777799, 0xf67b7b8d]),
+    ("crc32 table", &[0x00000000, 0x77073096, 0xee0e612c, 0x990951ba]),
+    ("blowfish p-array", &[0x243f6a88, 0x85a308d3, 0x13198a2e, 0x03707344])
+];
+
+
+/// Cryptographic constants in `program`'s instructions and cryptographic
+/// tables in `memory`, ordered by address.
+pub fn crypto(architecture: &Architecture, program: &falcon::il::Program, memory: &Memory)
+    -> Vec<Match> {
+
+    let values: Vec<(String, u64)> = CRYPTO_CONSTANTS.iter()
+        .map(|&(name, value)| (name.to_string(), value))
+        .collect();
+    let mut matches = constants(program, &values);
+
+    let mut tables: Vec<(&str, Pattern)> = CRYPTO_TABLES.iter()
+        .map(|&(name, pattern)| (name, Pattern::parse(pattern).expect("crypto table pattern")))
+        .collect();
+    for &(name, words) in CRYPTO_WORD_TABLES {
+        let bytes: Vec<u8> = words.iter()
+            .flat_map(|&word| {
+                let bytes = [(word >> 24) as u8, (word >> 16) as u8, (word >> 8) as u8, word as u8];
+                match architecture.endian() {
+                    Endian::Big => bytes.to_vec(),
+                    Endian::Little => bytes.iter().rev().cloned().collect()
+                }
+            })
+            .collect();
+        tables.push((name, Pattern::exact(&bytes).expect("crypto table pattern")));
+    }
+
+    let extents = Extents::new(program);
+    for (name, pattern) in tables {
+        for address in search(memory, &pattern) {
+            matches.push(Match {
+                name: name.to_string(),
+                address: Some(address),
+                location: None,
+                function: extents.function(address)
+            });
+        }
+    }
+
+    matches.sort_by_key(|m| m.address);
+    matches
+}
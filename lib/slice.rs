@@ -0,0 +1,224 @@
+//! Intraprocedural program slicing.
+//!
+//! Slices follow data dependencies through scalars and memory. Memory is
+//! treated as a single location: a store may define any later load, and no
+//! store kills a previous one. Control dependencies are not followed.
+//!
+//! A call defines the calling convention's return register, the registers it
+//! trashes, and memory, so a slice through a call stops at the call rather
+//! than reaching past it to an earlier definition.
+
+use falcon;
+use falcon::architecture::Architecture;
+use falcon::error::Result;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use il;
+
+
+/// A value a slice tracks.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Variable {
+    Scalar(String),
+    Memory
+}
+
+impl Variable {
+    pub fn scalar(scalar: &falcon::il::Scalar) -> Variable {
+        Variable::Scalar(scalar.name().to_string())
+    }
+}
+
+
+fn reads(operation: &falcon::il::Operation) -> BTreeSet<Variable> {
+    let mut variables: BTreeSet<Variable> = il::scalars_read(operation)
+        .into_iter()
+        .map(Variable::scalar)
+        .collect();
+    if il::reads_memory(operation) {
+        variables.insert(Variable::Memory);
+    }
+    variables
+}
+
+fn writes(
+    architecture: &Architecture,
+    location: &falcon::il::RefProgramLocation,
+    operation: &falcon::il::Operation
+) -> BTreeSet<Variable> {
+
+    let mut variables: BTreeSet<Variable> = il::scalars_written(operation)
+        .into_iter()
+        .map(Variable::scalar)
+        .collect();
+    if il::writes_memory(operation) {
+        variables.insert(Variable::Memory);
+    }
+
+    let call = match *location.function_location() {
+        falcon::il::RefFunctionLocation::Instruction(block, instruction) =>
+            il::branch_returns(
+                architecture, location.function(), block.index(), instruction.index())
+                == Some(true),
+        _ => false
+    };
+    if call {
+        let calling_convention = architecture.calling_convention();
+        variables.insert(Variable::scalar(calling_convention.return_register()));
+        for scalar in calling_convention.trashed_registers() {
+            variables.insert(Variable::scalar(scalar));
+        }
+        variables.insert(Variable::Memory);
+    }
+
+    variables
+}
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Direction {
+    Backward,
+    Forward
+}
+
+
+fn slice(
+    architecture: &Architecture,
+    function: &falcon::il::Function,
+    start: &falcon::il::FunctionLocation,
+    criterion: Variable,
+    direction: Direction
+) -> Result<HashSet<falcon::il::FunctionLocation>> {
+
+    let start = falcon::il::RefProgramLocation::new(function, start.apply(function)?);
+
+    let mut slice = HashSet::new();
+    slice.insert(start.function_location().clone().into());
+
+    let mut relevant: HashMap<falcon::il::RefProgramLocation, BTreeSet<Variable>> =
+        HashMap::new();
+    let mut criterion_set = BTreeSet::new();
+    criterion_set.insert(criterion);
+
+    let next = |location: &falcon::il::RefProgramLocation| match direction {
+        Direction::Backward => location.backward(),
+        Direction::Forward => location.forward()
+    };
+
+    let mut queue: Vec<(falcon::il::RefProgramLocation, BTreeSet<Variable>)> =
+        next(&start)?.into_iter().map(|l| (l, criterion_set.clone())).collect();
+
+    while let Some((location, incoming)) = queue.pop() {
+        let seen = relevant.entry(location.clone()).or_insert(BTreeSet::new());
+        if incoming.is_subset(seen) {
+            continue;
+        }
+        seen.extend(incoming.into_iter());
+        let current = seen.clone();
+
+        let outgoing = match location.instruction() {
+            Some(instruction) => {
+                let operation = instruction.operation();
+                let reads = reads(operation);
+                let writes = writes(architecture, &location, operation);
+
+                match direction {
+                    // A definition of a relevant value contributes to it. What
+                    // it read becomes relevant, and scalars it wrote are no
+                    // longer relevant further back.
+                    Direction::Backward =>
+                        if writes.intersection(&current).next().is_some() {
+                            slice.insert(location.function_location().clone().into());
+                            let mut outgoing: BTreeSet<Variable> = current.iter()
+                                .filter(|v| **v == Variable::Memory || !writes.contains(v))
+                                .cloned()
+                                .collect();
+                            outgoing.extend(reads.into_iter());
+                            outgoing
+                        }
+                        else {
+                            current
+                        },
+                    // A use of a relevant value is affected by it, and so is
+                    // whatever it writes. Overwritten scalars stop being
+                    // relevant.
+                    Direction::Forward =>
+                        if reads.intersection(&current).next().is_some() {
+                            slice.insert(location.function_location().clone().into());
+                            let mut outgoing = current;
+                            outgoing.extend(writes.into_iter());
+                            outgoing
+                        }
+                        else {
+                            current.into_iter()
+                                .filter(|v| *v == Variable::Memory || !writes.contains(v))
+                                .collect()
+                        }
+                }
+            },
+            None => current
+        };
+
+        if outgoing.is_empty() {
+            continue;
+        }
+
+        for successor in next(&location)? {
+            queue.push((successor, outgoing.clone()));
+        }
+    }
+
+    Ok(slice)
+}
+
+
+/// The locations in `function` that contribute to `criterion` as read at
+/// `start`, including `start`.
+pub fn backward(
+    architecture: &Architecture,
+    function: &falcon::il::Function,
+    start: &falcon::il::FunctionLocation,
+    criterion: Variable
+) -> Result<HashSet<falcon::il::FunctionLocation>> {
+    slice(architecture, function, start, criterion, Direction::Backward)
+}
+
+
+/// The locations in `function` that `criterion`, as written at `start`,
+/// flows into, including `start`.
+pub fn forward(
+    architecture: &Architecture,
+    function: &falcon::il::Function,
+    start: &falcon::il::FunctionLocation,
+    criterion: Variable
+) -> Result<HashSet<falcon::il::FunctionLocation>> {
+    slice(architecture, function, start, criterion, Direction::Forward)
+}
+
+
+/// A copy of `function` where every instruction outside `locations` is
+/// replaced with a `nop`, so locations in the slice remain valid.
+pub fn reduce(
+    function: &falcon::il::Function,
+    locations: &HashSet<falcon::il::FunctionLocation>
+) -> Result<falcon::il::Function> {
+
+    let mut reduced = function.clone();
+
+    for block in function.blocks() {
+        for instruction in block.instructions() {
+            let location = falcon::il::FunctionLocation::Instruction(
+                block.index(), instruction.index());
+            if locations.contains(&location) {
+                continue;
+            }
+            *reduced.control_flow_graph_mut()
+                .block_mut(block.index())?
+                .instruction_mut(instruction.index())
+                .ok_or("instruction missing from cloned function")?
+                .operation_mut() = falcon::il::Operation::Nop;
+        }
+    }
+
+    Ok(reduced)
+}
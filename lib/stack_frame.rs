@@ -0,0 +1,495 @@
+//! Stack frame and local variable recovery.
+//!
+//! A forward dataflow analysis tracks which scalars hold the stack pointer's
+//! value on entry plus a constant offset. Every load, store and address
+//! computation relative to the entry stack pointer is then an access to the
+//! frame. Accessed offsets partition the frame into slots: locals below the
+//! entry stack pointer, and arguments above it. A slot whose address is taken,
+//! or which is indexed by a variable, is treated as a buffer extending to the
+//! next slot, and any other slot as a scalar as wide as its widest access.
+
+use falcon;
+use falcon::architecture::{Architecture, ReturnAddressType};
+use falcon::error::Result;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use dataflow;
+use il;
+
+
+/// Offsets from the entry stack pointer, by the name of the scalar holding
+/// them.
+pub type Offsets = BTreeMap<String, i64>;
+
+
+/// The domain of offsets from the entry stack pointer.
+pub struct OffsetDomain<'a> {
+    architecture: &'a Architecture
+}
+
+impl<'a> OffsetDomain<'a> {
+    pub fn new(architecture: &'a Architecture) -> OffsetDomain<'a> {
+        OffsetDomain { architecture: architecture }
+    }
+}
+
+/// The offset from the entry stack pointer `expression` evaluates to, given
+/// the scalars in `offsets`.
+pub fn offset_of(
+    architecture: &Architecture,
+    offsets: &Offsets,
+    expression: &falcon::il::Expression
+) -> Option<i64> {
+
+    let entry = falcon::il::Scalar::new("__entry_sp", architecture.stack_pointer().bits());
+    let entry = falcon::il::Expression::Scalar(entry);
+
+    let expression = il::replace_scalars(expression, &|scalar: &falcon::il::Scalar|
+        offsets.get(scalar.name()).and_then(|&offset|
+            falcon::il::Expression::add(
+                entry.clone(), falcon::il::expr_const(offset as u64, scalar.bits())).ok())
+    )?;
+
+    match il::base_offset(&il::simplify(&expression)) {
+        (Some(ref base), offset) if *base == entry => Some(offset),
+        _ => None
+    }
+}
+
+impl<'a> dataflow::Domain for OffsetDomain<'a> {
+    type State = Offsets;
+
+    fn bottom(&self) -> Result<Offsets> {
+        let mut offsets = Offsets::new();
+        offsets.insert(self.architecture.stack_pointer().name().to_string(), 0);
+        Ok(offsets)
+    }
+
+    fn join(&self, lhs: &Offsets, rhs: &Offsets) -> Result<Offsets> {
+        Ok(lhs.iter()
+            .filter(|&(name, offset)| rhs.get(name) == Some(offset))
+            .map(|(name, offset)| (name.clone(), *offset))
+            .collect())
+    }
+
+    fn leq(&self, lhs: &Offsets, rhs: &Offsets) -> Result<bool> {
+        Ok(rhs.iter().all(|(name, offset)| lhs.get(name) == Some(offset)))
+    }
+
+    fn transfer(
+        &self,
+        location: &falcon::il::RefProgramLocation,
+        operation: &falcon::il::Operation,
+        mut offsets: Offsets
+    ) -> Result<Offsets> {
+
+        match *operation {
+            falcon::il::Operation::Assign { ref dst, ref src } => {
+                match offset_of(self.architecture, &offsets, src) {
+                    Some(offset) => offsets.insert(dst.name().to_string(), offset),
+                    None => offsets.remove(dst.name())
+                };
+            },
+            falcon::il::Operation::Load { ref dst, .. } => {
+                offsets.remove(dst.name());
+            },
+            falcon::il::Operation::Branch { .. } => {
+                let returns = match *location.function_location() {
+                    falcon::il::RefFunctionLocation::Instruction(block, instruction) =>
+                        il::branch_returns(self.architecture,
+                            location.function(), block.index(), instruction.index()),
+                    _ => None
+                };
+                if returns == Some(true) {
+                    let calling_convention = self.architecture.calling_convention();
+                    for scalar in calling_convention.trashed_registers() {
+                        offsets.remove(scalar.name());
+                    }
+                    offsets.remove(calling_convention.return_register().name());
+
+                    // The callee pops a return address pushed by the call.
+                    if let ReturnAddressType::Stack(_) =
+                        *calling_convention.return_address_type() {
+                        let word = (self.architecture.word_size() / 8) as i64;
+                        let stack_pointer = self.architecture.stack_pointer();
+                        if let Some(offset) = offsets.get_mut(stack_pointer.name()) {
+                            *offset += word;
+                        }
+                    }
+                }
+            },
+            falcon::il::Operation::Store { .. } |
+            falcon::il::Operation::Intrinsic { .. } |
+            falcon::il::Operation::Nop => {}
+        }
+
+        Ok(offsets)
+    }
+}
+
+
+/// The offsets from the entry stack pointer held by scalars after every
+/// location in `function`.
+pub fn offsets(architecture: &Architecture, function: &falcon::il::Function)
+    -> Result<HashMap<falcon::il::FunctionLocation, Offsets>> {
+
+    let domain = OffsetDomain { architecture: architecture };
+    Ok(dataflow::forward(&domain, function)?.into_iter().collect())
+}
+
+
+/// The offsets held before `location`, agreed on by every predecessor.
+pub fn offsets_before(
+    architecture: &Architecture,
+    function: &falcon::il::Function,
+    offsets: &HashMap<falcon::il::FunctionLocation, Offsets>,
+    location: &falcon::il::FunctionLocation
+) -> Result<Offsets> {
+
+    let domain = OffsetDomain { architecture: architecture };
+    let location = falcon::il::RefProgramLocation::new(function, location.apply(function)?);
+
+    let mut before: Option<Offsets> = None;
+    for predecessor in location.backward()? {
+        let predecessor: falcon::il::FunctionLocation =
+            predecessor.function_location().clone().into();
+        let state = offsets.get(&predecessor).cloned().unwrap_or(Offsets::new());
+        before = Some(match before {
+            Some(before) => dataflow::Domain::join(&domain, &before, &state)?,
+            None => state
+        });
+    }
+
+    match before {
+        Some(before) => Ok(before),
+        None => dataflow::Domain::bottom(&domain)
+    }
+}
+
+
+/// The offset from the entry stack pointer of `expression`, read before
+/// `location`.
+pub fn expression_offset(
+    architecture: &Architecture,
+    function: &falcon::il::Function,
+    offsets: &HashMap<falcon::il::FunctionLocation, Offsets>,
+    location: &falcon::il::FunctionLocation,
+    expression: &falcon::il::Expression
+) -> Result<Option<i64>> {
+
+    let before = offsets_before(architecture, function, offsets, location)?;
+    Ok(offset_of(architecture, &before, expression))
+}
+
+
+/// An address into the frame made of a constant offset plus a variable index,
+/// such as `rbp + rax - 0x20`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Index {
+    /// The offset of the address when the index is zero.
+    pub offset: i64,
+    /// The non-constant terms of the index.
+    pub terms: Vec<falcon::il::Expression>
+}
+
+fn add_terms(expression: &falcon::il::Expression, terms: &mut Vec<falcon::il::Expression>) {
+    match *expression {
+        falcon::il::Expression::Add(ref l, ref r) => {
+            add_terms(l, terms);
+            add_terms(r, terms);
+        },
+        _ => terms.push(expression.clone())
+    }
+}
+
+/// `expression` as an indexed address into the frame, given the scalars in
+/// `offsets`. Exactly one term must hold an offset, and at least one must be
+/// neither an offset nor a constant.
+pub fn indexed(
+    architecture: &Architecture,
+    offsets: &Offsets,
+    expression: &falcon::il::Expression
+) -> Option<Index> {
+
+    let mut terms = Vec::new();
+    add_terms(expression, &mut terms);
+
+    let mut offset = 0i64;
+    let mut bases = 0;
+    let mut index = Vec::new();
+    for term in terms {
+        if let Some(term_offset) = offset_of(architecture, offsets, &term) {
+            offset = offset.wrapping_add(term_offset);
+            bases += 1;
+            continue;
+        }
+        match il::base_offset(&term) {
+            (None, constant) => offset = offset.wrapping_add(constant),
+            _ => index.push(term)
+        }
+    }
+
+    if bases == 1 && !index.is_empty() {
+        Some(Index { offset: offset, terms: index })
+    }
+    else {
+        None
+    }
+}
+
+
+/// How an instruction accesses the frame.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum AccessKind {
+    Read,
+    Write,
+    /// The address of the slot is computed, as for a buffer passed to a call.
+    Address
+}
+
+impl fmt::Display for AccessKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            AccessKind::Read => "read",
+            AccessKind::Write => "write",
+            AccessKind::Address => "address"
+        })
+    }
+}
+
+
+/// An access to the frame.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Access {
+    pub location: falcon::il::FunctionLocation,
+    /// The offset from the entry stack pointer accessed.
+    pub offset: i64,
+    /// The number of bytes accessed, or zero for an address computation.
+    pub size: u64,
+    pub kind: AccessKind
+}
+
+
+/// What a slot holds.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum SlotKind {
+    Local,
+    Argument,
+    ReturnAddress
+}
+
+impl fmt::Display for SlotKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            SlotKind::Local => "local",
+            SlotKind::Argument => "argument",
+            SlotKind::ReturnAddress => "return address"
+        })
+    }
+}
+
+
+/// A variable in the frame.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Slot {
+    /// The offset from the entry stack pointer of the slot.
+    pub offset: i64,
+    pub size: u64,
+    pub kind: SlotKind,
+    /// Whether the slot looks like a buffer, because its address is taken.
+    pub buffer: bool,
+    /// The accesses which fall in this slot.
+    pub accesses: Vec<Access>
+}
+
+impl Slot {
+    pub fn contains(&self, offset: i64) -> bool {
+        offset >= self.offset && offset < self.offset + self.size as i64
+    }
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.offset < 0 { "-" } else { "+" };
+        write!(f, "{} sp{}0x{:x} size 0x{:x}", self.kind, sign, self.offset.abs(), self.size)?;
+        if self.buffer {
+            write!(f, " buffer")?;
+        }
+        Ok(())
+    }
+}
+
+
+/// A function's recovered stack frame.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct StackFrame {
+    /// The number of bytes below the entry stack pointer the function uses.
+    pub size: u64,
+    /// Slots, ordered by offset.
+    pub slots: Vec<Slot>
+}
+
+impl StackFrame {
+    /// The slot containing `offset`.
+    pub fn slot(&self, offset: i64) -> Option<&Slot> {
+        self.slots.iter().find(|slot| slot.contains(offset))
+    }
+
+    pub fn locals(&self) -> Vec<&Slot> {
+        self.slots.iter().filter(|slot| slot.kind == SlotKind::Local).collect()
+    }
+
+    pub fn arguments(&self) -> Vec<&Slot> {
+        self.slots.iter().filter(|slot| slot.kind == SlotKind::Argument).collect()
+    }
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "frame size 0x{:x}", self.size)?;
+        for slot in &self.slots {
+            writeln!(f, "  {}", slot)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Recover the stack frame of `function`.
+pub fn stack_frame(architecture: &Architecture, function: &falcon::il::Function)
+    -> Result<StackFrame> {
+
+    stack_frame_offsets(architecture, function).map(|(frame, _)| frame)
+}
+
+
+/// Recover the stack frame of `function`, with the `offsets` it was
+/// recovered from.
+pub fn stack_frame_offsets(architecture: &Architecture, function: &falcon::il::Function)
+    -> Result<(StackFrame, HashMap<falcon::il::FunctionLocation, Offsets>)> {
+
+    let offsets = offsets(architecture, function)?;
+    let stack_pointer = architecture.stack_pointer();
+
+    let return_address = match *architecture.calling_convention().return_address_type() {
+        ReturnAddressType::Stack(_) => Some((architecture.word_size() / 8) as u64),
+        ReturnAddressType::Register(_) => None
+    };
+
+    // Registers which may carry a slot's address into a call. Stack and frame
+    // pointers are preserved across calls, so moving them is not taken as
+    // taking an address.
+    let calling_convention = architecture.calling_convention();
+    let carriers: BTreeSet<&str> = calling_convention.trashed_registers()
+        .iter()
+        .chain(calling_convention.argument_registers().iter())
+        .chain(Some(calling_convention.return_register()))
+        .map(|scalar| scalar.name())
+        .collect();
+
+    let mut accesses = Vec::new();
+    let mut lowest = 0;
+
+    for block in function.blocks() {
+        for instruction in block.instructions() {
+            let location = falcon::il::FunctionLocation::Instruction(
+                block.index(), instruction.index());
+            let before = offsets_before(architecture, function, &offsets, &location)?;
+            if let Some(&offset) = before.get(stack_pointer.name()) {
+                lowest = lowest.min(offset);
+            }
+
+            // Indexing from an offset takes the address of a buffer there.
+            let index_of = |expression: &falcon::il::Expression|
+                indexed(architecture, &before, expression)
+                .map(|index| (index.offset, 0, AccessKind::Address));
+
+            let access = match *instruction.operation() {
+                falcon::il::Operation::Load { ref dst, ref index } =>
+                    offset_of(architecture, &before, index)
+                        .map(|offset| (offset, (dst.bits() / 8) as u64, AccessKind::Read))
+                        .or_else(|| index_of(index)),
+                falcon::il::Operation::Store { ref index, ref src } =>
+                    offset_of(architecture, &before, index)
+                        .map(|offset| (offset, (src.bits() / 8) as u64, AccessKind::Write))
+                        .or_else(|| index_of(index)),
+                falcon::il::Operation::Assign { ref dst, ref src } =>
+                    if carriers.contains(dst.name()) && *dst != stack_pointer {
+                        offset_of(architecture, &before, src)
+                            .map(|offset| (offset, 0, AccessKind::Address))
+                            .or_else(|| index_of(src))
+                    }
+                    else {
+                        index_of(src)
+                    },
+                _ => None
+            };
+
+            if let Some((offset, size, kind)) = access {
+                accesses.push(Access {
+                    location: location,
+                    offset: offset,
+                    size: size,
+                    kind: kind
+                });
+            }
+        }
+    }
+
+    // Pushes below the lowest stack pointer seen still belong to the frame.
+    for access in &accesses {
+        lowest = lowest.min(access.offset);
+    }
+
+    let starts: BTreeSet<i64> = accesses.iter().map(|access| access.offset).collect();
+    let starts: Vec<i64> = starts.into_iter().collect();
+
+    let mut slots = Vec::new();
+    for (i, &offset) in starts.iter().enumerate() {
+        let slot_accesses: Vec<&Access> = accesses.iter()
+            .filter(|access| access.offset == offset)
+            .collect();
+        let buffer = slot_accesses.iter().any(|access| access.kind == AccessKind::Address);
+        let widest = slot_accesses.iter().map(|access| access.size).max().unwrap_or(0);
+
+        let kind = match return_address {
+            Some(size) if offset >= 0 && offset < size as i64 => SlotKind::ReturnAddress,
+            Some(size) if offset >= size as i64 => SlotKind::Argument,
+            None if offset >= 0 => SlotKind::Argument,
+            _ => SlotKind::Local
+        };
+
+        // Locals end at the next slot, or the entry stack pointer.
+        let limit = match starts.get(i + 1) {
+            Some(&next) if kind == SlotKind::Local => Some(next.min(0)),
+            Some(&next) => Some(next),
+            None if kind == SlotKind::Local => Some(0),
+            None => None
+        };
+
+        let size = match limit {
+            Some(limit) if buffer => (limit - offset) as u64,
+            Some(limit) => widest.min((limit - offset) as u64).max(1),
+            None => widest.max(1)
+        };
+
+        slots.push(Slot {
+            offset: offset,
+            size: size,
+            kind: kind,
+            buffer: buffer,
+            accesses: Vec::new()
+        });
+    }
+
+    // Attach every access to the slot containing it.
+    for access in accesses {
+        let slot = slots.iter_mut().rev().find(|slot| slot.offset <= access.offset);
+        if let Some(slot) = slot {
+            slot.accesses.push(access);
+        }
+    }
+
+    Ok((StackFrame { size: (-lowest) as u64, slots: slots }, offsets))
+}
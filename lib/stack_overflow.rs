@@ -0,0 +1,559 @@
+//! A stack buffer overflow checker.
+//!
+//! Writes into local slots of a function's recovered stack frame are flagged
+//! when their extent cannot be bounded:
+//!
+//! * calls to copying functions with a constant length larger than the room
+//!   left in the destination slot,
+//! * calls to unbounded string functions whose destination is a local, and
+//! * stores through a variable index into a local inside a loop, when no exit
+//!   from the loop which every iteration reaching the store passes tests a
+//!   value the index is derived from. Such an exit either dominates the
+//!   store, as the test at the top of a loop does, or dominates every latch
+//!   of the loop, as the test at the bottom of a rotated loop does.
+//!
+//! Index values are traced within a block to the scalars live on entry to it
+//! and the frame slots they were loaded from, so a loop counter kept in a
+//! register or in a local is recognised in both the store and the test.
+
+use falcon;
+use falcon::error::Result;
+use falcon::loader::Loader;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use call_site;
+use check;
+use stack_frame;
+
+
+/// The rule id of the stack buffer overflow checker.
+pub const RULE: &str = "stack-overflow";
+
+
+/// Functions writing to a buffer, the index of their destination argument,
+/// and the index of their length argument, or `None` if they are unbounded.
+pub const FUNCTIONS: &[(&str, usize, Option<usize>)] = &[
+    ("gets", 0, None),
+    ("strcpy", 0, None),
+    ("stpcpy", 0, None),
+    ("strcat", 0, None),
+    ("wcscpy", 0, None),
+    ("wcscat", 0, None),
+    ("sprintf", 0, None),
+    ("vsprintf", 0, None),
+    ("memcpy", 0, Some(2)),
+    ("memmove", 0, Some(2)),
+    ("memset", 0, Some(2)),
+    ("strncpy", 0, Some(2)),
+    ("stpncpy", 0, Some(2)),
+    ("strncat", 0, Some(2)),
+    ("bzero", 0, Some(1)),
+    ("bcopy", 1, Some(2)),
+    ("fgets", 0, Some(1)),
+    ("snprintf", 0, Some(1)),
+    ("vsnprintf", 0, Some(1)),
+    ("read", 1, Some(2)),
+    ("recv", 1, Some(2)),
+    ("recvfrom", 1, Some(2))
+];
+
+
+/// The destination and length argument indices of `name`.
+pub fn function_arguments(name: &str) -> Option<(usize, Option<usize>)> {
+    call_site::lookup(name, |name| FUNCTIONS.iter()
+        .find(|&&(function, _, _)| function == name)
+        .map(|&(_, destination, length)| (destination, length)))
+}
+
+
+/// Why a write into a slot is unsafe.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Reason {
+    /// A call copies a constant number of bytes which do not fit.
+    Length(String, u64),
+    /// A call to an unbounded string function.
+    Unbounded(String),
+    /// A store through an index in a loop with no bound check.
+    Loop
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Reason::Length(ref function, length) =>
+                write!(f, "{} writes 0x{:x} bytes", function, length),
+            Reason::Unbounded(ref function) =>
+                write!(f, "{} writes an unbounded string", function),
+            Reason::Loop =>
+                write!(f, "loop writes through an unchecked index")
+        }
+    }
+}
+
+
+/// A write which may overflow a local slot.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Finding {
+    /// The writing call or store.
+    pub location: falcon::il::ProgramLocation,
+    /// The native address of the write, if known.
+    pub address: Option<u64>,
+    /// The name of the function whose frame is written.
+    pub function: String,
+    /// The offset from the entry stack pointer the write starts at.
+    pub offset: i64,
+    /// The offset from the entry stack pointer of the slot written.
+    pub slot_offset: i64,
+    /// The size of the slot written.
+    pub slot_size: u64,
+    pub reason: Reason
+}
+
+impl Finding {
+    /// This finding as a checker finding.
+    pub fn finding(&self) -> check::Finding {
+        let severity = match self.reason {
+            Reason::Length(_, _) => check::Severity::High,
+            Reason::Unbounded(ref function) if function == "gets" => check::Severity::High,
+            Reason::Unbounded(_) | Reason::Loop => check::Severity::Medium
+        };
+        let message = format!("{}: {}", self.function, self.description());
+
+        let mut finding = check::Finding::new(RULE, severity, message);
+        finding.location = Some(self.location.clone());
+        finding.address = self.address;
+        finding
+    }
+
+    fn description(&self) -> String {
+        let sign = if self.slot_offset < 0 { "-" } else { "+" };
+        format!("{} into local at sp{}0x{:x} of size 0x{:x}",
+            self.reason, sign, self.slot_offset.abs(), self.slot_size)
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.address {
+            Some(address) => write!(f, "0x{:x}", address)?,
+            None => write!(f, "{}", self.location)?
+        }
+        write!(f, " {}: {}", self.function, self.description())
+    }
+}
+
+
+/// The local slot of `frame` containing `offset`.
+fn local(frame: &stack_frame::StackFrame, offset: i64) -> Option<&stack_frame::Slot> {
+    frame.slot(offset).filter(|slot| slot.kind == stack_frame::SlotKind::Local)
+}
+
+
+/// Where a value in a block came from.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+enum Source {
+    /// A scalar live on entry to the block.
+    Scalar(String),
+    /// A load from the frame at an offset.
+    Slot(i64)
+}
+
+type Sources = HashMap<String, BTreeSet<Source>>;
+
+fn sources_of(sources: &Sources, expression: &falcon::il::Expression) -> BTreeSet<Source> {
+    expression.scalars()
+        .into_iter()
+        .flat_map(|scalar| match sources.get(scalar.name()) {
+            Some(sources) => sources.clone(),
+            None => Some(Source::Scalar(scalar.name().to_string())).into_iter().collect()
+        })
+        .collect()
+}
+
+
+/// A natural loop.
+struct Loop {
+    /// The blocks which branch back to the loop's header.
+    latches: Vec<usize>,
+    body: BTreeSet<usize>
+}
+
+
+/// The natural loops in `function`, found from the edges which return to a
+/// block on the depth-first search stack. Loops sharing a header are one
+/// loop.
+fn loops(function: &falcon::il::Function) -> Vec<Loop> {
+    let control_flow_graph = function.control_flow_graph();
+    let entry = match control_flow_graph.entry() {
+        Some(entry) => entry,
+        None => return Vec::new()
+    };
+
+    let mut successors: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut predecessors: HashMap<usize, Vec<usize>> = HashMap::new();
+    for edge in control_flow_graph.edges() {
+        successors.entry(edge.head()).or_insert_with(Vec::new).push(edge.tail());
+        predecessors.entry(edge.tail()).or_insert_with(Vec::new).push(edge.head());
+    }
+    let none = Vec::new();
+
+    let mut back_edges = Vec::new();
+    let mut visited = HashSet::new();
+    let mut on_stack = HashSet::new();
+    let mut stack = vec![(entry, 0)];
+    visited.insert(entry);
+    on_stack.insert(entry);
+
+    while let Some((block, next)) = stack.pop() {
+        let block_successors = successors.get(&block).unwrap_or(&none);
+        match block_successors.get(next) {
+            Some(&successor) => {
+                stack.push((block, next + 1));
+                if on_stack.contains(&successor) {
+                    back_edges.push((block, successor));
+                }
+                else if visited.insert(successor) {
+                    on_stack.insert(successor);
+                    stack.push((successor, 0));
+                }
+            },
+            None => {
+                on_stack.remove(&block);
+            }
+        }
+    }
+
+    let mut loops: BTreeMap<usize, Loop> = BTreeMap::new();
+    for (latch, header) in back_edges {
+        let natural = loops.entry(header).or_insert_with(|| Loop {
+            latches: Vec::new(),
+            body: Some(header).into_iter().collect()
+        });
+        natural.latches.push(latch);
+        let mut queue = vec![latch];
+        while let Some(block) = queue.pop() {
+            if natural.body.insert(block) {
+                queue.extend(predecessors.get(&block).unwrap_or(&none));
+            }
+        }
+    }
+    loops.into_iter().map(|(_, natural)| natural).collect()
+}
+
+
+/// The blocks dominating each block of `function`, itself included.
+fn dominators(function: &falcon::il::Function) -> HashMap<usize, BTreeSet<usize>> {
+    let control_flow_graph = function.control_flow_graph();
+    let entry = match control_flow_graph.entry() {
+        Some(entry) => entry,
+        None => return HashMap::new()
+    };
+
+    let blocks: BTreeSet<usize> = control_flow_graph.blocks()
+        .into_iter()
+        .map(|block| block.index())
+        .collect();
+    let mut predecessors: HashMap<usize, Vec<usize>> = HashMap::new();
+    for edge in control_flow_graph.edges() {
+        predecessors.entry(edge.tail()).or_insert_with(Vec::new).push(edge.head());
+    }
+
+    let mut dominators: HashMap<usize, BTreeSet<usize>> = blocks.iter()
+        .map(|&block| (block, blocks.clone()))
+        .collect();
+    dominators.insert(entry, Some(entry).into_iter().collect());
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in blocks.iter().filter(|&&block| block != entry) {
+            let mut dominating: Option<BTreeSet<usize>> = None;
+            for predecessor in predecessors.get(&block).into_iter().flat_map(|p| p.iter()) {
+                let predecessor = &dominators[predecessor];
+                dominating = Some(match dominating {
+                    Some(dominating) => dominating.intersection(predecessor).cloned().collect(),
+                    None => predecessor.clone()
+                });
+            }
+            let mut dominating = dominating.unwrap_or_default();
+            dominating.insert(block);
+            if dominating != dominators[&block] {
+                dominators.insert(block, dominating);
+                changed = true;
+            }
+        }
+    }
+
+    dominators
+}
+
+
+/// A store through an index into the frame.
+struct IndexedStore {
+    block: usize,
+    location: falcon::il::FunctionLocation,
+    address: Option<u64>,
+    offset: i64,
+    sources: BTreeSet<Source>
+}
+
+
+/// Stores through an index in `function`, and the sources of every scalar at
+/// the end of each block.
+fn indexed_stores(
+    architecture: &falcon::architecture::Architecture,
+    function: &falcon::il::Function,
+    offsets: &HashMap<falcon::il::FunctionLocation, stack_frame::Offsets>
+) -> Result<(Vec<IndexedStore>, HashMap<usize, Sources>)> {
+
+    let mut stores = Vec::new();
+    let mut block_sources = HashMap::new();
+
+    for block in function.blocks() {
+        // Indexed addresses computed into a scalar earlier in the block.
+        let mut indices: HashMap<String, stack_frame::Index> = HashMap::new();
+        let mut sources = Sources::new();
+
+        for instruction in block.instructions() {
+            let location = falcon::il::FunctionLocation::Instruction(
+                block.index(), instruction.index());
+            let before = stack_frame::offsets_before(architecture, function, offsets, &location)?;
+
+            match *instruction.operation() {
+                falcon::il::Operation::Assign { ref dst, ref src } => {
+                    match stack_frame::indexed(architecture, &before, src) {
+                        Some(index) => indices.insert(dst.name().to_string(), index),
+                        None => indices.remove(dst.name())
+                    };
+                    let src_sources = sources_of(&sources, src);
+                    sources.insert(dst.name().to_string(), src_sources);
+                },
+                falcon::il::Operation::Load { ref dst, ref index } => {
+                    indices.remove(dst.name());
+                    let loaded = stack_frame::offset_of(architecture, &before, index)
+                        .map(Source::Slot)
+                        .into_iter()
+                        .collect();
+                    sources.insert(dst.name().to_string(), loaded);
+                },
+                falcon::il::Operation::Store { ref index, .. } => {
+                    let stored = match *index {
+                        falcon::il::Expression::Scalar(ref scalar) =>
+                            indices.get(scalar.name()).cloned(),
+                        _ => None
+                    };
+                    let stored = stored.or_else(||
+                        stack_frame::indexed(architecture, &before, index));
+                    if let Some(stored) = stored {
+                        let index_sources = stored.terms.iter()
+                            .flat_map(|term| sources_of(&sources, term))
+                            .collect();
+                        stores.push(IndexedStore {
+                            block: block.index(),
+                            location: location,
+                            address: instruction.address(),
+                            offset: stored.offset,
+                            sources: index_sources
+                        });
+                    }
+                },
+                // Calls trash registers, so nothing is known past one.
+                falcon::il::Operation::Branch { .. } => {
+                    indices.clear();
+                    sources.clear();
+                },
+                falcon::il::Operation::Intrinsic { .. } |
+                falcon::il::Operation::Nop => {}
+            }
+        }
+
+        block_sources.insert(block.index(), sources);
+    }
+
+    Ok((stores, block_sources))
+}
+
+
+/// Whether an exit from `natural`, which every iteration reaching the store
+/// in `block` passes, tests a value from `sources`. The exit's block either
+/// dominates the store, or dominates every latch, so that no iteration
+/// returns to the store without passing the test. Any other exit may be
+/// skipped on the way round, and does not bound the store.
+fn bounded(
+    function: &falcon::il::Function,
+    natural: &Loop,
+    dominators: &HashMap<usize, BTreeSet<usize>>,
+    block_sources: &HashMap<usize, Sources>,
+    block: usize,
+    sources: &BTreeSet<Source>
+) -> bool {
+
+    let none = Sources::new();
+    let dominates = |head: usize, block: usize| dominators.get(&block)
+        .map(|dominating| dominating.contains(&head))
+        .unwrap_or(false);
+    let passed = |head: usize| dominates(head, block) ||
+        natural.latches.iter().all(|&latch| dominates(head, latch));
+
+    function.control_flow_graph()
+        .edges()
+        .into_iter()
+        .filter(|edge| natural.body.contains(&edge.head()) &&
+                       !natural.body.contains(&edge.tail()))
+        .filter(|edge| passed(edge.head()))
+        .any(|edge| match *edge.condition() {
+            Some(ref condition) => {
+                let exit_sources = block_sources.get(&edge.head()).unwrap_or(&none);
+                !sources_of(exit_sources, condition).is_disjoint(sources)
+            },
+            None => false
+        })
+}
+
+
+/// Check the writes into the frame of `function`.
+fn check_function(
+    architecture: &falcon::architecture::Architecture,
+    callees: &call_site::Callees,
+    function: &falcon::il::Function
+) -> Result<Vec<Finding>> {
+
+    let (frame, offsets) = stack_frame::stack_frame_offsets(architecture, function)?;
+    if frame.locals().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut findings = Vec::new();
+
+    let finding = |location: &falcon::il::FunctionLocation,
+                   address: Option<u64>,
+                   offset: i64,
+                   slot: &stack_frame::Slot,
+                   reason: Reason| -> Result<Finding> {
+        let location = location.apply(function)?;
+        let location = falcon::il::RefProgramLocation::new(function, location);
+        Ok(Finding {
+            location: location.into(),
+            address: address,
+            function: function.name(),
+            offset: offset,
+            slot_offset: slot.offset,
+            slot_size: slot.size,
+            reason: reason
+        })
+    };
+
+    let arguments = FUNCTIONS.iter()
+        .map(|&(_, destination, length)| destination.max(length.unwrap_or(0)) + 1)
+        .max()
+        .unwrap_or(0);
+
+    for call_site in call_site::call_sites(architecture, function, arguments)? {
+        let name = match callees.callee(function, &call_site) {
+            Some((_, name)) => name,
+            None => continue
+        };
+        let (destination, length) = match function_arguments(name) {
+            Some(arguments) => arguments,
+            None => continue
+        };
+
+        let destination = match call_site.argument(destination) {
+            Some(destination) => destination,
+            None => continue
+        };
+        let offset = match destination.expression {
+            Some(ref expression) => stack_frame::expression_offset(
+                architecture, function, &offsets, &destination.origin, expression)?,
+            None => None
+        };
+        let (offset, slot) = match offset.and_then(|offset| local(&frame, offset)
+            .map(|slot| (offset, slot))) {
+            Some(destination) => destination,
+            None => continue
+        };
+
+        let reason = match length {
+            Some(length) => {
+                let length = call_site.argument(length)
+                    .and_then(|argument| argument.constant.as_ref())
+                    .and_then(|constant| constant.value_u64());
+                let room = (slot.offset + slot.size as i64 - offset) as u64;
+                match length {
+                    Some(length) if length > room => Reason::Length(name.to_string(), length),
+                    _ => continue
+                }
+            },
+            None => Reason::Unbounded(name.to_string())
+        };
+
+        findings.push(finding(&call_site.location, call_site.address, offset, slot, reason)?);
+    }
+
+    let loops = loops(function);
+    let dominators = dominators(function);
+    let (stores, block_sources) = indexed_stores(architecture, function, &offsets)?;
+
+    for store in stores {
+        let slot = match local(&frame, store.offset) {
+            Some(slot) => slot,
+            None => continue
+        };
+        let mut naturals = loops.iter()
+            .filter(|natural| natural.body.contains(&store.block))
+            .peekable();
+        if naturals.peek().is_none() {
+            continue;
+        }
+        if naturals.any(|natural| bounded(
+            function, natural, &dominators, &block_sources, store.block, &store.sources)) {
+            continue;
+        }
+        findings.push(finding(&store.location, store.address, store.offset, slot, Reason::Loop)?);
+    }
+
+    Ok(findings)
+}
+
+
+/// Check every function in `program` for writes overflowing a local.
+pub fn check(loader: &Loader, program: &falcon::il::Program) -> Result<Vec<Finding>> {
+    let callees = call_site::Callees::new(loader, program)?;
+    let architecture = loader.architecture();
+
+    let mut findings = Vec::new();
+    for function in program.functions() {
+        findings.extend(check_function(architecture, &callees, function)?);
+    }
+
+    Ok(findings)
+}
+
+
+/// The stack buffer overflow checker, for the checker registry.
+#[derive(Clone, Debug)]
+pub struct StackOverflowChecker;
+
+impl check::Checker for StackOverflowChecker {
+    fn id(&self) -> &str {
+        RULE
+    }
+
+    fn description(&self) -> &str {
+        "writes into stack buffers whose extent cannot be bounded"
+    }
+
+    fn check(
+        &self,
+        loader: &Arc<Loader>,
+        program: &Arc<falcon::il::Program>
+    ) -> Result<Vec<check::Finding>> {
+
+        Ok(check(&**loader, program)?
+            .iter()
+            .map(|finding| finding.finding())
+            .collect())
+    }
+}
@@ -0,0 +1,187 @@
+//! String extraction.
+//!
+//! Strings are runs of printable ASCII in memory, either one byte to a
+//! character or as UTF-16 code units in the architecture's byte order, in the
+//! manner of `strings(1)`. Every mapped section is scanned, as loaders which
+//! map segments put read-only data in executable memory.
+//!
+//! A string's references are the instructions with a constant operand equal
+//! to its address, from the cross-reference index.
+
+use falcon;
+use falcon::architecture::{Architecture, Endian};
+use falcon::memory::backing::Memory;
+use std::fmt;
+
+use xref;
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Encoding {
+    Ascii,
+    Utf16
+}
+
+impl Encoding {
+    /// The size of a character in bytes.
+    pub fn width(&self) -> usize {
+        match *self {
+            Encoding::Ascii => 1,
+            Encoding::Utf16 => 2
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            Encoding::Ascii => "ascii",
+            Encoding::Utf16 => "utf-16"
+        })
+    }
+}
+
+
+/// A string found in memory.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct MemoryString {
+    pub address: u64,
+    pub encoding: Encoding,
+    pub value: String
+}
+
+impl MemoryString {
+    /// The size of the string in memory in bytes, without a terminator.
+    pub fn size(&self) -> usize {
+        self.value.len() * self.encoding.width()
+    }
+}
+
+impl fmt::Display for MemoryString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:x} {} {:?}", self.address, self.encoding, self.value)
+    }
+}
+
+
+/// A string, and the code which references it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StringRef {
+    pub string: MemoryString,
+    pub references: Vec<xref::Xref>
+}
+
+
+fn printable(byte: u8) -> bool {
+    (byte >= 0x20 && byte < 0x7f) || byte == b'\t'
+}
+
+
+/// Runs of at least `min_length` printable characters in `data`, mapped at
+/// `address`. `character` reads a character from the bytes of one, or gives
+/// `None` if they are not printable.
+fn runs<F>(
+    data: &[u8],
+    address: u64,
+    encoding: Encoding,
+    min_length: usize,
+    character: F,
+    strings: &mut Vec<MemoryString>
+) where F: Fn(&[u8]) -> Option<u8> {
+
+    let width = encoding.width();
+    let mut start = 0;
+    let mut value = String::new();
+    let mut offset = 0;
+
+    while offset <= data.len() {
+        let c = data.get(offset..offset + width).and_then(|bytes| character(bytes));
+        match c {
+            Some(c) => {
+                if value.is_empty() {
+                    start = offset;
+                }
+                value.push(c as char);
+            },
+            None => {
+                if value.len() >= min_length {
+                    strings.push(MemoryString {
+                        address: address + start as u64,
+                        encoding: encoding,
+                        value: value.clone()
+                    });
+                }
+                value.clear();
+            }
+        }
+        offset += width;
+    }
+}
+
+
+/// The strings of at least `min_length` characters in `memory`, ordered by
+/// address.
+pub fn strings(architecture: &Architecture, memory: &Memory, min_length: usize)
+    -> Vec<MemoryString> {
+
+    let little = match architecture.endian() {
+        Endian::Little => true,
+        Endian::Big => false
+    };
+    let min_length = ::std::cmp::max(min_length, 1);
+
+    let mut strings = Vec::new();
+    for (&address, section) in memory.sections() {
+        let data = section.data();
+
+        runs(data, address, Encoding::Ascii, min_length,
+            |bytes| if printable(bytes[0]) { Some(bytes[0]) } else { None },
+            &mut strings);
+
+        // UTF-16 strings start on even addresses.
+        let skip = (address % 2) as usize;
+        let data = data.get(skip..).unwrap_or(&[]);
+        runs(data, address + skip as u64, Encoding::Utf16, min_length,
+            |bytes| {
+                let (c, zero) = if little { (bytes[0], bytes[1]) } else { (bytes[1], bytes[0]) };
+                if zero == 0 && printable(c) { Some(c) } else { None }
+            },
+            &mut strings);
+    }
+
+    strings.sort();
+    strings
+}
+
+
+/// The strings of at least `min_length` characters in `memory` which code in
+/// `program` references, ordered by address.
+pub fn string_refs(
+    architecture: &Architecture,
+    program: &falcon::il::Program,
+    memory: &Memory,
+    min_length: usize
+) -> Vec<StringRef> {
+
+    let xrefs = xref::xrefs(architecture, program, memory);
+
+    strings(architecture, memory, min_length)
+        .into_iter()
+        .filter_map(|string| {
+            let references: Vec<xref::Xref> = xrefs.to(string.address)
+                .into_iter()
+                .filter(|xref| match xref.source {
+                    xref::Source::Code(_) => true,
+                    xref::Source::Data(_) => false
+                })
+                .cloned()
+                .collect();
+            if references.is_empty() {
+                None
+            }
+            else {
+                Some(StringRef { string: string, references: references })
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,613 @@
+//! Taint analysis over a program.
+//!
+//! Sources and sinks are declared by function name. A source taints either
+//! the value a function returns, or the buffer one of its arguments points
+//! to. A sink is an argument of a function which must not receive tainted
+//! data, either directly or through the buffer it points to. Callees are
+//! named with `call_site::Callees`, so calls to imports through stubs such as
+//! PLT entries match, and names match with `call_site::lookup`.
+//!
+//! Each function is analyzed with the forward dataflow framework. Taint is
+//! tracked in scalars and in memory cells, where a cell is identified by its
+//! address as a base expression and constant offset, in terms of the values
+//! scalars held on entry to the function. Stack slots are therefore stable
+//! across stack pointer adjustments.
+//!
+//! Tainted arguments flow into callees in the program, and tainted return
+//! values flow back out, until every function's entry taint and return taint
+//! stop changing. Register and stack arguments carry their own taint, and
+//! register arguments also carry the taint of the buffer they point to.
+//! Calls to functions outside the program return taint if any argument is
+//! tainted. A function whose analysis fails is recorded in the report and
+//! otherwise skipped, and a report which stopped after `MAX_ROUNDS` rounds
+//! says it did not converge.
+
+use falcon;
+use falcon::architecture::{Architecture, ReturnAddressType};
+use falcon::error::Result;
+use falcon::loader::Loader;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+
+use call_site;
+use dataflow;
+use il;
+
+
+/// Rounds of interprocedural propagation before giving up on convergence.
+const MAX_ROUNDS: usize = 16;
+
+/// Call sites recorded in a label's trace before it stops growing.
+const MAX_TRACE: usize = 8;
+
+/// Arguments carried into callees.
+const MAX_ARGUMENTS: usize = 8;
+
+
+/// What a source taints.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SourceKind {
+    /// The value the function returns.
+    Return,
+    /// The buffer pointed to by the argument at this index.
+    Argument(usize)
+}
+
+
+/// A function which introduces taint.
+#[derive(Clone, Debug)]
+pub struct Source {
+    pub function: String,
+    pub kind: SourceKind
+}
+
+
+/// A function argument which must not receive tainted data.
+#[derive(Clone, Debug)]
+pub struct Sink {
+    pub function: String,
+    pub argument: usize
+}
+
+
+/// The sources and sinks for an analysis.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub sources: Vec<Source>,
+    pub sinks: Vec<Sink>
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config::default()
+    }
+
+    fn sources(&self, name: &str) -> Vec<&Source> {
+        call_site::lookup(name, |name| {
+            let sources: Vec<&Source> =
+                self.sources.iter().filter(|s| s.function == name).collect();
+            if sources.is_empty() { None } else { Some(sources) }
+        }).unwrap_or_default()
+    }
+
+    fn sinks(&self, name: &str) -> Vec<&Sink> {
+        call_site::lookup(name, |name| {
+            let sinks: Vec<&Sink> = self.sinks.iter().filter(|s| s.function == name).collect();
+            if sinks.is_empty() { None } else { Some(sinks) }
+        }).unwrap_or_default()
+    }
+}
+
+
+/// Where tainted data came from, and the call sites it passed through.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Label {
+    pub source: falcon::il::ProgramLocation,
+    pub trace: Vec<falcon::il::ProgramLocation>
+}
+
+impl Label {
+    fn through(&self, call_site: &falcon::il::ProgramLocation) -> Label {
+        let mut trace = self.trace.clone();
+        if trace.len() < MAX_TRACE && !trace.contains(call_site) {
+            trace.push(call_site.clone());
+        }
+        Label { source: self.source.clone(), trace: trace }
+    }
+}
+
+type Labels = BTreeSet<Label>;
+
+
+/// Tainted data reaching a sink.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Finding {
+    /// The call to the sink.
+    pub sink: falcon::il::ProgramLocation,
+    /// The name of the sink function.
+    pub function: String,
+    /// The sink argument receiving tainted data.
+    pub argument: usize,
+    /// The call to the source.
+    pub source: falcon::il::ProgramLocation,
+    /// Call sites between the source and the sink.
+    pub trace: Vec<falcon::il::ProgramLocation>
+}
+
+impl Finding {
+    /// The full path from source to sink.
+    pub fn path(&self) -> Vec<falcon::il::ProgramLocation> {
+        let mut path = vec![self.source.clone()];
+        path.extend(self.trace.iter().cloned());
+        path.push(self.sink.clone());
+        path
+    }
+}
+
+
+/// The result of a taint analysis.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+    /// Functions whose analysis failed, by name, with the error. They neither
+    /// report findings nor pass taint on.
+    pub failures: Vec<(String, String)>,
+    /// Whether propagation between functions reached a fixed point. If not,
+    /// findings may be missing.
+    pub converged: bool
+}
+
+
+/// A memory cell, as a base expression and constant offset.
+type Cell = (Option<falcon::il::Expression>, i64);
+
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct State {
+    /// Taint of scalars, by name.
+    scalars: BTreeMap<String, Labels>,
+    /// Taint of memory cells.
+    memory: BTreeMap<Cell, Labels>,
+    /// Symbolic values of scalars in terms of entry values. A scalar not in
+    /// this map still holds its entry value. `None` is unknown.
+    values: BTreeMap<String, Option<falcon::il::Expression>>
+}
+
+impl State {
+    /// The value of `expression` in terms of entry values, if known.
+    fn value(&self, expression: &falcon::il::Expression) -> Option<falcon::il::Expression> {
+        let value = il::replace_scalars(expression, &|scalar| {
+            match self.values.get(scalar.name()) {
+                None => Some(falcon::il::Expression::Scalar(scalar.clone())),
+                Some(value) => value.clone()
+            }
+        })?;
+        Some(il::simplify(&value))
+    }
+
+    fn cell(&self, address: &falcon::il::Expression) -> Option<Cell> {
+        self.value(address).map(|value| il::base_offset(&value))
+    }
+
+    fn expression_labels(&self, expression: &falcon::il::Expression) -> Labels {
+        let mut labels = Labels::new();
+        for scalar in expression.scalars() {
+            if let Some(l) = self.scalars.get(scalar.name()) {
+                labels.extend(l.iter().cloned());
+            }
+        }
+        labels
+    }
+
+    fn memory_labels(&self, address: &falcon::il::Expression) -> Labels {
+        self.cell(address)
+            .and_then(|cell| self.memory.get(&cell).cloned())
+            .unwrap_or(Labels::new())
+    }
+
+    fn register_labels(&self, scalar: &falcon::il::Scalar) -> Labels {
+        self.scalars.get(scalar.name()).cloned().unwrap_or(Labels::new())
+    }
+
+    fn set_scalar(&mut self, scalar: &falcon::il::Scalar, labels: Labels) {
+        if labels.is_empty() {
+            self.scalars.remove(scalar.name());
+        }
+        else {
+            self.scalars.insert(scalar.name().to_string(), labels);
+        }
+    }
+
+    fn join(&self, other: &State) -> State {
+        let mut state = self.clone();
+        for (name, labels) in &other.scalars {
+            state.scalars.entry(name.clone()).or_insert(Labels::new())
+                .extend(labels.iter().cloned());
+        }
+        for (cell, labels) in &other.memory {
+            state.memory.entry(cell.clone()).or_insert(Labels::new())
+                .extend(labels.iter().cloned());
+        }
+        let names: BTreeSet<&String> =
+            self.values.keys().chain(other.values.keys()).collect();
+        for name in names {
+            if self.values.get(name) != other.values.get(name) {
+                state.values.insert(name.clone(), None);
+            }
+        }
+        state
+    }
+
+    fn leq(&self, other: &State) -> bool {
+        let labels_leq = |lhs: Option<&Labels>, rhs: Option<&Labels>| match (lhs, rhs) {
+            (None, _) => true,
+            (Some(l), Some(r)) => l.is_subset(r),
+            (Some(l), None) => l.is_empty()
+        };
+        self.scalars.iter().all(|(k, v)| labels_leq(Some(v), other.scalars.get(k))) &&
+        self.memory.iter().all(|(k, v)| labels_leq(Some(v), other.memory.get(k))) &&
+        self.values.iter().chain(other.values.iter()).all(|(k, _)| {
+            match other.values.get(k) {
+                Some(&None) => true,
+                rhs => self.values.get(k) == rhs
+            }
+        })
+    }
+}
+
+
+/// What the analysis of one function learned about the rest of the program.
+#[derive(Default)]
+struct Effects {
+    /// Taint of arguments on entry to callees, by function index.
+    entries: BTreeMap<usize, State>,
+    /// Taint of the returned value.
+    returns: Labels,
+    findings: BTreeSet<Finding>
+}
+
+
+struct TaintDomain<'a> {
+    program: &'a falcon::il::Program,
+    architecture: &'a Architecture,
+    config: &'a Config,
+    callees: &'a call_site::Callees<'a>,
+    entry: State,
+    summaries: &'a BTreeMap<usize, Labels>,
+    effects: RefCell<Effects>
+}
+
+impl<'a> TaintDomain<'a> {
+    fn argument_address(&self, state: &State, n: usize) -> Option<falcon::il::Expression> {
+        match call_site::slot(self.architecture, n) {
+            call_site::Slot::Stack(offset) => {
+                let stack_pointer = self.architecture.stack_pointer();
+                let offset = falcon::il::expr_const(offset as u64, stack_pointer.bits());
+                let address = falcon::il::Expression::add(
+                    falcon::il::Expression::Scalar(stack_pointer), offset).ok()?;
+                state.value(&address)
+            },
+            call_site::Slot::Register(_) => None
+        }
+    }
+
+    /// The taint a callee sees on entry, from `state` at a call to it. Stack
+    /// arguments are cells at their offset from the callee's entry stack
+    /// pointer, and the buffer a register argument points to is the cells
+    /// at and above the register's entry value.
+    fn callee_entry(&self, state: &State, call_site: &falcon::il::ProgramLocation) -> State {
+        let through = |labels: &Labels| -> Labels {
+            labels.iter().map(|l| l.through(call_site)).collect()
+        };
+
+        let mut entry = State::default();
+        for n in 0..MAX_ARGUMENTS {
+            match call_site::slot(self.architecture, n) {
+                call_site::Slot::Register(scalar) => {
+                    entry.set_scalar(&scalar, through(&state.register_labels(&scalar)));
+
+                    let pointer = falcon::il::Expression::Scalar(scalar.clone());
+                    let (base, offset) = match state.cell(&pointer) {
+                        Some(cell) => cell,
+                        None => continue
+                    };
+                    for (&(ref cell_base, cell_offset), labels) in &state.memory {
+                        if *cell_base == base && cell_offset >= offset && !labels.is_empty() {
+                            entry.memory.insert(
+                                (Some(pointer.clone()), cell_offset - offset), through(labels));
+                        }
+                    }
+                },
+                call_site::Slot::Stack(offset) => {
+                    let labels = self.argument(state, n).0;
+                    if labels.is_empty() {
+                        continue;
+                    }
+                    let stack_pointer = self.architecture.stack_pointer();
+                    let address = falcon::il::Expression::add(
+                        falcon::il::Expression::Scalar(stack_pointer.clone()),
+                        falcon::il::expr_const(offset as u64, stack_pointer.bits()));
+                    if let Some(cell) = address.ok().and_then(|a| State::default().cell(&a)) {
+                        entry.memory.insert(cell, through(&labels));
+                    }
+                }
+            }
+        }
+        entry
+    }
+
+    /// Taint of an argument's value, and the cell it points to.
+    fn argument(&self, state: &State, n: usize) -> (Labels, Option<Cell>) {
+        match call_site::slot(self.architecture, n) {
+            call_site::Slot::Register(scalar) => (
+                state.register_labels(&scalar),
+                state.cell(&falcon::il::Expression::Scalar(scalar))
+            ),
+            call_site::Slot::Stack(_) => match self.argument_address(state, n) {
+                Some(address) => {
+                    let labels = state.memory.get(&il::base_offset(&address))
+                        .cloned()
+                        .unwrap_or(Labels::new());
+                    (labels, None)
+                },
+                None => (Labels::new(), None)
+            }
+        }
+    }
+
+    fn call(
+        &self,
+        location: &falcon::il::RefProgramLocation,
+        target: &falcon::il::Expression,
+        mut state: State
+    ) -> State {
+
+        let call_site: falcon::il::ProgramLocation = location.clone().into();
+        let calling_convention = self.architecture.calling_convention();
+        let return_register = calling_convention.return_register().clone();
+
+        let target = state.value(target)
+            .and_then(|target| match target {
+                falcon::il::Expression::Constant(ref c) => c.value_u64(),
+                _ => None
+            });
+        let resolved = self.callees.callee(location.function(), &call_site::CallSite {
+            location: location.function_location().clone().into(),
+            address: location.instruction().and_then(|instruction| instruction.address()),
+            target: target,
+            arguments: Vec::new()
+        });
+        let name = resolved.map(|(_, name)| name.to_string());
+        let function = resolved.map(|(address, _)| address)
+            .or(target)
+            .and_then(|address| self.program.function_by_address(address));
+
+        let mut returned = Labels::new();
+
+        if let Some(ref name) = name {
+            let mut effects = self.effects.borrow_mut();
+
+            for sink in self.config.sinks(name) {
+                let (mut labels, cell) = self.argument(&state, sink.argument);
+                if let Some(cell) = cell {
+                    if let Some(pointee) = state.memory.get(&cell) {
+                        labels.extend(pointee.iter().cloned());
+                    }
+                }
+                for label in labels {
+                    effects.findings.insert(Finding {
+                        sink: call_site.clone(),
+                        function: name.clone(),
+                        argument: sink.argument,
+                        source: label.source,
+                        trace: label.trace
+                    });
+                }
+            }
+
+            for source in self.config.sources(name) {
+                let mut labels = Labels::new();
+                labels.insert(Label { source: call_site.clone(), trace: Vec::new() });
+                match source.kind {
+                    SourceKind::Return => returned.extend(labels.into_iter()),
+                    SourceKind::Argument(n) => {
+                        let (_, cell) = self.argument(&state, n);
+                        if let Some(cell) = cell {
+                            state.memory.entry(cell).or_insert(Labels::new())
+                                .extend(labels.into_iter());
+                        }
+                    }
+                }
+            }
+        }
+
+        let is_modelled = name.map_or(false, |name|
+            !self.config.sources(&name).is_empty() || !self.config.sinks(&name).is_empty());
+
+        if !is_modelled {
+            match function.and_then(|function| function.index()) {
+                Some(index) => {
+                    // Carry tainted arguments into the callee, and its
+                    // returned taint back out.
+                    let entry = self.callee_entry(&state, &call_site);
+                    let mut effects = self.effects.borrow_mut();
+                    let joined = match effects.entries.get(&index) {
+                        Some(previous) => previous.join(&entry),
+                        None => entry
+                    };
+                    effects.entries.insert(index, joined);
+                    if let Some(labels) = self.summaries.get(&index) {
+                        returned.extend(labels.iter().map(|l| l.through(&call_site)));
+                    }
+                },
+                None => {
+                    for n in 0..MAX_ARGUMENTS {
+                        returned.extend(self.argument(&state, n).0.into_iter());
+                    }
+                }
+            }
+        }
+
+        for scalar in calling_convention.trashed_registers() {
+            state.scalars.remove(scalar.name());
+            state.values.insert(scalar.name().to_string(), None);
+        }
+        state.set_scalar(&return_register, returned);
+        state.values.insert(return_register.name().to_string(), None);
+
+        // The callee pops a return address pushed by the call.
+        if let ReturnAddressType::Stack(_) = *calling_convention.return_address_type() {
+            let stack_pointer = self.architecture.stack_pointer();
+            let word = falcon::il::expr_const(
+                (self.architecture.word_size() / 8) as u64, stack_pointer.bits());
+            let popped = falcon::il::Expression::add(
+                    falcon::il::Expression::Scalar(stack_pointer.clone()), word)
+                .ok()
+                .and_then(|e| state.value(&e));
+            state.values.insert(stack_pointer.name().to_string(), popped);
+        }
+
+        state
+    }
+}
+
+impl<'a> dataflow::Domain for TaintDomain<'a> {
+    type State = State;
+
+    fn bottom(&self) -> Result<State> {
+        Ok(self.entry.clone())
+    }
+
+    fn join(&self, lhs: &State, rhs: &State) -> Result<State> {
+        Ok(lhs.join(rhs))
+    }
+
+    fn leq(&self, lhs: &State, rhs: &State) -> Result<bool> {
+        Ok(lhs.leq(rhs))
+    }
+
+    fn transfer(
+        &self,
+        location: &falcon::il::RefProgramLocation,
+        operation: &falcon::il::Operation,
+        mut state: State
+    ) -> Result<State> {
+
+        match *operation {
+            falcon::il::Operation::Assign { ref dst, ref src } => {
+                let labels = state.expression_labels(src);
+                let value = state.value(src);
+                state.set_scalar(dst, labels);
+                state.values.insert(dst.name().to_string(), value);
+            },
+            falcon::il::Operation::Load { ref dst, ref index } => {
+                let labels = state.memory_labels(index);
+                state.set_scalar(dst, labels);
+                state.values.insert(dst.name().to_string(), None);
+            },
+            falcon::il::Operation::Store { ref index, ref src } => {
+                let labels = state.expression_labels(src);
+                if let Some(cell) = state.cell(index) {
+                    if labels.is_empty() {
+                        state.memory.remove(&cell);
+                    }
+                    else {
+                        state.memory.insert(cell, labels);
+                    }
+                }
+            },
+            falcon::il::Operation::Branch { ref target } => {
+                let (block, instruction) = match *location.function_location() {
+                    falcon::il::RefFunctionLocation::Instruction(block, instruction) =>
+                        (block.index(), instruction.index()),
+                    _ => return Ok(state)
+                };
+                let returns =
+                    il::branch_returns(self.architecture, location.function(), block, instruction);
+                if returns == Some(false) {
+                    let return_register = self.architecture.calling_convention()
+                        .return_register().clone();
+                    if let Some(labels) = state.scalars.get(return_register.name()) {
+                        self.effects.borrow_mut().returns.extend(labels.iter().cloned());
+                    }
+                }
+                else {
+                    state = self.call(location, target, state);
+                }
+            },
+            falcon::il::Operation::Intrinsic { .. } |
+            falcon::il::Operation::Nop => {}
+        }
+
+        Ok(state)
+    }
+}
+
+
+/// Find tainted data reaching sinks in `program`, which `loader` loaded.
+pub fn analyze(loader: &Loader, program: &falcon::il::Program, config: &Config)
+    -> Result<Report> {
+
+    let architecture = loader.architecture();
+    let callees = call_site::Callees::new(loader, program)?;
+
+    let mut entries: BTreeMap<usize, State> = BTreeMap::new();
+    let mut summaries: BTreeMap<usize, Labels> = BTreeMap::new();
+    let mut findings: BTreeSet<Finding> = BTreeSet::new();
+    let mut failures: BTreeMap<String, String> = BTreeMap::new();
+    let mut converged = false;
+
+    for _ in 0..MAX_ROUNDS {
+        let mut next_entries = entries.clone();
+        let mut next_summaries = summaries.clone();
+
+        for function in program.functions() {
+            let index = match function.index() {
+                Some(index) => index,
+                None => continue
+            };
+
+            let domain = TaintDomain {
+                program: program,
+                architecture: architecture,
+                config: config,
+                callees: &callees,
+                entry: entries.get(&index).cloned().unwrap_or_default(),
+                summaries: &summaries,
+                effects: RefCell::new(Effects::default())
+            };
+
+            if let Err(e) = dataflow::forward(&domain, function) {
+                failures.insert(function.name(), format!("{}", e));
+                continue;
+            }
+
+            let effects = domain.effects.into_inner();
+            findings.extend(effects.findings.into_iter());
+            for (callee, state) in effects.entries {
+                let joined = match next_entries.get(&callee) {
+                    Some(previous) => previous.join(&state),
+                    None => state
+                };
+                next_entries.insert(callee, joined);
+            }
+            if !effects.returns.is_empty() {
+                next_summaries.entry(index).or_insert(Labels::new())
+                    .extend(effects.returns.into_iter());
+            }
+        }
+
+        if next_entries == entries && next_summaries == summaries {
+            converged = true;
+            break;
+        }
+        entries = next_entries;
+        summaries = next_summaries;
+    }
+
+    Ok(Report {
+        findings: findings.into_iter().collect(),
+        failures: failures.into_iter().collect(),
+        converged: converged
+    })
+}
@@ -0,0 +1,419 @@
+//! An unchecked return value checker.
+//!
+//! From each call to a listed function, every path forward through the
+//! caller's control flow graph is followed while it carries the return value.
+//! Assignments which read the value carry it into their destination, and
+//! overwriting a carrier, or a call trashing it, drops it. A path ends well
+//! when an edge condition reads the value. It ends badly when the value is
+//! used as an address, or when no scalar carries it any longer. Storing the
+//! value, passing it to a call or returning it ends the path without a
+//! verdict. A call whose paths are not all followed within `MAX_STEPS` steps
+//! is reported as inconclusive, at `Info` severity.
+//!
+//! Only calls which return to the caller are checked. A tail call, a branch
+//! for which `il::branch_returns` is not `Some(true)`, hands its return value
+//! straight to the caller's caller and is not followed.
+//!
+//! Rules files use the same format as the unsafe call checker, with a
+//! `severity` setting.
+
+use falcon;
+use falcon::error::Result;
+use falcon::loader::Loader;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use call_site;
+use check;
+use il;
+
+
+/// The rule id of the unchecked return value checker.
+pub const RULE: &str = "unchecked-return";
+
+
+/// The rules the checker uses when no rules file is given.
+pub const DEFAULT_RULES: &str = "\
+malloc severity=medium
+calloc severity=medium
+realloc severity=medium
+strdup severity=low
+fopen severity=medium
+mmap severity=medium
+read severity=low
+recv severity=low
+recvfrom severity=low
+setuid severity=high
+setgid severity=high
+seteuid severity=high
+setegid severity=high
+setresuid severity=high
+setresgid severity=high
+chroot severity=high
+";
+
+
+/// Paths explored from one call site before giving up.
+const MAX_STEPS: usize = 10000;
+
+
+/// A function whose return value must be checked.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Rule {
+    pub function: String,
+    pub severity: check::Severity
+}
+
+
+/// Parse a rules file.
+pub fn parse(rules: &str) -> Result<Vec<Rule>> {
+    fn new(function: &str) -> Rule {
+        Rule { function: function.to_string(), severity: check::Severity::Medium }
+    }
+
+    check::parse_rules(rules, new, |rule, key, value| {
+        match key {
+            "severity" => rule.severity = value.parse()?,
+            _ => return Err(format!("unknown setting {}", key))
+        }
+        Ok(())
+    })
+}
+
+
+/// How a return value was misused.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Misuse {
+    /// The value was used as an address before it was checked.
+    Dereferenced(falcon::il::FunctionLocation),
+    /// The value was lost before it was checked.
+    Discarded(falcon::il::FunctionLocation),
+    /// Not every path from the call, at this location, was followed within
+    /// `MAX_STEPS` steps.
+    Inconclusive(falcon::il::FunctionLocation)
+}
+
+impl Misuse {
+    pub fn location(&self) -> &falcon::il::FunctionLocation {
+        match *self {
+            Misuse::Dereferenced(ref location) |
+            Misuse::Discarded(ref location) |
+            Misuse::Inconclusive(ref location) => location
+        }
+    }
+}
+
+impl fmt::Display for Misuse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Misuse::Dereferenced(_) => write!(f, "dereferenced before it is checked"),
+            Misuse::Discarded(_) => write!(f, "discarded without being checked"),
+            Misuse::Inconclusive(_) => write!(f, "not followed to a check within the step limit")
+        }
+    }
+}
+
+
+/// Names of the scalars carrying a return value.
+type Carriers = BTreeSet<String>;
+
+fn reads(carriers: &Carriers, expression: &falcon::il::Expression) -> bool {
+    expression.scalars().iter().any(|scalar| carriers.contains(scalar.name()))
+}
+
+
+/// The first misuse of the value returned by the call at `location`, on any
+/// path through `function`. `Misuse::Inconclusive` if the paths were not all
+/// followed.
+pub fn misuse(
+    architecture: &falcon::architecture::Architecture,
+    function: &falcon::il::Function,
+    location: &falcon::il::FunctionLocation
+) -> Result<Option<Misuse>> {
+
+    let calling_convention = architecture.calling_convention();
+    let argument_registers: HashSet<&str> = calling_convention.argument_registers()
+        .iter()
+        .map(|scalar| scalar.name())
+        .collect();
+
+    let start = falcon::il::RefProgramLocation::new(function, location.apply(function)?);
+
+    let mut carriers = Carriers::new();
+    carriers.insert(calling_convention.return_register().name().to_string());
+
+    let mut queue: Vec<(falcon::il::RefProgramLocation, Carriers)> = start.forward()?
+        .into_iter()
+        .map(|location| (location, carriers.clone()))
+        .collect();
+    let mut visited = HashSet::new();
+    let mut steps = 0;
+
+    while let Some((location, mut carriers)) = queue.pop() {
+        if !visited.insert((location.clone(), carriers.clone())) {
+            continue;
+        }
+        steps += 1;
+        if steps > MAX_STEPS {
+            return Ok(Some(Misuse::Inconclusive(location.function_location().clone().into())));
+        }
+
+        let function_location: falcon::il::FunctionLocation =
+            location.function_location().clone().into();
+
+        match *location.function_location() {
+            falcon::il::RefFunctionLocation::Edge(edge) => {
+                if edge.condition().as_ref().map(|c| reads(&carriers, c)).unwrap_or(false) {
+                    continue;
+                }
+            },
+            falcon::il::RefFunctionLocation::Instruction(block, instruction) => {
+                match *instruction.operation() {
+                    falcon::il::Operation::Assign { ref dst, ref src } => {
+                        if reads(&carriers, src) {
+                            carriers.insert(dst.name().to_string());
+                        }
+                        else {
+                            carriers.remove(dst.name());
+                        }
+                    },
+                    falcon::il::Operation::Load { ref dst, ref index } => {
+                        if reads(&carriers, index) {
+                            return Ok(Some(Misuse::Dereferenced(function_location)));
+                        }
+                        carriers.remove(dst.name());
+                    },
+                    falcon::il::Operation::Store { ref index, ref src } => {
+                        if reads(&carriers, index) {
+                            return Ok(Some(Misuse::Dereferenced(function_location)));
+                        }
+                        if reads(&carriers, src) {
+                            continue;
+                        }
+                    },
+                    falcon::il::Operation::Branch { .. } => {
+                        let returns = il::branch_returns(
+                            architecture, function, block.index(), instruction.index());
+                        match returns {
+                            Some(true) => {
+                                let passed = carriers.iter()
+                                    .any(|c| argument_registers.contains(c.as_str()));
+                                if passed {
+                                    continue;
+                                }
+                                for scalar in calling_convention.trashed_registers() {
+                                    carriers.remove(scalar.name());
+                                }
+                                carriers.remove(calling_convention.return_register().name());
+                            },
+                            // Returning the value hands the check to the
+                            // caller.
+                            _ => {
+                                let returned = calling_convention.return_register().name();
+                                if carriers.contains(returned) {
+                                    continue;
+                                }
+                                return Ok(Some(Misuse::Discarded(function_location)));
+                            }
+                        }
+                    },
+                    falcon::il::Operation::Intrinsic { .. } |
+                    falcon::il::Operation::Nop => {}
+                }
+            },
+            falcon::il::RefFunctionLocation::EmptyBlock(_) => {}
+        }
+
+        if carriers.is_empty() {
+            return Ok(Some(Misuse::Discarded(function_location)));
+        }
+
+        let successors = location.forward()?;
+        if successors.is_empty() &&
+            !carriers.contains(calling_convention.return_register().name()) {
+            return Ok(Some(Misuse::Discarded(function_location)));
+        }
+        for successor in successors {
+            queue.push((successor, carriers.clone()));
+        }
+    }
+
+    Ok(None)
+}
+
+
+/// Check every call to a function in `rules` in `program`.
+pub fn check(loader: &Loader, program: &falcon::il::Program, rules: &[Rule])
+    -> Result<Vec<check::Finding>> {
+
+    let callees = call_site::Callees::new(loader, program)?;
+    let architecture = loader.architecture();
+
+    let mut findings = Vec::new();
+
+    for function in program.functions() {
+        for call_site in call_site::call_sites(architecture, function, 0)? {
+            let name = match callees.callee(function, &call_site) {
+                Some((_, name)) => name,
+                None => continue
+            };
+            let rule = match call_site::lookup(name, |name|
+                rules.iter().find(|rule| rule.function == name)) {
+                Some(rule) => rule,
+                None => continue
+            };
+
+            let misuse = match misuse(architecture, function, &call_site.location)? {
+                Some(misuse) => misuse,
+                None => continue
+            };
+
+            let location = call_site.location.apply(function)?;
+            let location = falcon::il::RefProgramLocation::new(function, location);
+            let related = misuse.location().apply(function)?;
+            let related = falcon::il::RefProgramLocation::new(function, related);
+
+            let message = format!("{} calls {}: return value is {}",
+                function.name(), name, misuse);
+            let severity = match misuse {
+                Misuse::Inconclusive(_) => check::Severity::Info,
+                _ => rule.severity
+            };
+            let mut finding = check::Finding::new(RULE, severity, message);
+            finding.location = Some(location.into());
+            finding.address = call_site.address;
+            finding.related.push(related.into());
+            findings.push(finding);
+        }
+    }
+
+    Ok(findings)
+}
+
+
+/// The unchecked return value checker, for the checker registry.
+#[derive(Clone, Debug)]
+pub struct UncheckedReturnChecker {
+    pub rules: Vec<Rule>
+}
+
+impl UncheckedReturnChecker {
+    /// A checker using the rules in `rules`, in the rules file format.
+    pub fn from_rules(rules: &str) -> Result<UncheckedReturnChecker> {
+        Ok(UncheckedReturnChecker { rules: parse(rules)? })
+    }
+}
+
+impl Default for UncheckedReturnChecker {
+    fn default() -> UncheckedReturnChecker {
+        UncheckedReturnChecker::from_rules(DEFAULT_RULES).expect("default rules must parse")
+    }
+}
+
+impl check::Checker for UncheckedReturnChecker {
+    fn id(&self) -> &str {
+        RULE
+    }
+
+    fn description(&self) -> &str {
+        "return values which are used or discarded before they are checked"
+    }
+
+    fn check(
+        &self,
+        loader: &Arc<Loader>,
+        program: &Arc<falcon::il::Program>
+    ) -> Result<Vec<check::Finding>> {
+        check(&**loader, program, &self.rules)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use falcon::architecture::Amd64;
+    use falcon::il::{expr_const, expr_scalar, scalar, ControlFlowGraph, Expression, Function};
+    use super::*;
+
+    /// A function whose entry block calls 0x1000, then runs `after`, with
+    /// the location of the call.
+    fn call<F>(after: F) -> (Function, falcon::il::FunctionLocation)
+        where F: FnOnce(&mut ControlFlowGraph, usize) {
+
+        let mut control_flow_graph = ControlFlowGraph::new();
+        let entry = {
+            let block = control_flow_graph.new_block().unwrap();
+            block.branch(expr_const(0x1000, 64));
+            block.index()
+        };
+        control_flow_graph.set_entry(entry).unwrap();
+        after(&mut control_flow_graph, entry);
+
+        let function = Function::new(0, control_flow_graph);
+        let instruction = function.block(entry).unwrap().instructions()[0].index();
+        (function, falcon::il::FunctionLocation::Instruction(entry, instruction))
+    }
+
+    fn misuse_after<F>(after: F) -> Option<Misuse>
+        where F: FnOnce(&mut ControlFlowGraph, usize) {
+
+        let (function, location) = call(after);
+        misuse(&Amd64::new(), &function, &location).unwrap()
+    }
+
+    #[test]
+    fn checked_values_are_not_misused() {
+        let misuse = misuse_after(|control_flow_graph, entry| {
+            let null = Expression::cmpeq(expr_scalar("rax", 64), expr_const(0, 64)).unwrap();
+            let nonnull = Expression::cmpneq(expr_scalar("rax", 64), expr_const(0, 64)).unwrap();
+            let (fail, pass) = {
+                let fail = control_flow_graph.new_block().unwrap().index();
+                let pass = control_flow_graph.new_block().unwrap().index();
+                (fail, pass)
+            };
+            control_flow_graph.conditional_edge(entry, fail, null).unwrap();
+            control_flow_graph.conditional_edge(entry, pass, nonnull).unwrap();
+        });
+        assert_eq!(misuse, None);
+    }
+
+    #[test]
+    fn dereferenced_values_are_misused() {
+        let misuse = misuse_after(|control_flow_graph, entry| {
+            let block = control_flow_graph.block_mut(entry).unwrap();
+            block.assign(scalar("rbx", 64), expr_scalar("rax", 64));
+            block.load(scalar("rcx", 64), expr_scalar("rbx", 64));
+        });
+        match misuse {
+            Some(Misuse::Dereferenced(falcon::il::FunctionLocation::Instruction(0, _))) => {},
+            misuse => panic!("expected a dereference, got {:?}", misuse)
+        }
+    }
+
+    #[test]
+    fn overwritten_values_are_discarded() {
+        let misuse = misuse_after(|control_flow_graph, entry| {
+            control_flow_graph.block_mut(entry).unwrap()
+                .assign(scalar("rax", 64), expr_const(1, 64));
+        });
+        match misuse {
+            Some(Misuse::Discarded(_)) => {},
+            misuse => panic!("expected a discard, got {:?}", misuse)
+        }
+    }
+
+    #[test]
+    fn long_paths_are_inconclusive() {
+        let misuse = misuse_after(|control_flow_graph, entry| {
+            let block = control_flow_graph.block_mut(entry).unwrap();
+            for _ in 0..MAX_STEPS + 1 {
+                block.assign(scalar("rbx", 64), expr_scalar("rcx", 64));
+            }
+        });
+        match misuse {
+            Some(Misuse::Inconclusive(_)) => {},
+            misuse => panic!("expected an inconclusive search, got {:?}", misuse)
+        }
+    }
+}
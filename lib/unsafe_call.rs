@@ -0,0 +1,282 @@
+//! An unsafe libc call checker.
+//!
+//! Rules name a function and when a call to it is unsafe: always, or when an
+//! argument, such as a length or a command, is not constant. Findings report
+//! the recovered arguments of the call, and note the stack frame slot, with
+//! its offset from the entry stack pointer and size, of destinations on the
+//! stack.
+//!
+//! Rules files have one rule per line, a function name followed by
+//! `key=value` settings. `#` starts a comment.
+//!
+//! ```text
+//! gets severity=high destination=0
+//! memcpy severity=medium nonconstant=2 destination=0
+//! system severity=high nonconstant=0
+//! ```
+
+use falcon;
+use falcon::error::Result;
+use falcon::loader::Loader;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use call_site;
+use check;
+use stack_frame;
+
+
+/// The rule id of the unsafe call checker.
+pub const RULE: &str = "unsafe-call";
+
+
+/// The rules the checker uses when no rules file is given.
+pub const DEFAULT_RULES: &str = "\
+gets severity=high destination=0
+strcpy severity=medium destination=0
+stpcpy severity=medium destination=0
+strcat severity=medium destination=0
+wcscpy severity=medium destination=0
+wcscat severity=medium destination=0
+sprintf severity=medium destination=0
+vsprintf severity=medium destination=0
+memcpy severity=medium nonconstant=2 destination=0
+memmove severity=medium nonconstant=2 destination=0
+strncpy severity=low nonconstant=2 destination=0
+strncat severity=low nonconstant=2 destination=0
+system severity=high nonconstant=0
+popen severity=high nonconstant=0
+";
+
+
+/// When a call to a function is unsafe.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Rule {
+    pub function: String,
+    pub severity: check::Severity,
+    /// The argument which must be constant, or `None` if every call is
+    /// unsafe.
+    pub nonconstant: Option<usize>,
+    /// The argument which is a destination buffer, if any.
+    pub destination: Option<usize>
+}
+
+impl Rule {
+    /// The number of arguments this rule inspects.
+    fn arguments(&self) -> usize {
+        let nonconstant = self.nonconstant.map(|n| n + 1).unwrap_or(0);
+        let destination = self.destination.map(|n| n + 1).unwrap_or(0);
+        nonconstant.max(destination)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} severity={}", self.function, self.severity)?;
+        if let Some(n) = self.nonconstant {
+            write!(f, " nonconstant={}", n)?;
+        }
+        if let Some(n) = self.destination {
+            write!(f, " destination={}", n)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Parse a rules file.
+pub fn parse(rules: &str) -> Result<Vec<Rule>> {
+    fn new(function: &str) -> Rule {
+        Rule {
+            function: function.to_string(),
+            severity: check::Severity::Medium,
+            nonconstant: None,
+            destination: None
+        }
+    }
+
+    check::parse_rules(rules, new, |rule, key, value| {
+        let error = |_| format!("bad {} {}", key, value);
+        match key {
+            "severity" => rule.severity = value.parse()?,
+            "nonconstant" => rule.nonconstant = Some(value.parse().map_err(error)?),
+            "destination" => rule.destination = Some(value.parse().map_err(error)?),
+            _ => return Err(format!("unknown setting {}", key))
+        }
+        Ok(())
+    })
+}
+
+
+/// The stack frame of a function, to place arguments in.
+struct Frame {
+    frame: stack_frame::StackFrame,
+    offsets: HashMap<falcon::il::FunctionLocation, stack_frame::Offsets>
+}
+
+impl Frame {
+    fn new(architecture: &falcon::architecture::Architecture, function: &falcon::il::Function)
+        -> Result<Frame> {
+
+        let (frame, offsets) = stack_frame::stack_frame_offsets(architecture, function)?;
+        Ok(Frame { frame: frame, offsets: offsets })
+    }
+}
+
+
+/// Argument `argument` of `call_site`, in `function`, as text.
+fn describe(
+    architecture: &falcon::architecture::Architecture,
+    function: &falcon::il::Function,
+    frame: &Frame,
+    call_site: &call_site::CallSite,
+    argument: usize
+) -> Result<String> {
+
+    let argument = match call_site.argument(argument) {
+        Some(argument) => argument,
+        None => return Ok("unknown".to_string())
+    };
+
+    if let Some(ref constant) = argument.constant {
+        return Ok(match constant.value_u64() {
+            Some(value) => format!("0x{:x}", value),
+            None => format!("{}", constant)
+        });
+    }
+
+    let expression = match argument.expression {
+        Some(ref expression) => expression,
+        None => return Ok("unknown".to_string())
+    };
+
+    let offset = stack_frame::expression_offset(
+        architecture, function, &frame.offsets, &argument.origin, expression)?;
+    Ok(match offset {
+        Some(offset) => {
+            let sign = if offset < 0 { "-" } else { "+" };
+            match frame.frame.slot(offset) {
+                Some(slot) => format!("{} (stack sp{}0x{:x} in {})",
+                    expression, sign, offset.abs(), slot),
+                None => format!("{} (stack sp{}0x{:x})", expression, sign, offset.abs())
+            }
+        },
+        None => format!("{}", expression)
+    })
+}
+
+
+/// A call matching a rule.
+fn finding(
+    architecture: &falcon::architecture::Architecture,
+    function: &falcon::il::Function,
+    frame: &Frame,
+    call_site: &call_site::CallSite,
+    name: &str,
+    rule: &Rule
+) -> Result<Option<check::Finding>> {
+
+    let reason = match rule.nonconstant {
+        Some(n) => {
+            let constant = call_site.argument(n)
+                .map(|argument| argument.constant.is_some())
+                .unwrap_or(false);
+            if constant {
+                return Ok(None);
+            }
+            format!("argument {} is not constant", n)
+        },
+        None => "unsafe function".to_string()
+    };
+
+    let arguments: Vec<String> = (0..rule.arguments())
+        .map(|n| describe(architecture, function, frame, call_site, n))
+        .collect::<Result<_>>()?;
+
+    let message = format!("{} calls {}({}): {}",
+        function.name(), name, arguments.join(", "), reason);
+
+    let location = call_site.location.apply(function)?;
+    let location = falcon::il::RefProgramLocation::new(function, location);
+
+    let mut finding = check::Finding::new(RULE, rule.severity, message);
+    finding.location = Some(location.into());
+    finding.address = call_site.address;
+    Ok(Some(finding))
+}
+
+
+/// Check every call matching one of `rules` in `program`.
+pub fn check(loader: &Loader, program: &falcon::il::Program, rules: &[Rule])
+    -> Result<Vec<check::Finding>> {
+
+    let callees = call_site::Callees::new(loader, program)?;
+    let architecture = loader.architecture();
+    let arguments = rules.iter().map(|rule| rule.arguments()).max().unwrap_or(0);
+
+    let mut findings = Vec::new();
+
+    for function in program.functions() {
+        // Recovered once a call in the function matches a rule.
+        let mut frame = None;
+        for call_site in call_site::call_sites(architecture, function, arguments)? {
+            let name = match callees.callee(function, &call_site) {
+                Some((_, name)) => name,
+                None => continue
+            };
+            let rule = call_site::lookup(name, |name|
+                rules.iter().find(|rule| rule.function == name));
+            if let Some(rule) = rule {
+                if frame.is_none() {
+                    frame = Some(Frame::new(architecture, function)?);
+                }
+                let frame = frame.as_ref().unwrap();
+                if let Some(finding) =
+                    finding(architecture, function, frame, &call_site, name, rule)? {
+                    findings.push(finding);
+                }
+            }
+        }
+    }
+
+    Ok(findings)
+}
+
+
+/// The unsafe call checker, for the checker registry.
+#[derive(Clone, Debug)]
+pub struct UnsafeCallChecker {
+    pub rules: Vec<Rule>
+}
+
+impl UnsafeCallChecker {
+    /// A checker using the rules in `rules`, in the rules file format.
+    pub fn from_rules(rules: &str) -> Result<UnsafeCallChecker> {
+        Ok(UnsafeCallChecker { rules: parse(rules)? })
+    }
+}
+
+impl Default for UnsafeCallChecker {
+    fn default() -> UnsafeCallChecker {
+        UnsafeCallChecker::from_rules(DEFAULT_RULES).expect("default rules must parse")
+    }
+}
+
+impl check::Checker for UnsafeCallChecker {
+    fn id(&self) -> &str {
+        RULE
+    }
+
+    fn description(&self) -> &str {
+        "calls to unsafe libc functions, or with non-constant lengths or commands"
+    }
+
+    fn check(
+        &self,
+        loader: &Arc<Loader>,
+        program: &Arc<falcon::il::Program>
+    ) -> Result<Vec<check::Finding>> {
+        check(&**loader, program, &self.rules)
+    }
+}
@@ -0,0 +1,1195 @@
+//! A value-set analysis over strided intervals.
+//!
+//! Every scalar, every stack cell at a known offset from the entry stack
+//! pointer, and every global cell at a constant address holds a strided
+//! interval: the unsigned values `lo, lo + stride, ..., hi`. Anything not
+//! tracked is unknown. Every join widens its bounds to the next power of two,
+//! so loops converge in at most one step per bit. Rounding the bounds whether
+//! or not they differ keeps the widening monotone, as the dataflow framework
+//! joins predecessor states without the state a location held before. It
+//! also does not say where a join happens, so joins where paths merge after
+//! a branch widen too, and lose precision loop heads alone would keep.
+//!
+//! Conditional edges refine the state. Conditions are read through the
+//! expressions last assigned to the flags and temporaries they use, so `cmp`
+//! followed by a conditional jump narrows the compared scalar, and the stack
+//! or global cell it was loaded from. The low bits of a wider register are
+//! narrowed on their own when a comparison reads only them. Unsigned and
+//! equality comparisons are understood, and signed comparisons between
+//! non-negative values.
+//!
+//! Stores through unknown pointers are assumed not to alias the stack frame,
+//! and calls forget every global cell and the registers they trash.
+
+use falcon;
+use falcon::architecture::Architecture;
+use falcon::error::Result;
+use falcon::il::Expression;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use dataflow;
+use il;
+use stack_frame;
+
+
+fn mask(bits: usize) -> u64 {
+    if bits >= 64 { u64::max_value() } else { (1 << bits) - 1 }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// The smallest `2^k - 1` at least `value`.
+fn round_up(value: u64) -> u64 {
+    if value == 0 {
+        return 0;
+    }
+    u64::max_value() >> value.leading_zeros()
+}
+
+/// The largest power of two at most `value`, or 0.
+fn round_down(value: u64) -> u64 {
+    if value == 0 {
+        return 0;
+    }
+    1 << (63 - value.leading_zeros())
+}
+
+
+/// The unsigned values `lo, lo + stride, ..., hi` of a `bits` wide value. A
+/// single value has a stride of 0.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct StridedInterval {
+    pub bits: usize,
+    pub stride: u64,
+    pub lo: u64,
+    pub hi: u64
+}
+
+impl StridedInterval {
+    /// The values from `lo` to `hi` a multiple of `stride` from `lo`.
+    pub fn new(bits: usize, stride: u64, lo: u64, hi: u64) -> StridedInterval {
+        let hi = cmp::min(hi, mask(bits));
+        let lo = cmp::min(lo, hi);
+        if lo == hi {
+            return StridedInterval { bits: bits, stride: 0, lo: lo, hi: hi };
+        }
+        let stride = if stride == 0 { 1 } else { stride };
+        let hi = lo + (hi - lo) / stride * stride;
+        let stride = if lo == hi { 0 } else { stride };
+        StridedInterval { bits: bits, stride: stride, lo: lo, hi: hi }
+    }
+
+    /// Every `bits` wide value.
+    pub fn top(bits: usize) -> StridedInterval {
+        StridedInterval::new(bits, 1, 0, mask(bits))
+    }
+
+    pub fn constant(bits: usize, value: u64) -> StridedInterval {
+        StridedInterval::new(bits, 0, value & mask(bits), value & mask(bits))
+    }
+
+    fn boolean(value: bool) -> StridedInterval {
+        StridedInterval::constant(1, value as u64)
+    }
+
+    fn unknown_boolean() -> StridedInterval {
+        StridedInterval::top(1)
+    }
+
+    pub fn is_top(&self) -> bool {
+        self.lo == 0 && self.hi == mask(self.bits) && self.stride <= 1
+    }
+
+    /// The value, if there is only one.
+    pub fn value(&self) -> Option<u64> {
+        if self.lo == self.hi { Some(self.lo) } else { None }
+    }
+
+    pub fn contains(&self, value: u64) -> bool {
+        value >= self.lo && value <= self.hi &&
+            (self.stride == 0 || (value - self.lo) % self.stride == 0)
+    }
+
+    /// The number of values.
+    pub fn size(&self) -> u64 {
+        if self.stride == 0 { 1 } else { (self.hi - self.lo) / self.stride + 1 }
+    }
+
+    /// The values in this interval as `bits` wide, if they all fit.
+    fn resize(&self, bits: usize) -> StridedInterval {
+        if self.hi <= mask(bits) {
+            StridedInterval { bits: bits, ..*self }
+        }
+        else {
+            StridedInterval::top(bits)
+        }
+    }
+
+    /// Whether every value in `self` is in `other`.
+    pub fn leq(&self, other: &StridedInterval) -> bool {
+        if self.lo < other.lo || self.hi > other.hi {
+            return false;
+        }
+        if other.stride == 0 {
+            return self.lo == other.lo && self.hi == other.hi;
+        }
+        (self.lo - other.lo) % other.stride == 0 && self.stride % other.stride == 0
+    }
+
+    /// The smallest interval containing `self` and `other`.
+    pub fn join(&self, other: &StridedInterval) -> StridedInterval {
+        let bits = cmp::max(self.bits, other.bits);
+        let lo = cmp::min(self.lo, other.lo);
+        let hi = cmp::max(self.hi, other.hi);
+        let stride = gcd(gcd(self.stride, other.stride), self.lo.max(other.lo) - lo);
+        StridedInterval::new(bits, stride, lo, hi)
+    }
+
+    /// `join`, with its bounds widened to powers of two. Both bounds are
+    /// rounded even when `self` and `other` agree on them, so that widening
+    /// larger intervals never gives a smaller one.
+    pub fn widen(&self, other: &StridedInterval) -> StridedInterval {
+        let joined = self.join(other);
+        let lo = round_down(joined.lo);
+        let hi = round_up(joined.hi);
+        let stride = gcd(joined.stride, joined.lo - lo);
+        StridedInterval::new(joined.bits, stride, lo, hi)
+    }
+
+    /// The values of `self` from `lo` to `hi`, or `None` if there are none.
+    pub fn intersect(&self, lo: u64, hi: u64) -> Option<StridedInterval> {
+        let stride = cmp::max(self.stride, 1);
+        let lo = if lo > self.lo {
+            let steps = (lo - self.lo + stride - 1) / stride;
+            self.lo.checked_add(steps.checked_mul(stride)?)?
+        }
+        else {
+            self.lo
+        };
+        let hi = cmp::min(hi, self.hi);
+        if lo > hi {
+            None
+        }
+        else {
+            Some(StridedInterval::new(self.bits, self.stride, lo, hi))
+        }
+    }
+
+    fn add(&self, other: &StridedInterval) -> StridedInterval {
+        let bits = self.bits;
+        // A large constant is a negative one.
+        if let Some(value) = other.value() {
+            if value > mask(bits) >> 1 {
+                let negated = StridedInterval::constant(bits, value.wrapping_neg());
+                return self.sub(&negated);
+            }
+        }
+        if let Some(value) = self.value() {
+            if value > mask(bits) >> 1 {
+                let negated = StridedInterval::constant(bits, value.wrapping_neg());
+                return other.sub(&negated);
+            }
+        }
+        match (self.lo.checked_add(other.lo), self.hi.checked_add(other.hi)) {
+            (Some(lo), Some(hi)) if hi <= mask(bits) =>
+                StridedInterval::new(bits, gcd(self.stride, other.stride), lo, hi),
+            _ => StridedInterval::top(bits)
+        }
+    }
+
+    fn sub(&self, other: &StridedInterval) -> StridedInterval {
+        if self.lo < other.hi {
+            return StridedInterval::top(self.bits);
+        }
+        StridedInterval::new(self.bits, gcd(self.stride, other.stride),
+            self.lo - other.hi, self.hi - other.lo)
+    }
+
+    fn mul(&self, other: &StridedInterval) -> StridedInterval {
+        let bits = self.bits;
+        let stride = match (self.value(), other.value()) {
+            (_, Some(value)) => self.stride.checked_mul(value),
+            (Some(value), _) => other.stride.checked_mul(value),
+            _ => Some(1)
+        };
+        match (self.lo.checked_mul(other.lo), self.hi.checked_mul(other.hi), stride) {
+            (Some(lo), Some(hi), Some(stride)) if hi <= mask(bits) =>
+                StridedInterval::new(bits, stride, lo, hi),
+            _ => StridedInterval::top(bits)
+        }
+    }
+
+    fn shr(&self, shift: u64) -> StridedInterval {
+        if shift >= 64 {
+            return StridedInterval::constant(self.bits, 0);
+        }
+        let stride = if self.stride % (1 << shift) == 0 { self.stride >> shift } else { 1 };
+        StridedInterval::new(self.bits, stride, self.lo >> shift, self.hi >> shift)
+    }
+
+    fn unsigned_less(&self, other: &StridedInterval) -> StridedInterval {
+        if self.hi < other.lo {
+            StridedInterval::boolean(true)
+        }
+        else if self.lo >= other.hi {
+            StridedInterval::boolean(false)
+        }
+        else {
+            StridedInterval::unknown_boolean()
+        }
+    }
+
+    /// Whether every value is non-negative as a signed value.
+    fn non_negative(&self) -> bool {
+        self.hi <= mask(self.bits) >> 1
+    }
+}
+
+impl fmt::Display for StridedInterval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.value() {
+            Some(value) => write!(f, "0x{:x}", value),
+            None => write!(f, "0x{:x}[0x{:x}, 0x{:x}]", self.stride, self.lo, self.hi)
+        }
+    }
+}
+
+
+/// A memory cell whose value is tracked.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Cell {
+    /// A cell at an offset from the entry stack pointer.
+    Stack(i64),
+    /// A cell at a constant address.
+    Global(u64)
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Cell::Stack(offset) => {
+                let sign = if offset < 0 { "-" } else { "+" };
+                write!(f, "[sp{}0x{:x}]", sign, offset.abs())
+            },
+            Cell::Global(address) => write!(f, "[0x{:x}]", address)
+        }
+    }
+}
+
+impl Cell {
+    /// The cell `delta` bytes further on.
+    fn add(&self, delta: u64) -> Cell {
+        match *self {
+            Cell::Stack(offset) => Cell::Stack(offset.wrapping_add(delta as i64)),
+            Cell::Global(address) => Cell::Global(address.wrapping_add(delta))
+        }
+    }
+
+    fn same_space(&self, other: &Cell) -> bool {
+        match (*self, *other) {
+            (Cell::Stack(_), Cell::Stack(_)) | (Cell::Global(_), Cell::Global(_)) => true,
+            _ => false
+        }
+    }
+}
+
+
+/// Expressions with more scalars than this are not kept to read conditions
+/// through.
+const MAX_DEFINITION_SCALARS: usize = 4;
+
+
+/// The values of scalars and memory cells at a location.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ValueSet {
+    /// Offsets from the entry stack pointer held by scalars.
+    pub offsets: stack_frame::Offsets,
+    /// Scalar values, by name.
+    pub scalars: BTreeMap<String, StridedInterval>,
+    /// Memory cell values.
+    pub cells: BTreeMap<Cell, StridedInterval>,
+    /// The expression last assigned to a scalar, in terms of scalars which
+    /// have not changed since.
+    definitions: BTreeMap<String, Expression>,
+    /// The cell a scalar was loaded from, while both are unchanged.
+    origins: BTreeMap<String, Cell>
+}
+
+fn join_maps<K: Clone + Ord, V: Clone + Eq>(lhs: &BTreeMap<K, V>, rhs: &BTreeMap<K, V>)
+    -> BTreeMap<K, V> {
+
+    lhs.iter()
+        .filter(|&(key, value)| rhs.get(key) == Some(value))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+fn join_intervals<K: Clone + Ord, F>(
+    lhs: &BTreeMap<K, StridedInterval>,
+    rhs: &BTreeMap<K, StridedInterval>,
+    join: F
+) -> BTreeMap<K, StridedInterval>
+    where F: Fn(&StridedInterval, &StridedInterval) -> StridedInterval {
+
+    lhs.iter()
+        .filter_map(|(key, l)| {
+            let r = rhs.get(key)?;
+            let joined = join(l, r);
+            if joined.is_top() { None } else { Some((key.clone(), joined)) }
+        })
+        .collect()
+}
+
+impl ValueSet {
+    /// The values of `scalar`.
+    pub fn scalar(&self, scalar: &falcon::il::Scalar) -> StridedInterval {
+        self.scalars.get(scalar.name())
+            .map(|value| value.resize(scalar.bits()))
+            .unwrap_or(StridedInterval::top(scalar.bits()))
+    }
+
+    /// The values of the `bits` wide cell `cell`.
+    pub fn cell(&self, cell: &Cell, bits: usize) -> StridedInterval {
+        match self.cells.get(cell) {
+            Some(value) if value.bits == bits => *value,
+            _ => StridedInterval::top(bits)
+        }
+    }
+
+    /// The values `expression` may evaluate to.
+    pub fn eval(&self, expression: &Expression) -> StridedInterval {
+        let bits = expression.bits();
+        let binary = |l: &Expression, r: &Expression| (self.eval(l), self.eval(r));
+        let constants = |l: &Expression, r: &Expression| {
+            let (l, r) = binary(l, r);
+            match (l.value(), r.value()) {
+                (Some(l), Some(r)) => Some((l, r)),
+                _ => None
+            }
+        };
+
+        match *expression {
+            Expression::Scalar(ref scalar) => self.scalar(scalar),
+            Expression::Constant(ref constant) => match constant.value_u64() {
+                Some(value) if bits <= 64 => StridedInterval::constant(bits, value),
+                _ => StridedInterval::top(bits)
+            },
+            Expression::Add(ref l, ref r) => {
+                let (l, r) = binary(l, r);
+                l.add(&r)
+            },
+            Expression::Sub(ref l, ref r) => {
+                let (l, r) = binary(l, r);
+                l.sub(&r)
+            },
+            Expression::Mul(ref l, ref r) => {
+                let (l, r) = binary(l, r);
+                l.mul(&r)
+            },
+            Expression::Divu(ref l, ref r) => {
+                let (l, r) = binary(l, r);
+                match r.value() {
+                    Some(0) | None => StridedInterval::top(bits),
+                    Some(divisor) => StridedInterval::new(bits,
+                        if l.stride % divisor == 0 { l.stride / divisor } else { 1 },
+                        l.lo / divisor, l.hi / divisor)
+                }
+            },
+            Expression::Modu(ref l, ref r) => {
+                let (l, r) = binary(l, r);
+                match r.value() {
+                    Some(0) | None => StridedInterval::top(bits),
+                    Some(divisor) if l.hi < divisor => l,
+                    Some(divisor) => StridedInterval::new(bits, 1, 0, divisor - 1)
+                }
+            },
+            Expression::Divs(_, _) |
+            Expression::Mods(_, _) => StridedInterval::top(bits),
+            Expression::And(ref l, ref r) => {
+                let (l, r) = binary(l, r);
+                match (l.value(), r.value()) {
+                    (Some(l), Some(r)) => StridedInterval::constant(bits, l & r),
+                    (Some(m), None) => StridedInterval::new(bits, 1, 0, cmp::min(r.hi, m)),
+                    (None, Some(m)) if round_up(m) == m && l.hi <= m => l,
+                    (None, Some(m)) => StridedInterval::new(bits, 1, 0, cmp::min(l.hi, m)),
+                    (None, None) => StridedInterval::new(bits, 1, 0, cmp::min(l.hi, r.hi))
+                }
+            },
+            Expression::Or(ref l, ref r) |
+            Expression::Xor(ref l, ref r) => {
+                let (lv, rv) = binary(l, r);
+                match (lv.value(), rv.value()) {
+                    (Some(a), Some(b)) => StridedInterval::constant(bits, match *expression {
+                        Expression::Or(_, _) => a | b,
+                        _ => a ^ b
+                    }),
+                    _ => StridedInterval::new(bits, 1, 0, round_up(cmp::max(lv.hi, rv.hi)))
+                }
+            },
+            Expression::Shl(ref l, ref r) => {
+                let (l, r) = binary(l, r);
+                match r.value() {
+                    Some(shift) if shift < 64 =>
+                        l.mul(&StridedInterval::constant(64, 1 << shift)),
+                    _ => StridedInterval::top(bits)
+                }
+            },
+            Expression::Shr(ref l, ref r) => {
+                let (l, r) = binary(l, r);
+                match r.value() {
+                    Some(shift) => l.shr(shift),
+                    None => StridedInterval::new(bits, 1, 0, l.hi)
+                }
+            },
+            Expression::Cmpeq(ref l, ref r) |
+            Expression::Cmpneq(ref l, ref r) => {
+                let equal = match *expression {
+                    Expression::Cmpeq(_, _) => true,
+                    _ => false
+                };
+                let (l, r) = binary(l, r);
+                match (l.value(), r.value()) {
+                    (Some(a), Some(b)) => StridedInterval::boolean((a == b) == equal),
+                    _ if l.hi < r.lo || r.hi < l.lo => StridedInterval::boolean(!equal),
+                    _ => StridedInterval::unknown_boolean()
+                }
+            },
+            Expression::Cmpltu(ref l, ref r) => {
+                let (l, r) = binary(l, r);
+                l.unsigned_less(&r)
+            },
+            Expression::Cmplts(ref l, ref r) => {
+                let operand_bits = l.bits();
+                if operand_bits > 0 && operand_bits <= 64 {
+                    if let Some((a, b)) = constants(l, r) {
+                        let sign = 1 << (operand_bits - 1);
+                        return StridedInterval::boolean((a ^ sign) < (b ^ sign));
+                    }
+                }
+                let (l, r) = binary(l, r);
+                if l.non_negative() && r.non_negative() {
+                    l.unsigned_less(&r)
+                }
+                else {
+                    StridedInterval::unknown_boolean()
+                }
+            },
+            Expression::Zext(bits, ref e) => {
+                let value = self.eval(e);
+                StridedInterval { bits: bits, ..value }
+            },
+            Expression::Sext(bits, ref e) => {
+                let value = self.eval(e);
+                let from = e.bits();
+                if from > 64 {
+                    StridedInterval::top(bits)
+                }
+                else if let Some(constant) = value.value() {
+                    let sign = 1u64 << (from - 1);
+                    let extended = if constant & sign != 0 && from < 64 {
+                        constant | !mask(from)
+                    }
+                    else {
+                        constant
+                    };
+                    StridedInterval::constant(bits, extended)
+                }
+                else if value.non_negative() {
+                    StridedInterval { bits: bits, ..value }
+                }
+                else {
+                    StridedInterval::top(bits)
+                }
+            },
+            Expression::Trun(bits, ref e) => {
+                let value = self.eval(e);
+                match value.value() {
+                    Some(value) => StridedInterval::constant(bits, value),
+                    None if value.hi <= mask(bits) => value.resize(bits),
+                    None => match **e {
+                        Expression::Scalar(ref scalar) => self.scalars
+                            .get(&truncated(scalar.name(), bits))
+                            .cloned()
+                            .unwrap_or(StridedInterval::top(bits)),
+                        _ => StridedInterval::top(bits)
+                    }
+                }
+            },
+            Expression::Ite(ref c, ref t, ref e) => match self.eval(c).value() {
+                Some(0) => self.eval(e),
+                Some(_) => self.eval(t),
+                None => self.eval(t).join(&self.eval(e))
+            }
+        }
+    }
+
+    /// Forget everything known about `name`, and everything read through it.
+    fn forget(&mut self, name: &str) {
+        self.scalars.remove(name);
+        let prefix = format!("{}:", name);
+        self.scalars = ::std::mem::replace(&mut self.scalars, BTreeMap::new())
+            .into_iter()
+            .filter(|&(ref key, _)| !key.starts_with(&prefix))
+            .collect();
+        self.origins.remove(name);
+        self.definitions.remove(name);
+        self.definitions = ::std::mem::replace(&mut self.definitions, BTreeMap::new())
+            .into_iter()
+            .filter(|&(_, ref definition)|
+                !definition.scalars().iter().any(|scalar| scalar.name() == name))
+            .collect();
+    }
+
+    fn set(&mut self, name: &str, value: StridedInterval) {
+        if value.is_top() {
+            self.scalars.remove(name);
+        }
+        else {
+            self.scalars.insert(name.to_string(), value);
+        }
+    }
+
+    /// Forget every cell overlapping `bytes` bytes from `start`, or every
+    /// cell from `start` on if `bytes` is `None`.
+    fn clobber(&mut self, start: Cell, bytes: Option<u64>) {
+        let overlaps = |cell: &Cell, value: &StridedInterval| {
+            if !cell.same_space(&start) {
+                return false;
+            }
+            let end = cell.add(cmp::max(value.bits as u64 / 8, 1));
+            match bytes {
+                Some(bytes) => *cell < start.add(bytes) && end > start,
+                None => end > start
+            }
+        };
+        let clobbered: Vec<Cell> = self.cells.iter()
+            .filter(|&(cell, value)| overlaps(cell, value))
+            .map(|(cell, _)| *cell)
+            .collect();
+        for cell in &clobbered {
+            self.cells.remove(cell);
+        }
+        self.origins = ::std::mem::replace(&mut self.origins, BTreeMap::new())
+            .into_iter()
+            .filter(|&(_, ref cell)| !clobbered.contains(cell))
+            .collect();
+    }
+
+    fn forget_globals(&mut self) {
+        self.cells = ::std::mem::replace(&mut self.cells, BTreeMap::new())
+            .into_iter()
+            .filter(|&(ref cell, _)| match *cell {
+                Cell::Stack(_) => true,
+                Cell::Global(_) => false
+            })
+            .collect();
+        self.origins = ::std::mem::replace(&mut self.origins, BTreeMap::new())
+            .into_iter()
+            .filter(|&(_, ref cell)| match *cell {
+                Cell::Stack(_) => true,
+                Cell::Global(_) => false
+            })
+            .collect();
+    }
+
+    /// The cell `index` addresses.
+    fn address(&self, architecture: &Architecture, index: &Expression) -> Option<Cell> {
+        if let Some(offset) = stack_frame::offset_of(architecture, &self.offsets, index) {
+            return Some(Cell::Stack(offset));
+        }
+        self.eval(index).value().map(Cell::Global)
+    }
+
+    /// `expression` with scalars replaced by their definitions.
+    fn defined(&self, expression: &Expression) -> Expression {
+        let expression = il::replace_scalars(expression, &|scalar: &falcon::il::Scalar|
+            Some(self.definitions.get(scalar.name())
+                .cloned()
+                .unwrap_or(Expression::Scalar(scalar.clone()))))
+            .unwrap_or(expression.clone());
+        il::simplify(&expression)
+    }
+
+    /// Narrow the values of `expression` to those from `lo` to `hi`.
+    fn narrow(&mut self, expression: &Expression, lo: u64, hi: u64) {
+        match *expression {
+            Expression::Scalar(ref scalar) => {
+                let value = match self.scalar(scalar).intersect(lo, hi) {
+                    Some(value) => value,
+                    None => return
+                };
+                self.set(scalar.name(), value);
+                if let Some(cell) = self.origins.get(scalar.name()).cloned() {
+                    if let Some(value) = self.cell(&cell, scalar.bits()).intersect(lo, hi) {
+                        self.cells.insert(cell, value);
+                    }
+                }
+            },
+            Expression::Zext(_, ref e) => {
+                let hi = cmp::min(hi, mask(e.bits()));
+                if lo <= hi {
+                    self.narrow(e, lo, hi);
+                }
+            },
+            Expression::Trun(bits, ref e) =>
+                if self.eval(e).hi <= mask(bits) {
+                    self.narrow(e, lo, hi);
+                }
+                else if let Expression::Scalar(ref scalar) = **e {
+                    // Remember the low bits of a wider scalar on their own.
+                    let key = truncated(scalar.name(), bits);
+                    let value = self.scalars.get(&key)
+                        .cloned()
+                        .unwrap_or(StridedInterval::top(bits));
+                    if let Some(value) = value.intersect(lo, hi) {
+                        self.set(&key, value);
+                    }
+                },
+            Expression::Add(ref l, ref r) => {
+                let (e, constant) = match (self.eval(l).value(), self.eval(r).value()) {
+                    (_, Some(constant)) => (l, constant),
+                    (Some(constant), _) => (r, constant),
+                    _ => return
+                };
+                // No value of `e` wraps into the range when it starts above
+                // the constant.
+                if lo >= constant {
+                    self.narrow(e, lo - constant, cmp::min(hi, mask(e.bits())) - constant);
+                }
+            },
+            Expression::Sub(ref e, ref c) => {
+                let constant = match self.eval(c).value() {
+                    Some(constant) => constant,
+                    None => return
+                };
+                // No value of `e` wraps into the range when it ends below
+                // the constant's distance from the top.
+                match (lo.checked_add(constant), hi.checked_add(constant)) {
+                    (Some(lo), Some(hi)) if hi <= mask(e.bits()) => self.narrow(e, lo, hi),
+                    _ => {}
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Narrow `expression` to values other than `value`.
+    fn exclude(&mut self, expression: &Expression, value: u64) {
+        let current = self.eval(expression);
+        if current.size() == 1 {
+            return;
+        }
+        let step = cmp::max(current.stride, 1);
+        if current.lo == value {
+            self.narrow(expression, value + step, current.hi);
+        }
+        else if current.hi == value {
+            self.narrow(expression, current.lo, value - step);
+        }
+    }
+
+    /// Narrow values so `l < r` as unsigned values is `truth`.
+    fn refine_less(&mut self, l: &Expression, r: &Expression, truth: bool) {
+        let (lv, rv) = (self.eval(l), self.eval(r));
+        if truth {
+            if rv.hi > 0 {
+                self.narrow(l, 0, rv.hi - 1);
+            }
+            if lv.lo < mask(r.bits()) {
+                self.narrow(r, lv.lo + 1, u64::max_value());
+            }
+        }
+        else {
+            self.narrow(l, rv.lo, u64::max_value());
+            self.narrow(r, 0, lv.hi);
+        }
+    }
+
+    /// Narrow values so `l < r` as signed values is `truth`, when that is the
+    /// same as unsigned.
+    fn refine_signed_less(&mut self, l: &Expression, r: &Expression, truth: bool) {
+        let (lv, rv) = (self.eval(l), self.eval(r));
+        if !rv.non_negative() {
+            return;
+        }
+        if truth {
+            if lv.non_negative() {
+                self.refine_less(l, r, true);
+            }
+        }
+        else {
+            // Only non-negative values are at least a non-negative value.
+            self.narrow(l, rv.lo, mask(l.bits()) >> 1);
+            if lv.non_negative() {
+                self.narrow(r, 0, lv.hi);
+            }
+        }
+    }
+
+    /// Refine values so `condition` is `truth`.
+    fn refine(&mut self, condition: &Expression, truth: bool) {
+        match *condition {
+            Expression::Scalar(ref scalar) => {
+                let value = truth as u64;
+                self.narrow(&Expression::Scalar(scalar.clone()), value, value);
+            },
+            Expression::Cmpeq(ref l, ref r) | Expression::Cmpneq(ref l, ref r) => {
+                let equal = match *condition {
+                    Expression::Cmpeq(_, _) => truth,
+                    _ => !truth
+                };
+
+                // The sign of a difference compared with the overflow flag.
+                let (lv, rv) = (self.eval(l), self.eval(r));
+                let signed = match (sign_of_difference(l), sign_of_difference(r)) {
+                    (Some(operands), None) if rv.value() != Some(1) => Some(operands),
+                    (None, Some(operands)) if lv.value() != Some(1) => Some(operands),
+                    _ => None
+                };
+                if let Some((a, b)) = signed {
+                    self.refine_signed_less(&a, &b, !equal);
+                    return;
+                }
+
+                if l.bits() == 1 && rv.value().is_some() {
+                    return self.refine(l, (rv.value() == Some(1)) == equal);
+                }
+                if r.bits() == 1 && lv.value().is_some() {
+                    return self.refine(r, (lv.value() == Some(1)) == equal);
+                }
+                if equal {
+                    self.narrow(l, rv.lo, rv.hi);
+                    self.narrow(r, lv.lo, lv.hi);
+                }
+                else {
+                    if let Some(value) = rv.value() {
+                        self.exclude(l, value);
+                    }
+                    if let Some(value) = lv.value() {
+                        self.exclude(r, value);
+                    }
+                }
+            },
+            Expression::Cmpltu(ref l, ref r) => self.refine_less(l, r, truth),
+            Expression::Cmplts(ref l, ref r) => match sign_of_difference(condition) {
+                Some((a, b)) => self.refine_signed_less(&a, &b, truth),
+                None => self.refine_signed_less(l, r, truth)
+            },
+            Expression::And(ref l, ref r) if condition.bits() == 1 => {
+                if truth {
+                    self.refine(l, true);
+                    self.refine(r, true);
+                }
+                else {
+                    self.refine_either(l, r, false);
+                }
+            },
+            Expression::Or(ref l, ref r) if condition.bits() == 1 => {
+                if truth {
+                    self.refine_either(l, r, true);
+                }
+                else {
+                    self.refine(l, false);
+                    self.refine(r, false);
+                }
+            },
+            Expression::Xor(ref l, ref r) if condition.bits() == 1 => {
+                match (self.eval(l).value(), self.eval(r).value()) {
+                    (_, Some(value)) => self.refine(l, truth != (value == 1)),
+                    (Some(value), _) => self.refine(r, truth != (value == 1)),
+                    _ => {}
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Refine values so either `l` or `r` is `truth`.
+    fn refine_either(&mut self, l: &Expression, r: &Expression, truth: bool) {
+        let mut left = self.clone();
+        left.refine(l, truth);
+        let mut right = self.clone();
+        right.refine(r, truth);
+        *self = left.join(&right, false);
+    }
+
+    /// Join `self` with `other`, widening the bounds if `widen`.
+    pub fn join(&self, other: &ValueSet, widen: bool) -> ValueSet {
+        let join = |l: &StridedInterval, r: &StridedInterval|
+            if widen { l.widen(r) } else { l.join(r) };
+        ValueSet {
+            offsets: join_maps(&self.offsets, &other.offsets),
+            scalars: join_intervals(&self.scalars, &other.scalars, &join),
+            cells: join_intervals(&self.cells, &other.cells, &join),
+            definitions: join_maps(&self.definitions, &other.definitions),
+            origins: join_maps(&self.origins, &other.origins)
+        }
+    }
+
+    /// Whether everything `other` knows, `self` knows at least as precisely.
+    pub fn leq(&self, other: &ValueSet) -> bool {
+        fn below<K: Ord, V, F: Fn(&V, &V) -> bool>(
+            lhs: &BTreeMap<K, V>,
+            rhs: &BTreeMap<K, V>,
+            f: F
+        ) -> bool {
+            rhs.iter().all(|(key, r)| lhs.get(key).map(|l| f(l, r)).unwrap_or(false))
+        }
+
+        below(&self.offsets, &other.offsets, |l, r| l == r) &&
+            below(&self.scalars, &other.scalars, |l, r| l.leq(r)) &&
+            below(&self.cells, &other.cells, |l, r| l.leq(r)) &&
+            below(&self.definitions, &other.definitions, |l, r| l == r) &&
+            below(&self.origins, &other.origins, |l, r| l == r)
+    }
+}
+
+impl fmt::Display for ValueSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scalars = self.scalars.iter().map(|(name, value)| format!("{} = {}", name, value));
+        let cells = self.cells.iter().map(|(cell, value)| format!("{} = {}", cell, value));
+        let values: Vec<String> = scalars.chain(cells).collect();
+        write!(f, "{{ {} }}", values.join(", "))
+    }
+}
+
+
+/// The key under which the low `bits` bits of the scalar `name` are kept.
+fn truncated(name: &str, bits: usize) -> String {
+    format!("{}:{}", name, bits)
+}
+
+
+/// `(a, b)` if `expression` is the sign bit of `a - b`.
+fn sign_of_difference(expression: &Expression) -> Option<(Expression, Expression)> {
+    let difference = match *expression {
+        Expression::Trun(1, ref e) => match **e {
+            Expression::Shr(ref difference, ref shift) => {
+                let shift = match **shift {
+                    Expression::Constant(ref c) => c.value_u64()?,
+                    _ => return None
+                };
+                if shift + 1 != difference.bits() as u64 {
+                    return None;
+                }
+                difference
+            },
+            _ => return None
+        },
+        Expression::Cmplts(ref difference, ref zero) => match **zero {
+            Expression::Constant(ref c) if c.value_u64() == Some(0) => difference,
+            _ => return None
+        },
+        _ => return None
+    };
+    match **difference {
+        Expression::Sub(ref a, ref b) => Some(((**a).clone(), (**b).clone())),
+        _ => None
+    }
+}
+
+
+struct ValueSetDomain<'a> {
+    architecture: &'a Architecture,
+    offsets: stack_frame::OffsetDomain<'a>
+}
+
+impl<'a> dataflow::Domain for ValueSetDomain<'a> {
+    type State = ValueSet;
+
+    fn bottom(&self) -> Result<ValueSet> {
+        Ok(ValueSet {
+            offsets: dataflow::Domain::bottom(&self.offsets)?,
+            ..ValueSet::default()
+        })
+    }
+
+    fn join(&self, lhs: &ValueSet, rhs: &ValueSet) -> Result<ValueSet> {
+        Ok(lhs.join(rhs, true))
+    }
+
+    fn leq(&self, lhs: &ValueSet, rhs: &ValueSet) -> Result<bool> {
+        Ok(lhs.leq(rhs))
+    }
+
+    fn transfer(
+        &self,
+        location: &falcon::il::RefProgramLocation,
+        operation: &falcon::il::Operation,
+        mut state: ValueSet
+    ) -> Result<ValueSet> {
+
+        let offsets = dataflow::Domain::transfer(
+            &self.offsets, location, operation, state.offsets.clone())?;
+
+        match *operation {
+            falcon::il::Operation::Assign { ref dst, ref src } => {
+                let value = state.eval(src);
+                let definition = state.defined(src);
+                let origin = match *src {
+                    Expression::Scalar(ref scalar) => state.origins.get(scalar.name()).cloned(),
+                    _ => None
+                };
+                state.forget(dst.name());
+                state.set(dst.name(), value);
+                let scalars = definition.scalars();
+                if scalars.len() <= MAX_DEFINITION_SCALARS &&
+                    !scalars.iter().any(|scalar| scalar.name() == dst.name()) {
+                    state.definitions.insert(dst.name().to_string(), definition);
+                }
+                if let Some(origin) = origin {
+                    state.origins.insert(dst.name().to_string(), origin);
+                }
+            },
+            falcon::il::Operation::Load { ref dst, ref index } => {
+                let cell = state.address(self.architecture, index);
+                state.forget(dst.name());
+                if let Some(cell) = cell {
+                    let value = state.cell(&cell, dst.bits());
+                    state.set(dst.name(), value);
+                    state.origins.insert(dst.name().to_string(), cell);
+                }
+            },
+            falcon::il::Operation::Store { ref index, ref src } => {
+                let bytes = cmp::max(src.bits() as u64 / 8, 1);
+                let value = state.eval(src);
+                match state.address(self.architecture, index) {
+                    Some(cell) => {
+                        state.clobber(cell, Some(bytes));
+                        if !value.is_top() {
+                            state.cells.insert(cell, value);
+                        }
+                    },
+                    None => match stack_frame::indexed(self.architecture, &state.offsets, index) {
+                        // A store into a buffer at a known offset only
+                        // clobbers the cells its index reaches.
+                        Some(index) => {
+                            let bits = self.architecture.word_size();
+                            let reach = index.terms.iter()
+                                .fold(StridedInterval::constant(bits, 0),
+                                    |reach, term| reach.add(&state.eval(term).resize(bits)));
+                            let start = Cell::Stack(index.offset);
+                            if reach.is_top() {
+                                state.clobber(start, None);
+                            }
+                            else {
+                                state.clobber(start.add(reach.lo),
+                                    Some(reach.hi - reach.lo + bytes));
+                            }
+                        },
+                        None => state.forget_globals()
+                    }
+                }
+            },
+            falcon::il::Operation::Branch { .. } => {
+                let returns = match *location.function_location() {
+                    falcon::il::RefFunctionLocation::Instruction(block, instruction) =>
+                        il::branch_returns(self.architecture,
+                            location.function(), block.index(), instruction.index()),
+                    _ => None
+                };
+                if returns == Some(true) {
+                    let calling_convention = self.architecture.calling_convention();
+                    for scalar in calling_convention.trashed_registers() {
+                        state.forget(scalar.name());
+                    }
+                    state.forget(calling_convention.return_register().name());
+                    state.forget_globals();
+                }
+            },
+            falcon::il::Operation::Intrinsic { .. } => {
+                for scalar in il::scalars_written(operation) {
+                    state.forget(scalar.name());
+                }
+                state.forget_globals();
+            },
+            falcon::il::Operation::Nop => {}
+        }
+
+        state.offsets = offsets;
+        Ok(state)
+    }
+
+    fn refine(&self, condition: &Expression, mut state: ValueSet) -> Result<ValueSet> {
+        let condition = state.defined(condition);
+        state.refine(&condition, true);
+        Ok(state)
+    }
+}
+
+
+/// The values at every location of a function.
+#[derive(Clone, Debug)]
+pub struct ValueSets {
+    /// The index of the function analysed.
+    pub function_index: Option<usize>,
+    /// The values when each location is reached. Values on an edge hold once
+    /// its condition has been taken.
+    pub states: HashMap<falcon::il::FunctionLocation, ValueSet>
+}
+
+impl ValueSets {
+    /// The values at `location` in the analysed function.
+    pub fn at(&self, location: &falcon::il::FunctionLocation) -> Option<&ValueSet> {
+        self.states.get(location)
+    }
+
+    fn at_program_location(&self, location: &falcon::il::ProgramLocation)
+        -> Option<&ValueSet> {
+
+        if location.function_index() != self.function_index {
+            return None;
+        }
+        self.at(location.function_location())
+    }
+
+    /// The values of `expression` at `location`, if `location` is in the
+    /// analysed function.
+    pub fn range_of(&self, location: &falcon::il::ProgramLocation, expression: &Expression)
+        -> Option<StridedInterval> {
+
+        self.at_program_location(location).map(|state| state.eval(expression))
+    }
+
+    /// The values of `scalar` at `location`, if `location` is in the
+    /// analysed function.
+    pub fn range_of_scalar(
+        &self,
+        location: &falcon::il::ProgramLocation,
+        scalar: &falcon::il::Scalar
+    ) -> Option<StridedInterval> {
+
+        self.at_program_location(location).map(|state| state.scalar(scalar))
+    }
+}
+
+
+/// Run the value-set analysis over `function`.
+pub fn value_sets(architecture: &Architecture, function: &falcon::il::Function)
+    -> Result<ValueSets> {
+
+    let domain = ValueSetDomain {
+        architecture: architecture,
+        offsets: stack_frame::OffsetDomain::new(architecture)
+    };
+    let after: HashMap<falcon::il::FunctionLocation, ValueSet> =
+        dataflow::forward(&domain, function)?.into_iter().collect();
+
+    let mut states = HashMap::new();
+    for (location, state) in &after {
+        if let falcon::il::FunctionLocation::Edge(_, _) = *location {
+            states.insert(location.clone(), state.clone());
+            continue;
+        }
+
+        let ref_location = falcon::il::RefProgramLocation::new(function, location.apply(function)?);
+        let mut before: Option<ValueSet> = None;
+        for predecessor in ref_location.backward()? {
+            let predecessor: falcon::il::FunctionLocation =
+                predecessor.function_location().clone().into();
+            if let Some(state) = after.get(&predecessor) {
+                before = Some(match before {
+                    Some(before) => before.join(state, false),
+                    None => state.clone()
+                });
+            }
+        }
+        let before = match before {
+            Some(before) => before,
+            None => dataflow::Domain::bottom(&domain)?
+        };
+        states.insert(location.clone(), before);
+    }
+
+    Ok(ValueSets { function_index: function.index(), states: states })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use falcon::il::Constant;
+    use super::*;
+
+    fn interval(stride: u64, lo: u64, hi: u64) -> StridedInterval {
+        StridedInterval::new(32, stride, lo, hi)
+    }
+
+    fn constant(value: u64, bits: usize) -> Expression {
+        Expression::Constant(Constant::new(value, bits))
+    }
+
+    #[test]
+    fn new_aligns_hi_to_stride() {
+        assert_eq!(interval(4, 0, 10), StridedInterval { bits: 32, stride: 4, lo: 0, hi: 8 });
+        assert_eq!(interval(4, 3, 3).stride, 0);
+        assert_eq!(StridedInterval::new(8, 1, 0, 0x1000).hi, 0xff);
+    }
+
+    #[test]
+    fn add() {
+        let c = |value| StridedInterval::constant(32, value);
+        assert_eq!(interval(4, 0, 8).add(&c(2)), interval(4, 2, 10));
+        assert_eq!(interval(4, 4, 12).add(&c(0xffffffff)), interval(4, 3, 11));
+        assert!(StridedInterval::top(8).add(&StridedInterval::new(8, 1, 1, 2)).is_top());
+    }
+
+    #[test]
+    fn sub() {
+        let c = |value| StridedInterval::constant(32, value);
+        assert_eq!(interval(2, 10, 20).sub(&c(4)), interval(2, 6, 16));
+        assert!(c(1).sub(&c(2)).is_top());
+    }
+
+    #[test]
+    fn join() {
+        let c = |value| StridedInterval::constant(32, value);
+        assert_eq!(c(4).join(&c(10)), interval(6, 4, 10));
+        assert_eq!(interval(4, 0, 8).join(&c(2)), interval(2, 0, 8));
+        assert!(c(4).leq(&c(4).join(&c(10))));
+        assert!(c(10).leq(&c(4).join(&c(10))));
+    }
+
+    #[test]
+    fn widen() {
+        assert_eq!(interval(1, 0, 10).widen(&interval(1, 0, 20)), interval(1, 0, 31));
+        assert_eq!(interval(1, 9, 20).widen(&interval(1, 5, 20)), interval(1, 4, 31));
+        assert_eq!(interval(4, 0, 8).widen(&interval(4, 0, 8)), interval(4, 0, 12));
+        assert_eq!(StridedInterval::constant(32, 0).widen(&StridedInterval::constant(32, 0)),
+                   StridedInterval::constant(32, 0));
+    }
+
+    #[test]
+    fn widen_is_monotone() {
+        let smaller = interval(1, 0, 5).widen(&interval(1, 0, 4));
+        let larger = interval(1, 0, 5).widen(&interval(1, 0, 5));
+        assert_eq!(smaller, interval(1, 0, 7));
+        assert!(smaller.leq(&larger));
+
+        let smaller = interval(2, 6, 10).widen(&interval(2, 6, 10));
+        let larger = interval(2, 4, 10).widen(&interval(2, 6, 12));
+        assert!(smaller.leq(&larger));
+    }
+
+    #[test]
+    fn intersect() {
+        assert_eq!(interval(4, 0, 40).intersect(5, 30), Some(interval(4, 8, 28)));
+        assert_eq!(interval(4, 0, 40).intersect(0, 100), Some(interval(4, 0, 40)));
+        assert_eq!(interval(4, 0, 40).intersect(41, 50), None);
+        assert_eq!(StridedInterval::top(64).intersect(u64::max_value(), u64::max_value()),
+                   Some(StridedInterval::constant(64, u64::max_value())));
+    }
+
+    #[test]
+    fn wide_signed_operations_do_not_overflow() {
+        let values = ValueSet::default();
+        let less = Expression::Cmplts(
+            Box::new(constant(1, 128)), Box::new(constant(2, 128)));
+        assert_eq!(values.eval(&less), StridedInterval::boolean(true));
+        let extended = Expression::Sext(256, Box::new(constant(1, 128)));
+        assert!(values.eval(&extended).is_top());
+    }
+}
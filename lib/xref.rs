@@ -0,0 +1,338 @@
+//! Cross-references.
+//!
+//! An index of the references between a program's code and the data in
+//! memory, answering "who references this address" and "what does this
+//! location reference". Three kinds of reference are collected:
+//!
+//! * code to code: branches with constant targets, and jumps between blocks
+//!   which do not fall through,
+//! * code to data: constants in loads, stores and other expressions which
+//!   fall inside mapped memory, and
+//! * data to code: word-aligned pointers into executable memory found in
+//!   memory which is not executable.
+//!
+//! A jump is taken to fall through when its target is the next native
+//! address in the function, which is only a guess, as the IL does not record
+//! instruction lengths.
+
+use falcon;
+use falcon::architecture::{Architecture, Endian};
+use falcon::memory::MemoryPermissions;
+use falcon::memory::backing::Memory;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use il;
+use image;
+
+
+/// How a reference uses its target.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Kind {
+    Call,
+    Jump,
+    Read,
+    Write,
+    /// The target's address is computed, but not read or written there.
+    Address,
+    /// A pointer stored in data.
+    Pointer
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            Kind::Call => "call",
+            Kind::Jump => "jump",
+            Kind::Read => "read",
+            Kind::Write => "write",
+            Kind::Address => "address",
+            Kind::Pointer => "pointer"
+        })
+    }
+}
+
+
+/// Where a reference is made from.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Source {
+    Code(falcon::il::ProgramLocation),
+    /// The address of a pointer in data.
+    Data(u64)
+}
+
+
+/// A reference to `target`.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Xref {
+    pub source: Source,
+    /// The native address of the source, if known.
+    pub address: Option<u64>,
+    pub target: u64,
+    pub kind: Kind
+}
+
+impl fmt::Display for Xref {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.address {
+            Some(address) => write!(f, "0x{:x}", address)?,
+            None => write!(f, "?")?
+        }
+        write!(f, " -> 0x{:x} {}", self.target, self.kind)
+    }
+}
+
+
+/// Cross-references, indexed by target and by source.
+#[derive(Clone, Debug, Default)]
+pub struct Xrefs {
+    xrefs: Vec<Xref>,
+    targets: BTreeMap<u64, Vec<usize>>,
+    sources: HashMap<Source, Vec<usize>>
+}
+
+impl Xrefs {
+    fn insert(&mut self, xref: Xref) {
+        let index = self.xrefs.len();
+        self.targets.entry(xref.target).or_insert_with(Vec::new).push(index);
+        self.sources.entry(xref.source.clone()).or_insert_with(Vec::new).push(index);
+        self.xrefs.push(xref);
+    }
+
+    fn select(&self, indices: Option<&Vec<usize>>) -> Vec<&Xref> {
+        indices.map(|indices| indices.iter().map(|&index| &self.xrefs[index]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Every reference.
+    pub fn xrefs(&self) -> &[Xref] {
+        &self.xrefs
+    }
+
+    /// The references to `target`.
+    pub fn to(&self, target: u64) -> Vec<&Xref> {
+        self.select(self.targets.get(&target))
+    }
+
+    /// The references to addresses in `[start, end)`.
+    pub fn to_range(&self, start: u64, end: u64) -> Vec<&Xref> {
+        self.targets.range(start..end)
+            .flat_map(|(_, indices)| indices.iter().map(|&index| &self.xrefs[index]))
+            .collect()
+    }
+
+    /// The references made at `location`.
+    pub fn from(&self, location: &falcon::il::ProgramLocation) -> Vec<&Xref> {
+        self.select(self.sources.get(&Source::Code(location.clone())))
+    }
+
+    /// The reference made by a pointer stored at `address`.
+    pub fn from_data(&self, address: u64) -> Vec<&Xref> {
+        self.select(self.sources.get(&Source::Data(address)))
+    }
+}
+
+
+/// Push every constant in `expression` onto `values`.
+pub fn constants(expression: &falcon::il::Expression, values: &mut Vec<u64>) {
+    use falcon::il::Expression;
+
+    match *expression {
+        Expression::Scalar(_) => {},
+        Expression::Constant(ref constant) => {
+            if let Some(value) = constant.value_u64() {
+                values.push(value);
+            }
+        },
+        Expression::Add(ref l, ref r) |
+        Expression::Sub(ref l, ref r) |
+        Expression::Mul(ref l, ref r) |
+        Expression::Divu(ref l, ref r) |
+        Expression::Modu(ref l, ref r) |
+        Expression::Divs(ref l, ref r) |
+        Expression::Mods(ref l, ref r) |
+        Expression::And(ref l, ref r) |
+        Expression::Or(ref l, ref r) |
+        Expression::Xor(ref l, ref r) |
+        Expression::Shl(ref l, ref r) |
+        Expression::Shr(ref l, ref r) |
+        Expression::Cmpeq(ref l, ref r) |
+        Expression::Cmpneq(ref l, ref r) |
+        Expression::Cmplts(ref l, ref r) |
+        Expression::Cmpltu(ref l, ref r) => {
+            constants(l, values);
+            constants(r, values);
+        },
+        Expression::Zext(_, ref e) |
+        Expression::Sext(_, ref e) |
+        Expression::Trun(_, ref e) => constants(e, values),
+        Expression::Ite(ref c, ref t, ref e) => {
+            constants(c, values);
+            constants(t, values);
+            constants(e, values);
+        }
+    }
+}
+
+
+/// The constant value of `expression`, if it is one.
+fn constant_value(expression: &falcon::il::Expression) -> Option<u64> {
+    match *expression {
+        falcon::il::Expression::Constant(ref constant) => constant.value_u64(),
+        _ => None
+    }
+}
+
+
+/// Word-aligned pointers into executable memory stored in memory which is
+/// not executable, as (address, pointer).
+pub fn code_pointers(architecture: &Architecture, memory: &Memory) -> Vec<(u64, u64)> {
+    let bytes = architecture.word_size() / 8;
+    let little = match architecture.endian() {
+        Endian::Little => true,
+        Endian::Big => false
+    };
+    let executable = |address: u64| memory.permissions(address)
+        .map(|permissions| permissions.contains(MemoryPermissions::EXECUTE))
+        .unwrap_or(false);
+
+    let mut pointers = Vec::new();
+    for (&address, section) in memory.sections() {
+        if section.permissions().contains(MemoryPermissions::EXECUTE) {
+            continue;
+        }
+        let data = section.data();
+        let reader = image::Reader::new(data, little);
+        // Align to the word size in memory, not within the section.
+        let mut offset = ((bytes as u64 - address % bytes as u64) % bytes as u64) as usize;
+        while offset + bytes <= data.len() {
+            if let Ok(pointer) = reader.word(offset as u64, bytes == 8) {
+                if executable(pointer) {
+                    pointers.push((address + offset as u64, pointer));
+                }
+            }
+            offset += bytes;
+        }
+    }
+    pointers
+}
+
+
+/// The references `function` makes, at native addresses `function` was
+/// lifted from.
+fn function_xrefs(
+    architecture: &Architecture,
+    function: &falcon::il::Function,
+    memory: &Memory,
+    xrefs: &mut Xrefs
+) {
+    let mapped = |address: u64| memory.permissions(address).is_some();
+    let location = |location: falcon::il::FunctionLocation|
+        falcon::il::ProgramLocation::new(function.index(), location);
+
+    for block in function.blocks() {
+        for instruction in block.instructions() {
+            let source = Source::Code(location(falcon::il::FunctionLocation::Instruction(
+                block.index(), instruction.index())));
+            let mut reference = |target: u64, kind: Kind| xrefs.insert(Xref {
+                source: source.clone(),
+                address: instruction.address(),
+                target: target,
+                kind: kind
+            });
+
+            // The operand a load, store or branch accesses is a reference of
+            // that kind when constant. Other constants only have their address
+            // taken.
+            let (accessed, kind, operands) = match *instruction.operation() {
+                falcon::il::Operation::Assign { ref src, .. } =>
+                    (None, Kind::Address, vec![src]),
+                falcon::il::Operation::Store { ref index, ref src } =>
+                    (Some(index), Kind::Write, vec![src]),
+                falcon::il::Operation::Load { ref index, .. } =>
+                    (Some(index), Kind::Read, Vec::new()),
+                falcon::il::Operation::Branch { ref target } => {
+                    let returns = il::branch_returns(
+                        architecture, function, block.index(), instruction.index());
+                    match returns {
+                        Some(true) => (Some(target), Kind::Call, Vec::new()),
+                        _ => (Some(target), Kind::Jump, Vec::new())
+                    }
+                },
+                _ => continue
+            };
+
+            let mut values = Vec::new();
+            match accessed.and_then(constant_value) {
+                Some(target) => if mapped(target) {
+                    reference(target, kind);
+                },
+                None => if let Some(accessed) = accessed {
+                    constants(accessed, &mut values);
+                }
+            }
+            for operand in operands {
+                constants(operand, &mut values);
+            }
+
+            let values: BTreeSet<u64> = values.into_iter()
+                .filter(|&value| value != 0 && mapped(value))
+                .collect();
+            for value in values {
+                reference(value, Kind::Address);
+            }
+        }
+    }
+
+    // Jumps between blocks, less those which fall through to the next native
+    // address.
+    let addresses: BTreeSet<u64> = function.blocks()
+        .iter()
+        .flat_map(|block| block.instructions().iter().filter_map(|i| i.address()))
+        .collect();
+    let first = |index: usize| function.block(index).ok()
+        .and_then(|block| block.instructions().iter().filter_map(|i| i.address()).next());
+    let last = |index: usize| function.block(index).ok()
+        .and_then(|block| block.instructions().iter().filter_map(|i| i.address()).last());
+
+    for edge in function.control_flow_graph().edges() {
+        let (from, to) = match (last(edge.head()), first(edge.tail())) {
+            (Some(from), Some(to)) => (from, to),
+            _ => continue
+        };
+        let next = addresses.range(from + 1..).next().cloned();
+        if to == from || Some(to) == next {
+            continue;
+        }
+        xrefs.insert(Xref {
+            source: Source::Code(location(
+                falcon::il::FunctionLocation::Edge(edge.head(), edge.tail()))),
+            address: Some(from),
+            target: to,
+            kind: Kind::Jump
+        });
+    }
+}
+
+
+/// The cross-references of `program`, loaded in `memory`.
+pub fn xrefs(architecture: &Architecture, program: &falcon::il::Program, memory: &Memory)
+    -> Xrefs {
+
+    let mut xrefs = Xrefs::default();
+
+    for function in program.functions() {
+        function_xrefs(architecture, function, memory, &mut xrefs);
+    }
+
+    for (address, pointer) in code_pointers(architecture, memory) {
+        xrefs.insert(Xref {
+            source: Source::Data(address),
+            address: Some(address),
+            target: pointer,
+            kind: Kind::Pointer
+        });
+    }
+
+    xrefs
+}
@@ -1,24 +1,399 @@
 extern crate clap;
+extern crate falcon;
 extern crate osprey;
 
+use osprey::check::Checker;
 use std::fs::File;
 use std::io::Read;
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+
+
+fn read(filename: &str) -> String {
+    let mut contents = String::new();
+    File::open(filename)
+        .and_then(|mut fh| fh.read_to_string(&mut contents))
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to read {}: {}", filename, e);
+            process::exit(1);
+        });
+    contents
+}
+
+
+fn load_loader(matches: &clap::ArgMatches) -> Arc<falcon::loader::Loader> {
+    let filename = matches.value_of("binary").unwrap();
+    match osprey::loader::load(Path::new(filename)) {
+        Some(loader) => loader,
+        None => {
+            eprintln!("Failed to load {}", filename);
+            process::exit(1);
+        }
+    }
+}
+
+
+fn lift(matches: &clap::ArgMatches, loader: &falcon::loader::Loader)
+    -> Arc<falcon::il::Program> {
+
+    let filename = matches.value_of("binary").unwrap();
+    match osprey::loader::program_recursive_resolution(loader) {
+        Ok((program, resolution)) => {
+            for (function, error) in resolution.failures {
+                eprintln!("Failed to resolve jump tables in {}: {}", function, error);
+            }
+            Arc::new(program)
+        },
+        Err(e) => {
+            eprintln!("Failed to lift {}: {}", filename, e);
+            process::exit(1);
+        }
+    }
+}
+
+
+fn load(matches: &clap::ArgMatches)
+    -> (Arc<falcon::loader::Loader>, Arc<falcon::il::Program>) {
+
+    let loader = load_loader(matches);
+    let program = lift(matches, &*loader);
+    (loader, program)
+}
+
+
+/// Print `report` in the format selected with `--format`, and exit with 2 if
+/// there are findings.
+fn print_report(
+    matches: &clap::ArgMatches,
+    loader: &Arc<falcon::loader::Loader>,
+    program: &falcon::il::Program,
+    report: &osprey::check::Report,
+    rules: &[(String, String)]
+) {
+    let format: osprey::output::Format = matches.value_of("format")
+        .unwrap_or("text")
+        .parse()
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+
+    let filename = matches.value_of("binary").unwrap();
+    let bytes = std::fs::read(filename).ok();
+    let symbols: Vec<(u64, String)> = loader.function_entries()
+        .unwrap_or(Vec::new())
+        .iter()
+        .filter_map(|entry| entry.name().map(|name| (entry.address(), name.to_string())))
+        .collect();
+    let symbolizer = osprey::output::Symbolizer::new(
+        bytes.as_ref().map(|bytes| bytes.as_slice()), program, &symbols);
+
+    print!("{}", osprey::output::render(format, &symbolizer, program, report, rules, filename));
+
+    if !report.findings.is_empty() {
+        process::exit(2);
+    }
+}
+
+
+fn format_strings(matches: &clap::ArgMatches) {
+    let (loader, program) = load(matches);
+
+    let checker = osprey::format_string::FormatStringChecker;
+    let findings = match checker.check(&loader, &program) {
+        Ok(findings) => findings,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let report = osprey::check::Report {
+        rules: vec![checker.id().to_string()],
+        findings: findings
+    };
+    let rules = vec![(checker.id().to_string(), checker.description().to_string())];
+
+    print_report(matches, &loader, &program, &report, &rules);
+}
+
+
+fn check(matches: &clap::ArgMatches) {
+    let mut registry = osprey::check::Registry::builtin();
+
+    for filename in matches.values_of("checkers").into_iter().flat_map(|v| v) {
+        let checkers = osprey::load_checkers(&read(filename)).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+        for checker in checkers {
+            registry.register(checker);
+        }
+    }
+
+    if let Some(filename) = matches.value_of("unsafe-call-rules") {
+        let checker = osprey::unsafe_call::UnsafeCallChecker::from_rules(&read(filename))
+            .unwrap_or_else(|e| {
+                eprintln!("{}: {}", filename, e);
+                process::exit(1);
+            });
+        registry.register(Arc::new(checker));
+    }
+
+    if let Some(filename) = matches.value_of("unchecked-return-rules") {
+        let rules = read(filename);
+        let checker = osprey::unchecked_return::UncheckedReturnChecker::from_rules(&rules)
+            .unwrap_or_else(|e| {
+                eprintln!("{}: {}", filename, e);
+                process::exit(1);
+            });
+        registry.register(Arc::new(checker));
+    }
+
+    if matches.is_present("list") {
+        for checker in registry.checkers() {
+            println!("{}\t{}", checker.id(), checker.description());
+        }
+        return;
+    }
+
+    let rules: Vec<String> = matches.values_of("rules")
+        .into_iter()
+        .flat_map(|v| v)
+        .flat_map(|rules| rules.split(','))
+        .filter(|rule| !rule.is_empty())
+        .map(|rule| rule.to_string())
+        .collect();
+
+    let (loader, program) = load(matches);
+
+    let report = match registry.run(&loader, &program, &rules) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let rules: Vec<(String, String)> = report.rules.iter()
+        .filter_map(|rule| registry.checker(rule))
+        .map(|checker| (checker.id().to_string(), checker.description().to_string()))
+        .collect();
+
+    print_report(matches, &loader, &program, &report, &rules);
+}
+
+
+/// Print the strings in a binary, by default only those code references.
+fn strings(matches: &clap::ArgMatches) {
+    let min_length: usize = matches.value_of("min-length")
+        .unwrap()
+        .parse()
+        .unwrap_or_else(|e| {
+            eprintln!("Invalid minimum length: {}", e);
+            process::exit(1);
+        });
+    let json = matches.value_of("format") == Some("json");
+
+    let loader = load_loader(matches);
+    let memory = loader.memory().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    let string_refs: Vec<(osprey::strings::MemoryString, Vec<Option<u64>>)> =
+        if matches.is_present("all") {
+            osprey::strings::strings(loader.architecture(), &memory, min_length)
+                .into_iter()
+                .map(|string| (string, Vec::new()))
+                .collect()
+        }
+        else {
+            let program = lift(matches, &*loader);
+            osprey::strings::string_refs(loader.architecture(), &program, &memory, min_length)
+                .into_iter()
+                .map(|string_ref| {
+                    let addresses = string_ref.references.iter()
+                        .map(|reference| reference.address)
+                        .collect();
+                    (string_ref.string, addresses)
+                })
+                .collect()
+        };
+
+    for (string, addresses) in string_refs {
+        if json {
+            use osprey::output::Json;
+            println!("{}", Json::object(vec![
+                ("address", Json::address(string.address)),
+                ("encoding", Json::string(format!("{}", string.encoding))),
+                ("value", Json::string(string.value.clone())),
+                ("references", Json::Array(addresses.into_iter()
+                    .map(|address| Json::option(address, Json::address))
+                    .collect()))
+            ]));
+        }
+        else {
+            println!("{}", string);
+            for address in addresses {
+                match address {
+                    Some(address) => println!("    <- 0x{:x}", address),
+                    None => println!("    <- ?")
+                }
+            }
+        }
+    }
+}
+
+
+/// Print a binary's security hardening.
+fn hardening(matches: &clap::ArgMatches) {
+    let filename = matches.value_of("binary").unwrap();
+    let loader = load_loader(matches);
+    let bytes = std::fs::read(filename).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", filename, e);
+        process::exit(1);
+    });
+    let hardening = osprey::hardening::hardening(&*loader, &bytes).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    if matches.value_of("format") == Some("json") {
+        use osprey::output::Json;
+        let mut fields: Vec<(&str, Json)> = hardening.properties()
+            .into_iter()
+            .map(|(name, value)| (name, Json::string(value)))
+            .collect();
+        fields.push(("fortified", Json::Array(hardening.fortified.iter()
+            .map(|name| Json::string(name.clone()))
+            .collect())));
+        println!("{}", Json::object(fields));
+    }
+    else {
+        print!("{}", hardening);
+    }
+}
 
 
 fn osprey () {
+    let binary = clap::Arg::with_name("binary")
+        .value_name("BINARY")
+        .help("Path to the binary to analyze")
+        .required(true)
+        .index(1);
+
+    let format = clap::Arg::with_name("format")
+        .short("f")
+        .long("format")
+        .value_name("FORMAT")
+        .help("Output format")
+        .possible_values(&["text", "json", "sarif"])
+        .default_value("text");
+
     let matches = clap::App::new("osprey")
         .version("0.1.0")
         .about("Program Analysis with Falcon")
         .author("Alex Eubanks")
+        .setting(clap::AppSettings::ArgRequiredElseHelp)
         .arg(clap::Arg::with_name("script")
              .short("s")
              .long("script")
              .value_name("FILE")
-             .help("Path to script to run")
-             .required(true))
+             .help("Path to script to run"))
+        .subcommand(clap::SubCommand::with_name("check")
+             .about("Run vulnerability checkers over a binary")
+             .arg(binary.clone().required_unless("list"))
+             .arg(format.clone())
+             .arg(clap::Arg::with_name("rules")
+                  .short("r")
+                  .long("rules")
+                  .value_name("RULES")
+                  .help("Comma-separated rule ids to run, defaults to every rule")
+                  .multiple(true)
+                  .number_of_values(1))
+             .arg(clap::Arg::with_name("checkers")
+                  .short("c")
+                  .long("checkers")
+                  .value_name("FILE")
+                  .help("Gluon script evaluating to an array of checkers to register")
+                  .multiple(true)
+                  .number_of_values(1))
+             .arg(clap::Arg::with_name("unsafe-call-rules")
+                  .long("unsafe-call-rules")
+                  .value_name("FILE")
+                  .help("Rules file for the unsafe-call checker"))
+             .arg(clap::Arg::with_name("unchecked-return-rules")
+                  .long("unchecked-return-rules")
+                  .value_name("FILE")
+                  .help("Rules file for the unchecked-return checker"))
+             .arg(clap::Arg::with_name("list")
+                  .short("l")
+                  .long("list")
+                  .help("List registered rules and exit")))
+        .subcommand(clap::SubCommand::with_name("format-strings")
+             .about("Find printf-family calls with unsafe format arguments")
+             .arg(binary.clone())
+             .arg(format))
+        .subcommand(clap::SubCommand::with_name("hardening")
+             .alias("checksec")
+             .about("Report the security hardening of a binary")
+             .arg(binary.clone())
+             .arg(clap::Arg::with_name("format")
+                  .short("f")
+                  .long("format")
+                  .value_name("FORMAT")
+                  .help("Output format")
+                  .possible_values(&["text", "json"])
+                  .default_value("text")))
+        .subcommand(clap::SubCommand::with_name("strings")
+             .about("List strings in a binary and the code referencing them")
+             .arg(binary)
+             .arg(clap::Arg::with_name("format")
+                  .short("f")
+                  .long("format")
+                  .value_name("FORMAT")
+                  .help("Output format")
+                  .possible_values(&["text", "json"])
+                  .default_value("text"))
+             .arg(clap::Arg::with_name("min-length")
+                  .short("n")
+                  .long("min-length")
+                  .value_name("LENGTH")
+                  .help("Minimum string length in characters")
+                  .default_value("4"))
+             .arg(clap::Arg::with_name("all")
+                  .short("a")
+                  .long("all")
+                  .help("List every string, without lifting the binary to find references")))
         .get_matches();
 
-    let mut fh = File::open(matches.value_of("script").unwrap()).unwrap();
+    if let Some(matches) = matches.subcommand_matches("check") {
+        return check(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("format-strings") {
+        return format_strings(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("hardening") {
+        return hardening(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("strings") {
+        return strings(matches);
+    }
+
+    let script = match matches.value_of("script") {
+        Some(script) => script,
+        None => {
+            eprintln!("{}", matches.usage());
+            process::exit(1);
+        }
+    };
+
+    let mut fh = File::open(script).unwrap();
     let mut script = String::new();
     fh.read_to_string(&mut script).unwrap();
 
@@ -28,4 +403,4 @@ fn osprey () {
 
 fn main () {
     osprey();
-}
\ No newline at end of file
+}